@@ -0,0 +1,111 @@
+//! OSC 52 clipboard policy.
+//!
+//! Programs in a terminal (tmux, neovim over ssh, CLI tools) can ask to set or
+//! read the system clipboard. Writes are allowed by default; reads are denied
+//! by default since they can leak whatever the user last copied. Either can be
+//! switched to prompt the user instead. The policy is stored in the settings
+//! table and applied by the render pump.
+
+use phantom_db::settings;
+use rusqlite::Connection;
+use serde::Serialize;
+
+/// Settings key for whether programs may set the clipboard.
+pub const SETTING_CLIPBOARD_WRITE: &str = "clipboard_osc52_write";
+
+/// Settings key for whether programs may read the clipboard.
+pub const SETTING_CLIPBOARD_READ: &str = "clipboard_osc52_read";
+
+/// What to do when a program asks for clipboard access.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardPermission {
+    /// Honor the request.
+    Allow,
+    /// Ignore the request.
+    Deny,
+    /// Ask the user first.
+    Prompt,
+}
+
+impl ClipboardPermission {
+    /// Parse a permission name ("allow", "deny" or "prompt").
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "allow" => Some(ClipboardPermission::Allow),
+            "deny" => Some(ClipboardPermission::Deny),
+            "prompt" => Some(ClipboardPermission::Prompt),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClipboardPermission::Allow => "allow",
+            ClipboardPermission::Deny => "deny",
+            ClipboardPermission::Prompt => "prompt",
+        }
+    }
+}
+
+/// Clipboard permissions for programs running in terminals.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipboardPolicy {
+    pub write: ClipboardPermission,
+    pub read: ClipboardPermission,
+}
+
+impl Default for ClipboardPolicy {
+    fn default() -> Self {
+        Self {
+            write: ClipboardPermission::Allow,
+            read: ClipboardPermission::Deny,
+        }
+    }
+}
+
+/// Read the clipboard policy from the database.
+///
+/// Missing or unrecognized values fall back to the defaults.
+pub fn read_policy(conn: &Connection) -> ClipboardPolicy {
+    let defaults = ClipboardPolicy::default();
+    let read = |key: &str| {
+        settings::get(conn, key)
+            .ok()
+            .flatten()
+            .and_then(|v| ClipboardPermission::parse(&v))
+    };
+    ClipboardPolicy {
+        write: read(SETTING_CLIPBOARD_WRITE).unwrap_or(defaults.write),
+        read: read(SETTING_CLIPBOARD_READ).unwrap_or(defaults.read),
+    }
+}
+
+/// Store the clipboard policy in the database.
+pub fn write_policy(conn: &Connection, policy: ClipboardPolicy) -> rusqlite::Result<()> {
+    settings::set(conn, SETTING_CLIPBOARD_WRITE, policy.write.as_str())?;
+    settings::set(conn, SETTING_CLIPBOARD_READ, policy.read.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        phantom_db::schema::initialize(&conn).unwrap();
+
+        assert_eq!(read_policy(&conn), ClipboardPolicy::default());
+
+        let policy = ClipboardPolicy {
+            write: ClipboardPermission::Prompt,
+            read: ClipboardPermission::Allow,
+        };
+        write_policy(&conn, policy).unwrap();
+        assert_eq!(read_policy(&conn), policy);
+
+        settings::set(&conn, SETTING_CLIPBOARD_READ, "sometimes").unwrap();
+        assert_eq!(read_policy(&conn).read, ClipboardPermission::Deny);
+    }
+}
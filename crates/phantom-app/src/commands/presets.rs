@@ -22,11 +22,12 @@ pub async fn create_cli_preset(
     flags: String,
     working_dir: Option<String>,
     budget_usd: Option<f64>,
+    record: Option<bool>,
 ) -> Result<i64, String> {
     let db = state.db.clone();
     tokio::task::spawn_blocking(move || {
         let conn = db.lock().map_err(|e| format!("db lock poisoned: {e}"))?;
-        presets::create_cli_preset(
+        let id = presets::create_cli_preset(
             &conn,
             &name,
             &cli_binary,
@@ -35,7 +36,11 @@ pub async fn create_cli_preset(
             None,
             budget_usd,
         )
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+        if record == Some(true) {
+            presets::set_cli_preset_record(&conn, id, true).map_err(|e| e.to_string())?;
+        }
+        Ok(id)
     })
     .await
     .map_err(|e| format!("task join error: {e}"))?
@@ -3,20 +3,38 @@
 //! These commands are invoked from the frontend via `invoke()` and handle
 //! creating, writing to, resizing, and closing terminal sessions.
 
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use phantom_vt::{GridPoint, Modifiers, MouseEvent, SearchKind};
 use tokio::sync::mpsc;
 
+use crate::clipboard::{self, ClipboardPermission, ClipboardPolicy};
 use crate::io_thread::start_io_thread;
-use crate::ipc::TerminalEvent;
+use crate::ipc::{
+    encode_line, parse_export_format, parse_mouse_button, parse_mouse_event_kind, parse_prompt_direction,
+    parse_selection_mode, parse_side, CommandBlockSpan, EncodedCells, HyperlinkDef, KeyInput, MatchSpan,
+    LinkSpan, ModesInfo, PasteResult, PromptDirection, ScrollbackLine, ScrollbackLines, SelectionSpan, TerminalEvent, ThemeInfo,
+};
 use crate::render_pump::start_render_pump;
 use crate::state::{AppState, SessionId, SessionState};
+use crate::theme;
 
 /// Create a new terminal session.
 ///
 /// Spawns a PTY with the given shell (or default), starts the I/O thread
 /// and render pump, and returns the session ID.
+///
+/// If `working_dir` is not given and `inherit_cwd_from` names a session whose
+/// shell has reported a working directory (OSC 7) that still exists, the new
+/// shell starts there.
+///
+/// With `record` set, the session is recorded from the start, including
+/// input if `record_input` is also set; see `start_recording`.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_terminal(
     state: tauri::State<'_, AppState>,
     shell: Option<String>,
@@ -24,7 +42,17 @@ pub async fn create_terminal(
     rows: u16,
     channel: tauri::ipc::Channel<TerminalEvent>,
     working_dir: Option<String>,
+    inherit_cwd_from: Option<SessionId>,
+    record: Option<bool>,
+    record_input: Option<bool>,
 ) -> Result<SessionId, String> {
+    let working_dir = match (working_dir, inherit_cwd_from) {
+        (Some(dir), _) => Some(dir),
+        (None, Some(source_id)) => session_cwd(&state, source_id)
+            .filter(|dir| std::path::Path::new(dir).is_dir()),
+        (None, None) => None,
+    };
+
     let session_id = state.next_session_id();
 
     let mut session = phantom_pty::TerminalSession::new(
@@ -35,17 +63,44 @@ pub async fn create_terminal(
         working_dir.as_deref(),
     )
     .map_err(|e| format!("Failed to create terminal session: {e}"))?;
+    if record == Some(true) {
+        start_session_recording(&state, &mut session, record_input == Some(true))?;
+    }
 
     // Extract the PTY reader before putting session behind the mutex.
     // The I/O thread owns the reader directly so it can block without
     // holding the session lock.
     let pty_reader = session.take_pty_reader();
+    let clipboard_policy = *state
+        .clipboard_policy
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let theme = state
+        .theme
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?
+        .clone();
+    session.vt_mut().set_theme(theme);
 
     let session_state = Arc::new(Mutex::new(SessionState {
         session,
         needs_full_frame: true,
+        last_cursor: None,
         last_title: None,
+        last_icon_name: None,
+        last_cwd: None,
+        last_colors: None,
+        last_progress: None,
+        last_modes: None,
+        clipboard_policy,
+        last_scroll: (0, 0),
+        last_selection: None,
+        last_search: (Vec::new(), None),
+        last_images: Vec::new(),
+        sent_images: HashSet::new(),
+        search_dirty: false,
         has_pty_data: false,
+        sync_held_since: None,
     }));
 
     // Create stop channels for I/O thread and render pump.
@@ -83,13 +138,22 @@ pub async fn create_terminal(
     Ok(session_id)
 }
 
-/// Write user input bytes to a terminal session's PTY.
+/// Write user input to a terminal session's PTY.
+///
+/// Takes raw bytes in `data`, or a structured key event in `key` that is
+/// encoded according to the terminal's keyboard modes (cursor key mode,
+/// keypad mode, modifyOtherKeys, kitty keyboard protocol). A key event that
+/// the program should not receive, such as an unrequested key release, is
+/// dropped.
 #[tauri::command]
 pub async fn write_input(
     state: tauri::State<'_, AppState>,
     session_id: SessionId,
-    data: Vec<u8>,
+    data: Option<Vec<u8>>,
+    key: Option<KeyInput>,
 ) -> Result<(), String> {
+    let key = key.map(|k| k.to_key_event()).transpose()?;
+
     let session_state = {
         let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
         sessions
@@ -101,19 +165,161 @@ pub async fn write_input(
     let mut state = session_state
         .lock()
         .map_err(|e| format!("Lock error: {e}"))?;
+    let data = match (data, key) {
+        (Some(data), None) => data,
+        (None, Some(key)) => match state.session.vt().encode_key(&key) {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        },
+        _ => return Err("Expected exactly one of `data` or `key`".to_string()),
+    };
     state
         .session
         .write_input(&data)
         .map_err(|e| format!("Write error: {e}"))
 }
 
+/// Paste text into a terminal session.
+///
+/// The text is sanitized and bracketed per the program's modes. Risky pastes
+/// are not sent unless `confirmed` is set; the result lists the risks so the
+/// frontend can ask the user and paste again with `confirmed`.
+#[tauri::command]
+pub async fn paste_text(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    text: String,
+    confirmed: bool,
+) -> Result<PasteResult, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let outcome = state
+        .session
+        .paste(&text, confirmed)
+        .map_err(|e| format!("Write error: {e}"))?;
+    Ok(PasteResult::from(outcome))
+}
+
+/// Tell a terminal session whether it has keyboard focus.
+///
+/// The cursor is drawn hollow and stops blinking while unfocused; programs
+/// that enabled focus reporting (mode 1004) receive `CSI I` / `CSI O`.
+#[tauri::command]
+pub async fn set_terminal_focus(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    focused: bool,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state
+        .session
+        .set_focused(focused)
+        .map_err(|e| format!("Write error: {e}"))
+}
+
+/// Report a mouse event to the program running in a terminal session.
+///
+/// `kind` is "press", "release" or "motion"; `button` is "left", "middle",
+/// "right", "wheel_up", "wheel_down", "wheel_left", "wheel_right" or "none"
+/// (motion with no button held). `row`/`col` are viewport cells, and
+/// `modifiers` is a bitmask: shift = 1, alt = 2, ctrl = 4, super = 8.
+///
+/// Returns `true` if the event was sent. When it is not, the program did not
+/// ask for mouse input and the frontend should handle the event itself.
+#[tauri::command]
+pub async fn send_mouse_event(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    kind: String,
+    button: String,
+    row: u16,
+    col: u16,
+    modifiers: u8,
+) -> Result<bool, String> {
+    let event = MouseEvent {
+        kind: parse_mouse_event_kind(&kind)
+            .ok_or_else(|| format!("Unknown mouse event kind: {kind}"))?,
+        button: parse_mouse_button(&button)
+            .ok_or_else(|| format!("Unknown mouse button: {button}"))?,
+        modifiers: Modifiers::from_bits_truncate(modifiers),
+        row,
+        col,
+    };
+
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    let Some(bytes) = state.session.vt().encode_mouse(&event) else {
+        return Ok(false);
+    };
+    state
+        .session
+        .write_input(&bytes)
+        .map_err(|e| format!("Write error: {e}"))?;
+    Ok(true)
+}
+
+/// Get the terminal modes that affect input handling.
+#[tauri::command]
+pub async fn get_terminal_modes(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<ModesInfo, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(ModesInfo::from(state.session.vt().modes()))
+}
+
 /// Resize a terminal session's PTY and VT terminal.
+///
+/// `cell_width` and `cell_height` give the cell size in pixels, which
+/// decides how many cells newly shown images cover.
 #[tauri::command]
 pub async fn resize_terminal(
     state: tauri::State<'_, AppState>,
     session_id: SessionId,
     cols: u16,
     rows: u16,
+    cell_width: Option<u16>,
+    cell_height: Option<u16>,
 ) -> Result<(), String> {
     let session_state = {
         let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
@@ -130,6 +336,9 @@ pub async fn resize_terminal(
         .session
         .resize(cols, rows)
         .map_err(|e| format!("Resize error: {e}"))?;
+    if let (Some(width), Some(height)) = (cell_width, cell_height) {
+        state.session.vt_mut().set_cell_size(width, height);
+    }
 
     // Mark as needing a full frame after resize.
     state.needs_full_frame = true;
@@ -137,42 +346,947 @@ pub async fn resize_terminal(
     Ok(())
 }
 
-/// Close a terminal session.
+/// Choose whether resizing a terminal session rewraps soft-wrapped lines
+/// (the default) or crops and pads them so text keeps its columns.
+#[tauri::command]
+pub async fn set_terminal_reflow(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    reflow: bool,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state.session.vt_mut().set_reflow(reflow);
+    Ok(())
+}
+
+/// Scroll a terminal session's viewport by `delta` lines.
 ///
-/// Sends stop signals to the I/O thread and render pump, then removes the
-/// session from the global state.
+/// Positive values scroll up into the scrollback, negative values scroll back
+/// towards the live screen. The render pump sends a full frame for the new
+/// viewport on its next tick.
 #[tauri::command]
-pub async fn close_terminal(
+pub async fn scroll_terminal(
     state: tauri::State<'_, AppState>,
     session_id: SessionId,
+    delta: i32,
 ) -> Result<(), String> {
-    // Extract senders from the locks before awaiting, to avoid holding
-    // std::sync::MutexGuard across an await (which is not Send).
-    let io_stop_tx = state
-        .io_stops
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
         .lock()
-        .ok()
-        .and_then(|mut stops| stops.remove(&session_id));
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state.session.vt_mut().scroll_display(delta);
 
-    let render_stop_tx = state
-        .render_stops
+    Ok(())
+}
+
+/// Read `count` lines of a terminal session's grid starting at `start`.
+///
+/// Line 0 is the top of the live screen and negative lines index into the
+/// scrollback. Lines outside the buffer are skipped. Hyperlinks the lines
+/// use that the frontend has not been told about come with them.
+#[tauri::command]
+pub async fn read_scrollback(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    start: i32,
+    count: u32,
+) -> Result<ScrollbackLines, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
         .lock()
-        .ok()
-        .and_then(|mut stops| stops.remove(&session_id));
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let screen = state.session.vt().screen();
 
-    // Send stop signals (now safe to await since we dropped the MutexGuards).
-    if let Some(tx) = io_stop_tx {
-        let _ = tx.send(()).await;
-    }
-    if let Some(tx) = render_stop_tx {
-        let _ = tx.send(()).await;
-    }
+    let lines = (0..count as i64)
+        .filter_map(|i| i32::try_from(start as i64 + i).ok())
+        .filter_map(|line| {
+            encode_line(&screen, line)
+                .map(|EncodedCells { cells, graphemes }| ScrollbackLine { line, cells, graphemes })
+        })
+        .collect();
+    let links = state
+        .session
+        .vt_mut()
+        .take_new_hyperlinks()
+        .into_iter()
+        .map(HyperlinkDef::from)
+        .collect();
 
-    // Remove session from global state (this drops the session, which kills
-    // the PTY child process via PtyHandle's Drop impl).
-    if let Ok(mut sessions) = state.sessions.lock() {
-        sessions.remove(&session_id);
-    }
+    Ok(ScrollbackLines { lines, links })
+}
+
+/// Start a selection at a viewport position.
+///
+/// `mode` is one of `simple`, `word`, `line`, or `block`; `side` is `left` or
+/// `right` depending on which half of the cell was clicked.
+#[tauri::command]
+pub async fn start_selection(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    row: u16,
+    col: u16,
+    mode: String,
+    side: Option<String>,
+) -> Result<Option<SelectionSpan>, String> {
+    let mode =
+        parse_selection_mode(&mode).ok_or_else(|| format!("Unknown selection mode: {mode}"))?;
+
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let vt = state.session.vt_mut();
+    let point = GridPoint::from_viewport(row, col, vt.display_offset());
+    vt.start_selection(mode, point, parse_side(side.as_deref()));
+
+    Ok(vt.selection_range().map(SelectionSpan::from))
+}
+
+/// Extend the current selection to a viewport position.
+#[tauri::command]
+pub async fn update_selection(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    row: u16,
+    col: u16,
+    side: Option<String>,
+) -> Result<Option<SelectionSpan>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let vt = state.session.vt_mut();
+    let point = GridPoint::from_viewport(row, col, vt.display_offset());
+    vt.update_selection(point, parse_side(side.as_deref()));
+
+    Ok(vt.selection_range().map(SelectionSpan::from))
+}
+
+/// Clear the current selection.
+#[tauri::command]
+pub async fn clear_selection(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state.session.vt_mut().clear_selection();
 
     Ok(())
 }
+
+/// Get the text of the current selection, for copying to the clipboard.
+#[tauri::command]
+pub async fn get_selection_text(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Option<String>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(state.session.vt().selection_text())
+}
+
+/// Export a terminal session's scrollback and screen, or only the current
+/// selection, as "text", "ansi" or "html".
+///
+/// Returns `None` when `selection_only` is set and nothing is selected.
+#[tauri::command]
+pub async fn export_terminal(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    format: String,
+    selection_only: bool,
+) -> Result<Option<String>, String> {
+    let format =
+        parse_export_format(&format).ok_or_else(|| format!("Unknown export format: {format}"))?;
+
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    let vt = state.session.vt();
+    if !selection_only {
+        return Ok(Some(vt.export(format, None)));
+    }
+    Ok(vt.selection_range().map(|range| vt.export(format, Some(range))))
+}
+
+/// Start recording a terminal session's output and resizes to a new
+/// asciicast v2 file in the recordings directory. Returns the file's path.
+///
+/// Input is recorded too only with `record_input` set, like `asciinema
+/// --stdin`, since it holds everything the user types. A recording already
+/// running is stopped first. If writing to the file fails later, the
+/// recording stops and a `RecordingStopped` event is sent.
+#[tauri::command]
+pub async fn start_recording(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    record_input: Option<bool>,
+) -> Result<String, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut session_state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    start_session_recording(
+        &state,
+        &mut session_state.session,
+        record_input == Some(true),
+    )
+}
+
+/// Stop recording a terminal session. Does nothing if it is not recorded.
+#[tauri::command]
+pub async fn stop_recording(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    state.session.stop_recording();
+    Ok(())
+}
+
+/// Start recording `session` to a new file named after the session and the
+/// current time. The file is readable by the user only, since the output
+/// may hold anything shown in the terminal.
+fn start_session_recording(
+    state: &AppState,
+    session: &mut phantom_pty::TerminalSession,
+    record_input: bool,
+) -> Result<String, String> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = state
+        .recordings_dir
+        .join(format!("session-{}-{started}.cast", session.id()));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options
+        .open(&path)
+        .map_err(|e| format!("Failed to create recording {}: {e}", path.display()))?;
+    session
+        .start_recording(Box::new(file), record_input)
+        .map_err(|e| format!("Failed to start recording: {e}"))?;
+    Ok(path.display().to_string())
+}
+
+/// Save a snapshot of a terminal session's screen, scrollback, cursor,
+/// modes, title and palette to a new file in the snapshots directory.
+/// Returns the file's path.
+#[tauri::command]
+pub async fn save_terminal_snapshot(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<String, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let bytes = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?
+        .session
+        .vt()
+        .snapshot();
+    let taken = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = state
+        .snapshots_dir
+        .join(format!("session-{session_id}-{taken}.phsnap"));
+    std::fs::write(&path, bytes)
+        .map_err(|e| format!("Failed to write snapshot {}: {e}", path.display()))?;
+    Ok(path.display().to_string())
+}
+
+/// Replace a terminal session's screen and scrollback with a snapshot file
+/// saved by `save_terminal_snapshot`. The shell keeps running.
+#[tauri::command]
+pub async fn restore_terminal_snapshot(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    path: String,
+) -> Result<(), String> {
+    let bytes =
+        std::fs::read(&path).map_err(|e| format!("Failed to read snapshot {path}: {e}"))?;
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state
+        .session
+        .restore_snapshot(&bytes)
+        .map_err(|e| format!("Failed to restore snapshot {path}: {e}"))?;
+    state.needs_full_frame = true;
+    Ok(())
+}
+
+/// Start a search over a terminal session's screen and scrollback.
+///
+/// When `regex` is false the query is matched literally. Returns the total
+/// number of matches; use `search_next`/`search_previous` to navigate.
+#[tauri::command]
+pub async fn search_terminal(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    query: String,
+    regex: bool,
+) -> Result<usize, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let kind = if regex { SearchKind::Regex } else { SearchKind::Literal };
+    state
+        .session
+        .vt_mut()
+        .search(&query, kind)
+        .map_err(|e| format!("Search error: {e}"))?;
+    state.search_dirty = true;
+
+    Ok(state.session.vt_mut().search_all_matches().len())
+}
+
+/// Focus the next search match, scrolling the viewport to it.
+#[tauri::command]
+pub async fn search_next(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Option<MatchSpan>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let found = state.session.vt_mut().search_next();
+    state.search_dirty = true;
+
+    Ok(found.map(MatchSpan::from))
+}
+
+/// Focus the previous search match, scrolling the viewport to it.
+#[tauri::command]
+pub async fn search_previous(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Option<MatchSpan>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    let found = state.session.vt_mut().search_previous();
+    state.search_dirty = true;
+
+    Ok(found.map(MatchSpan::from))
+}
+
+/// End the active search and remove its highlights.
+#[tauri::command]
+pub async fn clear_search(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    state.session.vt_mut().clear_search();
+    state.search_dirty = true;
+
+    Ok(())
+}
+
+/// Get the working directory last reported by a session's shell (OSC 7).
+#[tauri::command]
+pub async fn get_terminal_cwd(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Option<String>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(state.session.cwd().map(|s| s.to_string()))
+}
+
+/// List the titles a session's window has had, oldest first.
+///
+/// Includes titles restored with `CSI 23 t`; repeats in a row are collapsed.
+#[tauri::command]
+pub async fn get_title_history(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Vec<String>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(state.session.vt().title_history())
+}
+
+/// List the command blocks recorded by shell integration (OSC 133), oldest first.
+#[tauri::command]
+pub async fn list_command_blocks(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Vec<CommandBlockSpan>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(state
+        .session
+        .command_blocks()
+        .into_iter()
+        .map(CommandBlockSpan::from)
+        .collect())
+}
+
+/// Scroll the viewport to the previous or next shell prompt.
+///
+/// `direction` is "previous" or "next". Returns the block whose prompt is now
+/// at the top of the viewport, or `None` if there is no prompt that way.
+#[tauri::command]
+pub async fn jump_to_prompt(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    direction: String,
+) -> Result<Option<CommandBlockSpan>, String> {
+    let direction = parse_prompt_direction(&direction)
+        .ok_or_else(|| format!("Unknown prompt direction: {direction}"))?;
+
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    let vt = state.session.vt_mut();
+    let block = match direction {
+        PromptDirection::Previous => vt.scroll_to_previous_prompt(),
+        PromptDirection::Next => vt.scroll_to_next_prompt(),
+    };
+    Ok(block.map(CommandBlockSpan::from))
+}
+
+/// Find the URLs and file paths shown in the viewport, for the frontend to
+/// hit-test clicks against.
+///
+/// Relative paths are resolved against the shell's working directory (OSC 7)
+/// and `~/` against the home directory.
+#[tauri::command]
+pub async fn detect_links(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<Vec<LinkSpan>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    let home = std::env::var("HOME").ok();
+    Ok(state
+        .session
+        .vt_mut()
+        .detect_links()
+        .into_iter()
+        .map(|link| {
+            let mut span = LinkSpan::from(link);
+            if let (Some(home), Some(rest)) = (&home, span.target.strip_prefix("~/")) {
+                if span.kind == "file" {
+                    span.target = format!("{home}/{rest}");
+                }
+            }
+            span
+        })
+        .collect())
+}
+
+/// Get the output text of a command block.
+#[tauri::command]
+pub async fn get_command_output(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    block_id: u64,
+) -> Result<Option<String>, String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    Ok(state.session.vt().command_output(block_id))
+}
+
+/// Editors that open a file at a line and column, and the arguments they
+/// take for it, tried in order.
+const GOTO_EDITORS: &[(&str, &[&str])] = &[
+    ("code", &["--goto"]),
+    ("cursor", &["--goto"]),
+    ("codium", &["--goto"]),
+    ("zed", &[]),
+    ("subl", &[]),
+];
+
+/// Turn a file link's target into an absolute path, expanding a leading `~`.
+///
+/// `detect_links` resolves relative paths against the working directory the
+/// shell reported; if it had none, the target is still relative and is
+/// rejected rather than opened relative to Phantom's own directory.
+fn link_path(target: &str, home: Option<&Path>) -> Result<PathBuf, String> {
+    let path = match target.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = home.ok_or_else(|| format!("Cannot open {target}: no home directory"))?;
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(target),
+    };
+    if path.is_relative() {
+        return Err(format!(
+            "Cannot open {target}: the terminal's working directory is unknown"
+        ));
+    }
+    Ok(path)
+}
+
+/// Open a link found by `detect_links`: a URL in the default browser, or a
+/// file in an editor.
+///
+/// With a `line`, the first of the editors in `GOTO_EDITORS` that is
+/// installed opens the file there. Otherwise, or if none is, the file opens
+/// in its default application.
+#[tauri::command]
+pub async fn open_link(
+    app: tauri::AppHandle,
+    kind: String,
+    target: String,
+    line: Option<u32>,
+    column: Option<u32>,
+) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    match kind.as_str() {
+        "url" => app
+            .opener()
+            .open_url(&target, None::<&str>)
+            .map_err(|e| format!("Failed to open {target}: {e}")),
+        "file" => {
+            let path = link_path(&target, crate::home_dir().as_deref())?;
+            let path = path.to_string_lossy();
+            if let Some(line) = line {
+                let location = format!("{path}:{line}:{}", column.unwrap_or(1));
+                for (editor, args) in GOTO_EDITORS {
+                    let spawned = std::process::Command::new(editor)
+                        .args(*args)
+                        .arg(&location)
+                        .spawn();
+                    if let Ok(mut child) = spawned {
+                        // Reap the launcher once it hands the file over.
+                        std::thread::spawn(move || child.wait());
+                        return Ok(());
+                    }
+                }
+            }
+            app.opener()
+                .open_path(path.to_string(), None::<&str>)
+                .map_err(|e| format!("Failed to open {path}: {e}"))
+        }
+        _ => Err(format!("Unknown link kind: {kind}")),
+    }
+}
+
+/// Show a desktop notification a program asked for via OSC 9 or OSC 777.
+#[tauri::command]
+pub async fn show_notification(
+    app: tauri::AppHandle,
+    title: String,
+    body: String,
+) -> Result<(), String> {
+    use tauri_plugin_notification::NotificationExt;
+
+    app.notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .show()
+        .map_err(|e| format!("Failed to show notification: {e}"))
+}
+
+/// Answer a clipboard read requested by a program via OSC 52.
+///
+/// `text` is sent back to the program; `None` declines the request.
+#[tauri::command]
+pub async fn answer_clipboard_read(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+    request_id: u64,
+    text: Option<String>,
+) -> Result<(), String> {
+    let session_state = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| format!("Session {session_id} not found"))?
+    };
+
+    let mut state = session_state
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+
+    match text {
+        Some(text) => {
+            state
+                .session
+                .answer_clipboard_load(request_id, &text)
+                .map_err(|e| format!("Write error: {e}"))?;
+        }
+        None => state.session.discard_clipboard_load(request_id),
+    }
+    Ok(())
+}
+
+/// Get the OSC 52 clipboard policy.
+#[tauri::command]
+pub async fn get_clipboard_policy(
+    state: tauri::State<'_, AppState>,
+) -> Result<ClipboardPolicy, String> {
+    let policy = state
+        .clipboard_policy
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))?;
+    Ok(*policy)
+}
+
+/// Set the OSC 52 clipboard policy, persist it, and apply it to all sessions.
+///
+/// `write` and `read` are each "allow", "deny" or "prompt".
+#[tauri::command]
+pub async fn set_clipboard_policy(
+    state: tauri::State<'_, AppState>,
+    write: String,
+    read: String,
+) -> Result<ClipboardPolicy, String> {
+    let policy = ClipboardPolicy {
+        write: ClipboardPermission::parse(&write)
+            .ok_or_else(|| format!("Unknown clipboard permission: {write}"))?,
+        read: ClipboardPermission::parse(&read)
+            .ok_or_else(|| format!("Unknown clipboard permission: {read}"))?,
+    };
+
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = db.lock().map_err(|e| format!("db lock poisoned: {e}"))?;
+        clipboard::write_policy(&conn, policy).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("task join error: {e}"))??;
+
+    *state
+        .clipboard_policy
+        .lock()
+        .map_err(|e| format!("Lock error: {e}"))? = policy;
+
+    let sessions: Vec<_> = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions.values().cloned().collect()
+    };
+    for session_state in sessions {
+        if let Ok(mut session) = session_state.lock() {
+            session.clipboard_policy = policy;
+        }
+    }
+    Ok(policy)
+}
+
+/// Set the terminal color scheme from a theme file (`.itermcolors`,
+/// Alacritty TOML or base16 YAML), or the default scheme for `None`.
+///
+/// With a `session_id` only that session changes. Otherwise the theme is
+/// saved and applied to all sessions, current and future.
+#[tauri::command]
+pub async fn set_terminal_theme(
+    state: tauri::State<'_, AppState>,
+    path: Option<String>,
+    session_id: Option<SessionId>,
+) -> Result<ThemeInfo, String> {
+    let theme = theme::load_theme(path.as_deref()).map_err(|e| e.to_string())?;
+    let info = ThemeInfo::from(&theme);
+
+    if let Some(session_id) = session_id {
+        let session_state = {
+            let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+            sessions
+                .get(&session_id)
+                .cloned()
+                .ok_or_else(|| format!("Session {session_id} not found"))?
+        };
+        let mut state = session_state
+            .lock()
+            .map_err(|e| format!("Lock error: {e}"))?;
+        state.session.vt_mut().set_theme(theme);
+        return Ok(info);
+    }
+
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = db.lock().map_err(|e| format!("db lock poisoned: {e}"))?;
+        theme::write_theme_path(&conn, path.as_deref()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("task join error: {e}"))??;
+
+    *state.theme.lock().map_err(|e| format!("Lock error: {e}"))? = theme.clone();
+
+    let sessions: Vec<_> = {
+        let sessions = state.sessions.lock().map_err(|e| format!("Lock error: {e}"))?;
+        sessions.values().cloned().collect()
+    };
+    for session_state in sessions {
+        if let Ok(mut session) = session_state.lock() {
+            session.session.vt_mut().set_theme(theme.clone());
+        }
+    }
+    Ok(info)
+}
+
+/// Look up a session's reported working directory, if the session exists.
+fn session_cwd(state: &AppState, session_id: SessionId) -> Option<String> {
+    let session_state = state.sessions.lock().ok()?.get(&session_id).cloned()?;
+    let state = session_state.lock().ok()?;
+    state.session.cwd().map(|s| s.to_string())
+}
+
+/// Close a terminal session.
+///
+/// Sends stop signals to the I/O thread and render pump, then removes the
+/// session from the global state.
+#[tauri::command]
+pub async fn close_terminal(
+    state: tauri::State<'_, AppState>,
+    session_id: SessionId,
+) -> Result<(), String> {
+    // Extract senders from the locks before awaiting, to avoid holding
+    // std::sync::MutexGuard across an await (which is not Send).
+    let io_stop_tx = state
+        .io_stops
+        .lock()
+        .ok()
+        .and_then(|mut stops| stops.remove(&session_id));
+
+    let render_stop_tx = state
+        .render_stops
+        .lock()
+        .ok()
+        .and_then(|mut stops| stops.remove(&session_id));
+
+    // Send stop signals (now safe to await since we dropped the MutexGuards).
+    if let Some(tx) = io_stop_tx {
+        let _ = tx.send(()).await;
+    }
+    if let Some(tx) = render_stop_tx {
+        let _ = tx.send(()).await;
+    }
+
+    // Remove session from global state (this drops the session, which kills
+    // the PTY child process via PtyHandle's Drop impl).
+    if let Ok(mut sessions) = state.sessions.lock() {
+        sessions.remove(&session_id);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_path() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            link_path("~/proj/src/lib.rs", Some(home)),
+            Ok(PathBuf::from("/home/me/proj/src/lib.rs"))
+        );
+        assert_eq!(link_path("~", Some(home)), Ok(PathBuf::from("/home/me")));
+        assert_eq!(link_path("/etc/hosts", Some(home)), Ok(PathBuf::from("/etc/hosts")));
+        assert!(link_path("~/proj", None).is_err());
+        // Relative paths, including other users' homes, are not guessed at.
+        assert!(link_path("src/lib.rs", Some(home)).is_err());
+        assert!(link_path("-x/foo.rs", Some(home)).is_err());
+        assert!(link_path("~other/foo.rs", Some(home)).is_err());
+    }
+}
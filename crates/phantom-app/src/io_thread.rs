@@ -62,7 +62,7 @@ fn io_loop(
                 Err(_) => return, // Poisoned lock
             };
 
-            state.session.vt_mut().write(&buf[..n]);
+            state.session.feed_output(&buf[..n]);
             let _ = state.session.handle_write_backs();
             state.has_pty_data = true;
         }
@@ -3,8 +3,16 @@
 //! All events sent to the frontend go through `TerminalEvent`, which is
 //! serialized as tagged JSON via Tauri's channel mechanism.
 
-use phantom_vt::{ScreenView, VtCell};
-use serde::Serialize;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use phantom_pty::PasteOutcome;
+use phantom_vt::{
+    ClipboardKind, CommandBlock, DetectedLink, ExportFormat, Hyperlink, Image, ImagePlacement,
+    Key, KeyEvent, KeyEventKind, KeypadKey, LinkTarget, Modifiers, MouseButton, MouseEncoding,
+    MouseEventKind, MouseTracking, PasteRisk, Progress, ProgressState, Rgb, ScreenView,
+    SearchMatch, SelectionMode, SelectionRange, Side, TerminalModes, Theme, UnderlineStyle, VtCell,
+};
+use serde::{Deserialize, Serialize};
 
 /// Events sent from the backend to the frontend over a Tauri channel.
 #[derive(Serialize, Clone, Debug)]
@@ -14,12 +22,15 @@ pub enum TerminalEvent {
     FullFrame {
         cols: u16,
         rows: u16,
-        /// Binary cell data, 16 bytes per cell, row-major order.
+        /// Binary cell data, `CELL_SIZE` bytes per cell, row-major order.
         cells: Vec<u8>,
+        /// Text of cells holding more than one codepoint.
+        graphemes: Vec<Grapheme>,
         cursor_row: u16,
         cursor_col: u16,
         cursor_shape: String,
         cursor_visible: bool,
+        cursor_blinking: bool,
     },
     /// Incremental update with only changed rows.
     DirtyRows {
@@ -28,10 +39,96 @@ pub enum TerminalEvent {
         cursor_col: u16,
         cursor_shape: String,
         cursor_visible: bool,
+        cursor_blinking: bool,
     },
-    /// The terminal title changed (via OSC escape sequences).
+    /// The terminal title or icon name changed (via OSC escape sequences,
+    /// or restored with `CSI 23 t`).
     TitleChanged {
         title: String,
+        /// Short name for tabs (OSC 0 / OSC 1), if the program set one.
+        icon_name: Option<String>,
+    },
+    /// The shell reported a new working directory via OSC 7.
+    CwdChanged {
+        cwd: String,
+    },
+    /// The viewport scroll position or the scrollback length changed.
+    ScrollChanged {
+        /// Lines the viewport is scrolled up into the history (0 = live screen).
+        display_offset: usize,
+        /// Number of lines currently held in the scrollback.
+        history_size: usize,
+    },
+    /// The selection was created, extended, moved by scrolling, or cleared.
+    SelectionChanged {
+        selection: Option<SelectionSpan>,
+    },
+    /// Search matches visible in the viewport changed, or focus moved.
+    SearchMatches {
+        matches: Vec<MatchSpan>,
+        focused: Option<MatchSpan>,
+    },
+    /// OSC 8 hyperlinks referenced by cells for the first time. An ID whose
+    /// link left the grid can be given to a new link, which replaces the old
+    /// definition.
+    ///
+    /// Sent before the frame or rows containing those cells.
+    HyperlinksDefined {
+        links: Vec<HyperlinkDef>,
+    },
+    /// Images placed on the screen changed: an image was shown or deleted,
+    /// or the placements scrolled.
+    ///
+    /// `placements` replaces the previous set. `images` holds the pixels of
+    /// images not sent since they were last placed; the frontend may drop
+    /// any image no placement refers to.
+    ImagesChanged {
+        images: Vec<ImageDef>,
+        placements: Vec<ImageSpan>,
+    },
+    /// A command run under shell integration (OSC 133) finished.
+    CommandFinished {
+        block: CommandBlockSpan,
+    },
+    /// The program changed a mode that affects input handling.
+    ModesChanged {
+        modes: ModesInfo,
+    },
+    /// The colors in effect changed: a new theme, or the program set colors
+    /// via OSC 4/10/11/12.
+    ThemeChanged {
+        theme: ThemeInfo,
+    },
+    /// A program asked to set the clipboard (OSC 52).
+    ClipboardWrite {
+        /// "clipboard" or "primary".
+        target: String,
+        text: String,
+        /// The user must confirm before the clipboard is set.
+        confirm: bool,
+    },
+    /// A program asked to read the clipboard (OSC 52). Answer with
+    /// `answer_clipboard_read`.
+    ClipboardRead {
+        request_id: u64,
+        /// "clipboard" or "primary".
+        target: String,
+        /// The user must confirm before the clipboard is sent.
+        confirm: bool,
+    },
+    /// The program asked for a desktop notification (OSC 9 / OSC 777).
+    Notification {
+        title: Option<String>,
+        body: String,
+    },
+    /// The program reported the progress of a task (OSC 9;4), or cleared it
+    /// (`None`).
+    Progress {
+        progress: Option<ProgressInfo>,
+    },
+    /// A recording stopped because writing to its file failed.
+    RecordingStopped {
+        error: String,
     },
     /// The terminal bell rang.
     Bell,
@@ -45,11 +142,332 @@ pub enum TerminalEvent {
 #[derive(Serialize, Clone, Debug)]
 pub struct DirtyRow {
     pub y: u16,
-    /// Binary cell data for this row, 16 bytes per cell.
+    /// Binary cell data for this row, `CELL_SIZE` bytes per cell.
     pub cells: Vec<u8>,
+    /// Text of cells holding more than one codepoint.
+    pub graphemes: Vec<Grapheme>,
+}
+
+/// Lines read from the scrollback on request.
+#[derive(Serialize, Clone, Debug)]
+pub struct ScrollbackLines {
+    pub lines: Vec<ScrollbackLine>,
+    /// Hyperlinks the lines reference for the first time, or under an ID that
+    /// was given to another link before.
+    pub links: Vec<HyperlinkDef>,
+}
+
+/// A line of binary cell data read from the scrollback on request.
+#[derive(Serialize, Clone, Debug)]
+pub struct ScrollbackLine {
+    /// Grid line index: 0 is the top of the live screen, negative is history.
+    pub line: i32,
+    /// Binary cell data for this line, `CELL_SIZE` bytes per cell.
+    pub cells: Vec<u8>,
+    /// Text of cells holding more than one codepoint.
+    pub graphemes: Vec<Grapheme>,
+}
+
+/// The full text of a cell whose grapheme cluster has more than one
+/// codepoint (combining marks, emoji sequences).
+///
+/// Sent alongside binary cell data, which only has room for the first
+/// codepoint.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Grapheme {
+    /// Index of the cell in the accompanying cell data.
+    pub index: u32,
+    pub text: String,
+}
+
+/// The extent of a selection in grid coordinates (inclusive on both ends).
+///
+/// Lines use the same indexing as `ScrollbackLine::line`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct SelectionSpan {
+    pub start_line: i32,
+    pub start_col: u16,
+    pub end_line: i32,
+    pub end_col: u16,
+    pub block: bool,
+}
+
+impl From<SelectionRange> for SelectionSpan {
+    fn from(range: SelectionRange) -> Self {
+        Self {
+            start_line: range.start.line,
+            start_col: range.start.col,
+            end_line: range.end.line,
+            end_col: range.end.col,
+            block: range.is_block,
+        }
+    }
+}
+
+/// The extent of a search match in grid coordinates (inclusive on both ends).
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct MatchSpan {
+    pub start_line: i32,
+    pub start_col: u16,
+    pub end_line: i32,
+    pub end_col: u16,
+}
+
+impl From<SearchMatch> for MatchSpan {
+    fn from(m: SearchMatch) -> Self {
+        Self {
+            start_line: m.start.line,
+            start_col: m.start.col,
+            end_line: m.end.line,
+            end_col: m.end.col,
+        }
+    }
+}
+
+/// A URL or file path detected in the viewport text. The extent is in grid
+/// coordinates, inclusive on both ends, like `MatchSpan`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct LinkSpan {
+    pub start_line: i32,
+    pub start_col: u16,
+    pub end_line: i32,
+    pub end_col: u16,
+    /// The text as shown.
+    pub text: String,
+    /// "url" or "file".
+    pub kind: String,
+    /// The URL, or the file path.
+    pub target: String,
+    /// Line and column in the file, if the text gave them.
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl From<DetectedLink> for LinkSpan {
+    fn from(link: DetectedLink) -> Self {
+        let (kind, target, line, column) = match link.target {
+            LinkTarget::Url(url) => ("url", url, None, None),
+            LinkTarget::File { path, line, column } => ("file", path, line, column),
+        };
+        Self {
+            start_line: link.start.line,
+            start_col: link.start.col,
+            end_line: link.end.line,
+            end_col: link.end.col,
+            text: link.text,
+            kind: kind.to_string(),
+            target,
+            line,
+            column,
+        }
+    }
+}
+
+/// A command block from OSC 133 shell integration.
+///
+/// Lines use the same indexing as `ScrollbackLine::line` and are current as
+/// of when the block was sent.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CommandBlockSpan {
+    pub id: u64,
+    pub prompt_line: i32,
+    pub command_line: Option<i32>,
+    pub output_start_line: Option<i32>,
+    /// Line where the output ends (exclusive), once the command finished.
+    pub output_end_line: Option<i32>,
+    pub command: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+}
+
+impl From<CommandBlock> for CommandBlockSpan {
+    fn from(block: CommandBlock) -> Self {
+        Self {
+            id: block.id,
+            prompt_line: block.prompt.line,
+            command_line: block.command_start.map(|p| p.line),
+            output_start_line: block.output_start.map(|p| p.line),
+            output_end_line: block.output_end.map(|p| p.line),
+            command: block.command,
+            exit_code: block.exit_code,
+            duration_ms: block.duration.map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Progress of a task reported by the program.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProgressInfo {
+    /// "normal", "error", "indeterminate" or "paused".
+    pub state: String,
+    /// Percentage done, 0 to 100.
+    pub percent: u8,
+}
+
+impl From<Progress> for ProgressInfo {
+    fn from(progress: Progress) -> Self {
+        let state = match progress.state {
+            ProgressState::Normal => "normal",
+            ProgressState::Error => "error",
+            ProgressState::Indeterminate => "indeterminate",
+            ProgressState::Paused => "paused",
+        };
+        Self {
+            state: state.to_string(),
+            percent: progress.percent,
+        }
+    }
+}
+
+/// A hyperlink definition, keyed by the ID carried in the cell encoding.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct HyperlinkDef {
+    pub id: u16,
+    pub uri: String,
+    /// The `id=` parameter of the OSC 8 sequence, if any.
+    pub link_id: Option<String>,
+}
+
+impl From<Hyperlink> for HyperlinkDef {
+    fn from(link: Hyperlink) -> Self {
+        Self {
+            id: link.id,
+            uri: link.uri,
+            link_id: link.link_id,
+        }
+    }
+}
+
+/// The pixels of an image shown by the kitty graphics protocol or sixel.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImageDef {
+    /// The ID placements refer to.
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// RGBA pixels, row-major order, base64-encoded: a JSON array would
+    /// take several bytes per byte.
+    pub rgba: String,
+}
+
+impl From<&Image> for ImageDef {
+    fn from(image: &Image) -> Self {
+        Self {
+            id: image.id,
+            width: image.width,
+            height: image.height,
+            rgba: BASE64.encode(&image.rgba),
+        }
+    }
+}
+
+/// Where an image is shown, in grid coordinates.
+///
+/// Lines use the same indexing as `ScrollbackLine::line`. The image is
+/// scaled to cover `cols` x `rows` cells.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImageSpan {
+    pub image_id: u64,
+    pub line: i32,
+    pub col: u16,
+    pub cols: u16,
+    pub rows: u16,
+    /// Negative values are drawn below the text.
+    pub z: i32,
+}
+
+impl From<ImagePlacement> for ImageSpan {
+    fn from(placement: ImagePlacement) -> Self {
+        Self {
+            image_id: placement.image_id,
+            line: placement.line,
+            col: placement.col,
+            cols: placement.cols,
+            rows: placement.rows,
+            z: placement.z,
+        }
+    }
+}
+
+/// Terminal modes relevant to the frontend's input handling.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ModesInfo {
+    /// "none", "x10", "normal", "button" or "any".
+    pub mouse_tracking: String,
+    /// "default", "utf8" or "sgr".
+    pub mouse_encoding: String,
+    pub alternate_scroll: bool,
+    pub app_cursor: bool,
+    pub app_keypad: bool,
+    /// xterm modifyOtherKeys level (0-2).
+    pub modify_other_keys: u8,
+    /// Kitty keyboard protocol flags (0 = legacy input).
+    pub kitty_keyboard: u8,
+    pub bracketed_paste: bool,
+    pub focus_reporting: bool,
+    pub alt_screen: bool,
+}
+
+impl From<TerminalModes> for ModesInfo {
+    fn from(modes: TerminalModes) -> Self {
+        let mouse_tracking = match modes.mouse_tracking {
+            MouseTracking::None => "none",
+            MouseTracking::X10 => "x10",
+            MouseTracking::Normal => "normal",
+            MouseTracking::ButtonEvent => "button",
+            MouseTracking::AnyEvent => "any",
+        };
+        let mouse_encoding = match modes.mouse_encoding {
+            MouseEncoding::Default => "default",
+            MouseEncoding::Utf8 => "utf8",
+            MouseEncoding::Sgr => "sgr",
+        };
+        Self {
+            mouse_tracking: mouse_tracking.to_string(),
+            mouse_encoding: mouse_encoding.to_string(),
+            alternate_scroll: modes.alternate_scroll,
+            app_cursor: modes.app_cursor,
+            app_keypad: modes.app_keypad,
+            modify_other_keys: modes.modify_other_keys,
+            kitty_keyboard: modes.kitty_keyboard.bits(),
+            bracketed_paste: modes.bracketed_paste,
+            focus_reporting: modes.focus_reporting,
+            alt_screen: modes.alt_screen,
+        }
+    }
+}
+
+/// The colors in effect for a terminal, as CSS hex colors (`#rrggbb`).
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ThemeInfo {
+    /// The 16 ANSI colors.
+    pub ansi: Vec<String>,
+    pub foreground: String,
+    pub background: String,
+    pub cursor: String,
+    pub selection: String,
+}
+
+impl From<&Theme> for ThemeInfo {
+    fn from(theme: &Theme) -> Self {
+        Self {
+            ansi: theme.ansi.iter().map(|c| css_color(*c)).collect(),
+            foreground: css_color(theme.foreground),
+            background: css_color(theme.background),
+            cursor: css_color(theme.cursor),
+            selection: css_color(theme.selection),
+        }
+    }
 }
 
-/// Encode a single VtCell into 16 bytes.
+fn css_color(color: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Size of an encoded cell in bytes.
+pub const CELL_SIZE: usize = 20;
+
+/// Encode a single VtCell into `CELL_SIZE` bytes.
 ///
 /// Layout (little-endian where applicable):
 /// - bytes 0..4:  codepoint as u32 LE
@@ -57,11 +475,15 @@ pub struct DirtyRow {
 /// - bytes 7..10: background RGB
 /// - byte 10:     CellFlags bits
 /// - byte 11:     cell width (0, 1, or 2)
-/// - bytes 12..14: reserved (hyperlink_id)
-/// - byte 14:     reserved (grapheme_len)
-/// - byte 15:     padding
-pub fn encode_cell(cell: &VtCell) -> [u8; 16] {
-    let mut buf = [0u8; 16];
+/// - bytes 12..14: OSC 8 hyperlink ID as u16 LE (0 = none)
+/// - byte 14:     grapheme_len: further codepoints in the cell (capped at 255;
+///   the full text is in the `Grapheme` side table)
+/// - byte 15:     underline style (0 none, 1 single, 2 double, 3 curly,
+///   4 dotted, 5 dashed)
+/// - bytes 16..19: underline color RGB (SGR 58)
+/// - byte 19:     1 if the underline color is set, 0 to use the foreground
+pub fn encode_cell(cell: &VtCell) -> [u8; CELL_SIZE] {
+    let mut buf = [0u8; CELL_SIZE];
     // codepoint as u32 LE (4 bytes)
     buf[0..4].copy_from_slice(&(cell.codepoint as u32).to_le_bytes());
     // fg RGB (3 bytes)
@@ -76,47 +498,317 @@ pub fn encode_cell(cell: &VtCell) -> [u8; 16] {
     buf[10] = cell.flags.bits();
     // width (1 byte)
     buf[11] = cell.width;
-    // hyperlink_id (2 bytes, reserved)
-    buf[12] = 0;
-    buf[13] = 0;
-    // grapheme_len (1 byte, reserved)
-    buf[14] = 0;
-    // padding (1 byte)
-    buf[15] = 0;
+    // hyperlink_id (2 bytes)
+    buf[12..14].copy_from_slice(&cell.hyperlink_id.to_le_bytes());
+    // grapheme_len (1 byte)
+    buf[14] = cell.combining.len().min(u8::MAX as usize) as u8;
+    // underline style (1 byte)
+    buf[15] = underline_style_code(cell.underline);
+    // underline color (3 bytes + set flag)
+    if let Some(color) = cell.underline_color {
+        buf[16] = color.r;
+        buf[17] = color.g;
+        buf[18] = color.b;
+        buf[19] = 1;
+    }
     buf
 }
 
+/// Wire code of an underline style, as documented on `encode_cell`.
+fn underline_style_code(style: UnderlineStyle) -> u8 {
+    match style {
+        UnderlineStyle::None => 0,
+        UnderlineStyle::Single => 1,
+        UnderlineStyle::Double => 2,
+        UnderlineStyle::Curly => 3,
+        UnderlineStyle::Dotted => 4,
+        UnderlineStyle::Dashed => 5,
+    }
+}
+
+/// Binary cell data and the grapheme side table for it.
+#[derive(Clone, Debug, Default)]
+pub struct EncodedCells {
+    pub cells: Vec<u8>,
+    pub graphemes: Vec<Grapheme>,
+}
+
+impl EncodedCells {
+    pub fn with_capacity(cells: usize) -> Self {
+        Self {
+            cells: Vec::with_capacity(cells * CELL_SIZE),
+            graphemes: Vec::new(),
+        }
+    }
+
+    /// Append a cell, adding it to the side table if it holds more than one
+    /// codepoint.
+    pub fn push(&mut self, cell: &VtCell) {
+        if !cell.combining.is_empty() {
+            self.graphemes.push(Grapheme {
+                index: (self.cells.len() / CELL_SIZE) as u32,
+                text: cell.grapheme(),
+            });
+        }
+        self.cells.extend_from_slice(&encode_cell(cell));
+    }
+}
+
+/// Encode the whole screen into binary data, row-major.
+pub fn encode_screen(screen: &ScreenView) -> EncodedCells {
+    let (cols, rows) = (screen.cols(), screen.rows());
+    let mut data = EncodedCells::with_capacity(cols as usize * rows as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            data.push(&screen.cell(row, col));
+        }
+    }
+    data
+}
+
 /// Encode an entire row of cells into binary data.
-pub fn encode_row(screen: &ScreenView, row: u16) -> Vec<u8> {
+pub fn encode_row(screen: &ScreenView, row: u16) -> EncodedCells {
     let cols = screen.cols();
-    let mut data = Vec::with_capacity(cols as usize * 16);
+    let mut data = EncodedCells::with_capacity(cols as usize);
     for col in 0..cols {
-        data.extend_from_slice(&encode_cell(&screen.cell(row, col)));
+        data.push(&screen.cell(row, col));
     }
     data
 }
 
+/// Encode a grid line (screen or scrollback) into binary data.
+///
+/// Uses the same line indexing as `ScreenView::line_cell`. Returns `None` if
+/// the line is outside the scrollback and screen.
+pub fn encode_line(screen: &ScreenView, line: i32) -> Option<EncodedCells> {
+    if !screen.contains_line(line) {
+        return None;
+    }
+    let cols = screen.cols();
+    let mut data = EncodedCells::with_capacity(cols as usize);
+    for col in 0..cols {
+        data.push(&screen.line_cell(line, col));
+    }
+    Some(data)
+}
+
 /// Convert a CursorShape to the string format expected by the frontend.
 pub fn cursor_shape_str(shape: phantom_vt::CursorShape) -> &'static str {
     match shape {
         phantom_vt::CursorShape::Block => "block",
+        phantom_vt::CursorShape::HollowBlock => "hollow_block",
         phantom_vt::CursorShape::Underline => "underline",
         phantom_vt::CursorShape::Bar => "bar",
         phantom_vt::CursorShape::Hidden => "hidden",
     }
 }
 
+/// Parse an export format name sent by the frontend.
+pub fn parse_export_format(format: &str) -> Option<ExportFormat> {
+    match format {
+        "text" => Some(ExportFormat::Text),
+        "ansi" => Some(ExportFormat::Ansi),
+        "html" => Some(ExportFormat::Html),
+        _ => None,
+    }
+}
+
+/// Parse a selection mode name sent by the frontend.
+pub fn parse_selection_mode(mode: &str) -> Option<SelectionMode> {
+    match mode {
+        "simple" => Some(SelectionMode::Simple),
+        "word" => Some(SelectionMode::Word),
+        "line" => Some(SelectionMode::Line),
+        "block" => Some(SelectionMode::Block),
+        _ => None,
+    }
+}
+
+/// A key event sent by the frontend, encoded by the backend per the
+/// terminal's keyboard modes.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeyInput {
+    /// A DOM `KeyboardEvent.key` name ("Enter", "ArrowUp", "F5", ...), a
+    /// `KeyboardEvent.code` name for keypad keys ("Numpad5", "NumpadEnter"),
+    /// or a single character.
+    pub key: String,
+    /// Bitmask: shift = 1, alt = 2, ctrl = 4, super = 8.
+    #[serde(default)]
+    pub modifiers: u8,
+    /// Text the key types, if any.
+    #[serde(default)]
+    pub text: Option<String>,
+    /// "press" (default), "repeat" or "release".
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+impl KeyInput {
+    /// Convert to a key event. Fails on unknown key names or kinds.
+    pub fn to_key_event(&self) -> Result<KeyEvent, String> {
+        let key = parse_key(&self.key).ok_or_else(|| format!("Unknown key: {}", self.key))?;
+        let kind = match self.kind.as_deref() {
+            None | Some("press") => KeyEventKind::Press,
+            Some("repeat") => KeyEventKind::Repeat,
+            Some("release") => KeyEventKind::Release,
+            Some(other) => return Err(format!("Unknown key event kind: {other}")),
+        };
+        Ok(KeyEvent {
+            key,
+            modifiers: Modifiers::from_bits_truncate(self.modifiers),
+            text: self.text.clone(),
+            kind,
+        })
+    }
+}
+
+/// Parse a key name sent by the frontend.
+pub fn parse_key(name: &str) -> Option<Key> {
+    let key = match name {
+        "Enter" => Key::Enter,
+        "Tab" => Key::Tab,
+        "Backspace" => Key::Backspace,
+        "Escape" => Key::Escape,
+        "ArrowUp" => Key::Up,
+        "ArrowDown" => Key::Down,
+        "ArrowLeft" => Key::Left,
+        "ArrowRight" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Insert" => Key::Insert,
+        "Delete" => Key::Delete,
+        "NumpadDecimal" => Key::Keypad(KeypadKey::Decimal),
+        "NumpadDivide" => Key::Keypad(KeypadKey::Divide),
+        "NumpadMultiply" => Key::Keypad(KeypadKey::Multiply),
+        "NumpadSubtract" => Key::Keypad(KeypadKey::Subtract),
+        "NumpadAdd" => Key::Keypad(KeypadKey::Add),
+        "NumpadEnter" => Key::Keypad(KeypadKey::Enter),
+        "NumpadEqual" => Key::Keypad(KeypadKey::Equal),
+        _ => {
+            if let Some(digit) = name.strip_prefix("Numpad") {
+                let digit: u8 = digit.parse().ok().filter(|d| *d <= 9)?;
+                return Some(Key::Keypad(KeypadKey::Digit(digit)));
+            }
+            if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) {
+                return (1..=20).contains(&n).then_some(Key::F(n));
+            }
+            let mut chars = name.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            Key::Char(c.to_ascii_lowercase())
+        }
+    };
+    Some(key)
+}
+
+/// Parse a mouse event kind sent by the frontend.
+pub fn parse_mouse_event_kind(kind: &str) -> Option<MouseEventKind> {
+    match kind {
+        "press" => Some(MouseEventKind::Press),
+        "release" => Some(MouseEventKind::Release),
+        "motion" => Some(MouseEventKind::Motion),
+        _ => None,
+    }
+}
+
+/// Parse a mouse button name sent by the frontend.
+pub fn parse_mouse_button(button: &str) -> Option<MouseButton> {
+    match button {
+        "left" => Some(MouseButton::Left),
+        "middle" => Some(MouseButton::Middle),
+        "right" => Some(MouseButton::Right),
+        "wheel_up" => Some(MouseButton::WheelUp),
+        "wheel_down" => Some(MouseButton::WheelDown),
+        "wheel_left" => Some(MouseButton::WheelLeft),
+        "wheel_right" => Some(MouseButton::WheelRight),
+        "none" => Some(MouseButton::None),
+        _ => None,
+    }
+}
+
+/// Convert a clipboard kind to the name sent to the frontend.
+pub fn clipboard_kind_str(kind: ClipboardKind) -> &'static str {
+    match kind {
+        ClipboardKind::Clipboard => "clipboard",
+        ClipboardKind::Primary => "primary",
+    }
+}
+
+/// Result of a paste, sent to the frontend.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct PasteResult {
+    /// Whether the text was sent to the program.
+    pub written: bool,
+    /// If not written, why the user should confirm the paste:
+    /// "multi_line", "sudo" or "pipe_to_shell".
+    pub risks: Vec<&'static str>,
+}
+
+impl From<PasteOutcome> for PasteResult {
+    fn from(outcome: PasteOutcome) -> Self {
+        match outcome {
+            PasteOutcome::Written => Self {
+                written: true,
+                risks: Vec::new(),
+            },
+            PasteOutcome::NeedsConfirmation(risks) => Self {
+                written: false,
+                risks: risks.into_iter().map(paste_risk_str).collect(),
+            },
+        }
+    }
+}
+
+/// Convert a paste risk to the name sent to the frontend.
+pub fn paste_risk_str(risk: PasteRisk) -> &'static str {
+    match risk {
+        PasteRisk::MultiLine => "multi_line",
+        PasteRisk::Sudo => "sudo",
+        PasteRisk::PipeToShell => "pipe_to_shell",
+    }
+}
+
+/// Direction to jump between shell prompts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptDirection {
+    Previous,
+    Next,
+}
+
+/// Parse a prompt jump direction sent by the frontend.
+pub fn parse_prompt_direction(direction: &str) -> Option<PromptDirection> {
+    match direction {
+        "previous" => Some(PromptDirection::Previous),
+        "next" => Some(PromptDirection::Next),
+        _ => None,
+    }
+}
+
+/// Parse a cell side name sent by the frontend. Defaults to the left half.
+pub fn parse_side(side: Option<&str>) -> Side {
+    match side {
+        Some("right") => Side::Right,
+        _ => Side::Left,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use phantom_vt::invariants::check_widths;
     use phantom_vt::{CellFlags, Rgb, VtCell};
+    use proptest::prelude::*;
 
     #[test]
     fn test_encode_cell_default() {
         let cell = VtCell::default();
         let encoded = encode_cell(&cell);
 
-        assert_eq!(encoded.len(), 16);
+        assert_eq!(encoded.len(), CELL_SIZE);
 
         // Default cell is a space (U+0020 = 32).
         let codepoint = u32::from_le_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]);
@@ -138,21 +830,28 @@ mod tests {
         // Width 1.
         assert_eq!(encoded[11], 1);
 
-        // Reserved bytes are zero.
-        assert_eq!(encoded[12], 0);
-        assert_eq!(encoded[13], 0);
+        // No hyperlink, no further codepoints, no underline.
+        assert_eq!(u16::from_le_bytes([encoded[12], encoded[13]]), 0);
         assert_eq!(encoded[14], 0);
         assert_eq!(encoded[15], 0);
+
+        // No underline color: the flag is clear and the color left zero.
+        assert_eq!(encoded[19], 0);
+        assert_eq!(encoded[16..19], [0, 0, 0]);
     }
 
     #[test]
     fn test_encode_cell_with_attributes() {
         let cell = VtCell {
             codepoint: 'A',
+            combining: Vec::new(),
             fg: Rgb::new(255, 0, 0),
             bg: Rgb::new(0, 0, 128),
             flags: CellFlags::BOLD | CellFlags::ITALIC,
+            underline: UnderlineStyle::None,
+            underline_color: None,
             width: 1,
+            hyperlink_id: 0,
         };
         let encoded = encode_cell(&cell);
 
@@ -177,14 +876,51 @@ mod tests {
         assert_eq!(encoded[11], 1);
     }
 
+    #[test]
+    fn test_encode_cell_hyperlink_id() {
+        let cell = VtCell {
+            hyperlink_id: 0x1234,
+            ..VtCell::default()
+        };
+        let encoded = encode_cell(&cell);
+
+        assert_eq!(u16::from_le_bytes([encoded[12], encoded[13]]), 0x1234);
+    }
+
+    #[test]
+    fn test_encode_cell_underline() {
+        let cell = VtCell {
+            flags: CellFlags::UNDERLINE,
+            underline: UnderlineStyle::Curly,
+            underline_color: Some(Rgb::new(255, 64, 0)),
+            ..VtCell::default()
+        };
+        let encoded = encode_cell(&cell);
+        assert_eq!(encoded[15], 3);
+        assert_eq!(encoded[16..20], [255, 64, 0, 1]);
+
+        let cell = VtCell {
+            flags: CellFlags::UNDERLINE,
+            underline: UnderlineStyle::Dashed,
+            ..VtCell::default()
+        };
+        let encoded = encode_cell(&cell);
+        assert_eq!(encoded[15], 5);
+        assert_eq!(encoded[19], 0);
+    }
+
     #[test]
     fn test_encode_cell_wide_char() {
         let cell = VtCell {
             codepoint: '\u{4e16}', // CJK character
+            combining: Vec::new(),
             fg: Rgb::new(200, 200, 200),
             bg: Rgb::new(30, 30, 30),
             flags: CellFlags::empty(),
+            underline: UnderlineStyle::None,
+            underline_color: None,
             width: 2,
+            hyperlink_id: 0,
         };
         let encoded = encode_cell(&cell);
 
@@ -198,10 +934,14 @@ mod tests {
     fn test_encode_cell_emoji_codepoint() {
         let cell = VtCell {
             codepoint: '\u{1F600}', // grinning face emoji
+            combining: Vec::new(),
             fg: Rgb::new(255, 255, 255),
             bg: Rgb::new(0, 0, 0),
             flags: CellFlags::empty(),
+            underline: UnderlineStyle::None,
+            underline_color: None,
             width: 2,
+            hyperlink_id: 0,
         };
         let encoded = encode_cell(&cell);
 
@@ -213,6 +953,7 @@ mod tests {
     fn test_encode_cell_all_flags() {
         let cell = VtCell {
             codepoint: 'X',
+            combining: Vec::new(),
             fg: Rgb::new(0, 0, 0),
             bg: Rgb::new(0, 0, 0),
             flags: CellFlags::BOLD
@@ -223,7 +964,10 @@ mod tests {
                 | CellFlags::DIM
                 | CellFlags::HIDDEN
                 | CellFlags::BLINK,
+            underline: UnderlineStyle::None,
+            underline_color: None,
             width: 1,
+            hyperlink_id: 0,
         };
         let encoded = encode_cell(&cell);
 
@@ -231,6 +975,229 @@ mod tests {
         assert_eq!(encoded[10], 0xFF);
     }
 
+    #[test]
+    fn test_encode_line_scrollback() {
+        let mut term = phantom_vt::VtTerminal::new(10, 3);
+        term.write(b"first\r\nsecond\r\nthird\r\nfourth");
+
+        let screen = term.screen();
+        let encoded = encode_line(&screen, -1).unwrap().cells;
+        assert_eq!(encoded.len(), 10 * CELL_SIZE);
+        let codepoint = u32::from_le_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]);
+        assert_eq!(codepoint, 'f' as u32);
+
+        assert!(encode_line(&screen, -2).is_none());
+        assert!(encode_line(&screen, 3).is_none());
+    }
+
+    #[test]
+    fn test_encode_graphemes() {
+        let mut term = phantom_vt::VtTerminal::new(10, 2);
+        term.write("a\r\nx\u{301}y🏳\u{fe0f}\u{200d}🌈".as_bytes());
+
+        let screen = term.screen();
+        let row = encode_row(&screen, 1);
+        assert_eq!(row.cells[14], 1);
+        assert_eq!(row.cells[CELL_SIZE + 14], 0);
+        assert_eq!(row.cells[2 * CELL_SIZE + 14], 3);
+        assert_eq!(
+            row.graphemes,
+            vec![
+                Grapheme {
+                    index: 0,
+                    text: "x\u{301}".to_string(),
+                },
+                Grapheme {
+                    index: 2,
+                    text: "🏳\u{fe0f}\u{200d}🌈".to_string(),
+                },
+            ]
+        );
+
+        // Indices count cells from the start of the frame.
+        let frame = encode_screen(&screen);
+        assert_eq!(frame.cells.len(), 20 * CELL_SIZE);
+        let indices: Vec<u32> = frame.graphemes.iter().map(|g| g.index).collect();
+        assert_eq!(indices, vec![10, 12]);
+    }
+
+    #[derive(Debug, Clone)]
+    enum EncodeStep {
+        Write(Vec<u8>),
+        Resize(u16, u16),
+    }
+
+    /// Wide and combining text mixed with garbage bytes, resized in between.
+    fn encode_step() -> impl Strategy<Value = EncodeStep> {
+        const TEXT: &[&str] = &["日本", "😀", "e\u{301}", "👨\u{200d}👧", "\x1b[4h"];
+        prop_oneof![
+            1 => (1u16..40, 1u16..10).prop_map(|(cols, rows)| EncodeStep::Resize(cols, rows)),
+            3 => prop_oneof![
+                prop::sample::select(TEXT).prop_map(|text| text.as_bytes().to_vec()),
+                prop::collection::vec(any::<u8>(), 1..60),
+            ]
+            .prop_map(EncodeStep::Write),
+        ]
+    }
+
+    proptest! {
+        // See also phantom-vt's `tests/fuzz.rs`, which checks the screen
+        // itself.
+        #[test]
+        fn test_encode_row_random_output(
+            (cols, rows) in (1u16..40, 1u16..10),
+            steps in prop::collection::vec(encode_step(), 1..20),
+        ) {
+            let mut term = phantom_vt::VtTerminal::new(cols, rows);
+            for step in steps {
+                match step {
+                    EncodeStep::Write(bytes) => term.write(&bytes),
+                    EncodeStep::Resize(cols, rows) => term.resize(cols, rows),
+                }
+
+                let screen = term.screen();
+                let cols = screen.cols() as usize;
+                for row in 0..screen.rows() {
+                    let encoded = encode_row(&screen, row);
+                    prop_assert_eq!(encoded.cells.len(), cols * CELL_SIZE);
+                    let widths: Vec<u8> = encoded.cells.chunks(CELL_SIZE).map(|c| c[11]).collect();
+                    check_widths(&widths).map_err(|e| TestCaseError::fail(format!("row {row}: {e}")))?;
+                    for grapheme in &encoded.graphemes {
+                        let index = grapheme.index as usize;
+                        prop_assert!(index < cols, "row {}: grapheme at column {}", row, index);
+                        prop_assert_ne!(encoded.cells[index * CELL_SIZE + 14], 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_modes_info() {
+        let mut term = phantom_vt::VtTerminal::new(80, 24);
+        term.write(b"\x1b[?1003h\x1b[?1006h\x1b[?2004h");
+
+        let modes = ModesInfo::from(term.modes());
+        assert_eq!(modes.mouse_tracking, "any");
+        assert_eq!(modes.mouse_encoding, "sgr");
+        assert!(modes.bracketed_paste);
+        assert!(!modes.alt_screen);
+
+        assert_eq!(parse_mouse_button("wheel_up"), Some(MouseButton::WheelUp));
+        assert_eq!(parse_mouse_button("back"), None);
+        assert_eq!(parse_mouse_event_kind("motion"), Some(MouseEventKind::Motion));
+    }
+
+    #[test]
+    fn test_key_input() {
+        let input = KeyInput {
+            key: "A".to_string(),
+            modifiers: 5,
+            text: Some("A".to_string()),
+            kind: None,
+        };
+        let event = input.to_key_event().unwrap();
+        assert_eq!(event.key, Key::Char('a'));
+        assert_eq!(event.modifiers, Modifiers::SHIFT | Modifiers::CTRL);
+        assert_eq!(event.kind, KeyEventKind::Press);
+
+        assert_eq!(parse_key("ArrowLeft"), Some(Key::Left));
+        assert_eq!(parse_key("F12"), Some(Key::F(12)));
+        assert_eq!(parse_key("F0"), None);
+        assert_eq!(parse_key("Numpad7"), Some(Key::Keypad(KeypadKey::Digit(7))));
+        assert_eq!(parse_key("Unidentified"), None);
+
+        let release = KeyInput {
+            kind: Some("hold".to_string()),
+            ..input
+        };
+        assert!(release.to_key_event().is_err());
+    }
+
+    #[test]
+    fn test_theme_info() {
+        let info = ThemeInfo::from(&Theme::default());
+        assert_eq!(info.ansi.len(), 16);
+        assert_eq!(info.ansi[1], "#cd0000");
+        assert_eq!(info.foreground, "#ffffff");
+        assert_eq!(info.background, "#000000");
+    }
+
+    #[test]
+    fn test_image_def_base64() {
+        let image = Image {
+            id: 3,
+            width: 1,
+            height: 1,
+            rgba: vec![255, 0, 128, 255],
+        };
+        let def = ImageDef::from(&image);
+        assert_eq!(def.rgba, "/wCA/w==");
+    }
+
+    #[test]
+    fn test_paste_result() {
+        assert_eq!(
+            PasteResult::from(PasteOutcome::Written),
+            PasteResult {
+                written: true,
+                risks: vec![]
+            }
+        );
+        let result = PasteResult::from(PasteOutcome::NeedsConfirmation(vec![
+            PasteRisk::MultiLine,
+            PasteRisk::PipeToShell,
+        ]));
+        assert!(!result.written);
+        assert_eq!(result.risks, vec!["multi_line", "pipe_to_shell"]);
+    }
+
+    #[test]
+    fn test_parse_selection_mode() {
+        assert_eq!(parse_selection_mode("simple"), Some(SelectionMode::Simple));
+        assert_eq!(parse_selection_mode("word"), Some(SelectionMode::Word));
+        assert_eq!(parse_selection_mode("line"), Some(SelectionMode::Line));
+        assert_eq!(parse_selection_mode("block"), Some(SelectionMode::Block));
+        assert_eq!(parse_selection_mode("bogus"), None);
+        assert_eq!(parse_side(Some("right")), Side::Right);
+        assert_eq!(parse_side(None), Side::Left);
+    }
+
+    #[test]
+    fn test_command_block_span() {
+        let mut term = phantom_vt::VtTerminal::new(40, 10);
+        term.write(b"\x1b]133;A\x07$ \x1b]133;B\x07make\r\n\x1b]133;C\x07err\r\n\x1b]133;D;2\x07");
+
+        let block = term.take_finished_commands().pop().unwrap();
+        let span = CommandBlockSpan::from(block);
+        assert_eq!(span.prompt_line, 0);
+        assert_eq!(span.command_line, Some(0));
+        assert_eq!(span.output_start_line, Some(1));
+        assert_eq!(span.output_end_line, Some(2));
+        assert_eq!(span.command.as_deref(), Some("make"));
+        assert_eq!(span.exit_code, Some(2));
+        assert!(span.duration_ms.is_some());
+
+        assert_eq!(parse_prompt_direction("previous"), Some(PromptDirection::Previous));
+        assert_eq!(parse_prompt_direction("up"), None);
+    }
+
+    #[test]
+    fn test_link_span() {
+        let mut term = phantom_vt::VtTerminal::new(40, 5);
+        term.write(b"\x1b]7;file://host/work\x07  --> src/lib.rs:7:3\r\nhttps://example.com");
+
+        let spans: Vec<LinkSpan> = term.detect_links().into_iter().map(LinkSpan::from).collect();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].kind, "file");
+        assert_eq!(spans[0].target, "/work/src/lib.rs");
+        assert_eq!((spans[0].line, spans[0].column), (Some(7), Some(3)));
+        assert_eq!((spans[0].start_line, spans[0].start_col), (0, 6));
+        assert_eq!(spans[1].kind, "url");
+        assert_eq!(spans[1].target, "https://example.com");
+        assert_eq!(spans[1].line, None);
+    }
+
     #[test]
     fn test_cursor_shape_str() {
         assert_eq!(cursor_shape_str(phantom_vt::CursorShape::Block), "block");
@@ -239,6 +1206,10 @@ mod tests {
             "underline"
         );
         assert_eq!(cursor_shape_str(phantom_vt::CursorShape::Bar), "bar");
+        assert_eq!(
+            cursor_shape_str(phantom_vt::CursorShape::HollowBlock),
+            "hollow_block"
+        );
         assert_eq!(
             cursor_shape_str(phantom_vt::CursorShape::Hidden),
             "hidden"
@@ -1,6 +1,7 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod clipboard;
 mod commands;
 mod io_thread;
 mod ipc;
@@ -8,6 +9,7 @@ mod render_pump;
 mod sandbox;
 mod scheduler;
 mod state;
+mod theme;
 
 use state::AppState;
 use std::path::PathBuf;
@@ -34,6 +36,8 @@ fn main() {
     std::fs::create_dir_all(phantom_home.join("repos")).expect("failed to create ~/.phantom/repos");
     std::fs::create_dir_all(phantom_home.join("worktrees")).expect("failed to create ~/.phantom/worktrees");
     std::fs::create_dir_all(phantom_home.join("sandbox")).expect("failed to create ~/.phantom/sandbox");
+    std::fs::create_dir_all(phantom_home.join("recordings")).expect("failed to create ~/.phantom/recordings");
+    std::fs::create_dir_all(phantom_home.join("snapshots")).expect("failed to create ~/.phantom/snapshots");
 
     // Open (or create) the SQLite database at ~/.phantom/phantom.db.
     let db_path = phantom_home.join("phantom.db");
@@ -42,11 +46,18 @@ fn main() {
     // Seed built-in presets on first launch.
     scheduler::seed_presets(&db).expect("failed to seed presets");
 
-    let app_state = AppState::new(db, repo_path);
+    let app_state = AppState::new(
+        db,
+        repo_path,
+        phantom_home.join("recordings"),
+        phantom_home.join("snapshots"),
+    );
     let scheduler_db = app_state.db.clone();
     let scheduler_repo = app_state.repo_path.clone();
 
     tauri::Builder::default()
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .manage(app_state)
         .setup(move |app| {
@@ -77,7 +88,39 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::terminal::create_terminal,
             commands::terminal::write_input,
+            commands::terminal::paste_text,
+            commands::terminal::set_terminal_focus,
+            commands::terminal::send_mouse_event,
+            commands::terminal::get_terminal_modes,
             commands::terminal::resize_terminal,
+            commands::terminal::set_terminal_reflow,
+            commands::terminal::scroll_terminal,
+            commands::terminal::read_scrollback,
+            commands::terminal::start_selection,
+            commands::terminal::update_selection,
+            commands::terminal::clear_selection,
+            commands::terminal::get_selection_text,
+            commands::terminal::export_terminal,
+            commands::terminal::start_recording,
+            commands::terminal::stop_recording,
+            commands::terminal::save_terminal_snapshot,
+            commands::terminal::restore_terminal_snapshot,
+            commands::terminal::search_terminal,
+            commands::terminal::search_next,
+            commands::terminal::search_previous,
+            commands::terminal::clear_search,
+            commands::terminal::get_terminal_cwd,
+            commands::terminal::get_title_history,
+            commands::terminal::list_command_blocks,
+            commands::terminal::detect_links,
+            commands::terminal::open_link,
+            commands::terminal::jump_to_prompt,
+            commands::terminal::get_command_output,
+            commands::terminal::show_notification,
+            commands::terminal::answer_clipboard_read,
+            commands::terminal::get_clipboard_policy,
+            commands::terminal::set_clipboard_policy,
+            commands::terminal::set_terminal_theme,
             commands::terminal::close_terminal,
             commands::git::list_branches,
             commands::git::get_current_branch,
@@ -205,6 +248,6 @@ fn dirs_or_cwd() -> PathBuf {
 }
 
 /// Get the user's home directory.
-fn home_dir() -> Option<PathBuf> {
+pub(crate) fn home_dir() -> Option<PathBuf> {
     std::env::var_os("HOME").map(PathBuf::from)
 }
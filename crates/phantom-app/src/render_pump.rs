@@ -4,25 +4,48 @@
 //! grabs the session lock briefly each tick to extract cell data and check
 //! for changes, then sends events to the frontend via a Tauri channel.
 
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 
-use phantom_vt::DamageInfo;
+use phantom_vt::{ClipboardRequest, DamageInfo};
 
-use crate::ipc::{cursor_shape_str, encode_row, DirtyRow, TerminalEvent};
+use crate::clipboard::ClipboardPermission;
+use crate::ipc::{
+    clipboard_kind_str, cursor_shape_str, encode_row, encode_screen, CommandBlockSpan, DirtyRow,
+    EncodedCells, HyperlinkDef, ImageDef, ImageSpan, MatchSpan, ModesInfo, ProgressInfo,
+    SelectionSpan, TerminalEvent, ThemeInfo,
+};
 use crate::state::{SessionId, SessionState};
 
+/// Longest the pump holds frames for one synchronized update, however often
+/// the program extends it.
+const MAX_SYNC_HOLD: Duration = Duration::from_millis(500);
+
 /// Start the render pump for a session.
 ///
 /// Runs at ~60Hz. Each tick:
-/// 1. Lock the session
+/// 1. Lock the session; skip the tick while a synchronized update is in
+///    progress, unless it timed out
 /// 2. Check if needs_full_frame -> send FullFrame event
 /// 3. Otherwise check damage -> send DirtyRows for changed rows
-/// 4. Check for title changes -> send TitleChanged
-/// 5. Check for bell -> send Bell
-/// 6. Check if process exited -> send Exited
+/// 4. Check for newly referenced hyperlinks -> send HyperlinksDefined first
+/// 5. Check for scroll position changes -> send ScrollChanged
+/// 6. Check for selection changes -> send SelectionChanged
+/// 7. Check for search match changes -> send SearchMatches
+/// 8. Check for image placement changes -> send ImagesChanged
+/// 9. Check for title or icon name changes -> send TitleChanged
+/// 10. Check for working directory changes -> send CwdChanged
+/// 11. Check for finished shell commands -> send CommandFinished
+/// 12. Check for notifications (OSC 9 / OSC 777) -> send Notification
+/// 13. Check for progress changes (OSC 9;4) -> send Progress
+/// 14. Check for input mode changes -> send ModesChanged
+/// 15. Check for color changes (theme or OSC 4/10/11/12) -> send ThemeChanged
+/// 16. Apply the clipboard policy to OSC 52 requests -> send ClipboardWrite / ClipboardRead
+/// 17. Check for bell -> send Bell
+/// 18. Check if process exited -> send Exited
 ///
 /// The pump runs in a tokio task and stops when it receives a signal
 /// on the stop channel, or when the session exits.
@@ -64,6 +87,25 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
         Err(_) => return events, // Poisoned lock.
     };
 
+    // Hold everything while the program is mid-frame, so the screen is never
+    // sent half-drawn. A program that never ends its update (or exits
+    // during one) is cut off and its output so far is shown.
+    if state.session.vt().is_synchronized_update() {
+        let now = Instant::now();
+        let held_since = *state.sync_held_since.get_or_insert(now);
+        let timed_out = state
+            .session
+            .vt()
+            .synchronized_update_deadline()
+            .is_some_and(|deadline| deadline <= now)
+            || now.duration_since(held_since) >= MAX_SYNC_HOLD;
+        if !timed_out && state.session.is_alive() {
+            return events;
+        }
+        state.session.vt_mut().end_synchronized_update();
+    }
+    state.sync_held_since = None;
+
     // Snapshot and clear the PTY data flag.
     let had_pty_data = state.has_pty_data;
     state.has_pty_data = false;
@@ -74,15 +116,15 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
     let cursor_col = cursor.col;
     let cursor_shape = cursor_shape_str(cursor.shape);
     let cursor_visible = cursor.visible;
+    let cursor_blinking = cursor.blinking;
+    // Focus changes restyle the cursor without any output from the program.
+    let cursor_changed = state.last_cursor.as_ref() != Some(&cursor);
 
     if state.needs_full_frame {
         let screen = state.session.vt().screen();
         let cols = screen.cols();
         let rows = screen.rows();
-        let mut cells = Vec::with_capacity(cols as usize * rows as usize * 16);
-        for row in 0..rows {
-            cells.extend_from_slice(&encode_row(&screen, row));
-        }
+        let EncodedCells { cells, graphemes } = encode_screen(&screen);
 
         let _ = state.session.vt_mut().damage();
         state.session.vt_mut().reset_damage();
@@ -92,10 +134,12 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
             cols,
             rows,
             cells,
+            graphemes,
             cursor_row,
             cursor_col,
             cursor_shape: cursor_shape.to_string(),
             cursor_visible,
+            cursor_blinking,
         });
     } else {
         let damage = state.session.vt_mut().damage();
@@ -104,27 +148,28 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
                 let screen = state.session.vt().screen();
                 let cols = screen.cols();
                 let rows = screen.rows();
-                let mut cells = Vec::with_capacity(cols as usize * rows as usize * 16);
-                for row in 0..rows {
-                    cells.extend_from_slice(&encode_row(&screen, row));
-                }
+                let EncodedCells { cells, graphemes } = encode_screen(&screen);
                 state.session.vt_mut().reset_damage();
 
                 events.push(TerminalEvent::FullFrame {
                     cols,
                     rows,
                     cells,
+                    graphemes,
                     cursor_row,
                     cursor_col,
                     cursor_shape: cursor_shape.to_string(),
                     cursor_visible,
+                    cursor_blinking,
                 });
             }
             DamageInfo::Partial(damaged_rows) => {
                 // Suppress cursor-only damage when idle. alacritty always marks
                 // the cursor row dirty (for blink support). If no PTY data
-                // arrived since the last tick, skip encoding + sending.
+                // arrived since the last tick and the cursor looks the same,
+                // skip encoding + sending.
                 let only_cursor = !had_pty_data
+                    && !cursor_changed
                     && damaged_rows.len() == 1
                     && damaged_rows[0].row == cursor_row;
 
@@ -139,9 +184,11 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
                     row_indices.dedup();
 
                     for row_idx in row_indices {
+                        let EncodedCells { cells, graphemes } = encode_row(&screen, row_idx);
                         dirty_rows.push(DirtyRow {
                             y: row_idx,
-                            cells: encode_row(&screen, row_idx),
+                            cells,
+                            graphemes,
                         });
                     }
 
@@ -153,6 +200,7 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
                         cursor_col,
                         cursor_shape: cursor_shape.to_string(),
                         cursor_visible,
+                        cursor_blinking,
                     });
                 } else {
                     state.session.vt_mut().reset_damage();
@@ -161,12 +209,170 @@ fn extract_events(session_state: &Arc<Mutex<SessionState>>) -> Vec<TerminalEvent
         }
     }
 
+    if !events.is_empty() {
+        state.last_cursor = Some(cursor);
+    }
+
+    // Encoding cells interns any new hyperlinks; define them ahead of the
+    // frame that references them.
+    let new_links = state.session.vt_mut().take_new_hyperlinks();
+    if !new_links.is_empty() {
+        events.insert(
+            0,
+            TerminalEvent::HyperlinksDefined {
+                links: new_links.into_iter().map(HyperlinkDef::from).collect(),
+            },
+        );
+    }
+
+    let scroll = (
+        state.session.vt().display_offset(),
+        state.session.vt().history_size(),
+    );
+    if scroll != state.last_scroll {
+        events.push(TerminalEvent::ScrollChanged {
+            display_offset: scroll.0,
+            history_size: scroll.1,
+        });
+        state.last_scroll = scroll;
+    }
+
+    // Matches only move when the screen changes or a search command ran, so
+    // skip the regex scan on idle ticks.
+    let frame_sent = !events.is_empty();
+    if frame_sent || state.search_dirty {
+        state.search_dirty = false;
+        let matches = state.session.vt_mut().visible_search_matches();
+        let focused = state.session.vt().search_focused();
+        if (&matches, &focused) != (&state.last_search.0, &state.last_search.1) {
+            events.push(TerminalEvent::SearchMatches {
+                matches: matches.iter().copied().map(MatchSpan::from).collect(),
+                focused: focused.map(MatchSpan::from),
+            });
+            state.last_search = (matches, focused);
+        }
+    }
+
+    let selection = state.session.vt().selection_range();
+    if selection != state.last_selection {
+        events.push(TerminalEvent::SelectionChanged {
+            selection: selection.map(SelectionSpan::from),
+        });
+        state.last_selection = selection;
+    }
+
+    // Pixels go out once per image while it stays placed; an image placed
+    // again after the frontend dropped it is sent again.
+    let placements = state.session.vt().images();
+    if placements != state.last_images {
+        let mut images = Vec::new();
+        let mut sent = HashSet::new();
+        for placement in &placements {
+            let id = placement.image_id;
+            if sent.insert(id) && !state.sent_images.contains(&id) {
+                if let Some(image) = state.session.vt().image(id) {
+                    images.push(ImageDef::from(image.as_ref()));
+                }
+            }
+        }
+        events.push(TerminalEvent::ImagesChanged {
+            images,
+            placements: placements.iter().copied().map(ImageSpan::from).collect(),
+        });
+        state.sent_images = sent;
+        state.last_images = placements;
+    }
+
     // Read title, bell, and PTY writes in one lock acquisition on EventProxy.
     let current_title = state.session.title().map(|s| s.to_string());
-    if current_title != state.last_title {
+    let current_icon_name = state.session.vt().icon_name();
+    if current_title != state.last_title || current_icon_name != state.last_icon_name {
         let title = current_title.clone().unwrap_or_default();
-        events.push(TerminalEvent::TitleChanged { title });
+        events.push(TerminalEvent::TitleChanged {
+            title,
+            icon_name: current_icon_name.clone(),
+        });
         state.last_title = current_title;
+        state.last_icon_name = current_icon_name;
+    }
+
+    let current_cwd = state.session.cwd().map(|s| s.to_string());
+    if current_cwd != state.last_cwd {
+        if let Some(cwd) = current_cwd.clone() {
+            events.push(TerminalEvent::CwdChanged { cwd });
+        }
+        state.last_cwd = current_cwd;
+    }
+
+    for block in state.session.vt_mut().take_finished_commands() {
+        events.push(TerminalEvent::CommandFinished {
+            block: CommandBlockSpan::from(block),
+        });
+    }
+
+    for notification in state.session.vt_mut().take_notifications() {
+        events.push(TerminalEvent::Notification {
+            title: notification.title,
+            body: notification.body,
+        });
+    }
+
+    let progress = state.session.vt().progress();
+    if progress != state.last_progress {
+        events.push(TerminalEvent::Progress {
+            progress: progress.map(ProgressInfo::from),
+        });
+        state.last_progress = progress;
+    }
+
+    let modes = state.session.vt().modes();
+    if state.last_modes != Some(modes) {
+        events.push(TerminalEvent::ModesChanged {
+            modes: ModesInfo::from(modes),
+        });
+        state.last_modes = Some(modes);
+    }
+
+    let colors = state.session.vt().colors();
+    if state.last_colors.as_ref() != Some(&colors) {
+        events.push(TerminalEvent::ThemeChanged {
+            theme: ThemeInfo::from(&colors),
+        });
+        state.last_colors = Some(colors);
+    }
+
+    // Denied requests never reach the frontend; denied reads are dropped so
+    // they do not linger as pending.
+    let policy = state.clipboard_policy;
+    for request in state.session.take_clipboard_requests() {
+        match request {
+            ClipboardRequest::Store { kind, text } => {
+                if policy.write != ClipboardPermission::Deny {
+                    events.push(TerminalEvent::ClipboardWrite {
+                        target: clipboard_kind_str(kind).to_string(),
+                        text,
+                        confirm: policy.write == ClipboardPermission::Prompt,
+                    });
+                }
+            }
+            ClipboardRequest::Load { id, kind } => {
+                if policy.read == ClipboardPermission::Deny {
+                    state.session.discard_clipboard_load(id);
+                } else {
+                    events.push(TerminalEvent::ClipboardRead {
+                        request_id: id,
+                        target: clipboard_kind_str(kind).to_string(),
+                        confirm: policy.read == ClipboardPermission::Prompt,
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(err) = state.session.take_recording_error() {
+        events.push(TerminalEvent::RecordingStopped {
+            error: err.to_string(),
+        });
     }
 
     if state.session.vt_mut().has_bell() {
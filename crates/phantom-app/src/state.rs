@@ -1,13 +1,17 @@
 //! Application state shared between Tauri commands, I/O threads, and the render pump.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use rusqlite::Connection;
 use tokio::sync::{mpsc, Semaphore};
 
+use crate::clipboard::{self, ClipboardPolicy};
+use crate::theme;
+
 pub type SessionId = u64;
 
 /// Per-session state shared between I/O thread, render pump, and commands.
@@ -16,12 +20,43 @@ pub struct SessionState {
     pub session: phantom_pty::TerminalSession,
     /// Set `true` on creation and after resize to trigger a full frame send.
     pub needs_full_frame: bool,
+    /// Cursor as of the last frame sent, used to detect cursor-only changes.
+    pub last_cursor: Option<phantom_vt::CursorState>,
     /// Cached title from the last render pump tick, used to detect changes.
     pub last_title: Option<String>,
+    /// Icon name (OSC 0 / OSC 1) from the last render pump tick.
+    pub last_icon_name: Option<String>,
+    /// Last reported working directory (OSC 7), used to detect changes.
+    pub last_cwd: Option<String>,
+    /// OSC 52 clipboard policy, kept in sync with `AppState::clipboard_policy`.
+    pub clipboard_policy: ClipboardPolicy,
+    /// Last reported colors in effect, used to detect theme changes.
+    pub last_colors: Option<phantom_vt::Theme>,
+    /// Last reported task progress (OSC 9;4), used to detect changes.
+    pub last_progress: Option<phantom_vt::Progress>,
+    /// Last reported terminal modes, used to detect mode changes.
+    pub last_modes: Option<phantom_vt::TerminalModes>,
+    /// Last reported `(display_offset, history_size)`, used to detect scrolling.
+    pub last_scroll: (usize, usize),
+    /// Last reported selection extent, used to detect selection changes.
+    pub last_selection: Option<phantom_vt::SelectionRange>,
+    /// Last reported visible search matches and focused match.
+    pub last_search: (Vec<phantom_vt::SearchMatch>, Option<phantom_vt::SearchMatch>),
+    /// Last reported image placements, used to detect image changes.
+    pub last_images: Vec<phantom_vt::ImagePlacement>,
+    /// Images whose pixels the frontend holds: those placed when
+    /// `last_images` was reported.
+    pub sent_images: HashSet<u64>,
+    /// Set by search commands so the render pump re-reports matches even
+    /// when the screen did not change.
+    pub search_dirty: bool,
     /// Set by the I/O thread after writing PTY data; cleared by the render pump.
     /// Used to suppress DirtyRows events when only the cursor row is damaged
     /// (alacritty always marks the cursor row dirty for blink support).
     pub has_pty_data: bool,
+    /// When the render pump started holding frames for the synchronized
+    /// update (DEC mode 2026) in progress, if any.
+    pub sync_held_since: Option<Instant>,
 }
 
 /// Global app state managed by Tauri.
@@ -40,11 +75,27 @@ pub struct AppState {
     pub repo_path: PathBuf,
     /// Shared semaphore to limit concurrent analysis jobs.
     pub analysis_semaphore: Arc<Semaphore>,
+    /// OSC 52 clipboard policy applied to new sessions.
+    pub clipboard_policy: Mutex<ClipboardPolicy>,
+    /// Color scheme applied to new sessions.
+    pub theme: Mutex<phantom_vt::Theme>,
+    /// Directory session recordings are written to.
+    pub recordings_dir: PathBuf,
+    /// Directory terminal snapshots are written to.
+    pub snapshots_dir: PathBuf,
 }
 
 impl AppState {
-    /// Create a new AppState with a database connection and repo path.
-    pub fn new(db: Connection, repo_path: PathBuf) -> Self {
+    /// Create a new AppState with a database connection, repo path and the
+    /// directories for session recordings and terminal snapshots.
+    pub fn new(
+        db: Connection,
+        repo_path: PathBuf,
+        recordings_dir: PathBuf,
+        snapshots_dir: PathBuf,
+    ) -> Self {
+        let clipboard_policy = clipboard::read_policy(&db);
+        let theme = theme::read_theme(&db);
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
             io_stops: Arc::new(Mutex::new(HashMap::new())),
@@ -55,6 +106,10 @@ impl AppState {
             analysis_semaphore: Arc::new(Semaphore::new(
                 phantom_analysis::runner::DEFAULT_MAX_CONCURRENCY,
             )),
+            clipboard_policy: Mutex::new(clipboard_policy),
+            theme: Mutex::new(theme),
+            recordings_dir,
+            snapshots_dir,
         }
     }
 
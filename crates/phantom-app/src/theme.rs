@@ -0,0 +1,67 @@
+//! Terminal color scheme.
+//!
+//! The theme is loaded from a file the user picks (`.itermcolors`, Alacritty
+//! TOML or base16 YAML); only its path is stored in the settings table, so
+//! edits to the file are picked up on the next start.
+
+use std::path::Path;
+
+use phantom_db::settings;
+use phantom_vt::{Theme, ThemeError};
+use rusqlite::Connection;
+
+/// Settings key for the path of the theme file. Empty for the default theme.
+pub const SETTING_THEME_PATH: &str = "terminal_theme_path";
+
+/// Load a theme file, or the default theme for `None`.
+pub fn load_theme(path: Option<&str>) -> Result<Theme, ThemeError> {
+    match path {
+        Some(path) => Theme::load(Path::new(path)),
+        None => Ok(Theme::default()),
+    }
+}
+
+/// Read the theme configured in the database.
+///
+/// Falls back to the default theme if the file cannot be loaded.
+pub fn read_theme(conn: &Connection) -> Theme {
+    let path = settings::get(conn, SETTING_THEME_PATH)
+        .ok()
+        .flatten()
+        .filter(|p| !p.is_empty());
+    load_theme(path.as_deref()).unwrap_or_else(|e| {
+        log::warn!("Failed to load terminal theme: {e}");
+        Theme::default()
+    })
+}
+
+/// Store the path of the theme file, or `None` for the default theme.
+pub fn write_theme_path(conn: &Connection, path: Option<&str>) -> rusqlite::Result<()> {
+    settings::set(conn, SETTING_THEME_PATH, path.unwrap_or(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_setting() {
+        let conn = Connection::open_in_memory().unwrap();
+        phantom_db::schema::initialize(&conn).unwrap();
+
+        assert_eq!(read_theme(&conn), Theme::default());
+
+        let path = std::env::temp_dir().join(format!("phantom-theme-{}.yaml", std::process::id()));
+        let scheme: String = (0..16)
+            .map(|i| format!("base0{i:X}: \"{:02x}{:02x}{:02x}\"\n", i, i, i))
+            .collect();
+        std::fs::write(&path, scheme).unwrap();
+        write_theme_path(&conn, path.to_str()).unwrap();
+        assert_eq!(read_theme(&conn).background, phantom_vt::Rgb::new(0, 0, 0));
+        assert_eq!(read_theme(&conn).foreground, phantom_vt::Rgb::new(5, 5, 5));
+
+        // A file that went away falls back to the default.
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_theme(&conn), Theme::default());
+    }
+}
@@ -20,6 +20,8 @@ pub struct CliPreset {
     pub working_dir: Option<String>,
     pub env_vars: Option<String>,
     pub budget_usd: Option<f64>,
+    /// Record sessions launched from this preset (asciicast v2).
+    pub record: bool,
 }
 
 pub fn list_analysis_presets(conn: &Connection) -> rusqlite::Result<Vec<AnalysisPreset>> {
@@ -59,7 +61,7 @@ pub fn delete_analysis_preset(conn: &Connection, id: i64) -> rusqlite::Result<bo
 
 pub fn list_cli_presets(conn: &Connection) -> rusqlite::Result<Vec<CliPreset>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, cli_binary, flags, working_dir, env_vars, budget_usd \
+        "SELECT id, name, cli_binary, flags, working_dir, env_vars, budget_usd, record \
          FROM cli_presets ORDER BY name",
     )?;
     let rows = stmt.query_map([], |row| {
@@ -71,6 +73,7 @@ pub fn list_cli_presets(conn: &Connection) -> rusqlite::Result<Vec<CliPreset>> {
             working_dir: row.get(4)?,
             env_vars: row.get(5)?,
             budget_usd: row.get(6)?,
+            record: row.get(7)?,
         })
     })?;
     rows.collect()
@@ -92,3 +95,12 @@ pub fn create_cli_preset(
     )?;
     Ok(conn.last_insert_rowid())
 }
+
+/// Set whether sessions launched from a CLI preset are recorded.
+pub fn set_cli_preset_record(conn: &Connection, id: i64, record: bool) -> rusqlite::Result<bool> {
+    let changed = conn.execute(
+        "UPDATE cli_presets SET record = ?1 WHERE id = ?2",
+        params![record, id],
+    )?;
+    Ok(changed > 0)
+}
@@ -1,7 +1,7 @@
 use rusqlite::Connection;
 
 /// Current schema version. Bump this when adding migrations.
-const CURRENT_VERSION: i64 = 4;
+const CURRENT_VERSION: i64 = 5;
 
 pub fn initialize(conn: &Connection) -> rusqlite::Result<()> {
     // Create base tables (idempotent)
@@ -34,6 +34,7 @@ pub fn initialize(conn: &Connection) -> rusqlite::Result<()> {
             working_dir TEXT,
             env_vars TEXT,
             budget_usd REAL,
+            record INTEGER NOT NULL DEFAULT 0,
             created_at TEXT NOT NULL DEFAULT (datetime('now'))
         );
 
@@ -151,6 +152,24 @@ fn migrate(conn: &Connection) -> rusqlite::Result<()> {
             ",
         )?;
 
+        conn.execute(
+            "INSERT OR REPLACE INTO schema_version (version) VALUES (?1)",
+            [4_i64],
+        )?;
+    }
+
+    if version < 5 {
+        // Migration v5: record sessions launched from a CLI preset.
+        let has_record = conn
+            .prepare("SELECT record FROM cli_presets LIMIT 0")
+            .is_ok();
+
+        if !has_record {
+            conn.execute_batch(
+                "ALTER TABLE cli_presets ADD COLUMN record INTEGER NOT NULL DEFAULT 0;",
+            )?;
+        }
+
         conn.execute(
             "INSERT OR REPLACE INTO schema_version (version) VALUES (?1)",
             [CURRENT_VERSION],
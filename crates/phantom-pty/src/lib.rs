@@ -10,11 +10,15 @@
 //! - [`TerminalSession`] — Pairs a `PtyHandle` with a `VtTerminal` for a complete
 //!   terminal tab experience.
 //! - [`Multiplexer`] — Manages multiple `TerminalSession`s for tab-based multiplexing.
+//! - [`Recorder`] / [`Replay`] — asciicast v2 recording of a session, and
+//!   headless replay of a recording into a `VtTerminal`.
 
 pub mod multiplexer;
 pub mod pty;
+pub mod recording;
 pub mod session;
 
 pub use multiplexer::Multiplexer;
 pub use pty::{PtyError, PtyHandle};
-pub use session::{SessionId, TerminalSession};
+pub use recording::{Recorder, Recording, RecordingError, Replay};
+pub use session::{PasteOutcome, SessionId, TerminalSession};
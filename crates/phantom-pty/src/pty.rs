@@ -1,4 +1,6 @@
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
 use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
 
@@ -39,10 +41,50 @@ impl From<std::io::Error> for PtyError {
 pub struct PtyHandle {
     master: Box<dyn MasterPty + Send>,
     reader: Box<dyn Read + Send>,
-    writer: Box<dyn Write + Send>,
+    writer: PtyWriter,
     child: Box<dyn Child + Send + Sync>,
 }
 
+/// Writes input to the PTY on a thread of its own.
+///
+/// A write blocks once the PTY buffer is full and the program is not reading
+/// its input, which it may not do until its own output is drained. The I/O
+/// thread drains it under the session lock, so writing with the lock held
+/// could deadlock a large paste.
+struct PtyWriter {
+    queue: mpsc::Sender<Vec<u8>>,
+    /// The error that stopped the writer thread, until reported.
+    error: Arc<Mutex<Option<io::Error>>>,
+}
+
+impl PtyWriter {
+    fn spawn(mut writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        let (queue, rx) = mpsc::channel::<Vec<u8>>();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = Arc::clone(&error);
+        thread::Builder::new()
+            .name("pty-writer".to_string())
+            .spawn(move || {
+                for data in rx {
+                    if let Err(err) = writer.write_all(&data).and_then(|()| writer.flush()) {
+                        *thread_error.lock().unwrap() = Some(err);
+                        return;
+                    }
+                }
+            })?;
+        Ok(Self { queue, error })
+    }
+
+    fn write(&self, data: &[u8]) -> io::Result<()> {
+        if let Some(err) = self.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        self.queue
+            .send(data.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "PTY writer stopped"))
+    }
+}
+
 impl PtyHandle {
     /// Spawn a new PTY with the given shell command and dimensions.
     ///
@@ -86,6 +128,8 @@ impl PtyHandle {
             .master
             .take_writer()
             .map_err(|e| PtyError::SpawnFailed(format!("failed to take writer: {e}")))?;
+        let writer = PtyWriter::spawn(writer)
+            .map_err(|e| PtyError::SpawnFailed(format!("failed to start writer: {e}")))?;
 
         Ok(Self {
             master: pair.master,
@@ -108,9 +152,11 @@ impl PtyHandle {
     }
 
     /// Write bytes to the PTY master (user input -> shell).
+    ///
+    /// The bytes are queued and written in order without blocking the
+    /// caller. A failed write is reported by the next call.
     pub fn write(&mut self, data: &[u8]) -> Result<(), PtyError> {
-        self.writer.write_all(data)?;
-        self.writer.flush()?;
+        self.writer.write(data)?;
         Ok(())
     }
 
@@ -0,0 +1,521 @@
+//! Session recording in asciicast v2 format, and headless replay.
+//!
+//! A recording is a JSON header line followed by one JSON array per event:
+//! `[seconds, code, data]`, where code is `"o"` for output, `"i"` for input,
+//! `"r"` for a resize (`"COLSxROWS"`) and `"m"` for a marker. Recordings
+//! play in asciinema and can be fed back into a `VtTerminal` with [`Replay`].
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use phantom_vt::VtTerminal;
+use serde_json::{json, Value};
+
+/// Errors from reading a recording.
+#[derive(Debug)]
+pub enum RecordingError {
+    IoError(io::Error),
+    /// A line is not a valid header or event.
+    Malformed {
+        line: usize,
+        message: String,
+    },
+    UnsupportedVersion(u64),
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::IoError(err) => write!(f, "recording I/O error: {err}"),
+            RecordingError::Malformed { line, message } => {
+                write!(f, "malformed recording at line {line}: {message}")
+            }
+            RecordingError::UnsupportedVersion(version) => {
+                write!(f, "unsupported asciicast version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordingError::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(err: io::Error) -> Self {
+        RecordingError::IoError(err)
+    }
+}
+
+/// The header of a recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Header {
+    /// Terminal size when the recording started.
+    pub width: u16,
+    pub height: u16,
+    /// Start of the recording, in seconds since the Unix epoch.
+    pub timestamp: Option<u64>,
+    pub title: Option<String>,
+}
+
+/// A recorded event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// Time since the start of the recording.
+    pub time: Duration,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    /// Output from the program.
+    Output(String),
+    /// Input sent to the program.
+    Input(String),
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    Marker(String),
+}
+
+/// Writes events to an asciicast v2 stream as they happen.
+pub struct Recorder {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    /// Start of a UTF-8 sequence split across two output reads.
+    partial_output: Vec<u8>,
+}
+
+impl Recorder {
+    /// Start a recording, writing its header.
+    pub fn new(mut writer: Box<dyn Write + Send>, header: &Header) -> io::Result<Self> {
+        let mut line = json!({
+            "version": 2,
+            "width": header.width,
+            "height": header.height,
+        });
+        if let Some(timestamp) = header.timestamp {
+            line["timestamp"] = json!(timestamp);
+        }
+        if let Some(title) = &header.title {
+            line["title"] = json!(title);
+        }
+        writeln!(writer, "{line}")?;
+        writer.flush()?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            partial_output: Vec::new(),
+        })
+    }
+
+    /// Record output from the program.
+    ///
+    /// A UTF-8 sequence cut off at the end of `bytes` is held back until the
+    /// next call, since each event must be valid text.
+    pub fn output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.partial_output.extend_from_slice(bytes);
+        let text = take_utf8(&mut self.partial_output);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.event("o", &text)
+    }
+
+    /// Record input sent to the program.
+    pub fn input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.event("i", &String::from_utf8_lossy(bytes))
+    }
+
+    /// Record a terminal resize.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> io::Result<()> {
+        self.event("r", &format!("{cols}x{rows}"))
+    }
+
+    /// Record a marker, a labelled point to jump to when replaying.
+    pub fn marker(&mut self, label: &str) -> io::Result<()> {
+        self.event("m", label)
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let line = format!("[{time:.6}, \"{code}\", {}]\n", Value::from(data));
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+/// Take the longest valid UTF-8 prefix of `bytes`, leaving an incomplete
+/// trailing sequence in place. Invalid bytes become U+FFFD.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut rest = bytes.as_slice();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let consumed = bytes.len() - rest.len();
+    bytes.drain(..consumed);
+    text
+}
+
+/// A recording read back from an asciicast v2 stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    /// Read a recording from a file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    /// Read a recording. Events with codes other than output, input, resize
+    /// and marker are skipped, as are blank lines.
+    pub fn parse(reader: impl BufRead) -> Result<Self, RecordingError> {
+        let mut lines = reader.lines().enumerate();
+        let header = loop {
+            let Some((index, line)) = lines.next() else {
+                return Err(malformed(1, "missing header"));
+            };
+            let line = line?;
+            if !line.trim().is_empty() {
+                break parse_header(index + 1, &line)?;
+            }
+        };
+
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(event) = parse_event(index + 1, &line)? {
+                events.push(event);
+            }
+        }
+        Ok(Self { header, events })
+    }
+
+    /// Time of the last event.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |e| e.time)
+    }
+}
+
+fn malformed(line: usize, message: &str) -> RecordingError {
+    RecordingError::Malformed {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_header(line: usize, text: &str) -> Result<Header, RecordingError> {
+    let value: Value = serde_json::from_str(text).map_err(|e| malformed(line, &e.to_string()))?;
+    let version = value["version"]
+        .as_u64()
+        .ok_or_else(|| malformed(line, "header has no version"))?;
+    if version != 2 {
+        return Err(RecordingError::UnsupportedVersion(version));
+    }
+    let size = |key: &str| {
+        value[key]
+            .as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| malformed(line, &format!("header has no valid {key}")))
+    };
+    Ok(Header {
+        width: size("width")?,
+        height: size("height")?,
+        timestamp: value["timestamp"].as_u64(),
+        title: value["title"].as_str().map(str::to_string),
+    })
+}
+
+fn parse_event(line: usize, text: &str) -> Result<Option<Event>, RecordingError> {
+    let value: Value = serde_json::from_str(text).map_err(|e| malformed(line, &e.to_string()))?;
+    let (Some(time), Some(code), Some(data)) =
+        (value[0].as_f64(), value[1].as_str(), value[2].as_str())
+    else {
+        return Err(malformed(line, "expected [time, code, data]"));
+    };
+    let time = Duration::try_from_secs_f64(time).map_err(|e| malformed(line, &e.to_string()))?;
+
+    let kind = match code {
+        "o" => EventKind::Output(data.to_string()),
+        "i" => EventKind::Input(data.to_string()),
+        "m" => EventKind::Marker(data.to_string()),
+        "r" => {
+            let size = data
+                .split_once('x')
+                .and_then(|(cols, rows)| Some((cols.parse().ok()?, rows.parse().ok()?)));
+            let Some((cols, rows)) = size else {
+                return Err(malformed(line, "expected COLSxROWS"));
+            };
+            EventKind::Resize { cols, rows }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(Event { time, kind }))
+}
+
+/// Plays a recording into a `VtTerminal`, without a PTY.
+///
+/// Output and resize events are applied to the terminal; input and markers
+/// only advance the position.
+pub struct Replay {
+    recording: Recording,
+    vt: VtTerminal,
+    /// Index of the next event to apply.
+    next: usize,
+    position: Duration,
+}
+
+impl Replay {
+    /// Start a replay at the beginning of `recording`.
+    pub fn new(recording: Recording) -> Self {
+        let vt = VtTerminal::new(recording.header.width, recording.header.height);
+        Self {
+            recording,
+            vt,
+            next: 0,
+            position: Duration::ZERO,
+        }
+    }
+
+    /// The terminal as of the current position.
+    pub fn vt(&self) -> &VtTerminal {
+        &self.vt
+    }
+
+    /// Get a mutable reference to the terminal, e.g. to scroll or search it.
+    pub fn vt_mut(&mut self) -> &mut VtTerminal {
+        &mut self.vt
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Time of the last event applied.
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Whether every event has been applied.
+    pub fn is_finished(&self) -> bool {
+        self.next == self.recording.events.len()
+    }
+
+    /// Apply the next event. Returns `None` at the end of the recording.
+    pub fn step(&mut self) -> Option<&Event> {
+        let event = self.recording.events.get(self.next)?;
+        match &event.kind {
+            EventKind::Output(text) => {
+                self.vt.write(text.as_bytes());
+                // Replies to the program's queries have nowhere to go.
+                let _ = self.vt.take_pty_writes();
+            }
+            EventKind::Resize { cols, rows } => self.vt.resize(*cols, *rows),
+            EventKind::Input(_) | EventKind::Marker(_) => {}
+        }
+        self.next += 1;
+        self.position = event.time;
+        Some(event)
+    }
+
+    /// Move to `time`, applying every event up to and including it.
+    ///
+    /// Seeking backwards replays the recording from the start.
+    pub fn seek(&mut self, time: Duration) {
+        if time < self.position {
+            let recording = std::mem::replace(
+                &mut self.recording,
+                Recording {
+                    header: Header::default(),
+                    events: Vec::new(),
+                },
+            );
+            *self = Self::new(recording);
+        }
+        while self
+            .recording
+            .events
+            .get(self.next)
+            .is_some_and(|e| e.time <= time)
+        {
+            self.step();
+        }
+    }
+
+    /// Play the rest of the recording, sleeping between events.
+    ///
+    /// `speed` scales time: 1.0 is real time, 4.0 four times as fast, and
+    /// `f64::INFINITY` applies events without waiting. `on_event` is called
+    /// after each event is applied.
+    pub fn play(&mut self, speed: f64, mut on_event: impl FnMut(&Event, &VtTerminal)) {
+        while let Some(event) = self.recording.events.get(self.next) {
+            let wait = event.time.saturating_sub(self.position).as_secs_f64() / speed;
+            if wait.is_finite() && wait > 0.0 {
+                std::thread::sleep(Duration::from_secs_f64(wait));
+            }
+            let Some(event) = self.step().cloned() else {
+                break;
+            };
+            on_event(&event, &self.vt);
+        }
+    }
+}
+
+/// The header for a recording of `vt` starting now.
+pub(crate) fn header_for(vt: &VtTerminal) -> Header {
+    let screen = vt.screen();
+    Header {
+        width: screen.cols(),
+        height: screen.rows(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs()),
+        title: vt.title_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer whose output the test can read after handing it over.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_parse() {
+        let buffer = SharedBuffer::default();
+        let header = Header {
+            width: 20,
+            height: 4,
+            timestamp: Some(1_700_000_000),
+            title: Some("agent".to_string()),
+        };
+        let mut recorder = Recorder::new(Box::new(buffer.clone()), &header).unwrap();
+        // "é" split across two reads is held back until it is complete.
+        recorder.output(b"caf\xc3").unwrap();
+        recorder.output(b"\xa9 \x1b[1mbold\x1b[0m\r\n").unwrap();
+        recorder.input(b"q").unwrap();
+        recorder.resize(30, 5).unwrap();
+        recorder.marker("done").unwrap();
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let first = text.lines().next().unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(first).unwrap(),
+            json!({"version": 2, "width": 20, "height": 4,
+                   "timestamp": 1_700_000_000, "title": "agent"})
+        );
+
+        let recording = Recording::parse(text.as_bytes()).unwrap();
+        assert_eq!(recording.header, header);
+        let kinds: Vec<EventKind> = recording.events.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Output("caf".to_string()),
+                EventKind::Output("é \x1b[1mbold\x1b[0m\r\n".to_string()),
+                EventKind::Input("q".to_string()),
+                EventKind::Resize { cols: 30, rows: 5 },
+                EventKind::Marker("done".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Recording::parse(&b"{\"version\": 1, \"width\": 80, \"height\": 24}\n"[..]),
+            Err(RecordingError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Recording::parse(
+                &b"{\"version\": 2, \"width\": 80, \"height\": 24}\n[1.0, \"o\"]\n"[..]
+            ),
+            Err(RecordingError::Malformed { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_seek_and_play() {
+        let cast = concat!(
+            "{\"version\": 2, \"width\": 10, \"height\": 3}\n",
+            "[0.5, \"o\", \"one\\r\\n\"]\n",
+            "[1.0, \"i\", \"x\"]\n",
+            "[1.5, \"o\", \"two\"]\n",
+            "[2.0, \"r\", \"12x4\"]\n",
+        );
+        let mut replay = Replay::new(Recording::parse(cast.as_bytes()).unwrap());
+        assert_eq!(replay.recording().duration(), Duration::from_secs(2));
+
+        replay.seek(Duration::from_secs(1));
+        assert_eq!(replay.position(), Duration::from_secs(1));
+        assert_eq!(
+            replay.vt().export(phantom_vt::ExportFormat::Text, None),
+            "one"
+        );
+
+        // Seeking back rebuilds the screen from the start.
+        replay.seek(Duration::from_millis(100));
+        assert_eq!(replay.vt().export(phantom_vt::ExportFormat::Text, None), "");
+
+        let mut applied = 0;
+        replay.play(f64::INFINITY, |_, _| applied += 1);
+        assert_eq!(applied, 4);
+        assert!(replay.is_finished());
+        assert_eq!(replay.vt().screen().cols(), 12);
+        assert_eq!(
+            replay.vt().export(phantom_vt::ExportFormat::Text, None),
+            "one\ntwo"
+        );
+    }
+}
@@ -1,10 +1,24 @@
-use phantom_vt::VtTerminal;
+use std::io::{self, Write};
+
+use phantom_vt::{
+    ClipboardRequest, CommandBlock, PasteRisk, SnapshotError, VtTerminal, MAX_COLUMNS, MAX_ROWS,
+};
 
 use crate::pty::{PtyError, PtyHandle};
+use crate::recording::{self, Recorder};
 
 /// Unique identifier for a terminal session.
 pub type SessionId = u64;
 
+/// Result of [`TerminalSession::paste`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PasteOutcome {
+    /// The text was written to the PTY.
+    Written,
+    /// Nothing was written; the user should confirm the paste first.
+    NeedsConfirmation(Vec<PasteRisk>),
+}
+
 /// A terminal session that pairs a PTY process with a VT terminal emulator.
 ///
 /// Reads shell output from the PTY, feeds it into the VtTerminal for parsing,
@@ -17,13 +31,19 @@ pub struct TerminalSession {
     title: Option<String>,
     alive: bool,
     exit_code: Option<u32>,
+    recorder: Option<Recorder>,
+    /// Whether the running recording includes input.
+    record_input: bool,
+    /// Why the last recording stopped by itself, until taken.
+    recording_error: Option<io::Error>,
 }
 
 impl TerminalSession {
     /// Create a new terminal session.
     ///
     /// Spawns a PTY process with the given shell (or the user's default shell)
-    /// and a VtTerminal with the given dimensions.
+    /// and a VtTerminal with the given dimensions, clamped to what the
+    /// VtTerminal supports so both agree on the size.
     pub fn new(
         id: SessionId,
         shell: Option<&str>,
@@ -31,6 +51,7 @@ impl TerminalSession {
         rows: u16,
         working_dir: Option<&str>,
     ) -> Result<Self, PtyError> {
+        let (cols, rows) = (cols.clamp(1, MAX_COLUMNS), rows.clamp(1, MAX_ROWS));
         let pty = PtyHandle::spawn(shell, cols, rows, working_dir)?;
         let vt = VtTerminal::new(cols, rows);
 
@@ -41,6 +62,9 @@ impl TerminalSession {
             title: None,
             alive: true,
             exit_code: None,
+            recorder: None,
+            record_input: false,
+            recording_error: None,
         })
     }
 
@@ -61,7 +85,7 @@ impl TerminalSession {
         let n = self.pty.read(&mut buf)?;
 
         if n > 0 {
-            self.vt.write(&buf[..n]);
+            self.feed_output(&buf[..n]);
 
             // Handle VT write-backs (e.g., device status responses).
             let writes = self.vt.take_pty_writes();
@@ -82,18 +106,113 @@ impl TerminalSession {
         Ok(n)
     }
 
+    /// Feed PTY output into the VT terminal, recording it if a recording
+    /// is running.
+    ///
+    /// For callers reading the PTY themselves (see `take_pty_reader`).
+    pub fn feed_output(&mut self, data: &[u8]) {
+        self.vt.write(data);
+        self.record(|r| r.output(data));
+    }
+
     /// Write user input to the PTY.
     pub fn write_input(&mut self, data: &[u8]) -> Result<(), PtyError> {
-        self.pty.write(data)
+        self.pty.write(data)?;
+        if self.record_input {
+            self.record(|r| r.input(data));
+        }
+        Ok(())
     }
 
-    /// Resize both the PTY and VT terminal.
+    /// Paste text into the session.
+    ///
+    /// Escape sequences are stripped from the text, and it is wrapped in
+    /// bracketed paste markers if the program enabled mode 2004. Unless
+    /// `confirmed` is set, risky pastes (multi-line text into a shell without
+    /// bracketed paste, `sudo`, `curl | sh`) are not written; the caller gets
+    /// the risks back to confirm with the user, then pastes again.
+    ///
+    /// Like all input, the text is written on the PTY's writer thread, so a
+    /// paste larger than the PTY buffer does not block the caller while it
+    /// holds the session.
+    pub fn paste(&mut self, text: &str, confirmed: bool) -> Result<PasteOutcome, PtyError> {
+        if !confirmed {
+            let risks = self.vt.paste_risks(text);
+            if !risks.is_empty() {
+                return Ok(PasteOutcome::NeedsConfirmation(risks));
+            }
+        }
+        let bytes = self.vt.encode_paste(text);
+        self.write_input(&bytes)?;
+        Ok(PasteOutcome::Written)
+    }
+
+    /// Tell the session whether it has keyboard focus, sending a focus report
+    /// to the program if it asked for them (mode 1004).
+    pub fn set_focused(&mut self, focused: bool) -> Result<(), PtyError> {
+        match self.vt.set_focused(focused) {
+            Some(report) => self.write_input(&report),
+            None => Ok(()),
+        }
+    }
+
+    /// Resize both the PTY and VT terminal, clamped like [`Self::new`].
     pub fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        let (cols, rows) = (cols.clamp(1, MAX_COLUMNS), rows.clamp(1, MAX_ROWS));
         self.pty.resize(cols, rows)?;
         self.vt.resize(cols, rows);
+        self.record(|r| r.resize(cols, rows));
+        Ok(())
+    }
+
+    /// Start recording output and resizes to `writer` in asciicast v2
+    /// format, replacing any recording in progress.
+    ///
+    /// Input is only recorded with `record_input` set, since it includes
+    /// whatever the user types, passwords among it.
+    pub fn start_recording(
+        &mut self,
+        writer: Box<dyn Write + Send>,
+        record_input: bool,
+    ) -> io::Result<()> {
+        let header = recording::header_for(&self.vt);
+        self.recorder = Some(Recorder::new(writer, &header)?);
+        self.record_input = record_input;
+        self.recording_error = None;
         Ok(())
     }
 
+    /// Stop recording. The writer is dropped, which closes a file.
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+
+    /// Whether a recording is running. A recording stops by itself if
+    /// writing to it fails; see [`Self::take_recording_error`].
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Take the error that stopped the last recording, if writing to it
+    /// failed since this was last called.
+    pub fn take_recording_error(&mut self) -> Option<io::Error> {
+        self.recording_error.take()
+    }
+
+    /// Add a marker to the recording, if one is running.
+    pub fn add_recording_marker(&mut self, label: &str) {
+        self.record(|r| r.marker(label));
+    }
+
+    fn record(&mut self, f: impl FnOnce(&mut Recorder) -> io::Result<()>) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = f(recorder) {
+                self.recorder = None;
+                self.recording_error = Some(err);
+            }
+        }
+    }
+
     /// Get a reference to the VT terminal for screen reading.
     pub fn vt(&self) -> &VtTerminal {
         &self.vt
@@ -104,6 +223,24 @@ impl TerminalSession {
         &mut self.vt
     }
 
+    /// Replace the screen and scrollback with a snapshot taken with
+    /// `VtTerminal::snapshot`, fitted to the current size.
+    ///
+    /// The shell keeps running and draws over the restored contents; the
+    /// theme, cell size and focus carry over to the restored terminal.
+    pub fn restore_snapshot(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut vt = VtTerminal::from_snapshot(bytes)?;
+        let screen = self.vt.screen();
+        vt.resize(screen.cols(), screen.rows());
+        vt.set_theme(self.vt.theme().clone());
+        let (width, height) = self.vt.cell_size();
+        vt.set_cell_size(width, height);
+        // A focus report would go to a program that is no longer there.
+        let _ = vt.set_focused(self.vt.is_focused());
+        self.vt = vt;
+        Ok(())
+    }
+
     /// Check if the session is still alive.
     ///
     /// Returns `false` after the child process has exited.
@@ -131,15 +268,15 @@ impl TerminalSession {
     /// Extract the PTY reader for use in a dedicated I/O thread.
     ///
     /// After calling this, `process_pty_output()` will no longer read from the PTY.
-    /// The caller should read from the returned reader and feed bytes into
-    /// `vt_mut().write()` manually, then call `handle_write_backs()`.
+    /// The caller should read from the returned reader and pass the bytes to
+    /// `feed_output()`, then call `handle_write_backs()`.
     pub fn take_pty_reader(&mut self) -> Box<dyn std::io::Read + Send> {
         self.pty.take_reader()
     }
 
     /// Write VT write-back data to the PTY and sync title.
     ///
-    /// Call this after feeding bytes into `vt_mut().write()` to handle
+    /// Call this after `feed_output()` to handle
     /// device status responses and title changes.
     pub fn handle_write_backs(&mut self) -> Result<(), PtyError> {
         let writes = self.vt.take_pty_writes();
@@ -154,11 +291,50 @@ impl TerminalSession {
     pub fn title(&self) -> Option<&str> {
         self.title.as_deref()
     }
+
+    /// Get the shell's current working directory (reported via OSC 7).
+    ///
+    /// `None` if the shell has not reported one. This is not necessarily the
+    /// directory the session was started in.
+    pub fn cwd(&self) -> Option<&str> {
+        self.vt.cwd()
+    }
+
+    /// Get the commands run in this session, as recorded by OSC 133 shell
+    /// integration marks. Oldest first.
+    pub fn command_blocks(&self) -> Vec<CommandBlock> {
+        self.vt.command_blocks()
+    }
+
+    /// Drain OSC 52 clipboard requests made by programs in this session.
+    ///
+    /// The caller decides, per its clipboard policy, which requests to honor.
+    pub fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        self.vt.take_clipboard_requests()
+    }
+
+    /// Answer an OSC 52 clipboard read by writing `text` back to the program.
+    ///
+    /// Returns `Ok(false)` if the read is unknown or was already answered.
+    pub fn answer_clipboard_load(&mut self, id: u64, text: &str) -> Result<bool, PtyError> {
+        if !self.vt.respond_clipboard_load(id, text) {
+            return Ok(false);
+        }
+        self.handle_write_backs()?;
+        Ok(true)
+    }
+
+    /// Drop an OSC 52 clipboard read without answering it.
+    pub fn discard_clipboard_load(&mut self, id: u64) {
+        self.vt.discard_clipboard_load(id);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Duration;
 
@@ -212,6 +388,64 @@ mod tests {
         assert!(total_bytes > 0, "Expected some PTY output to be processed");
     }
 
+    #[test]
+    fn test_session_paste() {
+        let mut session = TerminalSession::new(1, Some("/bin/sh"), 80, 24, None).unwrap();
+
+        assert_eq!(
+            session.paste("echo one\necho two\n", false).unwrap(),
+            PasteOutcome::NeedsConfirmation(vec![PasteRisk::MultiLine])
+        );
+        assert_eq!(
+            session.paste("echo PASTE_TEST\n", true).unwrap(),
+            PasteOutcome::Written
+        );
+        assert_eq!(session.paste("ls", false).unwrap(), PasteOutcome::Written);
+    }
+
+    #[test]
+    fn test_large_paste_does_not_block_reader() {
+        use std::io::Read;
+        use std::time::Instant;
+
+        let mut session = TerminalSession::new(1, Some("/bin/cat"), 80, 24, None).unwrap();
+        let mut reader = session.take_pty_reader();
+        let session = Arc::new(Mutex::new(session));
+
+        // Drain output under the session lock, as the I/O thread does.
+        let drain_session = Arc::clone(&session);
+        thread::spawn(move || {
+            let mut buf = [0u8; 65536];
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                drain_session.lock().unwrap().feed_output(&buf[..n]);
+            }
+        });
+
+        // Far more than the PTY buffers hold, echoed back twice by cat.
+        let mut text: String = (0..5000)
+            .map(|i| format!("{i:05} {}\n", "x".repeat(74)))
+            .collect();
+        text.push_str("PASTE_END\n");
+        let start = Instant::now();
+        let outcome = session.lock().unwrap().paste(&text, true).unwrap();
+        assert_eq!(outcome, PasteOutcome::Written);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let deadline = Instant::now() + Duration::from_secs(20);
+        loop {
+            let screen_text = session
+                .lock()
+                .unwrap()
+                .vt()
+                .export(phantom_vt::ExportFormat::Text, None);
+            if screen_text.contains("PASTE_END") {
+                break;
+            }
+            assert!(Instant::now() < deadline, "paste never reached the program");
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
     #[test]
     fn test_session_resize() {
         let mut session = TerminalSession::new(1, Some("/bin/sh"), 80, 24, None).unwrap();
@@ -224,6 +458,72 @@ mod tests {
         assert_eq!(screen.rows(), 40);
     }
 
+    #[test]
+    fn test_session_restore_snapshot() {
+        let mut saved = VtTerminal::new(40, 10);
+        saved.write(b"\x1b]2;saved\x07restored text");
+        let mut session = TerminalSession::new(1, Some("/bin/sh"), 80, 24, None).unwrap();
+
+        session.restore_snapshot(&saved.snapshot()).unwrap();
+
+        let screen = session.vt().screen();
+        assert_eq!((screen.cols(), screen.rows()), (80, 24));
+        assert_eq!(session.vt().title_owned().as_deref(), Some("saved"));
+        assert!(session
+            .vt()
+            .export(phantom_vt::ExportFormat::Text, None)
+            .contains("restored text"));
+        assert!(session.restore_snapshot(b"junk").is_err());
+    }
+
+    /// A recording writer the test can read back, or make fail.
+    #[derive(Clone, Default)]
+    struct TestWriter {
+        buffer: Arc<Mutex<Vec<u8>>>,
+        broken: Arc<AtomicBool>,
+    }
+
+    impl Write for TestWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.broken.load(Ordering::Relaxed) {
+                return Err(io::Error::other("disk full"));
+            }
+            self.buffer.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_session_recording() {
+        let mut session = TerminalSession::new(1, Some("/bin/sh"), 80, 24, None).unwrap();
+        let writer = TestWriter::default();
+
+        // Input is left out unless asked for.
+        session.start_recording(Box::new(writer.clone()), false).unwrap();
+        session.write_input(b"secret\n").unwrap();
+        session.feed_output(b"shown");
+        let text = String::from_utf8(writer.buffer.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("shown"));
+        assert!(!text.contains("secret"));
+
+        session.start_recording(Box::new(writer.clone()), true).unwrap();
+        session.write_input(b"typed\n").unwrap();
+        let text = String::from_utf8(writer.buffer.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("typed"));
+
+        // A failed write stops the recording and is reported once.
+        writer.broken.store(true, Ordering::Relaxed);
+        session.feed_output(b"lost");
+        assert!(!session.is_recording());
+        let err = session.take_recording_error().unwrap();
+        assert_eq!(err.to_string(), "disk full");
+        assert!(session.take_recording_error().is_none());
+    }
+
     #[test]
     fn test_session_exit() {
         let mut session = TerminalSession::new(1, Some("/bin/sh"), 80, 24, None).unwrap();
@@ -0,0 +1,44 @@
+//! Coverage-guided version of `tests/fuzz.rs`: writes, resizes and scrolls
+//! chosen by libFuzzer, checked after every step.
+//!
+//! ```sh
+//! cargo +nightly fuzz run write_resize
+//! ```
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use phantom_vt::invariants::check_terminal;
+use phantom_vt::VtTerminal;
+
+#[derive(Arbitrary, Debug)]
+enum Step {
+    Write(Vec<u8>),
+    Resize(u8, u8),
+    Scroll(i8),
+    SetReflow(bool),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    cols: u8,
+    rows: u8,
+    steps: Vec<Step>,
+}
+
+fuzz_target!(|input: Input| {
+    // Sizes are kept small so runs stay fast; zero is clamped to one cell.
+    let mut vt = VtTerminal::new(input.cols as u16 % 120, input.rows as u16 % 50);
+    for (i, step) in input.steps.into_iter().enumerate() {
+        match step {
+            Step::Write(bytes) => vt.write(&bytes),
+            Step::Resize(cols, rows) => vt.resize(cols as u16 % 120, rows as u16 % 50),
+            Step::Scroll(delta) => vt.scroll_display(delta as i32),
+            Step::SetReflow(reflow) => vt.set_reflow(reflow),
+        }
+        if let Err(e) = check_terminal(&mut vt) {
+            panic!("step {i}: {e}");
+        }
+    }
+});
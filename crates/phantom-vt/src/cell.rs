@@ -16,7 +16,7 @@ impl Rgb {
 
 bitflags! {
     /// Cell attribute flags, packed into a single byte.
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
     pub struct CellFlags: u8 {
         const BOLD          = 0b0000_0001;
         const ITALIC        = 0b0000_0010;
@@ -29,29 +29,68 @@ bitflags! {
     }
 }
 
+/// Underline style, set with SGR 4 and 4:1 to 4:5.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnderlineStyle {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
 /// A single cell in the terminal grid.
 #[derive(Clone, Debug)]
 pub struct VtCell {
     /// The character displayed in this cell.
     pub codepoint: char,
+    /// Further codepoints of the grapheme cluster starting with `codepoint`:
+    /// combining marks, variation selectors and the rest of emoji sequences.
+    /// Empty for the common single-codepoint cell.
+    pub combining: Vec<char>,
     /// Foreground color.
     pub fg: Rgb,
     /// Background color.
     pub bg: Rgb,
     /// Cell attribute flags (bold, italic, etc.).
+    ///
+    /// `UNDERLINE` is set for every underline style.
     pub flags: CellFlags,
+    /// Underline style.
+    pub underline: UnderlineStyle,
+    /// Underline color set with SGR 58, or `None` to use the foreground.
+    pub underline_color: Option<Rgb>,
     /// Character width: 1 for normal, 2 for wide (CJK) chars.
     pub width: u8,
+    /// OSC 8 hyperlink ID, or 0 if the cell is not part of a link.
+    ///
+    /// Resolve with `VtTerminal::hyperlink`.
+    pub hyperlink_id: u16,
+}
+
+impl VtCell {
+    /// The full grapheme cluster displayed in this cell.
+    pub fn grapheme(&self) -> String {
+        std::iter::once(self.codepoint)
+            .chain(self.combining.iter().copied())
+            .collect()
+    }
 }
 
 impl Default for VtCell {
     fn default() -> Self {
         Self {
             codepoint: ' ',
+            combining: Vec::new(),
             fg: Rgb::new(255, 255, 255),
             bg: Rgb::new(0, 0, 0),
             flags: CellFlags::empty(),
+            underline: UnderlineStyle::None,
+            underline_color: None,
             width: 1,
+            hyperlink_id: 0,
         }
     }
 }
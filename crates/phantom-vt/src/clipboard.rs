@@ -0,0 +1,146 @@
+//! OSC 52 clipboard access.
+//!
+//! Programs set the clipboard with `OSC 52 ; c ; <base64>` and read it with
+//! `OSC 52 ; c ; ?`. alacritty decodes both into events; the terminal queues
+//! them as `ClipboardRequest`s and leaves it to the embedder to decide whether
+//! to honor them. A read is answered later by ID, since it may have to wait
+//! for the user to confirm.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use alacritty_terminal::term::ClipboardType;
+
+/// Upper bound on unanswered clipboard reads; the oldest is dropped first.
+const MAX_PENDING_LOADS: usize = 16;
+
+/// Which clipboard a request targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardKind {
+    /// The system clipboard (`c`).
+    Clipboard,
+    /// The primary selection (`p` or `s`).
+    Primary,
+}
+
+impl From<ClipboardType> for ClipboardKind {
+    fn from(ty: ClipboardType) -> Self {
+        match ty {
+            ClipboardType::Clipboard => ClipboardKind::Clipboard,
+            ClipboardType::Selection => ClipboardKind::Primary,
+        }
+    }
+}
+
+/// A clipboard request made by the program running in the terminal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    /// Set the clipboard to `text`.
+    Store { kind: ClipboardKind, text: String },
+    /// Send the clipboard contents back to the program.
+    ///
+    /// Answer with `VtTerminal::respond_clipboard_load`, or drop it with
+    /// `VtTerminal::discard_clipboard_load`.
+    Load { id: u64, kind: ClipboardKind },
+}
+
+/// Formats clipboard text as the OSC 52 reply the program asked for.
+pub(crate) type ReplyFormatter = Arc<dyn Fn(&str) -> String + Sync + Send + 'static>;
+
+/// A request as captured from alacritty, before loads are given an ID.
+pub(crate) enum RawClipboardRequest {
+    Store(ClipboardType, String),
+    Load(ClipboardType, ReplyFormatter),
+}
+
+/// Clipboard reads waiting for an answer.
+#[derive(Default)]
+pub(crate) struct PendingLoads {
+    next_id: u64,
+    loads: VecDeque<(u64, ReplyFormatter)>,
+}
+
+impl PendingLoads {
+    /// Queue a read and return its ID. IDs start at 1.
+    pub(crate) fn insert(&mut self, formatter: ReplyFormatter) -> u64 {
+        if self.loads.len() >= MAX_PENDING_LOADS {
+            self.loads.pop_front();
+        }
+        self.next_id += 1;
+        self.loads.push_back((self.next_id, formatter));
+        self.next_id
+    }
+
+    /// Remove a read, returning its reply formatter.
+    pub(crate) fn take(&mut self, id: u64) -> Option<ReplyFormatter> {
+        let index = self.loads.iter().position(|(load_id, _)| *load_id == id)?;
+        self.loads.remove(index).map(|(_, formatter)| formatter)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.loads.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    #[test]
+    fn test_osc52_store() {
+        let mut term = VtTerminal::new(40, 5);
+        // "hello" in base64.
+        term.write(b"\x1b]52;c;aGVsbG8=\x07\x1b]52;p;aGk=\x1b\\");
+
+        assert_eq!(
+            term.take_clipboard_requests(),
+            vec![
+                ClipboardRequest::Store {
+                    kind: ClipboardKind::Clipboard,
+                    text: "hello".to_string(),
+                },
+                ClipboardRequest::Store {
+                    kind: ClipboardKind::Primary,
+                    text: "hi".to_string(),
+                },
+            ]
+        );
+        assert!(term.take_clipboard_requests().is_empty());
+    }
+
+    #[test]
+    fn test_osc52_load_answered_by_id() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"\x1b]52;c;?\x1b\\");
+
+        let requests = term.take_clipboard_requests();
+        let [ClipboardRequest::Load { id, kind }] = requests[..] else {
+            panic!("expected one load, got {requests:?}");
+        };
+        assert_eq!(kind, ClipboardKind::Clipboard);
+        assert!(term.take_pty_writes().is_empty());
+
+        assert!(term.respond_clipboard_load(id, "hello"));
+        // The reply uses the same terminator as the query.
+        assert_eq!(term.take_pty_writes(), vec!["\x1b]52;c;aGVsbG8=\x1b\\"]);
+
+        // Each read is answered at most once.
+        assert!(!term.respond_clipboard_load(id, "again"));
+        assert!(term.take_pty_writes().is_empty());
+    }
+
+    #[test]
+    fn test_pending_loads_bounded() {
+        let mut pending = PendingLoads::default();
+        let formatter: ReplyFormatter = Arc::new(|text| text.to_string());
+        let first = pending.insert(formatter.clone());
+        for _ in 0..MAX_PENDING_LOADS {
+            pending.insert(formatter.clone());
+        }
+
+        assert!(pending.take(first).is_none());
+        assert!(pending.take(first + 1).is_some());
+        assert!(pending.take(first + 1).is_none());
+    }
+}
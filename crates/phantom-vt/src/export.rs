@@ -0,0 +1,455 @@
+//! Export of the terminal buffer as plain text, ANSI escaped text or HTML.
+//!
+//! Exports work on logical lines: rows joined by soft wraps. Trailing blank
+//! cells and trailing blank lines are dropped. Colors equal to the default
+//! foreground and background are written as the defaults, so an ANSI export
+//! replays in the reader's own colors and an HTML export sets them once.
+
+use crate::cell::{CellFlags, Rgb, UnderlineStyle, VtCell};
+
+/// Output format of `VtTerminal::export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Plain text, trailing whitespace trimmed.
+    Text,
+    /// Text with SGR escapes for colors and attributes, and OSC 8 hyperlinks.
+    Ansi,
+    /// A self-contained HTML document.
+    Html,
+}
+
+/// One grid row to export, already cut to the exported columns.
+pub(crate) struct ExportLine {
+    pub(crate) cells: Vec<VtCell>,
+    /// The row continues on the next one (soft wrap).
+    pub(crate) wrapped: bool,
+}
+
+/// Colors and links needed to render cells.
+pub(crate) struct ExportContext<F> {
+    pub(crate) foreground: Rgb,
+    pub(crate) background: Rgb,
+    /// Resolves a cell's hyperlink ID to its URI.
+    pub(crate) link: F,
+}
+
+/// Serialize `lines` in `format`.
+pub(crate) fn export<F>(
+    lines: Vec<ExportLine>,
+    format: ExportFormat,
+    cx: &ExportContext<F>,
+) -> String
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let lines = logical_lines(lines, format, cx);
+    match format {
+        ExportFormat::Text => lines
+            .iter()
+            .map(|cells| {
+                let text: String = cells.iter().map(cell_text).collect();
+                text.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ExportFormat::Ansi => lines
+            .iter()
+            .map(|cells| ansi_line(cells, cx))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ExportFormat::Html => html_document(&lines, cx),
+    }
+}
+
+/// Join soft-wrapped rows, drop wide-char spacers and trailing blanks.
+fn logical_lines<F>(
+    lines: Vec<ExportLine>,
+    format: ExportFormat,
+    cx: &ExportContext<F>,
+) -> Vec<Vec<VtCell>> {
+    let mut logical = Vec::new();
+    let mut current: Vec<VtCell> = Vec::new();
+    for line in lines {
+        current.extend(line.cells.into_iter().filter(|c| c.width != 0));
+        if !line.wrapped {
+            logical.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        logical.push(current);
+    }
+
+    // Plain text only cares about characters; styled formats keep blanks
+    // that show a background or decoration.
+    let blank = |cell: &VtCell| match format {
+        ExportFormat::Text => cell_text(cell).trim().is_empty(),
+        _ => is_blank(cell, cx),
+    };
+    for cells in &mut logical {
+        let end = cells.iter().rposition(|c| !blank(c)).map_or(0, |i| i + 1);
+        cells.truncate(end);
+    }
+    while logical.last().is_some_and(Vec::is_empty) {
+        logical.pop();
+    }
+    logical
+}
+
+/// A space with nothing visible drawn on it.
+fn is_blank<F>(cell: &VtCell, cx: &ExportContext<F>) -> bool {
+    cell_text(cell) == " "
+        && cell.bg == cx.background
+        && !cell
+            .flags
+            .intersects(CellFlags::INVERSE | CellFlags::UNDERLINE | CellFlags::STRIKETHROUGH)
+}
+
+/// The text a cell shows; hidden cells show blanks.
+fn cell_text(cell: &VtCell) -> String {
+    if cell.flags.contains(CellFlags::HIDDEN) {
+        return " ".repeat(cell.width as usize);
+    }
+    match cell.codepoint {
+        '\0' | '\t' => " ".to_string(),
+        _ => cell.grapheme(),
+    }
+}
+
+/// The attributes of a run of cells, with default colors as `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Style {
+    fg: Option<Rgb>,
+    bg: Option<Rgb>,
+    flags: CellFlags,
+    underline: UnderlineStyle,
+    underline_color: Option<Rgb>,
+    link: Option<String>,
+}
+
+impl Style {
+    fn of<F>(cell: &VtCell, cx: &ExportContext<F>) -> Self
+    where
+        F: Fn(u16) -> Option<String>,
+    {
+        Self {
+            fg: Some(cell.fg).filter(|&c| c != cx.foreground),
+            bg: Some(cell.bg).filter(|&c| c != cx.background),
+            flags: cell.flags - CellFlags::HIDDEN,
+            underline: cell.underline,
+            underline_color: cell.underline_color,
+            link: match cell.hyperlink_id {
+                0 => None,
+                id => (cx.link)(id),
+            },
+        }
+    }
+}
+
+/// Split a line into runs of cells with the same style.
+fn runs<F>(cells: &[VtCell], cx: &ExportContext<F>) -> Vec<(Style, String)>
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let mut runs: Vec<(Style, String)> = Vec::new();
+    for cell in cells {
+        let style = Style::of(cell, cx);
+        let text = cell_text(cell);
+        match runs.last_mut() {
+            Some((last, run)) if *last == style => run.push_str(&text),
+            _ => runs.push((style, text)),
+        }
+    }
+    runs
+}
+
+fn ansi_line<F>(cells: &[VtCell], cx: &ExportContext<F>) -> String
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut current = Style::default();
+    for (style, text) in runs(cells, cx) {
+        if style.link != current.link {
+            out.push_str(&format!(
+                "\x1b]8;;{}\x1b\\",
+                style.link.as_deref().unwrap_or("")
+            ));
+        }
+        if !same_attributes(&style, &current) {
+            out.push_str(&sgr(&style));
+        }
+        out.push_str(&text);
+        current = style;
+    }
+    if current.link.is_some() {
+        out.push_str("\x1b]8;;\x1b\\");
+    }
+    if !same_attributes(&current, &Style::default()) {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// Whether two styles look the same, ignoring links.
+fn same_attributes(a: &Style, b: &Style) -> bool {
+    (a.fg, a.bg, a.flags, a.underline, a.underline_color)
+        == (b.fg, b.bg, b.flags, b.underline, b.underline_color)
+}
+
+/// The SGR sequence that resets the attributes and applies `style`.
+fn sgr(style: &Style) -> String {
+    let mut params = vec!["0".to_string()];
+    let flags = [
+        (CellFlags::BOLD, "1"),
+        (CellFlags::DIM, "2"),
+        (CellFlags::ITALIC, "3"),
+        (CellFlags::BLINK, "5"),
+        (CellFlags::INVERSE, "7"),
+        (CellFlags::STRIKETHROUGH, "9"),
+    ];
+    for (flag, param) in flags {
+        if style.flags.contains(flag) {
+            params.push(param.to_string());
+        }
+    }
+    match style.underline {
+        UnderlineStyle::None => {}
+        UnderlineStyle::Single => params.push("4".to_string()),
+        UnderlineStyle::Double => params.push("4:2".to_string()),
+        UnderlineStyle::Curly => params.push("4:3".to_string()),
+        UnderlineStyle::Dotted => params.push("4:4".to_string()),
+        UnderlineStyle::Dashed => params.push("4:5".to_string()),
+    }
+    if let Some(c) = style.fg {
+        params.push(format!("38;2;{};{};{}", c.r, c.g, c.b));
+    }
+    if let Some(c) = style.bg {
+        params.push(format!("48;2;{};{};{}", c.r, c.g, c.b));
+    }
+    if let Some(c) = style.underline_color {
+        params.push(format!("58:2::{}:{}:{}", c.r, c.g, c.b));
+    }
+    format!("\x1b[{}m", params.join(";"))
+}
+
+fn html_document<F>(lines: &[Vec<VtCell>], cx: &ExportContext<F>) -> String
+where
+    F: Fn(u16) -> Option<String>,
+{
+    let mut body = String::new();
+    for (i, cells) in lines.iter().enumerate() {
+        if i > 0 {
+            body.push('\n');
+        }
+        for (style, text) in runs(cells, cx) {
+            let text = escape_html(&text);
+            let css = css(&style, cx);
+            let span = if css.is_empty() {
+                text
+            } else {
+                format!("<span style=\"{css}\">{text}</span>")
+            };
+            match style.link.as_deref().filter(|uri| is_safe_link(uri)) {
+                Some(uri) => body.push_str(&format!("<a href=\"{}\">{span}</a>", escape_html(uri))),
+                None => body.push_str(&span),
+            }
+        }
+    }
+
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Terminal</title>\n\
+         <style>\n\
+         body {{ margin: 0; background: {bg}; }}\n\
+         pre {{ margin: 0; padding: 8px; color: {fg}; background: {bg}; \
+         font-family: ui-monospace, Menlo, Consolas, monospace; }}\n\
+         a {{ color: inherit; }}\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         <pre>{body}</pre>\n\
+         </body>\n\
+         </html>\n",
+        fg = hex(cx.foreground),
+        bg = hex(cx.background),
+    )
+}
+
+/// Inline CSS for a run; empty for the default style.
+fn css<F>(style: &Style, cx: &ExportContext<F>) -> String {
+    let mut fg = style.fg;
+    let mut bg = style.bg;
+    if style.flags.contains(CellFlags::INVERSE) {
+        (fg, bg) = (
+            Some(bg.unwrap_or(cx.background)),
+            Some(fg.unwrap_or(cx.foreground)),
+        );
+    }
+
+    let mut rules = Vec::new();
+    if let Some(c) = fg {
+        rules.push(format!("color:{}", hex(c)));
+    }
+    if let Some(c) = bg {
+        rules.push(format!("background:{}", hex(c)));
+    }
+    if style.flags.contains(CellFlags::BOLD) {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.flags.contains(CellFlags::DIM) {
+        rules.push("opacity:0.6".to_string());
+    }
+    if style.flags.contains(CellFlags::ITALIC) {
+        rules.push("font-style:italic".to_string());
+    }
+
+    let mut lines = Vec::new();
+    if style.underline != UnderlineStyle::None {
+        lines.push("underline");
+    }
+    if style.flags.contains(CellFlags::STRIKETHROUGH) {
+        lines.push("line-through");
+    }
+    if !lines.is_empty() {
+        rules.push(format!("text-decoration-line:{}", lines.join(" ")));
+        let decoration = match style.underline {
+            UnderlineStyle::Double => Some("double"),
+            UnderlineStyle::Curly => Some("wavy"),
+            UnderlineStyle::Dotted => Some("dotted"),
+            UnderlineStyle::Dashed => Some("dashed"),
+            _ => None,
+        };
+        if let Some(decoration) = decoration {
+            rules.push(format!("text-decoration-style:{decoration}"));
+        }
+        if let Some(c) = style.underline_color {
+            rules.push(format!("text-decoration-color:{}", hex(c)));
+        }
+    }
+    rules.join(";")
+}
+
+/// Schemes an OSC 8 link may have to become a link in an HTML export. The
+/// URI comes from the program, so anything else (`javascript:`, `data:`)
+/// is exported as plain text.
+const HTML_LINK_SCHEMES: [&str; 4] = ["http", "https", "file", "mailto"];
+
+fn is_safe_link(uri: &str) -> bool {
+    uri.split_once(':').is_some_and(|(scheme, _)| {
+        HTML_LINK_SCHEMES
+            .iter()
+            .any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+    })
+}
+
+fn hex(c: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, wrapped: bool) -> ExportLine {
+        ExportLine {
+            cells: text
+                .chars()
+                .map(|codepoint| VtCell {
+                    codepoint,
+                    ..VtCell::default()
+                })
+                .collect(),
+            wrapped,
+        }
+    }
+
+    fn context() -> ExportContext<impl Fn(u16) -> Option<String>> {
+        ExportContext {
+            foreground: Rgb::new(255, 255, 255),
+            background: Rgb::new(0, 0, 0),
+            link: |id| match id {
+                1 => Some("https://example.com/?a&b".to_string()),
+                2 => Some("JavaScript:alert(1)".to_string()),
+                3 => Some("MAILTO:me@example.com".to_string()),
+                _ => None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_export_text_joins_wraps_and_trims() {
+        let lines = vec![
+            line("hello wor", true),
+            line("ld   ", false),
+            line("  x  ", false),
+            line("     ", false),
+            line("     ", false),
+        ];
+        let text = export(lines, ExportFormat::Text, &context());
+        assert_eq!(text, "hello world\n  x");
+    }
+
+    #[test]
+    fn test_export_ansi_styles() {
+        let mut red = line("ab", false);
+        red.cells[0].fg = Rgb::new(255, 0, 0);
+        red.cells[0].flags = CellFlags::BOLD;
+        red.cells[1].hyperlink_id = 1;
+        red.cells[1].underline = UnderlineStyle::Curly;
+        red.cells[1].flags = CellFlags::UNDERLINE;
+
+        let ansi = export(vec![red], ExportFormat::Ansi, &context());
+        assert_eq!(
+            ansi,
+            "\x1b[0;1;38;2;255;0;0ma\
+             \x1b]8;;https://example.com/?a&b\x1b\\\x1b[0;4:3mb\
+             \x1b]8;;\x1b\\\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_export_html() {
+        let mut cells = line("<a> ", false);
+        cells.cells[1].flags = CellFlags::INVERSE;
+        cells.cells[3].bg = Rgb::new(0, 0, 255);
+
+        let html = export(vec![cells], ExportFormat::Html, &context());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(
+            "<pre>&lt;<span style=\"color:#000000;background:#ffffff\">a</span>&gt;\
+             <span style=\"background:#0000ff\"> </span></pre>"
+        ));
+    }
+
+    #[test]
+    fn test_export_html_links() {
+        let mut cells = line("abc", false);
+        for (cell, id) in cells.cells.iter_mut().zip(1..) {
+            cell.hyperlink_id = id;
+        }
+
+        let html = export(vec![cells], ExportFormat::Html, &context());
+        assert!(html.contains(
+            "<pre><a href=\"https://example.com/?a&amp;b\">a</a>b\
+             <a href=\"MAILTO:me@example.com\">c</a></pre>"
+        ));
+        assert!(!html.contains("alert"));
+    }
+}
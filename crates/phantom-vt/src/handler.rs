@@ -0,0 +1,347 @@
+//! Parser handler that sits between the VTE parser and alacritty's `Term`.
+//!
+//! alacritty stores zero-width characters (combining marks, variation
+//! selectors, ZWJ) with the cell before them, but starts a new cell for every
+//! character with a width, so emoji sequences such as a ZWJ family, a skin
+//! tone or a flag end up spread over several cells. The interceptor joins
+//! those characters into the cell of the cluster they belong to and forwards
+//! everything else, correcting `Term` where it departs from xterm.
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::{Term, TermMode};
+use alacritty_terminal::vte::ansi::cursor_icon::CursorIcon;
+use alacritty_terminal::vte::ansi::{
+    Attr, CharsetIndex, ClearMode, CursorShape, CursorStyle, Handler, Hyperlink, KeyboardModes,
+    KeyboardModesApplyBehavior, LineClearMode, Mode, ModifyOtherKeys, PrivateMode, Rgb,
+    ScpCharPath, ScpUpdateMode, StandardCharset, TabulationClearMode,
+};
+use unicode_width::UnicodeWidthChar;
+
+/// Zero width joiner: the next character belongs to the same cluster.
+const ZWJ: char = '\u{200d}';
+
+/// State of the grapheme cluster being written, kept across parser calls so
+/// a cluster split between two reads is still joined.
+#[derive(Debug, Default)]
+pub(crate) struct GraphemeState {
+    /// The last character written, unless the cursor moved since.
+    last: Option<char>,
+    /// The last cluster is a single regional indicator waiting for the
+    /// second half of its flag.
+    open_flag: bool,
+}
+
+impl GraphemeState {
+    /// Whether `c` continues the cluster written last. Records `c` as the
+    /// last character either way.
+    fn joins(&mut self, c: char) -> bool {
+        let join = match self.last {
+            Some(ZWJ) => true,
+            Some(last) => {
+                (is_emoji_modifier(c) && is_emoji_base(last))
+                    || (self.open_flag && is_regional_indicator(c))
+            }
+            None => false,
+        };
+        self.open_flag = is_regional_indicator(c) && !(join && self.open_flag);
+        self.last = Some(c);
+        join
+    }
+
+    /// End the current cluster; nothing written after this joins it.
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+        self.open_flag = false;
+    }
+}
+
+/// Skin tone modifiers (U+1F3FB..U+1F3FF).
+fn is_emoji_modifier(c: char) -> bool {
+    ('\u{1f3fb}'..='\u{1f3ff}').contains(&c)
+}
+
+/// Characters a skin tone modifier may follow. An approximation of the
+/// Emoji_Modifier_Base property that covers the symbol and emoji blocks.
+fn is_emoji_base(c: char) -> bool {
+    matches!(c, '\u{261d}'..='\u{270d}' | '\u{1f385}'..='\u{1faff}') && !is_emoji_modifier(c)
+}
+
+/// Regional indicator symbols, two of which form a flag.
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
+
+/// Forwards parser callbacks to `Term`, joining grapheme clusters on input.
+pub(crate) struct Interceptor<'a, T> {
+    pub(crate) term: &'a mut Term<T>,
+    pub(crate) graphemes: &'a mut GraphemeState,
+    /// Number of lines the primary screen's history is kept at.
+    pub(crate) history_limit: usize,
+    /// Lines scrolled into the primary screen's history so far.
+    pub(crate) scrolled: usize,
+}
+
+impl<T: EventListener> Interceptor<'_, T> {
+    /// Add `c` to the cell holding the last character, the same cell
+    /// alacritty puts zero-width characters on.
+    fn push_to_previous_cell(&mut self, c: char) {
+        let grid = self.term.grid_mut();
+        let line = grid.cursor.point.line;
+        let mut column = grid.cursor.point.column;
+        if !grid.cursor.input_needs_wrap {
+            column.0 = column.saturating_sub(1);
+        }
+        if grid[line][column].flags.contains(Flags::WIDE_CHAR_SPACER) {
+            column.0 = column.saturating_sub(1);
+        }
+        grid[line][column].push_zerowidth(c);
+    }
+
+    /// Run `f`, counting the lines it scrolls into the history of the
+    /// primary screen.
+    ///
+    /// Once the history is full, alacritty drops a line for every line it
+    /// adds, so the history size alone cannot tell how far text moved. One
+    /// call scrolls at most a screen, so the limit is lifted by that much
+    /// while `f` runs and the history trimmed back right after; alacritty
+    /// keeps the trimmed rows around and reuses them for the next call.
+    fn count_scrolling(&mut self, f: impl FnOnce(&mut Self)) {
+        if self.term.mode().contains(TermMode::ALT_SCREEN) {
+            // The alternate screen has no history.
+            f(self);
+            return;
+        }
+        let before = self.term.history_size();
+        let headroom = self.term.screen_lines();
+        self.term
+            .grid_mut()
+            .update_history(self.history_limit + headroom);
+
+        f(self);
+
+        let after = self.term.history_size();
+        self.scrolled += after.saturating_sub(before);
+        self.term.grid_mut().update_history(self.history_limit);
+    }
+
+    /// Blank the halves of wide characters on the cursor line that lost
+    /// their other half. alacritty shifts and clears cells without regard
+    /// for wide characters.
+    fn blank_split_wide_chars(&mut self) {
+        let grid = self.term.grid();
+        let cursor = grid.cursor.point;
+        let row = &grid[cursor.line];
+        let columns = grid.columns();
+        let flags = |col: usize| row[Column(col)].flags;
+        let split: Vec<usize> = (0..columns)
+            .filter(|&col| {
+                let wide = flags(col).contains(Flags::WIDE_CHAR)
+                    && (col + 1 == columns || !flags(col + 1).contains(Flags::WIDE_CHAR_SPACER));
+                let spacer = flags(col).contains(Flags::WIDE_CHAR_SPACER)
+                    && (col == 0 || !flags(col - 1).contains(Flags::WIDE_CHAR));
+                wide || spacer
+            })
+            .collect();
+        // Erased through `Term` so the cells are damaged.
+        for col in split {
+            self.term.grid_mut().cursor.point.column = Column(col);
+            self.term.erase_chars(1);
+        }
+        self.term.grid_mut().cursor.point = cursor;
+    }
+}
+
+/// Forward handler methods to `Term`, ending the current grapheme cluster.
+macro_rules! forward {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $name(&mut self, $($arg: $ty),*) {
+                self.graphemes.reset();
+                self.term.$name($($arg),*);
+            }
+        )*
+    };
+}
+
+impl<T: EventListener> Handler for Interceptor<'_, T> {
+    fn input(&mut self, c: char) {
+        if self.graphemes.joins(c) {
+            self.push_to_previous_cell(c);
+            return;
+        }
+        // Input past the last column wraps and may scroll.
+        self.count_scrolling(|this| {
+            if c.width() == Some(2) && this.term.columns() < 2 {
+                // alacritty writes the spacer of a wide character past the
+                // end of a single column; show a blank in its place, as
+                // reflow does.
+                this.term.input(' ');
+            } else if this.term.mode().contains(TermMode::INSERT) {
+                this.term.input(c);
+                this.blank_split_wide_chars();
+            } else {
+                this.term.input(c);
+            }
+        });
+    }
+
+    fn linefeed(&mut self) {
+        self.graphemes.reset();
+        self.count_scrolling(|this| this.term.linefeed());
+    }
+
+    fn newline(&mut self) {
+        self.graphemes.reset();
+        self.count_scrolling(|this| this.term.newline());
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        self.graphemes.reset();
+        self.count_scrolling(|this| this.term.scroll_up(count));
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        self.graphemes.reset();
+        self.count_scrolling(|this| this.term.delete_lines(count));
+    }
+
+    fn clear_screen(&mut self, mode: ClearMode) {
+        self.graphemes.reset();
+        // alacritty only clears the lines above the cursor when there are two
+        // or more of them, leaving the top line alone with the cursor on the
+        // second.
+        let cursor = self.term.grid().cursor.point;
+        if matches!(mode, ClearMode::Above) && cursor.line == 1 {
+            self.term.grid_mut().cursor.point.line = Line(0);
+            self.term.clear_line(LineClearMode::All);
+            self.term.grid_mut().cursor.point = cursor;
+        }
+        // Clearing the whole screen scrolls its text into the history.
+        self.count_scrolling(|this| this.term.clear_screen(mode));
+        self.blank_split_wide_chars();
+    }
+
+    fn clear_line(&mut self, mode: LineClearMode) {
+        self.graphemes.reset();
+        self.term.clear_line(mode);
+        self.blank_split_wide_chars();
+    }
+
+    fn insert_blank(&mut self, count: usize) {
+        self.graphemes.reset();
+        self.term.insert_blank(count);
+        self.blank_split_wide_chars();
+    }
+
+    fn erase_chars(&mut self, count: usize) {
+        self.graphemes.reset();
+        self.term.erase_chars(count);
+        self.blank_split_wide_chars();
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        self.graphemes.reset();
+        self.term.delete_chars(count);
+        self.blank_split_wide_chars();
+    }
+
+    // The title stack is kept by `VtTerminal`, which sees from the side
+    // scanner whether the title, the icon name or both are meant.
+    fn push_title(&mut self) {
+        self.graphemes.reset();
+    }
+
+    fn pop_title(&mut self) {
+        self.graphemes.reset();
+    }
+
+    forward! {
+        set_title(title: Option<String>);
+        set_cursor_style(style: Option<CursorStyle>);
+        set_cursor_shape(shape: CursorShape);
+        goto(line: i32, col: usize);
+        goto_line(line: i32);
+        goto_col(col: usize);
+        move_up(count: usize);
+        move_down(count: usize);
+        identify_terminal(intermediate: Option<char>);
+        device_status(arg: usize);
+        move_forward(col: usize);
+        move_backward(col: usize);
+        move_down_and_cr(row: usize);
+        move_up_and_cr(row: usize);
+        put_tab(count: u16);
+        backspace();
+        carriage_return();
+        bell();
+        substitute();
+        set_horizontal_tabstop();
+        scroll_down(count: usize);
+        insert_blank_lines(count: usize);
+        move_backward_tabs(count: u16);
+        move_forward_tabs(count: u16);
+        save_cursor_position();
+        restore_cursor_position();
+        clear_tabs(mode: TabulationClearMode);
+        set_tabs(interval: u16);
+        reset_state();
+        reverse_index();
+        terminal_attribute(attr: Attr);
+        set_mode(mode: Mode);
+        unset_mode(mode: Mode);
+        report_mode(mode: Mode);
+        set_private_mode(mode: PrivateMode);
+        unset_private_mode(mode: PrivateMode);
+        report_private_mode(mode: PrivateMode);
+        set_scrolling_region(top: usize, bottom: Option<usize>);
+        set_keypad_application_mode();
+        unset_keypad_application_mode();
+        set_active_charset(index: CharsetIndex);
+        configure_charset(index: CharsetIndex, charset: StandardCharset);
+        set_color(index: usize, color: Rgb);
+        dynamic_color_sequence(prefix: String, index: usize, terminator: &str);
+        reset_color(index: usize);
+        clipboard_store(clipboard: u8, base64: &[u8]);
+        clipboard_load(clipboard: u8, terminator: &str);
+        decaln();
+        text_area_size_pixels();
+        text_area_size_chars();
+        set_hyperlink(hyperlink: Option<Hyperlink>);
+        set_mouse_cursor_icon(icon: CursorIcon);
+        report_keyboard_mode();
+        push_keyboard_mode(mode: KeyboardModes);
+        pop_keyboard_modes(to_pop: u16);
+        set_keyboard_mode(mode: KeyboardModes, behavior: KeyboardModesApplyBehavior);
+        set_modify_other_keys(mode: ModifyOtherKeys);
+        report_modify_other_keys();
+        set_scp(char_path: ScpCharPath, update_mode: ScpUpdateMode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grapheme_joins() {
+        let mut state = GraphemeState::default();
+        let joins: Vec<bool> = "👩\u{200d}👧🇺🇸🇫👍🏽a🏽"
+            .chars()
+            .map(|c| state.joins(c))
+            .collect();
+        assert_eq!(
+            joins,
+            // 👩 ZWJ 👧 🇺 🇸 🇫 👍 🏽 a 🏽
+            [false, false, true, false, true, false, false, true, false, false]
+        );
+
+        // Cursor movement ends the cluster.
+        state.joins('👩');
+        state.joins(ZWJ);
+        state.reset();
+        assert!(!state.joins('👧'));
+    }
+}
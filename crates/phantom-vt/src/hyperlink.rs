@@ -0,0 +1,230 @@
+//! OSC 8 hyperlink tracking.
+//!
+//! alacritty stores the hyperlink of each cell as a shared `(id, uri)` pair.
+//! To keep the per-cell encoding small, each distinct link is interned into a
+//! per-terminal table and cells carry a compact `u16` ID instead. Newly
+//! interned links are queued so the frontend can be told about them before it
+//! receives cells that reference them.
+//!
+//! IDs of links no cell on the grid refers to any more are freed once the
+//! table fills up, and handed out again, so a program that prints many
+//! distinct links does not run out of IDs.
+
+use std::collections::{HashMap, HashSet};
+
+use alacritty_terminal::term::cell::Hyperlink as AlacHyperlink;
+
+/// Suffix alacritty appends to IDs it generates for links without an `id=`.
+const GENERATED_ID_SUFFIX: &str = "_alacritty";
+
+/// A hyperlink defined by an OSC 8 escape sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hyperlink {
+    /// Compact ID referenced by `VtCell::hyperlink_id`. Never 0.
+    pub id: u16,
+    /// Target URI, e.g. `file://host/path/to/file.rs` or `https://...`.
+    pub uri: String,
+    /// The `id=` parameter from the escape sequence, if the program set one.
+    ///
+    /// Cells that share a `link_id` and URI belong to the same link even when
+    /// they are not adjacent (e.g. a link that wraps across lines). This is the
+    /// only parameter defined by the OSC 8 spec.
+    pub link_id: Option<String>,
+}
+
+/// Number of links in use at which unused IDs are first freed.
+const COLLECT_AT: usize = u16::MAX as usize / 4 * 3;
+
+/// Per-terminal table mapping alacritty hyperlinks to compact IDs.
+pub(crate) struct HyperlinkTable {
+    ids: HashMap<AlacHyperlink, u16>,
+    /// The link with ID `i + 1` at index `i`, or `None` if that ID is free.
+    links: Vec<Option<Hyperlink>>,
+    /// Freed IDs, handed out before new ones.
+    free: Vec<u16>,
+    new_links: Vec<Hyperlink>,
+    /// Number of links in use at which `retain` should run next.
+    collect_at: usize,
+}
+
+impl Default for HyperlinkTable {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            links: Vec::new(),
+            free: Vec::new(),
+            new_links: Vec::new(),
+            collect_at: COLLECT_AT,
+        }
+    }
+}
+
+impl HyperlinkTable {
+    /// Get the compact ID for a link, interning it on first use.
+    ///
+    /// Returns 0 (no link) if all 65,535 IDs are in use.
+    pub(crate) fn intern(&mut self, link: &AlacHyperlink) -> u16 {
+        if let Some(&id) = self.ids.get(link) {
+            return id;
+        }
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None if self.links.len() < u16::MAX as usize => {
+                self.links.push(None);
+                self.links.len() as u16
+            }
+            None => return 0,
+        };
+
+        let link_id = Some(link.id())
+            .filter(|id| !id.ends_with(GENERATED_ID_SUFFIX))
+            .map(str::to_string);
+        let entry = Hyperlink {
+            id,
+            uri: link.uri().to_string(),
+            link_id,
+        };
+
+        self.ids.insert(link.clone(), id);
+        self.links[id as usize - 1] = Some(entry.clone());
+        self.new_links.push(entry);
+        id
+    }
+
+    /// Look up a link by compact ID.
+    pub(crate) fn get(&self, id: u16) -> Option<&Hyperlink> {
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|i| self.links.get(i))
+            .and_then(Option::as_ref)
+    }
+
+    /// Whether enough IDs are in use that the unused ones should be freed
+    /// with `retain`.
+    pub(crate) fn needs_collection(&self) -> bool {
+        self.ids.len() >= self.collect_at
+    }
+
+    /// Free the IDs of links not in `live`, the links still on the grid.
+    ///
+    /// The next collection waits until half the IDs left are used up, so a
+    /// grid full of links is not scanned on every write.
+    pub(crate) fn retain(&mut self, live: &HashSet<AlacHyperlink>) {
+        let (links, free) = (&mut self.links, &mut self.free);
+        self.ids.retain(|link, &mut id| {
+            let keep = live.contains(link);
+            if !keep {
+                links[id as usize - 1] = None;
+                free.push(id);
+            }
+            keep
+        });
+        self.new_links
+            .retain(|link| self.links[link.id as usize - 1].as_ref() == Some(link));
+        let in_use = self.ids.len();
+        self.collect_at = COLLECT_AT.max(in_use + (u16::MAX as usize - in_use) / 2);
+    }
+
+    /// Drain links interned since the last call.
+    pub(crate) fn take_new(&mut self) -> Vec<Hyperlink> {
+        std::mem::take(&mut self.new_links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    #[test]
+    fn test_intern_reuses_ids() {
+        let mut table = HyperlinkTable::default();
+        let a = AlacHyperlink::new(Some("a"), "https://a.example".to_string());
+        let b = AlacHyperlink::new(None::<String>, "https://b.example".to_string());
+
+        assert_eq!(table.intern(&a), 1);
+        assert_eq!(table.intern(&b), 2);
+        assert_eq!(table.intern(&a.clone()), 1);
+
+        assert_eq!(table.get(1).unwrap().link_id.as_deref(), Some("a"));
+        assert_eq!(table.get(2).unwrap().link_id, None);
+        assert!(table.get(0).is_none());
+        assert!(table.get(3).is_none());
+
+        let new = table.take_new();
+        assert_eq!(new.len(), 2);
+        assert!(table.take_new().is_empty());
+    }
+
+    #[test]
+    fn test_retain_frees_and_reuses_ids() {
+        let mut table = HyperlinkTable::default();
+        let a = AlacHyperlink::new(None::<String>, "https://a.example".to_string());
+        let b = AlacHyperlink::new(None::<String>, "https://b.example".to_string());
+        let c = AlacHyperlink::new(None::<String>, "https://c.example".to_string());
+        assert_eq!(table.intern(&a), 1);
+        assert_eq!(table.intern(&b), 2);
+        table.take_new();
+
+        table.retain(&HashSet::from([b.clone()]));
+        assert!(table.get(1).is_none());
+        assert_eq!(table.intern(&b), 2);
+
+        // The freed ID goes to the next new link, which is announced again.
+        assert_eq!(table.intern(&c), 1);
+        assert_eq!(table.get(1).unwrap().uri, "https://c.example");
+        assert_eq!(table.take_new(), vec![table.get(1).unwrap().clone()]);
+        assert_eq!(table.intern(&a), 3);
+    }
+
+    #[test]
+    fn test_links_scrolled_off_free_their_ids() {
+        let mut term = VtTerminal::new(40, 5);
+        // Far more distinct links than there are IDs, read as they appear.
+        for i in 0..70_000 {
+            term.write(format!("\x1b]8;;https://example.com/{i}\x07{i}\x1b]8;;\x07\r\n").as_bytes());
+            let cell = term.screen().cell(i.min(3) as u16, 0);
+            assert_ne!(cell.hyperlink_id, 0, "link {i} got no ID");
+            let link = term.hyperlink(cell.hyperlink_id).unwrap();
+            assert_eq!(link.uri, format!("https://example.com/{i}"));
+        }
+    }
+
+    #[test]
+    fn test_osc8_cells_carry_hyperlink_id() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"see \x1b]8;;file:///src/main.rs\x1b\\main.rs\x1b]8;;\x1b\\ here");
+
+        let screen = term.screen();
+        assert_eq!(screen.cell(0, 0).hyperlink_id, 0);
+        let id = screen.cell(0, 4).hyperlink_id;
+        assert_ne!(id, 0);
+        assert_eq!(screen.cell(0, 10).hyperlink_id, id);
+        assert_eq!(screen.cell(0, 11).hyperlink_id, 0);
+
+        let link = term.hyperlink(id).unwrap();
+        assert_eq!(link.uri, "file:///src/main.rs");
+        assert_eq!(link.link_id, None);
+
+        let new = term.take_new_hyperlinks();
+        assert_eq!(new, vec![link]);
+        assert!(term.take_new_hyperlinks().is_empty());
+    }
+
+    #[test]
+    fn test_osc8_explicit_id_param() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"\x1b]8;id=err1;https://example.com/e1\x07one\x1b]8;;\x07");
+        term.write(b" \x1b]8;id=err1;https://example.com/e1\x07two\x1b]8;;\x07");
+
+        let first = term.screen().cell(0, 0).hyperlink_id;
+        let second = term.screen().cell(0, 4).hyperlink_id;
+
+        // Same id and URI: the same link.
+        assert_eq!(first, second);
+        assert_eq!(
+            term.hyperlink(first).unwrap().link_id.as_deref(),
+            Some("err1")
+        );
+    }
+}
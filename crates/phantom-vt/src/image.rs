@@ -0,0 +1,398 @@
+//! Inline images shown in the grid (kitty graphics protocol and sixel).
+//!
+//! Images are decoded to RGBA once and placed on the grid any number of
+//! times. A placement on the primary screen is anchored to an absolute line
+//! number, so it scrolls with the text around it and is dropped when that
+//! text leaves the history. Decoded pixels are kept under a memory budget by
+//! evicting the oldest images first.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Default memory budget for decoded image pixels.
+pub const DEFAULT_IMAGE_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Largest width or height accepted for an image, in pixels.
+pub(crate) const MAX_IMAGE_DIMENSION: u32 = 10_000;
+
+/// A decoded image.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Image {
+    /// ID of the image, unique within its terminal.
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels as 8-bit RGBA, row-major.
+    pub rgba: Vec<u8>,
+}
+
+/// An image shown at a place in the grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImagePlacement {
+    /// The image shown; look it up with `VtTerminal::image`.
+    pub image_id: u64,
+    /// Grid line of the top edge, indexed like `ScreenView::line_cell`.
+    pub line: i32,
+    /// Column of the left edge.
+    pub col: u16,
+    /// Number of columns the image is scaled to cover.
+    pub cols: u16,
+    /// Number of rows the image is scaled to cover.
+    pub rows: u16,
+    /// Stacking order; negative values are drawn below the text.
+    pub z: i32,
+}
+
+/// Why an image could not be decoded or placed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ImageError {
+    /// The image does not fit in the size limits or the memory budget.
+    TooLarge,
+    /// The data or command is malformed.
+    Invalid(&'static str),
+    /// The command refers to an image that is not stored.
+    NotFound,
+    /// The command asks for something not implemented.
+    Unsupported(&'static str),
+}
+
+impl ImageError {
+    /// POSIX-style error name used in kitty graphics replies.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            ImageError::TooLarge => "EFBIG",
+            ImageError::Invalid(_) | ImageError::Unsupported(_) => "EINVAL",
+            ImageError::NotFound => "ENOENT",
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::TooLarge => write!(f, "image too large"),
+            ImageError::Invalid(what) => write!(f, "invalid {what}"),
+            ImageError::NotFound => write!(f, "image not found"),
+            ImageError::Unsupported(what) => write!(f, "unsupported {what}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Image pixels before they are stored.
+#[derive(Debug)]
+pub(crate) struct DecodedImage {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) rgba: Vec<u8>,
+}
+
+/// Where a placement is anchored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Anchor {
+    /// Absolute line on the primary screen (see `VtTerminal::scrolled_lines`),
+    /// or the screen line on the alternate screen, which has no history.
+    pub(crate) line: i64,
+    pub(crate) col: u16,
+    pub(crate) alt_screen: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Placement {
+    image_id: u64,
+    /// kitty placement ID, 0 if none was given.
+    placement_id: u32,
+    anchor: Anchor,
+    cols: u16,
+    rows: u16,
+    z: i32,
+}
+
+struct StoredImage {
+    image: Arc<Image>,
+    /// kitty image ID the program refers to the image by. Images without one
+    /// (sixel) cannot be placed again and go away with their placement.
+    kitty_id: Option<u32>,
+}
+
+/// Decoded images and their placements.
+pub(crate) struct ImageStore {
+    /// Images by ID; IDs increase, so iteration is oldest first.
+    images: BTreeMap<u64, StoredImage>,
+    placements: Vec<Placement>,
+    next_id: u64,
+    /// Bytes of pixel data held.
+    used: usize,
+    budget: usize,
+}
+
+impl Default for ImageStore {
+    fn default() -> Self {
+        Self {
+            images: BTreeMap::new(),
+            placements: Vec::new(),
+            next_id: 1,
+            used: 0,
+            budget: DEFAULT_IMAGE_BUDGET,
+        }
+    }
+}
+
+impl ImageStore {
+    /// Bytes of pixel data that may be held.
+    pub(crate) fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Change the memory budget, evicting images to fit.
+    pub(crate) fn set_budget(&mut self, bytes: usize) {
+        self.budget = bytes;
+        self.evict(None);
+    }
+
+    /// Store an image, replacing the one with the same kitty ID. Older
+    /// images are evicted to stay within the budget.
+    pub(crate) fn insert(
+        &mut self,
+        image: DecodedImage,
+        kitty_id: Option<u32>,
+    ) -> Result<u64, ImageError> {
+        if image.rgba.len() > self.budget {
+            return Err(ImageError::TooLarge);
+        }
+        if let Some(old) = kitty_id.and_then(|k| self.find_kitty(k)) {
+            self.remove_image(old);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.used += image.rgba.len();
+        let image = Arc::new(Image {
+            id,
+            width: image.width,
+            height: image.height,
+            rgba: image.rgba,
+        });
+        self.images.insert(id, StoredImage { image, kitty_id });
+        self.evict(Some(id));
+        Ok(id)
+    }
+
+    /// Drop the oldest images, other than `keep`, until within budget.
+    /// Images that are not shown anywhere go first.
+    fn evict(&mut self, keep: Option<u64>) {
+        while self.used > self.budget {
+            let candidates = || self.images.keys().copied().filter(|&id| Some(id) != keep);
+            let unplaced = candidates().find(|&id| !self.is_placed(id));
+            let Some(victim) = unplaced.or_else(|| candidates().next()) else {
+                break;
+            };
+            self.remove_image(victim);
+        }
+    }
+
+    fn is_placed(&self, id: u64) -> bool {
+        self.placements.iter().any(|p| p.image_id == id)
+    }
+
+    /// The stored image a kitty image ID refers to.
+    pub(crate) fn find_kitty(&self, kitty_id: u32) -> Option<u64> {
+        self.images
+            .iter()
+            .find(|(_, stored)| stored.kitty_id == Some(kitty_id))
+            .map(|(&id, _)| id)
+    }
+
+    /// Look up an image by ID.
+    pub(crate) fn get(&self, id: u64) -> Option<Arc<Image>> {
+        self.images.get(&id).map(|stored| stored.image.clone())
+    }
+
+    /// Remove an image and its placements.
+    pub(crate) fn remove_image(&mut self, id: u64) {
+        if let Some(stored) = self.images.remove(&id) {
+            self.used -= stored.image.rgba.len();
+        }
+        self.retain_placements(|p| p.image_id != id);
+    }
+
+    /// Show an image. A kitty placement ID replaces the earlier placement of
+    /// the same image with that ID.
+    pub(crate) fn place(
+        &mut self,
+        image_id: u64,
+        placement_id: u32,
+        anchor: Anchor,
+        (cols, rows): (u16, u16),
+        z: i32,
+    ) {
+        if placement_id != 0 {
+            self.placements
+                .retain(|p| p.image_id != image_id || p.placement_id != placement_id);
+        }
+        self.placements.push(Placement {
+            image_id,
+            placement_id,
+            anchor,
+            cols,
+            rows,
+            z,
+        });
+    }
+
+    /// Delete placements of one image, or of all images for `None`. With
+    /// `free`, the images themselves are deleted once no longer shown.
+    pub(crate) fn delete(&mut self, image_id: Option<u64>, placement_id: u32, free: bool) {
+        self.retain_placements(|p| {
+            let image_matches = image_id.is_none_or(|id| p.image_id == id);
+            let placement_matches = placement_id == 0 || p.placement_id == placement_id;
+            !(image_matches && placement_matches)
+        });
+        if free {
+            let unplaced: Vec<u64> = self
+                .images
+                .keys()
+                .copied()
+                .filter(|&id| image_id.is_none_or(|i| i == id) && !self.is_placed(id))
+                .collect();
+            for id in unplaced {
+                self.remove_image(id);
+            }
+        }
+    }
+
+    /// Drop primary screen placements that end above absolute line
+    /// `topmost`, whose text is no longer in the history.
+    pub(crate) fn prune(&mut self, topmost: i64) {
+        self.retain_placements(|p| p.anchor.alt_screen || p.anchor.line + p.rows as i64 > topmost);
+    }
+
+    /// Drop the placements of the alternate screen, which is cleared when
+    /// left.
+    pub(crate) fn clear_alt_screen(&mut self) {
+        self.retain_placements(|p| !p.anchor.alt_screen);
+    }
+
+    /// Drop everything (full reset).
+    pub(crate) fn clear(&mut self) {
+        self.images.clear();
+        self.placements.clear();
+        self.used = 0;
+    }
+
+    /// Keep the placements matching `keep`. Images that cannot be placed
+    /// again are dropped along with their last placement.
+    fn retain_placements(&mut self, keep: impl Fn(&Placement) -> bool) {
+        let mut removed = Vec::new();
+        self.placements.retain(|p| {
+            let kept = keep(p);
+            if !kept {
+                removed.push(p.image_id);
+            }
+            kept
+        });
+
+        for id in removed {
+            let anonymous = self.images.get(&id).is_some_and(|s| s.kitty_id.is_none());
+            if anonymous && !self.is_placed(id) {
+                if let Some(stored) = self.images.remove(&id) {
+                    self.used -= stored.image.rgba.len();
+                }
+            }
+        }
+    }
+
+    /// Placements on the current screen, in grid lines.
+    pub(crate) fn placements(&self, alt_screen: bool, scrolled_lines: i64) -> Vec<ImagePlacement> {
+        self.placements
+            .iter()
+            .filter(|p| p.anchor.alt_screen == alt_screen)
+            .map(|p| {
+                let line = if alt_screen {
+                    p.anchor.line
+                } else {
+                    p.anchor.line - scrolled_lines
+                };
+                ImagePlacement {
+                    image_id: p.image_id,
+                    line: line as i32,
+                    col: p.anchor.col,
+                    cols: p.cols,
+                    rows: p.rows,
+                    z: p.z,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(bytes: usize) -> DecodedImage {
+        DecodedImage {
+            width: 1,
+            height: 1,
+            rgba: vec![0; bytes],
+        }
+    }
+
+    fn anchor(line: i64) -> Anchor {
+        Anchor {
+            line,
+            col: 0,
+            alt_screen: false,
+        }
+    }
+
+    #[test]
+    fn test_eviction_under_budget() {
+        let mut store = ImageStore::default();
+        store.set_budget(100);
+
+        let a = store.insert(image(40), Some(1)).unwrap();
+        let b = store.insert(image(40), Some(2)).unwrap();
+        store.place(a, 0, anchor(0), (1, 1), 0);
+
+        // Over budget: the unplaced image goes first, even though it is
+        // newer than the placed one.
+        let c = store.insert(image(40), Some(3)).unwrap();
+        assert!(store.get(a).is_some());
+        assert!(store.get(b).is_none());
+        assert!(store.get(c).is_some());
+
+        // Then the oldest, along with its placement.
+        store.place(c, 0, anchor(1), (1, 1), 0);
+        let d = store.insert(image(40), None).unwrap();
+        assert!(store.get(a).is_none());
+        assert_eq!(store.placements(false, 0).len(), 1);
+        assert!(store.get(d).is_some());
+
+        assert_eq!(store.insert(image(101), None), Err(ImageError::TooLarge));
+    }
+
+    #[test]
+    fn test_placements_scroll_and_prune() {
+        let mut store = ImageStore::default();
+        let kitty = store.insert(image(4), Some(7)).unwrap();
+        let sixel = store.insert(image(4), None).unwrap();
+        store.place(kitty, 0, anchor(10), (4, 2), 0);
+        store.place(sixel, 0, anchor(20), (4, 2), -1);
+
+        // Grid lines follow scrolling.
+        let lines: Vec<i32> = store.placements(false, 15).iter().map(|p| p.line).collect();
+        assert_eq!(lines, vec![-5, 5]);
+
+        // Pruned once their last line leaves the history; the sixel image
+        // cannot be placed again, so it is freed.
+        store.prune(12);
+        assert_eq!(store.placements(false, 15).len(), 1);
+        store.prune(22);
+        assert!(store.placements(false, 15).is_empty());
+        assert!(store.get(kitty).is_some());
+        assert!(store.get(sixel).is_none());
+    }
+}
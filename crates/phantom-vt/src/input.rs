@@ -0,0 +1,294 @@
+//! Encoding of user input into the bytes a program expects.
+//!
+//! The frontend reports abstract events (a button at a cell, with modifiers);
+//! how they must be sent depends on the modes the program has set, which only
+//! the terminal knows.
+
+use bitflags::bitflags;
+
+use crate::modes::{MouseEncoding, MouseTracking, TerminalModes};
+
+bitflags! {
+    /// Modifier keys held during an input event.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 0b0001;
+        const ALT   = 0b0010;
+        const CTRL  = 0b0100;
+        const SUPER = 0b1000;
+    }
+}
+
+/// A mouse button, or `None` for motion with no button held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    None,
+}
+
+impl MouseButton {
+    fn is_wheel(self) -> bool {
+        matches!(
+            self,
+            MouseButton::WheelUp
+                | MouseButton::WheelDown
+                | MouseButton::WheelLeft
+                | MouseButton::WheelRight
+        )
+    }
+
+    /// Button number in the xterm protocol.
+    fn code(self) -> u32 {
+        match self {
+            MouseButton::Left => 0,
+            MouseButton::Middle => 1,
+            MouseButton::Right => 2,
+            MouseButton::None => 3,
+            MouseButton::WheelUp => 64,
+            MouseButton::WheelDown => 65,
+            MouseButton::WheelLeft => 66,
+            MouseButton::WheelRight => 67,
+        }
+    }
+}
+
+/// What happened to the mouse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseEventKind {
+    Press,
+    Release,
+    /// The pointer moved to a different cell.
+    Motion,
+}
+
+/// A mouse event at a cell of the viewport.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    pub button: MouseButton,
+    pub modifiers: Modifiers,
+    /// Viewport row, 0-based.
+    pub row: u16,
+    /// Viewport column, 0-based.
+    pub col: u16,
+}
+
+/// Encode a mouse event for the program, given the terminal's modes.
+///
+/// Returns `None` if the program did not ask for this event, or if its
+/// position cannot be represented in the active encoding. With mouse
+/// reporting off, wheel events on the alternate screen become arrow keys when
+/// alternate scroll mode is set.
+pub fn encode_mouse(event: &MouseEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    use MouseEventKind::*;
+
+    let reported = match (modes.mouse_tracking, event.kind) {
+        (MouseTracking::None, _) => return encode_alternate_scroll(event, modes),
+        (MouseTracking::X10, Press) => matches!(
+            event.button,
+            MouseButton::Left | MouseButton::Middle | MouseButton::Right
+        ),
+        (MouseTracking::X10, _) => false,
+        (_, Press) => event.button != MouseButton::None,
+        (_, Release) => event.button != MouseButton::None && !event.button.is_wheel(),
+        (MouseTracking::ButtonEvent, Motion) => event.button != MouseButton::None,
+        (MouseTracking::AnyEvent, Motion) => true,
+        (MouseTracking::Normal, Motion) => false,
+    };
+    if !reported {
+        return None;
+    }
+
+    let mut code = event.button.code();
+    if event.kind == Motion {
+        code += 32;
+    }
+    // X10 mode reports no modifiers.
+    if modes.mouse_tracking != MouseTracking::X10 {
+        if event.modifiers.contains(Modifiers::SHIFT) {
+            code += 4;
+        }
+        if event.modifiers.contains(Modifiers::ALT) {
+            code += 8;
+        }
+        if event.modifiers.contains(Modifiers::CTRL) {
+            code += 16;
+        }
+    }
+
+    let x = event.col as u32 + 1;
+    let y = event.row as u32 + 1;
+    if modes.mouse_encoding == MouseEncoding::Sgr {
+        let action = if event.kind == Release { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{code};{x};{y}{action}").into_bytes());
+    }
+
+    // The legacy encodings cannot tell which button was released.
+    if event.kind == Release {
+        code = (code & !0b11) | 3;
+    }
+    let mut bytes = b"\x1b[M".to_vec();
+    bytes.push(32 + code as u8);
+    for pos in [x, y] {
+        let value = 32 + pos;
+        match modes.mouse_encoding {
+            MouseEncoding::Utf8 => {
+                let c = char::from_u32(value).filter(|_| value < 0x800)?;
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            _ => bytes.push(u8::try_from(value).ok()?),
+        }
+    }
+    Some(bytes)
+}
+
+/// Turn a wheel press into a cursor key, as mode 1007 asks.
+fn encode_alternate_scroll(event: &MouseEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    if !(modes.alt_screen && modes.alternate_scroll) || event.kind != MouseEventKind::Press {
+        return None;
+    }
+    let key = match event.button {
+        MouseButton::WheelUp => b'A',
+        MouseButton::WheelDown => b'B',
+        _ => return None,
+    };
+    let prefix = if modes.app_cursor { b'O' } else { b'[' };
+    Some(vec![0x1b, prefix, key])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    fn event(kind: MouseEventKind, button: MouseButton, row: u16, col: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            button,
+            modifiers: Modifiers::empty(),
+            row,
+            col,
+        }
+    }
+
+    fn modes(tracking: MouseTracking, encoding: MouseEncoding) -> TerminalModes {
+        TerminalModes {
+            mouse_tracking: tracking,
+            mouse_encoding: encoding,
+            ..TerminalModes::default()
+        }
+    }
+
+    #[test]
+    fn test_default_encoding() {
+        let modes = modes(MouseTracking::Normal, MouseEncoding::Default);
+        let press = event(MouseEventKind::Press, MouseButton::Left, 0, 0);
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[M !!");
+
+        let release = MouseEvent {
+            modifiers: Modifiers::CTRL,
+            ..event(MouseEventKind::Release, MouseButton::Right, 4, 9)
+        };
+        assert_eq!(encode_mouse(&release, &modes).unwrap(), b"\x1b[M3*%");
+
+        // Motion is not reported in normal tracking mode.
+        let motion = event(MouseEventKind::Motion, MouseButton::Left, 1, 1);
+        assert_eq!(encode_mouse(&motion, &modes), None);
+
+        // Positions past column 223 do not fit in a byte.
+        let far = event(MouseEventKind::Press, MouseButton::Left, 0, 223);
+        assert_eq!(encode_mouse(&far, &modes), None);
+    }
+
+    #[test]
+    fn test_sgr_encoding() {
+        let modes = modes(MouseTracking::ButtonEvent, MouseEncoding::Sgr);
+        let release = MouseEvent {
+            modifiers: Modifiers::SHIFT | Modifiers::ALT,
+            ..event(MouseEventKind::Release, MouseButton::Middle, 299, 499)
+        };
+        assert_eq!(
+            encode_mouse(&release, &modes).unwrap(),
+            b"\x1b[<13;500;300m"
+        );
+
+        let drag = event(MouseEventKind::Motion, MouseButton::Left, 2, 3);
+        assert_eq!(encode_mouse(&drag, &modes).unwrap(), b"\x1b[<32;4;3M");
+        let hover = event(MouseEventKind::Motion, MouseButton::None, 2, 3);
+        assert_eq!(encode_mouse(&hover, &modes), None);
+
+        let wheel = event(MouseEventKind::Press, MouseButton::WheelDown, 0, 0);
+        assert_eq!(encode_mouse(&wheel, &modes).unwrap(), b"\x1b[<65;1;1M");
+        let wheel_release = event(MouseEventKind::Release, MouseButton::WheelDown, 0, 0);
+        assert_eq!(encode_mouse(&wheel_release, &modes), None);
+    }
+
+    #[test]
+    fn test_utf8_and_x10_encoding() {
+        let utf8 = modes(MouseTracking::AnyEvent, MouseEncoding::Utf8);
+        let hover = event(MouseEventKind::Motion, MouseButton::None, 0, 299);
+        // Column 300 is sent as U+014C.
+        assert_eq!(
+            encode_mouse(&hover, &utf8).unwrap(),
+            "\x1b[MC\u{14c}!".as_bytes()
+        );
+
+        let x10 = modes(MouseTracking::X10, MouseEncoding::Default);
+        let press = MouseEvent {
+            modifiers: Modifiers::CTRL,
+            ..event(MouseEventKind::Press, MouseButton::Left, 0, 0)
+        };
+        assert_eq!(encode_mouse(&press, &x10).unwrap(), b"\x1b[M !!");
+        let release = event(MouseEventKind::Release, MouseButton::Left, 0, 0);
+        assert_eq!(encode_mouse(&release, &x10), None);
+    }
+
+    #[test]
+    fn test_alternate_scroll() {
+        let wheel = event(MouseEventKind::Press, MouseButton::WheelUp, 0, 0);
+        let mut modes = TerminalModes {
+            alternate_scroll: true,
+            ..TerminalModes::default()
+        };
+        assert_eq!(encode_mouse(&wheel, &modes), None);
+
+        modes.alt_screen = true;
+        assert_eq!(encode_mouse(&wheel, &modes).unwrap(), b"\x1b[A");
+        modes.app_cursor = true;
+        assert_eq!(encode_mouse(&wheel, &modes).unwrap(), b"\x1bOA");
+    }
+
+    #[test]
+    fn test_modes_follow_program() {
+        let mut term = VtTerminal::new(80, 24);
+        assert_eq!(
+            term.modes(),
+            TerminalModes {
+                alternate_scroll: true,
+                ..TerminalModes::default()
+            }
+        );
+
+        term.write(b"\x1b[?1002h\x1b[?1006h\x1b[?1h\x1b[?2004h\x1b[?1004h\x1b[?1049h");
+        let modes = term.modes();
+        assert_eq!(modes.mouse_tracking, MouseTracking::ButtonEvent);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Sgr);
+        assert!(modes.app_cursor && modes.bracketed_paste && modes.focus_reporting);
+        assert!(modes.alt_screen);
+
+        let press = event(MouseEventKind::Press, MouseButton::Left, 1, 1);
+        assert_eq!(term.encode_mouse(&press).unwrap(), b"\x1b[<0;2;2M");
+
+        term.write(b"\x1b[?1002l\x1b[?9h");
+        assert_eq!(term.modes().mouse_tracking, MouseTracking::X10);
+        term.write(b"\x1bc");
+        assert_eq!(term.modes().mouse_tracking, MouseTracking::None);
+    }
+}
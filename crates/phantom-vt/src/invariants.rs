@@ -0,0 +1,99 @@
+//! Checks of the state a renderer relies on, shared by the randomized tests
+//! and the fuzz targets.
+//!
+//! The checks return the first problem found instead of panicking, so the
+//! caller can say what input led to it.
+
+use crate::cell::VtCell;
+use crate::screen::DamageInfo;
+use crate::terminal::VtTerminal;
+
+/// Check the widths of a row's cells: every wide character (width 2) is
+/// followed by its spacer (width 0), and every spacer follows a wide
+/// character.
+pub fn check_widths(widths: &[u8]) -> Result<(), String> {
+    for (col, &width) in widths.iter().enumerate() {
+        match width {
+            2 if widths.get(col + 1) != Some(&0) => {
+                return Err(format!("wide character at column {col} has no spacer"));
+            }
+            0 if col == 0 || widths[col - 1] != 2 => {
+                return Err(format!("spacer at column {col} follows no wide character"));
+            }
+            0..=2 => {}
+            width => return Err(format!("width {width} at column {col}")),
+        }
+    }
+    Ok(())
+}
+
+/// Check a row of cells with [`check_widths`].
+pub fn check_row(cells: &[VtCell]) -> Result<(), String> {
+    let widths: Vec<u8> = cells.iter().map(|cell| cell.width).collect();
+    check_widths(&widths)
+}
+
+/// Check the screen, the oldest lines of the scrollback, the cursor and the
+/// damaged region, then reset the damage.
+pub fn check_terminal(vt: &mut VtTerminal) -> Result<(), String> {
+    let screen = vt.screen();
+    let (cols, rows) = (screen.cols(), screen.rows());
+    if cols == 0 || rows == 0 {
+        return Err(format!("{cols}x{rows} screen"));
+    }
+
+    for row in 0..rows {
+        check_row(&screen.row_cells(row)).map_err(|e| format!("row {row}: {e}"))?;
+    }
+    // And the oldest lines, which the viewport rarely shows.
+    for index in (0..screen.total_lines()).take(5) {
+        let cells = screen
+            .history_line_cells(index)
+            .ok_or_else(|| format!("line {index} of {} is missing", screen.total_lines()))?;
+        check_row(&cells).map_err(|e| format!("line {index}: {e}"))?;
+    }
+
+    let cursor = vt.cursor();
+    if cursor.col >= cols {
+        return Err(format!("cursor column {} of {cols}", cursor.col));
+    }
+    if cursor.visible && cursor.row >= rows {
+        return Err(format!("cursor row {} of {rows}", cursor.row));
+    }
+
+    if let DamageInfo::Partial(damaged) = vt.damage() {
+        for d in damaged {
+            if d.row >= rows {
+                return Err(format!("damaged row {} of {rows}", d.row));
+            }
+            if d.left > d.right || d.right >= cols {
+                return Err(format!(
+                    "damaged columns {}..={} of {cols}",
+                    d.left, d.right
+                ));
+            }
+        }
+    }
+    vt.reset_damage();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_widths() {
+        assert!(check_widths(&[1, 2, 0, 1]).is_ok());
+        assert!(check_widths(&[]).is_ok());
+        assert_eq!(
+            check_widths(&[1, 2]),
+            Err("wide character at column 1 has no spacer".to_string())
+        );
+        assert_eq!(
+            check_widths(&[1, 0]),
+            Err("spacer at column 1 follows no wide character".to_string())
+        );
+        assert_eq!(check_widths(&[3]), Err("width 3 at column 0".to_string()));
+    }
+}
@@ -0,0 +1,672 @@
+//! Encoding of key events into the bytes a program expects.
+//!
+//! The same key press must be sent differently depending on the modes the
+//! program has set: cursor keys follow DECCKM, the keypad follows DECKPAM,
+//! xterm's modifyOtherKeys reports modified keys as `CSI 27 ; m ; code ~`,
+//! and the kitty keyboard protocol replaces most legacy sequences with
+//! unambiguous `CSI code ; mods u` forms.
+
+use crate::input::Modifiers;
+use crate::modes::{KittyKeyboardFlags, TerminalModes};
+
+/// A logical key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// A key that types a character, e.g. `'a'` or `'['`. Letters should be
+    /// given unshifted; what the key types with the current modifiers and
+    /// layout goes in `KeyEvent::text`.
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// Function key F1 to F20.
+    F(u8),
+    Keypad(KeypadKey),
+}
+
+/// A key on the numeric keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeypadKey {
+    /// Digit 0 to 9.
+    Digit(u8),
+    Decimal,
+    Divide,
+    Multiply,
+    Subtract,
+    Add,
+    Enter,
+    Equal,
+}
+
+impl KeypadKey {
+    /// The character the key types with Num Lock on.
+    fn char(self) -> char {
+        match self {
+            KeypadKey::Digit(d) => char::from(b'0' + d.min(9)),
+            KeypadKey::Decimal => '.',
+            KeypadKey::Divide => '/',
+            KeypadKey::Multiply => '*',
+            KeypadKey::Subtract => '-',
+            KeypadKey::Add => '+',
+            KeypadKey::Enter => '\r',
+            KeypadKey::Equal => '=',
+        }
+    }
+
+    /// Final byte of the SS3 sequence sent in application keypad mode.
+    fn app_final(self) -> u8 {
+        match self {
+            KeypadKey::Digit(d) => b'p' + d.min(9),
+            KeypadKey::Decimal => b'n',
+            KeypadKey::Divide => b'o',
+            KeypadKey::Multiply => b'j',
+            KeypadKey::Subtract => b'm',
+            KeypadKey::Add => b'k',
+            KeypadKey::Enter => b'M',
+            KeypadKey::Equal => b'X',
+        }
+    }
+
+    /// Key code in the kitty keyboard protocol.
+    fn kitty_code(self) -> u32 {
+        match self {
+            KeypadKey::Digit(d) => 57399 + d.min(9) as u32,
+            KeypadKey::Decimal => 57409,
+            KeypadKey::Divide => 57410,
+            KeypadKey::Multiply => 57411,
+            KeypadKey::Subtract => 57412,
+            KeypadKey::Add => 57413,
+            KeypadKey::Enter => 57414,
+            KeypadKey::Equal => 57415,
+        }
+    }
+}
+
+/// Whether a key went down, auto-repeated, or went up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyEventKind {
+    #[default]
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key event from the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: Modifiers,
+    /// Text the key types, with shift and the keyboard layout applied (e.g.
+    /// `"A"` for Shift+a). `None` for keys that do not type text.
+    pub text: Option<String>,
+    pub kind: KeyEventKind,
+}
+
+/// Encode a key event for the program, given the terminal's modes.
+///
+/// Returns `None` if nothing should be sent, e.g. for key releases when the
+/// program did not ask for them.
+pub fn encode_key(event: &KeyEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    if !modes.kitty_keyboard.is_empty() {
+        return encode_kitty(event, modes.kitty_keyboard);
+    }
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    encode_legacy(event, modes)
+}
+
+/// The xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4) + super(8).
+fn modifier_param(mods: Modifiers) -> u8 {
+    1 + mods.bits()
+}
+
+/// Text typed by the event, if it is printable.
+fn printable_text(event: &KeyEvent) -> Option<&str> {
+    event
+        .text
+        .as_deref()
+        .filter(|t| !t.is_empty() && !t.chars().any(char::is_control))
+}
+
+/// Prefix `bytes` with ESC if Alt is held.
+fn alt_prefixed(mut bytes: Vec<u8>, mods: Modifiers) -> Vec<u8> {
+    if mods.contains(Modifiers::ALT) {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// The C0 control code for Ctrl+`c`, following xterm.
+fn ctrl_code(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' | 'A'..='Z' => Some(c as u8 & 0x1f),
+        '@' | ' ' | '2' => Some(0),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '-' | '/' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+/// `CSI 27 ; mods ; code ~`, as sent when modifyOtherKeys is active.
+fn modify_other_keys_seq(code: u32, mods: Modifiers) -> Vec<u8> {
+    format!("\x1b[27;{};{code}~", modifier_param(mods)).into_bytes()
+}
+
+/// Whether modifyOtherKeys asks for this key to be reported in its own form.
+fn use_modify_other_keys(level: u8, ambiguous: bool, mods: Modifiers) -> bool {
+    let modified = mods.intersects(Modifiers::CTRL | Modifiers::ALT);
+    match level {
+        1 => modified && ambiguous,
+        2 => modified,
+        _ => false,
+    }
+}
+
+fn encode_legacy(event: &KeyEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    // Super is never part of legacy sequences; only the window system sees it.
+    let mods = event.modifiers - Modifiers::SUPER;
+    let level = modes.modify_other_keys;
+
+    let bytes = match event.key {
+        Key::Char(c) => {
+            let text = printable_text(event)
+                .map(str::to_string)
+                .unwrap_or_else(|| c.to_string());
+            let code = text
+                .chars()
+                .next()
+                .filter(|_| text.chars().count() == 1)
+                .unwrap_or(c);
+            // Ctrl+key has no encoding of its own beyond the C0 codes, and
+            // Ctrl+Shift+letter is indistinguishable from Ctrl+letter.
+            let ambiguous = mods.contains(Modifiers::CTRL)
+                && (ctrl_code(c).is_none()
+                    || (mods.contains(Modifiers::SHIFT) && c.is_ascii_alphabetic()));
+            if use_modify_other_keys(level, ambiguous, mods) {
+                return Some(modify_other_keys_seq(code as u32, mods));
+            }
+            let bytes = match ctrl_code(c).filter(|_| mods.contains(Modifiers::CTRL)) {
+                Some(byte) => vec![byte],
+                None => text.into_bytes(),
+            };
+            alt_prefixed(bytes, mods)
+        }
+        Key::Enter | Key::Tab | Key::Backspace | Key::Escape => {
+            let code = match event.key {
+                Key::Enter => 0x0d,
+                Key::Tab => 0x09,
+                Key::Backspace => 0x7f,
+                _ => 0x1b,
+            };
+            // Level 1 leaves the forms with a well-known meaning alone: Alt's
+            // ESC prefix and Shift+Tab.
+            let back_tab = event.key == Key::Tab && mods == Modifiers::SHIFT;
+            let reported = match level {
+                1 => mods.intersects(Modifiers::CTRL | Modifiers::SHIFT) && !back_tab,
+                2 => !mods.is_empty() && !back_tab,
+                _ => false,
+            };
+            if reported {
+                return Some(modify_other_keys_seq(code as u32, mods));
+            }
+            let bytes = match event.key {
+                Key::Tab if mods.contains(Modifiers::SHIFT) => b"\x1b[Z".to_vec(),
+                Key::Backspace if mods.contains(Modifiers::CTRL) => vec![0x08],
+                _ => vec![code],
+            };
+            alt_prefixed(bytes, mods)
+        }
+        Key::Up | Key::Down | Key::Right | Key::Left | Key::Home | Key::End => {
+            let letter = cursor_key_final(event.key);
+            if mods.is_empty() {
+                let intro = if modes.app_cursor { b'O' } else { b'[' };
+                vec![0x1b, intro, letter]
+            } else {
+                format!("\x1b[1;{}{}", modifier_param(mods), letter as char).into_bytes()
+            }
+        }
+        Key::Insert | Key::Delete | Key::PageUp | Key::PageDown => {
+            tilde_seq(tilde_key_number(event.key), mods)
+        }
+        Key::F(n @ 1..=4) => {
+            let letter = b"PQRS"[n as usize - 1];
+            if mods.is_empty() {
+                vec![0x1b, b'O', letter]
+            } else {
+                format!("\x1b[1;{}{}", modifier_param(mods), letter as char).into_bytes()
+            }
+        }
+        Key::F(n) => tilde_seq(function_key_number(n)?, mods),
+        Key::Keypad(key) => {
+            if modes.app_keypad {
+                vec![0x1b, b'O', key.app_final()]
+            } else {
+                alt_prefixed(vec![key.char() as u8], mods)
+            }
+        }
+    };
+    Some(bytes)
+}
+
+/// Final byte of a cursor-movement key.
+fn cursor_key_final(key: Key) -> u8 {
+    match key {
+        Key::Up => b'A',
+        Key::Down => b'B',
+        Key::Right => b'C',
+        Key::Left => b'D',
+        Key::Home => b'H',
+        _ => b'F',
+    }
+}
+
+/// Number of an editing key sent as `CSI n ~`.
+fn tilde_key_number(key: Key) -> u8 {
+    match key {
+        Key::Insert => 2,
+        Key::Delete => 3,
+        Key::PageUp => 5,
+        _ => 6,
+    }
+}
+
+/// Number of F5 to F20 in `CSI n ~` sequences.
+fn function_key_number(n: u8) -> Option<u8> {
+    const NUMBERS: [u8; 16] = [
+        15, 17, 18, 19, 20, 21, 23, 24, 25, 26, 28, 29, 31, 32, 33, 34,
+    ];
+    NUMBERS.get((n as usize).checked_sub(5)?).copied()
+}
+
+fn tilde_seq(number: u8, mods: Modifiers) -> Vec<u8> {
+    if mods.is_empty() {
+        format!("\x1b[{number}~").into_bytes()
+    } else {
+        format!("\x1b[{number};{}~", modifier_param(mods)).into_bytes()
+    }
+}
+
+fn encode_kitty(event: &KeyEvent, flags: KittyKeyboardFlags) -> Option<Vec<u8>> {
+    let report_all = flags.contains(KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESC);
+    let kind = if flags.contains(KittyKeyboardFlags::REPORT_EVENT_TYPES) {
+        event.kind
+    } else if event.kind == KeyEventKind::Release {
+        return None;
+    } else {
+        KeyEventKind::Press
+    };
+    let mods = event.modifiers;
+    let text = printable_text(event);
+
+    if !report_all {
+        // Text keys typed with at most Shift are sent as plain text.
+        let types_text = matches!(event.key, Key::Char(_) | Key::Keypad(_)) && text.is_some();
+        if types_text && (mods - Modifiers::SHIFT).is_empty() && kind != KeyEventKind::Release {
+            return text.map(|t| t.as_bytes().to_vec());
+        }
+        // Unmodified Enter, Tab and Backspace keep their legacy bytes, so a
+        // shell stays usable if a program exits without resetting the mode.
+        if matches!(event.key, Key::Enter | Key::Tab | Key::Backspace) && mods.is_empty() {
+            if kind == KeyEventKind::Release {
+                return None;
+            }
+            let byte = match event.key {
+                Key::Enter => 0x0d,
+                Key::Tab => 0x09,
+                _ => 0x7f,
+            };
+            return Some(vec![byte]);
+        }
+    }
+
+    let (code, final_byte) = match event.key {
+        Key::Char(c) => (c.to_lowercase().next().unwrap_or(c) as u32, 'u'),
+        Key::Enter => (13, 'u'),
+        Key::Tab => (9, 'u'),
+        Key::Backspace => (127, 'u'),
+        Key::Escape => (27, 'u'),
+        Key::Up | Key::Down | Key::Right | Key::Left | Key::Home | Key::End => {
+            (1, cursor_key_final(event.key) as char)
+        }
+        Key::Insert | Key::Delete | Key::PageUp | Key::PageDown => {
+            (tilde_key_number(event.key) as u32, '~')
+        }
+        Key::F(1) => (1, 'P'),
+        Key::F(2) => (1, 'Q'),
+        // F3 is `CSI 13 ~`, since `CSI R` is a cursor position report.
+        Key::F(3) => (13, '~'),
+        Key::F(4) => (1, 'S'),
+        // F13 and up have functional key codes in the private use area.
+        Key::F(n @ 13..=20) => (57376 + (n - 13) as u32, 'u'),
+        Key::F(n) => (function_key_number(n)? as u32, '~'),
+        Key::Keypad(key) => (key.kitty_code(), 'u'),
+    };
+
+    let mut key_field = code.to_string();
+    if flags.contains(KittyKeyboardFlags::REPORT_ALTERNATE_KEYS)
+        && final_byte == 'u'
+        && mods.contains(Modifiers::SHIFT)
+    {
+        let shifted = text.and_then(|t| {
+            let mut chars = t.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        });
+        if let Some(shifted) = shifted.filter(|&s| s as u32 != code) {
+            key_field = format!("{code}:{}", shifted as u32);
+        }
+    }
+
+    let text_field = text
+        .filter(|_| {
+            report_all
+                && flags.contains(KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT)
+                && kind != KeyEventKind::Release
+        })
+        .map(|t| {
+            t.chars()
+                .map(|c| (c as u32).to_string())
+                .collect::<Vec<_>>()
+                .join(":")
+        });
+
+    let mut mods_field = String::new();
+    let event_type = match kind {
+        KeyEventKind::Press => None,
+        KeyEventKind::Repeat => Some(2),
+        KeyEventKind::Release => Some(3),
+    };
+    if !mods.is_empty() || event_type.is_some() || text_field.is_some() {
+        mods_field = modifier_param(mods).to_string();
+        if let Some(event_type) = event_type {
+            mods_field.push_str(&format!(":{event_type}"));
+        }
+    }
+
+    let mut seq = String::from("\x1b[");
+    // `CSI 1 A` and friends drop the key number when nothing follows it.
+    if !(code == 1 && final_byte != 'u' && mods_field.is_empty()) {
+        seq.push_str(&key_field);
+    }
+    if !mods_field.is_empty() {
+        seq.push(';');
+        seq.push_str(&mods_field);
+    }
+    if let Some(text_field) = text_field {
+        seq.push(';');
+        seq.push_str(&text_field);
+    }
+    seq.push(final_byte);
+    Some(seq.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    fn press(key: Key, modifiers: Modifiers, text: Option<&str>) -> KeyEvent {
+        KeyEvent {
+            key,
+            modifiers,
+            text: text.map(str::to_string),
+            kind: KeyEventKind::Press,
+        }
+    }
+
+    fn encode(event: &KeyEvent, modes: &TerminalModes) -> String {
+        String::from_utf8(encode_key(event, modes).unwrap()).unwrap()
+    }
+
+    fn kitty(flags: KittyKeyboardFlags) -> TerminalModes {
+        TerminalModes {
+            kitty_keyboard: flags,
+            ..TerminalModes::default()
+        }
+    }
+
+    #[test]
+    fn test_legacy_text_and_control() {
+        let modes = TerminalModes::default();
+        let none = Modifiers::empty();
+        assert_eq!(encode(&press(Key::Char('a'), none, Some("a")), &modes), "a");
+        assert_eq!(
+            encode(&press(Key::Char('a'), Modifiers::SHIFT, Some("A")), &modes),
+            "A"
+        );
+        assert_eq!(
+            encode(&press(Key::Char('c'), Modifiers::CTRL, Some("c")), &modes),
+            "\x03"
+        );
+        assert_eq!(
+            encode(
+                &press(Key::Char('x'), Modifiers::ALT | Modifiers::CTRL, None),
+                &modes
+            ),
+            "\x1b\x18"
+        );
+        assert_eq!(
+            encode(&press(Key::Char(' '), Modifiers::CTRL, Some(" ")), &modes),
+            "\0"
+        );
+        assert_eq!(encode(&press(Key::Backspace, none, None), &modes), "\x7f");
+        assert_eq!(
+            encode(&press(Key::Tab, Modifiers::SHIFT, None), &modes),
+            "\x1b[Z"
+        );
+        assert_eq!(
+            encode(&press(Key::Enter, Modifiers::ALT, None), &modes),
+            "\x1b\r"
+        );
+
+        let release = KeyEvent {
+            kind: KeyEventKind::Release,
+            ..press(Key::Char('a'), none, Some("a"))
+        };
+        assert_eq!(encode_key(&release, &modes), None);
+    }
+
+    #[test]
+    fn test_legacy_cursor_and_function_keys() {
+        let mut modes = TerminalModes::default();
+        let none = Modifiers::empty();
+        assert_eq!(encode(&press(Key::Up, none, None), &modes), "\x1b[A");
+        assert_eq!(
+            encode(&press(Key::Left, Modifiers::CTRL, None), &modes),
+            "\x1b[1;5D"
+        );
+        assert_eq!(encode(&press(Key::PageDown, none, None), &modes), "\x1b[6~");
+        assert_eq!(
+            encode(&press(Key::Delete, Modifiers::SHIFT, None), &modes),
+            "\x1b[3;2~"
+        );
+        assert_eq!(encode(&press(Key::F(1), none, None), &modes), "\x1bOP");
+        assert_eq!(
+            encode(&press(Key::F(2), Modifiers::ALT, None), &modes),
+            "\x1b[1;3Q"
+        );
+        assert_eq!(encode(&press(Key::F(12), none, None), &modes), "\x1b[24~");
+        assert_eq!(encode_key(&press(Key::F(21), none, None), &modes), None);
+
+        // DECCKM and DECKPAM.
+        modes.app_cursor = true;
+        modes.app_keypad = true;
+        assert_eq!(encode(&press(Key::Up, none, None), &modes), "\x1bOA");
+        assert_eq!(
+            encode(&press(Key::Up, Modifiers::SHIFT, None), &modes),
+            "\x1b[1;2A"
+        );
+        let kp5 = press(Key::Keypad(KeypadKey::Digit(5)), none, Some("5"));
+        assert_eq!(encode(&kp5, &modes), "\x1bOu");
+        modes.app_keypad = false;
+        assert_eq!(encode(&kp5, &modes), "5");
+    }
+
+    #[test]
+    fn test_modify_other_keys() {
+        let mut modes = TerminalModes {
+            modify_other_keys: 1,
+            ..TerminalModes::default()
+        };
+        // Level 1 leaves well-known combinations alone.
+        assert_eq!(
+            encode(&press(Key::Char('c'), Modifiers::CTRL, Some("c")), &modes),
+            "\x03"
+        );
+        assert_eq!(
+            encode(&press(Key::Char('1'), Modifiers::CTRL, Some("1")), &modes),
+            "\x1b[27;5;49~"
+        );
+        assert_eq!(
+            encode(
+                &press(
+                    Key::Char('a'),
+                    Modifiers::CTRL | Modifiers::SHIFT,
+                    Some("A")
+                ),
+                &modes
+            ),
+            "\x1b[27;6;65~"
+        );
+        assert_eq!(
+            encode(&press(Key::Enter, Modifiers::SHIFT, None), &modes),
+            "\x1b[27;2;13~"
+        );
+
+        modes.modify_other_keys = 2;
+        assert_eq!(
+            encode(&press(Key::Char('c'), Modifiers::CTRL, Some("c")), &modes),
+            "\x1b[27;5;99~"
+        );
+        assert_eq!(
+            encode(&press(Key::Char('x'), Modifiers::ALT, Some("x")), &modes),
+            "\x1b[27;3;120~"
+        );
+        // Shift alone still types text.
+        assert_eq!(
+            encode(&press(Key::Char('a'), Modifiers::SHIFT, Some("A")), &modes),
+            "A"
+        );
+    }
+
+    #[test]
+    fn test_kitty_disambiguate() {
+        let modes = kitty(KittyKeyboardFlags::DISAMBIGUATE_ESC_CODES);
+        let none = Modifiers::empty();
+        assert_eq!(encode(&press(Key::Escape, none, None), &modes), "\x1b[27u");
+        assert_eq!(
+            encode(&press(Key::Char('c'), Modifiers::CTRL, Some("c")), &modes),
+            "\x1b[99;5u"
+        );
+        assert_eq!(
+            encode(&press(Key::Char('i'), Modifiers::CTRL, Some("i")), &modes),
+            "\x1b[105;5u"
+        );
+        assert_eq!(
+            encode(&press(Key::Char('a'), Modifiers::SHIFT, Some("A")), &modes),
+            "A"
+        );
+        assert_eq!(encode(&press(Key::Enter, none, None), &modes), "\r");
+        assert_eq!(
+            encode(&press(Key::Enter, Modifiers::SHIFT, None), &modes),
+            "\x1b[13;2u"
+        );
+        assert_eq!(encode(&press(Key::Up, none, None), &modes), "\x1b[A");
+        assert_eq!(encode(&press(Key::F(3), none, None), &modes), "\x1b[13~");
+        assert_eq!(encode(&press(Key::F(12), none, None), &modes), "\x1b[24~");
+        assert_eq!(encode(&press(Key::F(13), none, None), &modes), "\x1b[57376u");
+        assert_eq!(
+            encode(&press(Key::F(20), Modifiers::SHIFT, None), &modes),
+            "\x1b[57383;2u"
+        );
+        assert_eq!(encode_key(&press(Key::F(21), none, None), &modes), None);
+        assert_eq!(
+            encode(&press(Key::Keypad(KeypadKey::Enter), none, None), &modes),
+            "\x1b[57414u"
+        );
+    }
+
+    #[test]
+    fn test_kitty_event_types_and_all_keys() {
+        let flags = KittyKeyboardFlags::DISAMBIGUATE_ESC_CODES
+            | KittyKeyboardFlags::REPORT_EVENT_TYPES
+            | KittyKeyboardFlags::REPORT_ALTERNATE_KEYS;
+        let modes = kitty(flags);
+        let release = KeyEvent {
+            kind: KeyEventKind::Release,
+            ..press(Key::Char('a'), Modifiers::empty(), Some("a"))
+        };
+        assert_eq!(encode(&release, &modes), "\x1b[97;1:3u");
+        let repeat = KeyEvent {
+            kind: KeyEventKind::Repeat,
+            ..press(Key::Left, Modifiers::CTRL, None)
+        };
+        assert_eq!(encode(&repeat, &modes), "\x1b[1;5:2D");
+        let enter_up = KeyEvent {
+            kind: KeyEventKind::Release,
+            ..press(Key::Enter, Modifiers::empty(), None)
+        };
+        assert_eq!(encode_key(&enter_up, &modes), None);
+        assert_eq!(
+            encode(
+                &press(
+                    Key::Char('a'),
+                    Modifiers::CTRL | Modifiers::SHIFT,
+                    Some("A")
+                ),
+                &modes
+            ),
+            "\x1b[97:65;6u"
+        );
+
+        let all = kitty(
+            KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESC | KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT,
+        );
+        assert_eq!(
+            encode(&press(Key::Char('a'), Modifiers::SHIFT, Some("A")), &all),
+            "\x1b[97;2;65u"
+        );
+        assert_eq!(
+            encode(&press(Key::Enter, Modifiers::empty(), None), &all),
+            "\x1b[13u"
+        );
+    }
+
+    #[test]
+    fn test_terminal_tracks_keyboard_modes() {
+        let mut term = VtTerminal::new(80, 24);
+        let ctrl_c = press(Key::Char('c'), Modifiers::CTRL, Some("c"));
+        assert_eq!(term.encode_key(&ctrl_c).unwrap(), b"\x03");
+
+        term.write(b"\x1b[>4;2m");
+        assert_eq!(term.modes().modify_other_keys, 2);
+        assert_eq!(term.encode_key(&ctrl_c).unwrap(), b"\x1b[27;5;99~");
+        term.write(b"\x1b[>4m");
+        assert_eq!(term.modes().modify_other_keys, 0);
+
+        // Push and pop kitty keyboard flags.
+        term.write(b"\x1b[>1u");
+        assert_eq!(
+            term.modes().kitty_keyboard,
+            KittyKeyboardFlags::DISAMBIGUATE_ESC_CODES
+        );
+        assert_eq!(term.encode_key(&ctrl_c).unwrap(), b"\x1b[99;5u");
+        term.write(b"\x1b[<u");
+        assert!(term.modes().kitty_keyboard.is_empty());
+    }
+}
@@ -0,0 +1,506 @@
+//! kitty graphics protocol commands.
+//!
+//! A command is an APC `G <key>=<value>,... ; <base64 payload>`. Supported:
+//! transmitting images directly in the payload (RGB, RGBA or PNG, optionally
+//! zlib-compressed, possibly split into chunks), displaying them, deleting
+//! them by ID or all at once, and queries. Images from files or shared memory
+//! are refused, since the program may not run on this machine.
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+use crate::image::{Anchor, DecodedImage, ImageError, ImageStore, MAX_IMAGE_DIMENSION};
+
+/// Base64 as kitty sends it: padding is optional.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Largest payload accepted for one image, across all its chunks.
+const MAX_PAYLOAD: usize = 64 * 1024 * 1024;
+
+/// First ID handed out for images transmitted with a number (`I=`) instead
+/// of an ID, kept out of the range programs usually pick.
+const FIRST_NUMBERED_ID: u32 = 1 << 31;
+
+/// The keys of a graphics command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Command {
+    /// `a`: t (transmit), T (transmit and display), p (display), d (delete),
+    /// q (query).
+    action: u8,
+    /// `f`: 24 (RGB), 32 (RGBA) or 100 (PNG).
+    format: u32,
+    /// `t`: d (direct), f (file), t (temporary file), s (shared memory).
+    medium: u8,
+    /// `o`: z for zlib-compressed data.
+    compression: Option<u8>,
+    /// `s`, `v`: pixel size of RGB(A) data.
+    width: u32,
+    height: u32,
+    /// `i`: image ID chosen by the program.
+    image_id: u32,
+    /// `I`: image number, for which the terminal picks the ID.
+    image_number: u32,
+    /// `p`: placement ID.
+    placement_id: u32,
+    /// `m`: more chunks follow.
+    more: bool,
+    /// `q`: 1 suppresses OK replies, 2 also error replies.
+    quiet: u32,
+    /// `c`, `r`: columns and rows to scale the image to.
+    cols: u32,
+    rows: u32,
+    /// `C`: 1 leaves the cursor where it is.
+    keep_cursor: bool,
+    /// `z`: stacking order.
+    z: i32,
+    /// `d`: what to delete.
+    delete: u8,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self {
+            action: b't',
+            format: 32,
+            medium: b'd',
+            compression: None,
+            width: 0,
+            height: 0,
+            image_id: 0,
+            image_number: 0,
+            placement_id: 0,
+            more: false,
+            quiet: 0,
+            cols: 0,
+            rows: 0,
+            keep_cursor: false,
+            z: 0,
+            delete: b'a',
+        }
+    }
+}
+
+impl Command {
+    /// Parse the comma-separated keys. Unknown keys are ignored.
+    fn parse(control: &[u8]) -> Command {
+        let mut cmd = Command::default();
+        for pair in control.split(|&b| b == b',') {
+            let [key, b'=', value @ ..] = pair else {
+                continue;
+            };
+            let char_value = value.first().copied().unwrap_or(0);
+            let number = || std::str::from_utf8(value).ok()?.parse::<i64>().ok();
+            let unsigned = || number().and_then(|n| u32::try_from(n).ok()).unwrap_or(0);
+            match key {
+                b'a' => cmd.action = char_value,
+                b'f' => cmd.format = unsigned(),
+                b't' => cmd.medium = char_value,
+                b'o' => cmd.compression = Some(char_value),
+                b's' => cmd.width = unsigned(),
+                b'v' => cmd.height = unsigned(),
+                b'i' => cmd.image_id = unsigned(),
+                b'I' => cmd.image_number = unsigned(),
+                b'p' => cmd.placement_id = unsigned(),
+                b'm' => cmd.more = unsigned() == 1,
+                b'q' => cmd.quiet = unsigned(),
+                b'c' => cmd.cols = unsigned(),
+                b'r' => cmd.rows = unsigned(),
+                b'C' => cmd.keep_cursor = unsigned() == 1,
+                b'z' => cmd.z = number().and_then(|n| i32::try_from(n).ok()).unwrap_or(0),
+                b'd' => cmd.delete = char_value,
+                _ => {}
+            }
+        }
+        cmd
+    }
+}
+
+/// What the terminal should do after a command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Outcome {
+    /// Reply to send to the program.
+    pub(crate) reply: Option<String>,
+    /// An image was displayed at the cursor covering this many columns and
+    /// rows, and the cursor should move past it.
+    pub(crate) move_cursor: Option<(u16, u16)>,
+}
+
+/// State kept between graphics commands.
+pub(crate) struct KittyGraphics {
+    /// A transmission split into chunks, waiting for its last chunk.
+    pending: Option<(Command, Vec<u8>)>,
+    /// IDs given to image numbers, so `I=` can refer to them again.
+    numbers: HashMap<u32, u32>,
+    /// Next ID for images sent with a number.
+    next_numbered_id: u32,
+}
+
+impl Default for KittyGraphics {
+    fn default() -> Self {
+        Self {
+            pending: None,
+            numbers: HashMap::new(),
+            next_numbered_id: FIRST_NUMBERED_ID,
+        }
+    }
+}
+
+impl KittyGraphics {
+    /// Run a command (the APC contents after `G`). `at` is the cursor
+    /// position and `cell_size` the pixel size of a cell.
+    pub(crate) fn handle(
+        &mut self,
+        data: &[u8],
+        store: &mut ImageStore,
+        at: Anchor,
+        cell_size: (u16, u16),
+    ) -> Outcome {
+        let (control, payload) = match data.iter().position(|&b| b == b';') {
+            Some(i) => (&data[..i], &data[i + 1..]),
+            None => (data, &[][..]),
+        };
+        let chunk = Command::parse(control);
+
+        // Later chunks only carry `m` (and maybe `q`); the first chunk has
+        // the rest of the keys.
+        let (cmd, payload) = match self.pending.take() {
+            Some((first, mut buffered)) => {
+                if buffered.len() + payload.len() > MAX_PAYLOAD {
+                    return reply(&first, Err(ImageError::TooLarge));
+                }
+                buffered.extend_from_slice(payload);
+                if chunk.more {
+                    self.pending = Some((first, buffered));
+                    return Outcome::default();
+                }
+                (first, buffered)
+            }
+            None if chunk.more && matches!(chunk.action, b't' | b'T' | b'q') => {
+                self.pending = Some((chunk, payload.to_vec()));
+                return Outcome::default();
+            }
+            None => (chunk, payload.to_vec()),
+        };
+        self.run(cmd, &payload, store, at, cell_size)
+    }
+
+    fn run(
+        &mut self,
+        mut cmd: Command,
+        payload: &[u8],
+        store: &mut ImageStore,
+        at: Anchor,
+        cell_size: (u16, u16),
+    ) -> Outcome {
+        match cmd.action {
+            b't' | b'T' => {
+                if cmd.image_id == 0 && cmd.image_number != 0 {
+                    cmd.image_id = self.next_numbered_id;
+                    self.next_numbered_id =
+                        self.next_numbered_id.wrapping_add(1).max(FIRST_NUMBERED_ID);
+                    self.numbers.insert(cmd.image_number, cmd.image_id);
+                }
+                let kitty_id = (cmd.image_id != 0).then_some(cmd.image_id);
+                let stored = decode(&cmd, payload, store.budget()).and_then(|image| {
+                    Ok((image.width, image.height, store.insert(image, kitty_id)?))
+                });
+                match stored {
+                    Ok((width, height, id)) if cmd.action == b'T' => {
+                        let size = place(&cmd, store, id, (width, height), at, cell_size);
+                        Outcome {
+                            move_cursor: (!cmd.keep_cursor).then_some(size),
+                            ..reply(&cmd, Ok(()))
+                        }
+                    }
+                    Ok(_) => reply(&cmd, Ok(())),
+                    Err(e) => reply(&cmd, Err(e)),
+                }
+            }
+            b'p' => {
+                let kitty_id = match cmd.image_id {
+                    0 => self.numbers.get(&cmd.image_number).copied(),
+                    id => Some(id),
+                };
+                let id = kitty_id.and_then(|k| store.find_kitty(k));
+                let Some(id) = id else {
+                    return reply(&cmd, Err(ImageError::NotFound));
+                };
+                let image = store.get(id).expect("found images are stored");
+                let size = place(&cmd, store, id, (image.width, image.height), at, cell_size);
+                Outcome {
+                    move_cursor: (!cmd.keep_cursor).then_some(size),
+                    ..reply(&cmd, Ok(()))
+                }
+            }
+            b'q' => reply(&cmd, decode(&cmd, payload, store.budget()).map(|_| ())),
+            b'd' => {
+                let free = cmd.delete.is_ascii_uppercase();
+                match cmd.delete.to_ascii_lowercase() {
+                    b'a' => store.delete(None, 0, free),
+                    b'i' => {
+                        if let Some(id) = store.find_kitty(cmd.image_id) {
+                            store.delete(Some(id), cmd.placement_id, free);
+                        }
+                    }
+                    _ => {}
+                }
+                Outcome::default()
+            }
+            _ => Outcome::default(),
+        }
+    }
+}
+
+/// Place an image at the cursor, returning the columns and rows it covers.
+fn place(
+    cmd: &Command,
+    store: &mut ImageStore,
+    id: u64,
+    (width, height): (u32, u32),
+    at: Anchor,
+    (cell_width, cell_height): (u16, u16),
+) -> (u16, u16) {
+    let cells = |given: u32, pixels: u32, cell: u16| {
+        let cells = if given > 0 {
+            given
+        } else {
+            pixels.div_ceil(cell.max(1) as u32)
+        };
+        cells.clamp(1, u16::MAX as u32) as u16
+    };
+    let size = (
+        cells(cmd.cols, width, cell_width),
+        cells(cmd.rows, height, cell_height),
+    );
+    store.place(id, cmd.placement_id, at, size, cmd.z);
+    size
+}
+
+/// Build the reply to a command, if the program asked for one.
+fn reply(cmd: &Command, result: Result<(), ImageError>) -> Outcome {
+    // Commands without an ID or number get no reply.
+    if cmd.image_id == 0 && cmd.image_number == 0 {
+        return Outcome::default();
+    }
+    let message = match &result {
+        Ok(()) if cmd.quiet == 0 => "OK".to_string(),
+        Err(e) if cmd.quiet < 2 => format!("{}:{e}", e.code()),
+        _ => return Outcome::default(),
+    };
+
+    let mut keys = Vec::new();
+    if cmd.image_id != 0 {
+        keys.push(format!("i={}", cmd.image_id));
+    }
+    if cmd.image_number != 0 {
+        keys.push(format!("I={}", cmd.image_number));
+    }
+    if cmd.placement_id != 0 {
+        keys.push(format!("p={}", cmd.placement_id));
+    }
+    Outcome {
+        reply: Some(format!("\x1b_G{};{message}\x1b\\", keys.join(","))),
+        move_cursor: None,
+    }
+}
+
+/// Decode the image data of a transmission.
+fn decode(cmd: &Command, payload: &[u8], budget: usize) -> Result<DecodedImage, ImageError> {
+    if cmd.medium != b'd' {
+        return Err(ImageError::Unsupported("transmission medium"));
+    }
+    let data = BASE64
+        .decode(payload)
+        .map_err(|_| ImageError::Invalid("base64 data"))?;
+    let data = match cmd.compression {
+        None => data,
+        Some(b'z') => {
+            let mut inflated = Vec::new();
+            flate2::read::ZlibDecoder::new(data.as_slice())
+                .take(budget as u64 + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|_| ImageError::Invalid("compressed data"))?;
+            if inflated.len() > budget {
+                return Err(ImageError::TooLarge);
+            }
+            inflated
+        }
+        Some(_) => return Err(ImageError::Unsupported("compression")),
+    };
+
+    match cmd.format {
+        24 | 32 => decode_raw(cmd, data),
+        100 => decode_png(&data),
+        _ => Err(ImageError::Unsupported("format")),
+    }
+}
+
+/// Check and convert RGB or RGBA data to RGBA.
+fn decode_raw(cmd: &Command, data: Vec<u8>) -> Result<DecodedImage, ImageError> {
+    let (width, height) = (cmd.width, cmd.height);
+    if width == 0 || height == 0 {
+        return Err(ImageError::Invalid("image size"));
+    }
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(ImageError::TooLarge);
+    }
+    let pixels = width as usize * height as usize;
+    let rgba = if cmd.format == 32 {
+        if data.len() != pixels * 4 {
+            return Err(ImageError::Invalid("image size"));
+        }
+        data
+    } else {
+        if data.len() != pixels * 3 {
+            return Err(ImageError::Invalid("image size"));
+        }
+        data.chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect()
+    };
+    Ok(DecodedImage {
+        width,
+        height,
+        rgba,
+    })
+}
+
+/// Decode a PNG to RGBA.
+fn decode_png(data: &[u8]) -> Result<DecodedImage, ImageError> {
+    let invalid = |_| ImageError::Invalid("PNG data");
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(ImageError::TooLarge);
+    }
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf).map_err(invalid)?;
+    let pixels = &buf[..frame.buffer_size()];
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => return Err(ImageError::Invalid("PNG data")),
+    };
+    Ok(DecodedImage {
+        width: frame.width,
+        height: frame.height,
+        rgba,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: (u16, u16) = (10, 20);
+
+    fn at(line: i64) -> Anchor {
+        Anchor {
+            line,
+            col: 3,
+            alt_screen: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let cmd = Command::parse(b"a=T,f=100,i=31,p=2,q=1,c=5,r=2,C=1,z=-3,m=1,x");
+        assert_eq!(cmd.action, b'T');
+        assert_eq!(cmd.format, 100);
+        assert_eq!((cmd.image_id, cmd.placement_id, cmd.quiet), (31, 2, 1));
+        assert_eq!((cmd.cols, cmd.rows, cmd.z), (5, 2, -3));
+        assert!(cmd.keep_cursor && cmd.more);
+        assert_eq!(Command::parse(b""), Command::default());
+    }
+
+    #[test]
+    fn test_transmit_and_display() {
+        let mut kitty = KittyGraphics::default();
+        let mut store = ImageStore::default();
+
+        // A 25x30 RGB image in two chunks; the base64 of 2250 zero bytes.
+        let encoded = BASE64.encode(vec![0u8; 25 * 30 * 3]);
+        let (first, rest) = encoded.split_at(1000);
+        let outcome = kitty.handle(
+            format!("a=T,f=24,s=25,v=30,i=7,m=1;{first}").as_bytes(),
+            &mut store,
+            at(4),
+            CELL,
+        );
+        assert_eq!(outcome, Outcome::default());
+        let outcome = kitty.handle(format!("m=0;{rest}").as_bytes(), &mut store, at(4), CELL);
+        assert_eq!(outcome.reply.as_deref(), Some("\x1b_Gi=7;OK\x1b\\"));
+        // 25x30 pixels cover 3x2 cells.
+        assert_eq!(outcome.move_cursor, Some((3, 2)));
+
+        let placements = store.placements(false, 0);
+        assert_eq!(placements.len(), 1);
+        assert_eq!((placements[0].line, placements[0].col), (4, 3));
+        let image = store.get(placements[0].image_id).unwrap();
+        assert_eq!(image.rgba.len(), 25 * 30 * 4);
+
+        // Display again with an explicit size, leaving the cursor alone.
+        let outcome = kitty.handle(b"a=p,i=7,c=1,r=1,C=1,q=1", &mut store, at(9), CELL);
+        assert_eq!(outcome, Outcome::default());
+        assert_eq!(store.placements(false, 0).len(), 2);
+
+        // Delete the placements and free the image.
+        kitty.handle(b"a=d,d=I,i=7", &mut store, at(0), CELL);
+        assert!(store.placements(false, 0).is_empty());
+        assert!(store.find_kitty(7).is_none());
+    }
+
+    #[test]
+    fn test_png_and_errors() {
+        // A 1x1 opaque red PNG.
+        let mut png_data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png_data, 1, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 0]).unwrap();
+        }
+        let mut kitty = KittyGraphics::default();
+        let mut store = ImageStore::default();
+        let cmd = format!("f=100,I=5;{}", BASE64.encode(&png_data));
+        let outcome = kitty.handle(cmd.as_bytes(), &mut store, at(0), CELL);
+        assert_eq!(
+            outcome.reply.as_deref(),
+            Some(format!("\x1b_Gi={FIRST_NUMBERED_ID},I=5;OK\x1b\\").as_str())
+        );
+        let id = store.find_kitty(FIRST_NUMBERED_ID).unwrap();
+        assert_eq!(store.get(id).unwrap().rgba, vec![255, 0, 0, 255]);
+
+        let outcome = kitty.handle(b"a=q,i=1,t=f;L3RtcC94", &mut store, at(0), CELL);
+        assert_eq!(
+            outcome.reply.as_deref(),
+            Some("\x1b_Gi=1;EINVAL:unsupported transmission medium\x1b\\")
+        );
+        let outcome = kitty.handle(b"a=p,i=99", &mut store, at(0), CELL);
+        assert_eq!(
+            outcome.reply.as_deref(),
+            Some("\x1b_Gi=99;ENOENT:image not found\x1b\\")
+        );
+        // Wrong data size, with errors suppressed.
+        let outcome = kitty.handle(b"f=24,s=2,v=2,i=3,q=2;AAAA", &mut store, at(0), CELL);
+        assert_eq!(outcome, Outcome::default());
+    }
+}
@@ -5,9 +5,46 @@
 //! and providing cell data for rendering.
 
 pub mod cell;
+pub mod clipboard;
+pub mod export;
+mod handler;
+pub mod hyperlink;
+pub mod image;
+pub mod input;
+pub mod invariants;
+pub mod keyboard;
+mod kitty;
+pub mod links;
+pub mod modes;
+pub mod notify;
+mod osc;
+pub mod paste;
+mod reflow;
 pub mod screen;
+pub mod search;
+pub mod selection;
+pub mod shell;
+mod sixel;
+pub mod snapshot;
 pub mod terminal;
+pub mod theme;
+mod titles;
 
-pub use cell::{CellFlags, Rgb, VtCell};
+pub use cell::{CellFlags, Rgb, UnderlineStyle, VtCell};
+pub use clipboard::{ClipboardKind, ClipboardRequest};
+pub use export::ExportFormat;
+pub use hyperlink::Hyperlink;
+pub use image::{Image, ImagePlacement};
+pub use input::{Modifiers, MouseButton, MouseEvent, MouseEventKind};
+pub use keyboard::{Key, KeyEvent, KeyEventKind, KeypadKey};
+pub use links::{DetectedLink, LinkTarget};
+pub use modes::{KittyKeyboardFlags, MouseEncoding, MouseTracking, TerminalModes};
+pub use notify::{Notification, Progress, ProgressState};
+pub use paste::PasteRisk;
 pub use screen::{CursorShape, CursorState, DamageInfo, DamagedRow, ScreenView};
-pub use terminal::VtTerminal;
+pub use search::{SearchError, SearchKind, SearchMatch};
+pub use selection::{GridPoint, SelectionMode, SelectionRange, Side};
+pub use shell::CommandBlock;
+pub use snapshot::SnapshotError;
+pub use terminal::{VtTerminal, MAX_COLUMNS, MAX_ROWS};
+pub use theme::{Theme, ThemeError};
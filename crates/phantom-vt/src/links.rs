@@ -0,0 +1,327 @@
+//! Detection of URLs and file paths in plain terminal output.
+//!
+//! Few programs mark their output up with OSC 8 hyperlinks, but URLs and
+//! compiler locations such as rustc's `--> src/main.rs:12:5` are easy to spot.
+//! Rows are scanned with alacritty's regex engine, which joins soft-wrapped
+//! lines, and each match becomes a [`DetectedLink`] covering the cells it
+//! spans. Text that already carries an OSC 8 link is left to that link.
+
+use std::path::{Component, Path, PathBuf};
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Direction, Line, Point};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::search::{RegexIter, RegexSearch};
+use alacritty_terminal::term::Term;
+
+use crate::selection::GridPoint;
+use crate::terminal::EventProxy;
+
+/// URL schemes that start a link.
+const URL_SCHEMES: &[&str] = &[
+    "https://", "http://", "file://", "ftp://", "ssh://", "git://", "mailto:",
+];
+
+/// Characters that end a URL: controls, whitespace and delimiters.
+const URL_BODY: &str = r#"[^\u{0000}-\u{001F}\u{007F}-\u{009F}\s<>"'`{-}\^\\⟨⟩]+"#;
+
+/// A path segment. Deliberately narrow so prose around a path is not taken in.
+const PATH_SEGMENT: &str = r"[A-Za-z0-9_.+@~-]+";
+
+/// An optional `:line` or `:line:column` suffix.
+const POSITION: &str = r"(?::[0-9]+(?::[0-9]+)?)?";
+
+/// Characters trimmed from the end of a link; usually sentence punctuation.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ':', ';', '!', '?', '\'', '"'];
+
+/// What a detected link points at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// A URL, exactly as printed.
+    Url(String),
+    /// A file, optionally at a line and column.
+    File {
+        /// Absolute when the path was, or when the working directory was
+        /// known to resolve it against; otherwise as printed.
+        path: String,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+/// A URL or file path found in the terminal text. Both ends are inclusive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetectedLink {
+    pub start: GridPoint,
+    pub end: GridPoint,
+    /// The text of the link as shown.
+    pub text: String,
+    pub target: LinkTarget,
+}
+
+impl DetectedLink {
+    /// Whether the given grid cell is part of the link.
+    pub fn contains(&self, point: GridPoint) -> bool {
+        (point.line, point.col) >= (self.start.line, self.start.col)
+            && (point.line, point.col) <= (self.end.line, self.end.col)
+    }
+}
+
+/// Finds links in the grid; holds the compiled patterns.
+pub(crate) struct LinkDetector {
+    regex: RegexSearch,
+}
+
+impl LinkDetector {
+    pub(crate) fn new() -> Self {
+        let schemes = URL_SCHEMES.join("|");
+        // Absolute, `./`, `../` and `~/` paths need one segment, other
+        // relative paths two; a bare file name needs a line number.
+        let pattern = format!(
+            "(?:{schemes}){URL_BODY}\
+             |(?:(?:~|\\.\\.?)?/{PATH_SEGMENT}(?:/{PATH_SEGMENT})*\
+             |{PATH_SEGMENT}(?:/{PATH_SEGMENT})+){POSITION}\
+             |{PATH_SEGMENT}\\.[A-Za-z][A-Za-z0-9]*:[0-9]+(?::[0-9]+)?"
+        );
+        let regex = RegexSearch::new(&pattern).expect("link patterns are valid");
+        Self { regex }
+    }
+
+    /// Find the links between two grid lines (inclusive), in order.
+    ///
+    /// Relative paths are resolved against `cwd` when it is known.
+    pub(crate) fn detect(
+        &mut self,
+        term: &Term<EventProxy>,
+        top: i32,
+        bottom: i32,
+        cwd: Option<&str>,
+    ) -> Vec<DetectedLink> {
+        let top = top.max(term.topmost_line().0);
+        let bottom = bottom.min(term.bottommost_line().0);
+        if top > bottom {
+            return Vec::new();
+        }
+
+        let start = term.line_search_left(Point::new(Line(top), Column(0)));
+        let end = term.line_search_right(Point::new(Line(bottom), term.last_column()));
+
+        RegexIter::new(start, end, Direction::Right, term, &mut self.regex)
+            .filter(|m| m.end().line.0 >= top)
+            .filter_map(|m| link_from_cells(term, *m.start(), *m.end(), cwd))
+            .collect()
+    }
+}
+
+/// Build a link from the cells of a match, or `None` if nothing is left
+/// after trimming or the text is already an OSC 8 link.
+fn link_from_cells(
+    term: &Term<EventProxy>,
+    start: Point,
+    end: Point,
+    cwd: Option<&str>,
+) -> Option<DetectedLink> {
+    let grid = term.grid();
+    let last_column = term.last_column().0;
+    let mut cells: Vec<(Point, char)> = Vec::new();
+    for line in start.line.0..=end.line.0 {
+        let first = if line == start.line.0 {
+            start.column.0
+        } else {
+            0
+        };
+        let last = if line == end.line.0 {
+            end.column.0
+        } else {
+            last_column
+        };
+        for col in first..=last {
+            let point = Point::new(Line(line), Column(col));
+            let cell = &grid[point];
+            if cell.hyperlink().is_some() {
+                return None;
+            }
+            if cell
+                .flags
+                .intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER)
+            {
+                continue;
+            }
+            cells.push((point, cell.c));
+        }
+    }
+
+    let mut text: String = cells.iter().map(|&(_, c)| c).collect();
+    while let Some(last) = text.chars().last() {
+        if !TRAILING_PUNCTUATION.contains(&last) && !is_unbalanced_close(&text, last) {
+            break;
+        }
+        text.pop();
+        cells.pop();
+    }
+    let (&(first, _), &(last, _)) = (cells.first()?, cells.last()?);
+
+    let target = if URL_SCHEMES.iter().any(|scheme| text.starts_with(scheme)) {
+        // Nothing follows the scheme once trailing punctuation is gone.
+        if URL_SCHEMES.contains(&text.as_str()) {
+            return None;
+        }
+        LinkTarget::Url(text.clone())
+    } else {
+        let (path, line, column) = split_position(&text);
+        LinkTarget::File {
+            path: resolve(path, cwd),
+            line,
+            column,
+        }
+    };
+
+    Some(DetectedLink {
+        start: GridPoint::from_alac(first),
+        end: GridPoint::from_alac(last),
+        text,
+        target,
+    })
+}
+
+/// Whether `last` closes a bracket that was never opened, as in
+/// `(see https://example.com)`.
+fn is_unbalanced_close(text: &str, last: char) -> bool {
+    let open = match last {
+        ')' => '(',
+        ']' => '[',
+        '}' => '{',
+        _ => return false,
+    };
+    text.chars().filter(|&c| c == last).count() > text.chars().filter(|&c| c == open).count()
+}
+
+/// Split a `:line` or `:line:column` suffix off a path.
+fn split_position(text: &str) -> (&str, Option<u32>, Option<u32>) {
+    fn number(text: &str) -> Option<(&str, u32)> {
+        let (rest, digits) = text.rsplit_once(':')?;
+        Some((rest, digits.parse().ok()?))
+    }
+    match number(text) {
+        Some((rest, last)) => match number(rest) {
+            Some((path, line)) => (path, Some(line), Some(last)),
+            None => (rest, Some(last), None),
+        },
+        None => (text, None, None),
+    }
+}
+
+/// Resolve a relative path against the working directory, dropping `.`
+/// components. `~` paths are left for the embedder to expand, and without a
+/// working directory relative paths stay relative.
+fn resolve(path: &str, cwd: Option<&str>) -> String {
+    let path = Path::new(path);
+    let joined = match cwd {
+        Some(cwd) if path.is_relative() && !path.starts_with("~") => Path::new(cwd).join(path),
+        _ => path.to_path_buf(),
+    };
+    let normalized: PathBuf = joined
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect();
+    normalized.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    fn file(path: &str, line: Option<u32>, column: Option<u32>) -> LinkTarget {
+        LinkTarget::File {
+            path: path.to_string(),
+            line,
+            column,
+        }
+    }
+
+    fn targets(term: &mut VtTerminal) -> Vec<LinkTarget> {
+        term.detect_links().into_iter().map(|l| l.target).collect()
+    }
+
+    #[test]
+    fn test_split_position() {
+        assert_eq!(
+            split_position("src/a.rs:12:5"),
+            ("src/a.rs", Some(12), Some(5))
+        );
+        assert_eq!(split_position("src/a.rs:12"), ("src/a.rs", Some(12), None));
+        assert_eq!(split_position("src/a.rs"), ("src/a.rs", None, None));
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("src/a.rs", Some("/work")), "/work/src/a.rs");
+        assert_eq!(resolve("./a.rs", Some("/work")), "/work/a.rs");
+        assert_eq!(resolve("/etc/hosts", Some("/work")), "/etc/hosts");
+        assert_eq!(resolve("~/notes.md", Some("/work")), "~/notes.md");
+        assert_eq!(resolve("src/a.rs", None), "src/a.rs");
+    }
+
+    #[test]
+    fn test_detects_compiler_locations() {
+        let mut term = VtTerminal::new(60, 5);
+        term.write(b"\x1b]7;file://host/home/me/proj\x07");
+        term.write(b"error[E0425]: cannot find value `x`\r\n");
+        term.write(b"  --> src/main.rs:12:5\r\n");
+        term.write(b"main.rs:3 and ./build.sh");
+
+        let links = term.detect_links();
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].text, "src/main.rs:12:5");
+        assert_eq!(links[0].start, GridPoint::new(1, 6));
+        assert_eq!(links[0].end, GridPoint::new(1, 21));
+        assert_eq!(
+            links[0].target,
+            file("/home/me/proj/src/main.rs", Some(12), Some(5))
+        );
+        assert_eq!(
+            links[1].target,
+            file("/home/me/proj/main.rs", Some(3), None)
+        );
+        assert_eq!(links[2].target, file("/home/me/proj/build.sh", None, None));
+    }
+
+    #[test]
+    fn test_detects_urls_without_trailing_punctuation() {
+        let mut term = VtTerminal::new(80, 5);
+        term.write(b"See https://example.com/docs.\r\n");
+        term.write(b"(https://en.wikipedia.org/wiki/Rust_(language))\r\n");
+        term.write(b"Plain words, v1.2 and a.b are not links");
+
+        assert_eq!(
+            targets(&mut term),
+            vec![
+                LinkTarget::Url("https://example.com/docs".to_string()),
+                LinkTarget::Url("https://en.wikipedia.org/wiki/Rust_(language)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_across_soft_wrap() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"go to https://example.com/a/long/path now");
+
+        let links = term.detect_links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].text, "https://example.com/a/long/path");
+        assert_eq!(links[0].start, GridPoint::new(0, 6));
+        assert_eq!(links[0].end, GridPoint::new(1, 16));
+        assert!(links[0].contains(GridPoint::new(1, 0)));
+        assert!(!links[0].contains(GridPoint::new(1, 18)));
+    }
+
+    #[test]
+    fn test_skips_osc8_links() {
+        let mut term = VtTerminal::new(60, 5);
+        term.write(b"\x1b]8;;https://example.com/x\x1b\\https://example.com/x\x1b]8;;\x1b\\");
+
+        assert!(term.detect_links().is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+//! Terminal modes that change how user input must be encoded.
+
+use bitflags::bitflags;
+
+/// Which mouse events the program asked to receive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MouseTracking {
+    /// Mouse events are not reported; the terminal handles them itself.
+    #[default]
+    None,
+    /// Mode 9: button presses only, without modifiers.
+    X10,
+    /// Mode 1000: presses and releases, including the wheel.
+    Normal,
+    /// Mode 1002: as `Normal`, plus motion while a button is held.
+    ButtonEvent,
+    /// Mode 1003: as `Normal`, plus all motion.
+    AnyEvent,
+}
+
+/// How reported mouse events are encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MouseEncoding {
+    /// `CSI M` followed by three bytes; positions past column 223 cannot be
+    /// reported.
+    #[default]
+    Default,
+    /// Mode 1005: as `Default`, with positions encoded as UTF-8 characters.
+    Utf8,
+    /// Mode 1006: `CSI < b ; x ; y M/m`, no position limit.
+    Sgr,
+}
+
+bitflags! {
+    /// Progressive enhancements of the kitty keyboard protocol, as pushed by
+    /// the program with `CSI > flags u`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct KittyKeyboardFlags: u8 {
+        const DISAMBIGUATE_ESC_CODES  = 0b0_0001;
+        const REPORT_EVENT_TYPES      = 0b0_0010;
+        const REPORT_ALTERNATE_KEYS   = 0b0_0100;
+        const REPORT_ALL_KEYS_AS_ESC  = 0b0_1000;
+        const REPORT_ASSOCIATED_TEXT  = 0b1_0000;
+    }
+}
+
+/// Snapshot of the modes set by the program running in the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TerminalModes {
+    pub mouse_tracking: MouseTracking,
+    pub mouse_encoding: MouseEncoding,
+    /// Mode 1007: on the alternate screen, the wheel sends arrow keys when
+    /// mouse events are not reported.
+    pub alternate_scroll: bool,
+    /// DECCKM: cursor keys send `ESC O` instead of `CSI` sequences.
+    pub app_cursor: bool,
+    /// DECKPAM: the numeric keypad sends application sequences.
+    pub app_keypad: bool,
+    /// xterm's modifyOtherKeys level (0, 1 or 2), set with `CSI > 4 ; n m`.
+    pub modify_other_keys: u8,
+    /// Active kitty keyboard protocol enhancements; empty for legacy input.
+    pub kitty_keyboard: KittyKeyboardFlags,
+    /// Mode 2004: pasted text must be wrapped in `CSI 200~` / `CSI 201~`.
+    pub bracketed_paste: bool,
+    /// Mode 1004: focus changes are reported with `CSI I` / `CSI O`.
+    pub focus_reporting: bool,
+    /// The alternate screen is active.
+    pub alt_screen: bool,
+}
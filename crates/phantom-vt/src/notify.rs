@@ -0,0 +1,242 @@
+//! Desktop notifications and progress reports sent by programs.
+//!
+//! Three families of OSC sequences are understood:
+//!
+//! - `OSC 9 ; message` (iTerm2): a notification with just a body
+//! - `OSC 777 ; notify ; title ; body` (rxvt-unicode): a titled notification
+//! - `OSC 9 ; 4 ; state [; percent]` (ConEmu, Windows Terminal): progress of
+//!   a long-running task, shown on the tab
+//!
+//! ConEmu uses other numeric `OSC 9` subcommands (sleep, message boxes, ...)
+//! that must not pop up as notifications; those are ignored.
+
+use std::collections::VecDeque;
+
+/// Maximum number of notifications waiting for the embedder; older ones are
+/// dropped when a program sends more.
+const MAX_PENDING_NOTIFICATIONS: usize = 16;
+
+/// A notification a program asked to show.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// Title, if the program gave one (OSC 777 only).
+    pub title: Option<String>,
+    /// The message.
+    pub body: String,
+}
+
+/// State of a task reported with `OSC 9 ; 4`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressState {
+    /// Running normally.
+    Normal,
+    /// Failed; the percentage shows how far it got.
+    Error,
+    /// Running, but how far along is unknown.
+    Indeterminate,
+    /// Paused or waiting for attention.
+    Paused,
+}
+
+/// Progress of a long-running task, as last reported by the program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub state: ProgressState,
+    /// Percentage done, 0 to 100. Kept from the last report when the program
+    /// leaves it out, as Windows Terminal does.
+    pub percent: u8,
+}
+
+/// A parsed notification or progress sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum NotifyEvent {
+    Notify(Notification),
+    /// New progress, or `None` to clear it.
+    Progress(Option<ProgressReport>),
+}
+
+/// An `OSC 9 ; 4` report before it is merged with the previous progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ProgressReport {
+    state: ProgressState,
+    percent: Option<u8>,
+}
+
+/// Parse the parameters of an `OSC 9` after the leading `9`.
+pub(crate) fn parse_osc9(params: &[&[u8]]) -> Option<NotifyEvent> {
+    let first = params.first()?;
+    match conemu_command(first) {
+        Some(4) => parse_progress(&params[1..]).map(NotifyEvent::Progress),
+        Some(_) => None,
+        None => {
+            // The parser splits the message at `;`.
+            let body = text(&params.join(&b';'));
+            (!body.is_empty()).then_some(NotifyEvent::Notify(Notification { title: None, body }))
+        }
+    }
+}
+
+/// Parse the parameters of an `OSC 777` after the leading `777`.
+pub(crate) fn parse_osc777(params: &[&[u8]]) -> Option<NotifyEvent> {
+    match params {
+        [b"notify", title, body @ ..] => {
+            let title = text(title);
+            let body = text(&body.join(&b';'));
+            if title.is_empty() && body.is_empty() {
+                return None;
+            }
+            Some(NotifyEvent::Notify(Notification {
+                title: (!title.is_empty()).then_some(title),
+                body,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// The ConEmu subcommand number, if `param` is one.
+fn conemu_command(param: &[u8]) -> Option<u8> {
+    let number = std::str::from_utf8(param).ok()?.parse::<u8>().ok()?;
+    (1..=12).contains(&number).then_some(number)
+}
+
+/// Parse `state [; percent]`; state 0 clears the progress.
+fn parse_progress(params: &[&[u8]]) -> Option<Option<ProgressReport>> {
+    let number =
+        |param: Option<&&[u8]>| -> Option<u16> { std::str::from_utf8(param?).ok()?.parse().ok() };
+    let state = match number(params.first()).unwrap_or(0) {
+        0 => return Some(None),
+        1 => ProgressState::Normal,
+        2 => ProgressState::Error,
+        3 => ProgressState::Indeterminate,
+        4 => ProgressState::Paused,
+        _ => return None,
+    };
+    let percent = number(params.get(1)).map(|p| p.min(100) as u8);
+    Some(Some(ProgressReport { state, percent }))
+}
+
+/// Decode text, dropping surrounding whitespace.
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim().to_string()
+}
+
+/// Notifications waiting for the embedder, and the current progress.
+#[derive(Default)]
+pub(crate) struct Notifications {
+    pending: VecDeque<Notification>,
+    progress: Option<Progress>,
+}
+
+impl Notifications {
+    /// Apply a parsed sequence.
+    pub(crate) fn handle(&mut self, event: NotifyEvent) {
+        match event {
+            NotifyEvent::Notify(notification) => {
+                if self.pending.len() == MAX_PENDING_NOTIFICATIONS {
+                    self.pending.pop_front();
+                }
+                self.pending.push_back(notification);
+            }
+            NotifyEvent::Progress(None) => self.progress = None,
+            NotifyEvent::Progress(Some(report)) => {
+                let previous = self.progress.map_or(0, |p| p.percent);
+                self.progress = Some(Progress {
+                    state: report.state,
+                    percent: report.percent.unwrap_or(previous),
+                });
+            }
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<Notification> {
+        self.pending.drain(..).collect()
+    }
+
+    pub(crate) fn progress(&self) -> Option<Progress> {
+        self.progress
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+        self.progress = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(payload: &[u8]) -> Vec<&[u8]> {
+        payload.split(|&b| b == b';').collect()
+    }
+
+    fn notify(title: Option<&str>, body: &str) -> Option<NotifyEvent> {
+        Some(NotifyEvent::Notify(Notification {
+            title: title.map(str::to_string),
+            body: body.to_string(),
+        }))
+    }
+
+    #[test]
+    fn test_notifications() {
+        assert_eq!(
+            parse_osc9(&split(b"Build done; 3 warnings")),
+            notify(None, "Build done; 3 warnings")
+        );
+        // ConEmu commands other than progress are not notifications.
+        assert_eq!(parse_osc9(&split(b"2;message box")), None);
+        assert_eq!(parse_osc9(&split(b"9;C:\\")), None);
+        assert_eq!(parse_osc9(&split(b"")), None);
+        // Numbers outside ConEmu's range are plain messages.
+        assert_eq!(parse_osc9(&split(b"42")), notify(None, "42"));
+
+        assert_eq!(
+            parse_osc777(&split(b"notify;Claude;Needs input; continue?")),
+            notify(Some("Claude"), "Needs input; continue?")
+        );
+        assert_eq!(parse_osc777(&split(b"notify;;done")), notify(None, "done"));
+        assert_eq!(parse_osc777(&split(b"notify;;")), None);
+        assert_eq!(parse_osc777(&split(b"preexec;ls")), None);
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut notifications = Notifications::default();
+        let mut report = |payload: &[u8]| {
+            notifications.handle(parse_osc9(&split(payload)).unwrap());
+            notifications.progress()
+        };
+
+        let normal = |percent| Progress {
+            state: ProgressState::Normal,
+            percent,
+        };
+        assert_eq!(report(b"4;1;40"), Some(normal(40)));
+        assert_eq!(report(b"4;1;250"), Some(normal(100)));
+        // A missing percentage keeps the last one.
+        let error = Progress {
+            state: ProgressState::Error,
+            percent: 100,
+        };
+        assert_eq!(report(b"4;2"), Some(error));
+        assert_eq!(
+            report(b"4;3").map(|p| p.state),
+            Some(ProgressState::Indeterminate)
+        );
+        assert_eq!(report(b"4;0"), None);
+        assert_eq!(parse_osc9(&split(b"4;7;10")), None);
+    }
+
+    #[test]
+    fn test_pending_notifications_capped() {
+        let mut notifications = Notifications::default();
+        for i in 0..20 {
+            notifications.handle(parse_osc9(&[format!("n{i}").as_bytes()]).unwrap());
+        }
+        let taken = notifications.take();
+        assert_eq!(taken.len(), MAX_PENDING_NOTIFICATIONS);
+        assert_eq!(taken[0].body, "n4");
+        assert!(notifications.take().is_empty());
+    }
+}
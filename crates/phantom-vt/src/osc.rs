@@ -0,0 +1,472 @@
+//! Side-channel scanning for sequences alacritty does not expose.
+//!
+//! alacritty's `ansi::Processor` silently drops OSC commands it does not know
+//! about (OSC 7, OSC 133, OSC 9/777 notifications and friends). To pick those
+//! up without forking the parser, a second `vte::Parser` runs over the same
+//! bytes in lockstep: the scanner stops right after each sequence of interest,
+//! the bytes up to that point are fed to the main parser, and the sequence is
+//! then applied against the up-to-date terminal state. During a synchronized
+//! update only sequences tied to a screen position (shell marks and images)
+//! apply the output held back so far, after which the update resumes; the
+//! rest wait for it to end.
+//!
+//! The scanner also stops at sequences that switch screens, which
+//! `VtTerminal` needs to see in isolation to keep its count of scrolled lines
+//! exact, at private modes alacritty does not track, and at the icon name and
+//! title stack sequences alacritty only half handles. Clearing the scrollback
+//! needs no event: state pointing into it is pruned after every parser call.
+//!
+//! Image data arrives in sequences the main parser discards: sixel in a DCS
+//! and the kitty graphics protocol in an APC. `vte` never reports APC
+//! contents, so those are picked out by a small byte-level tracker that
+//! follows the same state changes as the parser.
+
+use alacritty_terminal::vte::{Params, Parser, Perform};
+
+use crate::notify::{parse_osc777, parse_osc9, NotifyEvent};
+use crate::shell::{parse_osc133, ShellMark};
+use crate::titles::TitleTarget;
+
+/// A sequence recognized by the scanner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ScanEvent {
+    /// OSC 7: the shell reported its current working directory.
+    WorkingDirectory(String),
+    /// OSC 133: a semantic prompt mark.
+    ShellMark(ShellMark),
+    /// OSC 9 / OSC 777: a notification or progress report.
+    Notify(NotifyEvent),
+    /// OSC 0 / OSC 1: the icon name was set. alacritty handles the title.
+    IconName(String),
+    /// CSI 22/23 ; Ps t: save or restore the title and/or icon name.
+    TitleStack { push: bool, target: TitleTarget },
+    /// CSI 16 t: report the cell size in pixels.
+    ReportCellSize,
+    /// The alternate screen was entered or left.
+    AltScreen,
+    /// CSI ? 9 h/l: X10 mouse reporting, which alacritty does not implement.
+    X10Mouse(bool),
+    /// CSI > 4 ; n m: xterm's modifyOtherKeys level, ignored by alacritty.
+    ModifyOtherKeys(u8),
+    /// RIS (`ESC c`): full terminal reset.
+    Reset,
+    /// APC `G ... ST`: a kitty graphics command, without the leading `G`.
+    KittyGraphics(Vec<u8>),
+    /// DCS `P1 ; P2 ; P3 q ... ST`: a sixel image.
+    Sixel { params: Vec<u16>, data: Vec<u8> },
+}
+
+impl ScanEvent {
+    /// Whether the event acts at the cursor, so it must see the screen as it
+    /// stands where the sequence appears.
+    pub(crate) fn is_positional(&self) -> bool {
+        matches!(
+            self,
+            Self::ShellMark(_) | Self::KittyGraphics(_) | Self::Sixel { .. }
+        )
+    }
+}
+
+/// Longest image sequence collected; longer ones are dropped.
+const MAX_IMAGE_SEQUENCE: usize = 32 * 1024 * 1024;
+
+/// Lockstep scanner for sequences the main parser ignores or hides.
+#[derive(Default)]
+pub(crate) struct SideScanner {
+    parser: Parser,
+    collector: Collector,
+    apc: ApcTracker,
+}
+
+impl SideScanner {
+    /// Scan `bytes` until the end of the next recognized sequence.
+    ///
+    /// Returns how many bytes were consumed and the sequence found, if any.
+    /// When no sequence is found, all bytes are consumed.
+    /// If a sequence is found, it ended on the last consumed byte.
+    pub(crate) fn advance(&mut self, bytes: &[u8]) -> (usize, Option<ScanEvent>) {
+        // Stop the parser where a kitty graphics command ends, unless it
+        // finds a sequence of its own before that.
+        let limit = self.apc.find_end(bytes).map_or(bytes.len(), |end| end + 1);
+        let n = self
+            .parser
+            .advance_until_terminated(&mut self.collector, &bytes[..limit]);
+        let apc = self.apc.advance(&bytes[..n]);
+        (n, self.collector.event.take().or(apc))
+    }
+}
+
+#[derive(Default)]
+struct Collector {
+    event: Option<ScanEvent>,
+    /// The sixel DCS being collected.
+    sixel: Option<SixelCollector>,
+}
+
+struct SixelCollector {
+    params: Vec<u16>,
+    data: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Perform for Collector {
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        self.event = match params {
+            [b"7", rest @ ..] => parse_osc7(rest).map(ScanEvent::WorkingDirectory),
+            [b"133", rest @ ..] => parse_osc133(rest).map(ScanEvent::ShellMark),
+            [b"9", rest @ ..] => parse_osc9(rest).map(ScanEvent::Notify),
+            [b"777", rest @ ..] => parse_osc777(rest).map(ScanEvent::Notify),
+            // The parser splits the name at `;`; alacritty trims titles too.
+            [b"0" | b"1", rest @ ..] if !rest.is_empty() => {
+                let name = String::from_utf8_lossy(&rest.join(&b';')).trim().to_string();
+                Some(ScanEvent::IconName(name))
+            }
+            _ => None,
+        };
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        let has_param = |f: fn(u16) -> bool| params.iter().any(|p| f(p[0]));
+        self.event = match (intermediates, action) {
+            ([b'?'], 'h' | 'l') if has_param(|p| matches!(p, 47 | 1047 | 1049)) => {
+                Some(ScanEvent::AltScreen)
+            }
+            ([b'?'], 'h' | 'l') if has_param(|p| p == 9) => {
+                Some(ScanEvent::X10Mouse(action == 'h'))
+            }
+            ([], 't') => {
+                let mut params = params.iter().map(|p| p[0]);
+                match (params.next(), params.next().unwrap_or(0)) {
+                    (Some(16), _) => Some(ScanEvent::ReportCellSize),
+                    (Some(op @ (22 | 23)), which) => {
+                        TitleTarget::from_param(which).map(|target| ScanEvent::TitleStack {
+                            push: op == 22,
+                            target,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            ([b'>'], 'm') => {
+                let mut params = params.iter().map(|p| p[0]);
+                match (params.next(), params.next().unwrap_or(0)) {
+                    (Some(4), level @ 0..=2) => Some(ScanEvent::ModifyOtherKeys(level as u8)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+    }
+
+    fn hook(&mut self, params: &Params, intermediates: &[u8], ignore: bool, action: char) {
+        if !ignore && intermediates.is_empty() && action == 'q' {
+            self.sixel = Some(SixelCollector {
+                params: params.iter().map(|p| p[0]).collect(),
+                data: Vec::new(),
+                overflowed: false,
+            });
+        }
+    }
+
+    fn put(&mut self, byte: u8) {
+        if let Some(sixel) = self.sixel.as_mut() {
+            if sixel.data.len() < MAX_IMAGE_SEQUENCE {
+                sixel.data.push(byte);
+            } else {
+                sixel.overflowed = true;
+            }
+        }
+    }
+
+    fn unhook(&mut self) {
+        if let Some(sixel) = self.sixel.take() {
+            if !sixel.overflowed {
+                self.event = Some(ScanEvent::Sixel {
+                    params: sixel.params,
+                    data: sixel.data,
+                });
+            }
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if !ignore && intermediates.is_empty() && byte == b'c' {
+            self.event = Some(ScanEvent::Reset);
+        }
+    }
+
+    fn terminated(&self) -> bool {
+        self.event.is_some()
+    }
+}
+
+/// Where the APC tracker is in the byte stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ApcState {
+    #[default]
+    Ground,
+    Escape,
+    /// Inside an APC; `kitty` is known once its first byte arrived.
+    Apc { kitty: Option<bool> },
+}
+
+/// Follows the parser's transitions into and out of APC strings, keeping
+/// the contents of kitty graphics commands.
+///
+/// Like `vte`, an APC starts at `ESC _`, ends at the next ESC (normally the
+/// start of ST) and is cancelled by CAN or SUB.
+#[derive(Default)]
+struct ApcTracker {
+    state: ApcState,
+    data: Vec<u8>,
+    overflowed: bool,
+}
+
+impl ApcState {
+    /// The state after `byte`, and whether `byte` ended a kitty command.
+    fn step(self, byte: u8) -> (Self, bool) {
+        match (self, byte) {
+            (ApcState::Apc { kitty }, 0x1b) => (ApcState::Escape, kitty == Some(true)),
+            (ApcState::Apc { .. }, 0x18 | 0x1a) => (ApcState::Ground, false),
+            (ApcState::Apc { kitty: None }, _) => (ApcState::Apc { kitty: Some(byte == b'G') }, false),
+            (ApcState::Apc { .. }, _) => (self, false),
+            (_, 0x1b) => (ApcState::Escape, false),
+            (ApcState::Escape, b'_') => (ApcState::Apc { kitty: None }, false),
+            _ => (ApcState::Ground, false),
+        }
+    }
+}
+
+impl ApcTracker {
+    /// Index of the byte ending the next kitty command in `bytes`, without
+    /// consuming anything.
+    fn find_end(&self, bytes: &[u8]) -> Option<usize> {
+        let mut state = self.state;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let (next, ended) = state.step(byte);
+            if ended {
+                return Some(i);
+            }
+            state = next;
+        }
+        None
+    }
+
+    /// Consume `bytes`, returning the kitty command ending on the last byte.
+    fn advance(&mut self, bytes: &[u8]) -> Option<ScanEvent> {
+        const KITTY: ApcState = ApcState::Apc { kitty: Some(true) };
+        let mut event = None;
+        for &byte in bytes {
+            let (next, ended) = self.state.step(byte);
+            // The leading `G` moves into the kitty state and is not kept.
+            if self.state == KITTY && next == KITTY {
+                if self.data.len() < MAX_IMAGE_SEQUENCE {
+                    self.data.push(byte);
+                } else {
+                    self.overflowed = true;
+                }
+            }
+            if ended {
+                let data = std::mem::take(&mut self.data);
+                if !std::mem::take(&mut self.overflowed) {
+                    event = Some(ScanEvent::KittyGraphics(data));
+                }
+            } else if next != KITTY {
+                self.data.clear();
+                self.overflowed = false;
+            }
+            self.state = next;
+        }
+        event
+    }
+}
+
+/// Parse an OSC 7 payload (`file://host/path`) into a local path.
+///
+/// The host is ignored; the path is percent-decoded. Payloads containing `;`
+/// are split by the parser, so the pieces are joined back together.
+fn parse_osc7(params: &[&[u8]]) -> Option<String> {
+    let uri = params.join(&b';');
+    let rest = uri.strip_prefix(b"file://")?;
+    let path_start = rest.iter().position(|&b| b == b'/')?;
+    let path = percent_decode(&rest[path_start..]);
+    String::from_utf8(path).ok()
+}
+
+/// Decode `%XX` escapes. Malformed escapes are kept as-is.
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' && i + 2 < input.len() {
+            let hex = std::str::from_utf8(&input[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(input[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_all(bytes: &[u8]) -> Vec<(usize, ScanEvent)> {
+        let mut scanner = SideScanner::default();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (n, event) = scanner.advance(&bytes[offset..]);
+            offset += n;
+            if let Some(event) = event {
+                events.push((offset, event));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_osc7_parsed() {
+        let events = scan_all(b"ab\x1b]7;file://host/home/user/src\x07cd");
+        // Scanning stops right after the BEL, before "cd".
+        assert_eq!(
+            events,
+            vec![(
+                32,
+                ScanEvent::WorkingDirectory("/home/user/src".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_osc7_percent_decoded() {
+        let events = scan_all(b"\x1b]7;file:///tmp/my%20dir;x\x1b\\");
+        // The sequence is dispatched on the ESC of the string terminator.
+        assert_eq!(
+            events,
+            vec![(27, ScanEvent::WorkingDirectory("/tmp/my dir;x".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_osc7_split_across_writes() {
+        let mut scanner = SideScanner::default();
+        assert_eq!(scanner.advance(b"\x1b]7;file://h/va"), (15, None));
+        let (n, event) = scanner.advance(b"r/log\x07rest");
+        assert_eq!(n, 6);
+        assert_eq!(
+            event,
+            Some(ScanEvent::WorkingDirectory("/var/log".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ignores_other_osc_and_bad_uris() {
+        let bytes = b"\x1b]2;title\x07\x1b]7;http://x/y\x07\x1b]7;file://nopath\x07";
+        assert!(scan_all(bytes).is_empty());
+    }
+
+    #[test]
+    fn test_osc133_marks() {
+        let events = scan_all(b"\x1b]133;A\x07$ \x1b]133;D;1\x07");
+        assert_eq!(
+            events,
+            vec![
+                (8, ScanEvent::ShellMark(ShellMark::PromptStart)),
+                (
+                    20,
+                    ScanEvent::ShellMark(ShellMark::CommandFinished(Some(1)))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_history_boundaries() {
+        let events: Vec<_> = scan_all(b"\x1b[2J\x1b[3J\x1b[?1049h\x1b[?25l\x1b[?1049l\x1bc\x1b[?9h\x1b[>4;2m")
+            .into_iter()
+            .map(|(_, e)| e)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ScanEvent::AltScreen,
+                ScanEvent::AltScreen,
+                ScanEvent::Reset,
+                ScanEvent::X10Mouse(true),
+                ScanEvent::ModifyOtherKeys(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_titles_and_window_ops() {
+        let events: Vec<_> =
+            scan_all(b"\x1b]0;a;b \x07\x1b]1;icon\x07\x1b[22;2t\x1b[23t\x1b[22;5t\x1b[16t\x1b[18t")
+                .into_iter()
+                .map(|(_, e)| e)
+                .collect();
+        assert_eq!(
+            events,
+            vec![
+                ScanEvent::IconName("a;b".to_string()),
+                ScanEvent::IconName("icon".to_string()),
+                ScanEvent::TitleStack {
+                    push: true,
+                    target: TitleTarget::Title
+                },
+                ScanEvent::TitleStack {
+                    push: false,
+                    target: TitleTarget::Both
+                },
+                ScanEvent::ReportCellSize,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_sequences() {
+        let bytes = b"a\x1b_Gf=24,s=1,v=1;AAAA\x1b\\b\x1b_other\x1b\\\x1bP0;1q#1~-\x1b\\c";
+        assert_eq!(
+            scan_all(bytes),
+            vec![
+                // Both end on the ESC of their string terminator.
+                (22, ScanEvent::KittyGraphics(b"f=24,s=1,v=1;AAAA".to_vec())),
+                (
+                    44,
+                    ScanEvent::Sixel {
+                        params: vec![0, 1],
+                        data: b"#1~-".to_vec(),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kitty_graphics_split_across_writes() {
+        let mut scanner = SideScanner::default();
+        assert_eq!(scanner.advance(b"\x1b_Ga=T;AB"), (9, None));
+        assert_eq!(
+            scanner.advance(b"CD\x1b\\x"),
+            (3, Some(ScanEvent::KittyGraphics(b"a=T;ABCD".to_vec())))
+        );
+        // A cancelled command is dropped.
+        assert_eq!(scanner.advance(b"\\x\x1b_Ga=d\x18\x1b\\"), (11, None));
+    }
+
+    #[test]
+    fn test_percent_decode_malformed() {
+        assert_eq!(percent_decode(b"a%2"), b"a%2");
+        assert_eq!(percent_decode(b"a%zzb"), b"a%zzb");
+        assert_eq!(percent_decode(b"%41%42"), b"AB");
+    }
+}
@@ -0,0 +1,215 @@
+//! Encoding and safety checks for pasted text.
+//!
+//! Pasted text is untrusted: it may come from a web page that hides control
+//! sequences or extra lines in what looks like a harmless command. Escape
+//! sequences are stripped so the text cannot end a bracketed paste early or
+//! drive the terminal, and pastes that would run something dangerous are
+//! flagged so the embedder can ask the user first.
+
+use crate::modes::TerminalModes;
+
+/// Start and end markers of a bracketed paste (mode 2004).
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Shells a downloaded script is commonly piped into.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish"];
+
+/// Why a paste should be confirmed before it is sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasteRisk {
+    /// The text contains a line break and the program did not enable
+    /// bracketed paste, so a shell would run each line as it arrives.
+    MultiLine,
+    /// The text runs a command with `sudo`.
+    Sudo,
+    /// The text downloads a script and pipes it into a shell
+    /// (`curl ... | sh`).
+    PipeToShell,
+}
+
+/// Remove escape sequences and C1 control characters from pasted text, and
+/// turn line breaks into carriage returns as typed Enter keys would be.
+pub fn sanitize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => skip_escape_sequence(&mut chars),
+            '\r' => {
+                chars.next_if_eq(&'\n');
+                out.push('\r');
+            }
+            '\n' => out.push('\r'),
+            '\u{80}'..='\u{9f}' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Skip the rest of an escape sequence whose ESC was just consumed.
+fn skip_escape_sequence(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    match chars.next() {
+        // CSI: parameters and intermediates up to a final byte.
+        Some('[') => {
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+        // OSC, DCS and friends: up to BEL or ST.
+        Some(']' | 'P' | '_' | '^' | 'X') => {
+            while let Some(c) = chars.next() {
+                if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                    break;
+                }
+            }
+        }
+        // Two-character sequences.
+        _ => {}
+    }
+}
+
+/// Encode pasted text for the program: sanitized, and wrapped in bracketed
+/// paste markers if the program enabled mode 2004.
+pub fn encode_paste(text: &str, modes: &TerminalModes) -> Vec<u8> {
+    let text = sanitize(text);
+    if !modes.bracketed_paste {
+        return text.into_bytes();
+    }
+    let mut bytes = Vec::with_capacity(text.len() + PASTE_START.len() + PASTE_END.len());
+    bytes.extend_from_slice(PASTE_START);
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.extend_from_slice(PASTE_END);
+    bytes
+}
+
+/// Check pasted text for reasons to confirm it first.
+///
+/// Line breaks only count outside the alternate screen, where the program
+/// receiving the paste is most likely a shell.
+pub fn paste_risks(text: &str, modes: &TerminalModes) -> Vec<PasteRisk> {
+    let text = sanitize(text);
+    let mut risks = Vec::new();
+    if !modes.bracketed_paste && !modes.alt_screen && text.contains('\r') {
+        risks.push(PasteRisk::MultiLine);
+    }
+
+    let mut sudo = false;
+    let mut pipe_to_shell = false;
+    for line in text.split('\r') {
+        let words = shell_words(line);
+        sudo |= words.contains(&"sudo");
+        pipe_to_shell |= pipes_download_to_shell(&words);
+    }
+    if sudo {
+        risks.push(PasteRisk::Sudo);
+    }
+    if pipe_to_shell {
+        risks.push(PasteRisk::PipeToShell);
+    }
+    risks
+}
+
+/// Split a command line into words, with `|`, `;`, `&` and parentheses as
+/// words of their own. Quoting is ignored; this is a heuristic, not a parser.
+fn shell_words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        let separator = matches!(c, '|' | ';' | '&' | '(' | ')');
+        if c.is_whitespace() || separator {
+            if let Some(s) = start.take() {
+                words.push(&line[s..i]);
+            }
+            if separator {
+                words.push(&line[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push(&line[s..]);
+    }
+    words
+}
+
+/// Whether a `curl` or `wget` is followed by a pipe into a shell, possibly
+/// through `sudo` or `env`.
+fn pipes_download_to_shell(words: &[&str]) -> bool {
+    let Some(download) = words.iter().position(|w| matches!(*w, "curl" | "wget")) else {
+        return false;
+    };
+    let mut piped = false;
+    for word in &words[download + 1..] {
+        match *word {
+            "|" => piped = true,
+            ";" | "&" => piped = false,
+            "sudo" | "env" => {}
+            w if piped => {
+                let name = w.rsplit('/').next().unwrap_or(w);
+                if SHELLS.contains(&name) {
+                    return true;
+                }
+                if !w.starts_with('-') {
+                    piped = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_strips_escapes() {
+        // A paste that tries to end bracketed paste and run a command.
+        let text = "echo hi\x1b[201~rm -rf ~\x1b]0;title\x07\r\nls\u{9b}2J";
+        assert_eq!(sanitize(text), "echo hirm -rf ~\rls2J");
+    }
+
+    #[test]
+    fn test_encode_paste() {
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode_paste("a\nb", &modes), b"a\rb");
+
+        modes.bracketed_paste = true;
+        assert_eq!(encode_paste("a\x1b[201~b", &modes), b"\x1b[200~ab\x1b[201~");
+    }
+
+    #[test]
+    fn test_paste_risks() {
+        let plain = TerminalModes::default();
+        let bracketed = TerminalModes {
+            bracketed_paste: true,
+            ..TerminalModes::default()
+        };
+
+        assert!(paste_risks("ls -la", &plain).is_empty());
+        assert_eq!(paste_risks("ls\npwd", &plain), vec![PasteRisk::MultiLine]);
+        assert!(paste_risks("ls\npwd", &bracketed).is_empty());
+
+        assert_eq!(
+            paste_risks("sudo apt install foo", &bracketed),
+            vec![PasteRisk::Sudo]
+        );
+        assert!(paste_risks("echo pseudo", &bracketed).is_empty());
+
+        assert_eq!(
+            paste_risks("curl -fsSL https://x.sh | bash", &bracketed),
+            vec![PasteRisk::PipeToShell]
+        );
+        assert_eq!(
+            paste_risks("wget -qO- x.sh|sudo /bin/sh -s", &bracketed),
+            vec![PasteRisk::Sudo, PasteRisk::PipeToShell]
+        );
+        assert!(paste_risks("curl x | jq . ; sh build.sh", &bracketed).is_empty());
+    }
+}
@@ -0,0 +1,620 @@
+//! Rewrapping the primary screen and its history on resize.
+//!
+//! alacritty reflows on resize, but loses text when it grows a line the
+//! cursor sits on: the rows a long line wrapped onto below the cursor are
+//! taken for blank and dropped, and so are rows below the cursor when the
+//! screen gets shorter. The cursor also drifts off its place in the text.
+//! So the contents are taken out before alacritty resizes the grid and laid
+//! out again afterwards.
+//!
+//! Soft-wrapped rows are joined into logical lines and wrapped at the new
+//! width; wide characters that no longer fit at the end of a row move to the
+//! next one. Lines that fit on a single row are moved over as they are. The
+//! cursor and the top of the viewport keep their place in the text. Without
+//! reflow, rows are cropped or padded and keep their columns.
+//!
+//! When only the height changes nothing needs rewrapping, and alacritty
+//! resizes the grid in place once the cursor's line is made to fit.
+
+use alacritty_terminal::grid::{Dimensions, Grid, Row as GridRow, Scroll};
+use alacritty_terminal::index::{Column, Line, Point};
+use alacritty_terminal::term::cell::{Cell, Flags};
+
+/// Flags that make a space visible.
+const VISIBLE_BLANK_FLAGS: Flags = Flags::INVERSE
+    .union(Flags::UNDERLINE)
+    .union(Flags::DOUBLE_UNDERLINE)
+    .union(Flags::UNDERCURL)
+    .union(Flags::DOTTED_UNDERLINE)
+    .union(Flags::DASHED_UNDERLINE)
+    .union(Flags::STRIKEOUT);
+
+/// A row of cells, without the wrap flag; `cells` may be shorter than the
+/// grid is wide.
+struct Row {
+    cells: Vec<Cell>,
+    wrapped: bool,
+}
+
+/// A position in a list of rows.
+#[derive(Clone, Copy)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    needs_wrap: bool,
+}
+
+/// The rows of a grid's history and screen, ready to be laid out at another
+/// size.
+pub(crate) struct GridContents {
+    /// Oldest first.
+    rows: Vec<Row>,
+    cursor: Cursor,
+    /// Screen line of the cursor.
+    cursor_line: usize,
+    screen_lines: usize,
+    /// Row shown at the top of the viewport, when scrolled back.
+    viewport_top: Option<usize>,
+}
+
+impl GridContents {
+    /// Take the contents out of `grid`, leaving it blank and without history
+    /// for alacritty to resize.
+    ///
+    /// Cells are moved rather than copied, so this costs little more than a
+    /// look at the end of every row.
+    pub(crate) fn take(grid: &mut Grid<Cell>) -> Self {
+        let cols = grid.columns();
+        let topmost = grid.topmost_line().0;
+        let cursor = grid.cursor.clone();
+        let cursor_row = (cursor.point.line.0 - topmost) as usize;
+
+        let rows = (topmost..=grid.bottommost_line().0)
+            .enumerate()
+            .map(|(i, line)| {
+                let mut row = std::mem::replace(&mut grid[Line(line)], GridRow::new(1));
+                let mut cells = row.front_split_off(cols);
+                let wrapped = cells[cols - 1].flags.contains(Flags::WRAPLINE);
+                cells[cols - 1].flags.remove(Flags::WRAPLINE);
+                // Blanks at the end of a wrapped row are part of the text.
+                if !wrapped {
+                    let default = Cell::default();
+                    let mut end = cells
+                        .iter()
+                        .rposition(|c| *c != default)
+                        .map_or(0, |i| i + 1);
+                    if i == cursor_row {
+                        let needs_wrap = cursor.input_needs_wrap as usize;
+                        end = end.max(cursor.point.column.0 + needs_wrap);
+                    }
+                    cells.truncate(end);
+                }
+                Row { cells, wrapped }
+            })
+            .collect();
+
+        let offset = grid.display_offset();
+        let contents = Self {
+            rows,
+            cursor: Cursor {
+                row: cursor_row,
+                col: cursor.point.column.0,
+                needs_wrap: cursor.input_needs_wrap,
+            },
+            cursor_line: cursor.point.line.0 as usize,
+            screen_lines: grid.screen_lines(),
+            viewport_top: (offset > 0).then(|| (-(offset as i32) - topmost) as usize),
+        };
+        grid.clear_history();
+        for line in 0..grid.screen_lines() {
+            grid[Line(line as i32)] = GridRow::new(cols);
+        }
+        contents
+    }
+
+    /// Lay the contents out in `grid`, which was resized already, keeping at
+    /// most `history` lines of history.
+    pub(crate) fn restore(self, grid: &mut Grid<Cell>, reflow: bool, history: usize) {
+        let screen = grid.screen_lines();
+        // The cursor stays on its screen line, and moves down by as many lines
+        // as the screen grew when the history can fill them, as on a resize
+        // without reflow.
+        let cursor_line = self.cursor_line + screen.saturating_sub(self.screen_lines);
+
+        let width = grid.columns();
+        let (rows, cursor, line_end, viewport_top) = if reflow {
+            self.rewrap(width)
+        } else {
+            self.crop(width)
+        };
+
+        // Scroll up for the rest of the cursor's line if it no longer fits
+        // below the cursor, but keep the cursor on the screen. Other rows
+        // below the screen are lost, as alacritty drops them.
+        let screen_top = cursor
+            .row
+            .saturating_sub(cursor_line)
+            .max((line_end + 1).saturating_sub(screen).min(cursor.row));
+        write_rows(grid, rows, cursor, screen_top, viewport_top, history);
+    }
+
+    /// Join wrapped rows into lines and wrap them at `width`.
+    ///
+    /// Returns the rows, the cursor, the last row of the cursor's line and
+    /// the row at the top of the viewport.
+    fn rewrap(self, width: usize) -> (Vec<Row>, Cursor, usize, Option<usize>) {
+        let mut laid_out = Layout {
+            width,
+            rows: Vec::new(),
+            cursor: None,
+            cursor_line_end: 0,
+            viewport_top: None,
+        };
+        let mut line = Vec::new();
+        let mut cursor = None;
+        let mut viewport_top = None;
+        for (i, row) in self.rows.into_iter().enumerate() {
+            if i == self.cursor.row {
+                cursor = Some(line.len() + self.cursor.col + self.cursor.needs_wrap as usize);
+            }
+            if self.viewport_top == Some(i) {
+                viewport_top = Some(line.len());
+            }
+            // A line on a row of its own that fits stays as it is.
+            let plain = !row.wrapped && line.is_empty() && cursor.is_none();
+            if plain
+                && row.cells.len() <= width
+                && width > 1
+                && i != self.cursor.row
+                && self.viewport_top != Some(i)
+            {
+                laid_out.rows.push(row);
+                continue;
+            }
+            let mut cells = row.cells;
+            if row.wrapped
+                && cells
+                    .last()
+                    .is_some_and(|c| c.flags.contains(Flags::LEADING_WIDE_CHAR_SPACER))
+            {
+                cells.pop();
+            }
+            line.extend(cells);
+            if !row.wrapped {
+                laid_out.line(
+                    std::mem::take(&mut line),
+                    cursor.take(),
+                    viewport_top.take(),
+                );
+            }
+        }
+        // The last row can only be wrapped if it was cut off by the history
+        // limit.
+        if !line.is_empty() || cursor.is_some() {
+            laid_out.line(line, cursor, viewport_top);
+        }
+
+        let cursor = laid_out.cursor.unwrap_or(Cursor {
+            row: laid_out.rows.len().saturating_sub(1),
+            col: 0,
+            needs_wrap: false,
+        });
+        let line_end = laid_out.cursor_line_end.max(cursor.row);
+        (laid_out.rows, cursor, line_end, laid_out.viewport_top)
+    }
+
+    /// Crop or pad every row to `width`.
+    fn crop(self, width: usize) -> (Vec<Row>, Cursor, usize, Option<usize>) {
+        let rows = self
+            .rows
+            .into_iter()
+            .map(|mut row| {
+                if row.cells.len() > width {
+                    row.cells.truncate(width);
+                    let last = &mut row.cells[width - 1];
+                    if last.flags.contains(Flags::WIDE_CHAR) {
+                        *last = Cell::default();
+                    }
+                }
+                row
+            })
+            .collect();
+        let cursor = Cursor {
+            col: self.cursor.col.min(width - 1),
+            ..self.cursor
+        };
+        (rows, cursor, cursor.row, self.viewport_top)
+    }
+}
+
+/// Wraps lines into rows, keeping track of where the cursor and the top of
+/// the viewport end up.
+struct Layout {
+    width: usize,
+    rows: Vec<Row>,
+    cursor: Option<Cursor>,
+    /// Last row of the line the cursor is on.
+    cursor_line_end: usize,
+    viewport_top: Option<usize>,
+}
+
+impl Layout {
+    /// Wrap one line. `cursor` and `viewport_top` are cell offsets into it.
+    fn line(&mut self, cells: Vec<Cell>, cursor: Option<usize>, viewport_top: Option<usize>) {
+        // Blanks past the text and the cursor are dropped, not wrapped, so a
+        // line filled with a background color stays one row.
+        let text_end = cells
+            .iter()
+            .rposition(|c| !is_blank(c))
+            .map_or(0, |i| i + 1);
+        let keep = text_end.max(cursor.unwrap_or(0));
+
+        let len = cells.len();
+        let mut row: Vec<Cell> = Vec::with_capacity(self.width);
+        let mut placed_cursor = false;
+        let mut skip_spacer = false;
+        for (i, mut cell) in cells.into_iter().enumerate() {
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                // Follows its wide character, which made room for it.
+                if !std::mem::take(&mut skip_spacer) {
+                    if cursor == Some(i) {
+                        self.set_cursor(row.len(), false);
+                        placed_cursor = true;
+                    }
+                    row.push(cell);
+                }
+                continue;
+            }
+            let mut cell_width = 1;
+            if cell.flags.contains(Flags::WIDE_CHAR) {
+                if self.width < 2 {
+                    cell.c = ' ';
+                    cell.flags.remove(Flags::WIDE_CHAR);
+                    skip_spacer = true;
+                } else {
+                    cell_width = 2;
+                }
+            }
+
+            if row.len() + cell_width > self.width {
+                if i >= keep {
+                    break;
+                }
+                if row.len() < self.width {
+                    let mut spacer = Cell::default();
+                    spacer.flags.insert(Flags::LEADING_WIDE_CHAR_SPACER);
+                    row.push(spacer);
+                }
+                self.rows.push(Row {
+                    cells: std::mem::take(&mut row),
+                    wrapped: true,
+                });
+            }
+
+            if cursor == Some(i) {
+                self.set_cursor(row.len(), false);
+                placed_cursor = true;
+            }
+            if viewport_top == Some(i) {
+                self.viewport_top = Some(self.rows.len());
+            }
+            row.push(cell);
+        }
+
+        if cursor.is_some() && !placed_cursor {
+            // After the text, or on a blank that was dropped.
+            if row.len() >= self.width {
+                self.set_cursor(self.width - 1, true);
+            } else {
+                self.set_cursor(row.len(), false);
+            }
+        }
+        if viewport_top.is_some_and(|top| top >= len) {
+            self.viewport_top = Some(self.rows.len());
+        }
+        self.rows.push(Row {
+            cells: row,
+            wrapped: false,
+        });
+        if cursor.is_some() {
+            self.cursor_line_end = self.rows.len() - 1;
+        }
+    }
+
+    /// Put the cursor at `col` of the row being filled.
+    fn set_cursor(&mut self, col: usize, needs_wrap: bool) {
+        self.cursor = Some(Cursor {
+            row: self.rows.len(),
+            col,
+            needs_wrap,
+        });
+    }
+}
+
+/// Whether a cell shows nothing but its background.
+fn is_blank(cell: &Cell) -> bool {
+    cell.c == ' '
+        && !cell.flags.intersects(VISIBLE_BLANK_FLAGS)
+        && cell.zerowidth().is_none()
+        && cell.hyperlink().is_none()
+}
+
+/// Replace the history and screen of `grid` with `rows`, showing them from
+/// `screen_top` on. Rows below the screen are lost, as when it shrinks.
+fn write_rows(
+    grid: &mut Grid<Cell>,
+    rows: Vec<Row>,
+    cursor: Cursor,
+    screen_top: usize,
+    viewport_top: Option<usize>,
+    history: usize,
+) {
+    let screen = grid.screen_lines();
+    let width = grid.columns();
+    let first = screen_top.saturating_sub(history);
+
+    // Make room for the history all at once, then fill it in.
+    grid.clear_history();
+    let history_lines = screen_top - first;
+    if history_lines > 0 {
+        grid.scroll_up(&(Line(0)..Line(screen as i32)), history_lines);
+    }
+    let mut rows = rows.into_iter().skip(first);
+    for line in -(history_lines as i32)..screen as i32 {
+        write_row(grid, line, rows.next());
+    }
+
+    grid.cursor.point = Point::new(
+        Line((cursor.row - screen_top) as i32),
+        Column(cursor.col.min(width - 1)),
+    );
+    grid.cursor.input_needs_wrap = cursor.needs_wrap;
+
+    if let Some(top) = viewport_top.filter(|&top| top < screen_top) {
+        grid.scroll_display(Scroll::Delta((screen_top - top.max(first)) as i32));
+    }
+}
+
+/// Scroll the screen up so that the rows the cursor's line wraps onto below
+/// the cursor still fit when it shrinks to `lines`, keeping the cursor on
+/// it; alacritty only keeps the cursor itself in view.
+///
+/// A resize that keeps the width needs no rewrapping, so alacritty resizes
+/// the grid in place after this. Returns whether it scrolled.
+pub(crate) fn fit_cursor_line(grid: &mut Grid<Cell>, lines: usize) -> bool {
+    let cursor = grid.cursor.point.line.0 as usize;
+    let last = grid.last_column();
+    let mut line_end = cursor;
+    while line_end + 1 < grid.screen_lines()
+        && grid[Line(line_end as i32)][last].flags.contains(Flags::WRAPLINE)
+    {
+        line_end += 1;
+    }
+    // alacritty scrolls as far as the cursor needs by itself.
+    let needed = (line_end + 1).saturating_sub(lines).min(cursor);
+    if needed <= (cursor + 1).saturating_sub(lines) {
+        return false;
+    }
+    let region = Line(0)..Line(grid.screen_lines() as i32);
+    grid.scroll_up(&region, needed);
+    grid.cursor.point.line -= needed;
+    true
+}
+
+/// Blank wide characters in the last column of the screen, whose spacer a
+/// resize without reflow cut off.
+pub(crate) fn blank_cut_wide_chars(grid: &mut Grid<Cell>) {
+    let last = Column(grid.columns() - 1);
+    for line in 0..grid.screen_lines() {
+        let cell = &mut grid[Line(line as i32)][last];
+        if cell.flags.contains(Flags::WIDE_CHAR) {
+            *cell = Cell::default();
+        }
+    }
+}
+
+/// Fill a line with a row, or blanks.
+fn write_row(grid: &mut Grid<Cell>, line: i32, row: Option<Row>) {
+    let width = grid.columns();
+    let (mut cells, wrapped) = row.map_or((Vec::new(), false), |r| (r.cells, r.wrapped));
+    let occupied = if wrapped { width } else { cells.len() };
+    cells.resize(width, Cell::default());
+    if wrapped {
+        cells[width - 1].flags.insert(Flags::WRAPLINE);
+    }
+    grid[Line(line)] = GridRow::from_vec(cells, occupied);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use alacritty_terminal::grid::Dimensions;
+    use alacritty_terminal::index::{Column, Line};
+    use alacritty_terminal::term::cell::{Cell, Flags};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use crate::terminal::VtTerminal;
+    use crate::ExportFormat;
+
+    /// Random text with spaces and wide characters, some of it long enough
+    /// to wrap.
+    fn random_lines() -> impl Strategy<Value = Vec<String>> {
+        let alphanumeric = proptest::char::ranges(vec!['a'..='z', 'A'..='Z', '0'..='9'].into());
+        let char = prop_oneof![1 => Just('日'), 1 => Just(' '), 8 => alphanumeric];
+        vec(vec(char, 0..60).prop_map(String::from_iter), 1..30)
+    }
+
+    /// The cursor as a line of text (counting from the oldest) and a
+    /// character offset into it.
+    fn text_cursor(vt: &VtTerminal) -> (usize, usize) {
+        let term = vt.inner();
+        let grid = term.grid();
+        let cursor = grid.cursor.point;
+        let (mut line, mut offset) = (0, 0);
+        for row in term.topmost_line().0..=cursor.line.0 {
+            let cells = &grid[Line(row)];
+            // A pending wrap puts the cursor past the last column.
+            let end = if row != cursor.line.0 {
+                term.columns()
+            } else {
+                cursor.column.0 + grid.cursor.input_needs_wrap as usize
+            };
+            offset += (0..end)
+                .filter(|&col| {
+                    let spacers = Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER;
+                    !cells[Column(col)].flags.intersects(spacers)
+                })
+                .count();
+            if row != cursor.line.0 && !cells[term.last_column()].flags.contains(Flags::WRAPLINE) {
+                line += 1;
+                offset = 0;
+            }
+        }
+        (line, offset)
+    }
+
+    /// The text of a screen row, without wide character spacers.
+    fn row_text(vt: &VtTerminal, row: i32) -> String {
+        let term = vt.inner();
+        let cells = &term.grid()[Line(row)];
+        (0..term.columns())
+            .map(|col| &cells[Column(col)])
+            .filter(|cell| !cell.flags.contains(Flags::WIDE_CHAR_SPACER))
+            .map(|cell| cell.c)
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_resizes_keep_text_and_cursor(
+            (cols, rows) in (5u16..40, 3u16..12),
+            lines in random_lines(),
+            back in 0u8..5,
+            // Half the resizes keep the width.
+            resizes in vec((prop::option::of(5u16..40), 3u16..12, 0i32..10), 1..6),
+        ) {
+            let mut vt = VtTerminal::new(cols, rows);
+            vt.write(lines.join("\r\n").as_bytes());
+            // Leave some text after the cursor, as when editing a command.
+            vt.write(format!("\x1b[{back}D").as_bytes());
+
+            let text = vt.export(ExportFormat::Text, None);
+            let cursor = text_cursor(&vt);
+            for (cols, rows, scroll) in resizes {
+                let cols = cols.unwrap_or(vt.inner().columns() as u16);
+                vt.scroll_display(scroll);
+                vt.resize(cols, rows);
+                prop_assert_eq!(&vt.export(ExportFormat::Text, None), &text, "resized to {}x{}", cols, rows);
+                prop_assert_eq!(text_cursor(&vt), cursor, "resized to {}x{}", cols, rows);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cursor_line_stays_on_screen() {
+        let mut vt = VtTerminal::new(20, 5);
+        for i in 0..10 {
+            vt.write(format!("line {i}\r\n").as_bytes());
+        }
+        vt.write(b"$ a long command\x1b[10D");
+
+        // The command wraps onto three rows; the screen scrolls so the one
+        // below the cursor is still shown.
+        vt.resize(6, 5);
+        assert_eq!(row_text(&vt, 0), "line 8");
+        assert_eq!(row_text(&vt, 2), "$ a lo");
+        assert_eq!(row_text(&vt, 4), "mand  ");
+        assert_eq!((vt.cursor().row, vt.cursor().col), (3, 0));
+
+        vt.resize(40, 5);
+        assert_eq!(row_text(&vt, 3).trim_end(), "$ a long command");
+        assert_eq!((vt.cursor().row, vt.cursor().col), (3, 6));
+    }
+
+    #[test]
+    fn test_primary_reflows_behind_alt_screen() {
+        let mut vt = VtTerminal::new(10, 4);
+        vt.write(b"0123456789abcdef\r\n$ ");
+        vt.write(b"\x1b[?1049h\x1b[Hfullscreen\x1b[2;1Hbar");
+
+        vt.resize(5, 4);
+        assert!(vt.modes().alt_screen);
+        assert_eq!(row_text(&vt, 0), "fulls");
+        assert_eq!(row_text(&vt, 1), "bar  ");
+        assert_eq!((vt.cursor().row, vt.cursor().col), (1, 3));
+
+        vt.write(b"\x1b[?1049l");
+        vt.resize(20, 4);
+        assert_eq!(vt.export(ExportFormat::Text, None), "0123456789abcdef\n$");
+    }
+
+    #[test]
+    fn test_resize_without_reflow() {
+        let mut vt = VtTerminal::new(10, 4);
+        vt.set_reflow(false);
+        vt.write("0123456789abc\r\n日本日本".as_bytes());
+
+        vt.resize(5, 4);
+        assert_eq!(row_text(&vt, 0), "01234");
+        assert_eq!(row_text(&vt, 1), "abc  ");
+        assert_eq!(row_text(&vt, 2), "日本 ");
+        assert_eq!((vt.cursor().row, vt.cursor().col), (2, 4));
+
+        vt.resize(10, 4);
+        assert_eq!(row_text(&vt, 2), "日本      ");
+    }
+
+    #[test]
+    fn test_resize_cost() {
+        // Where a row of the history lives in memory, found by its text.
+        fn row_address(vt: &VtTerminal, text: &str) -> *const Cell {
+            let term = vt.inner();
+            let line = (term.topmost_line().0..0)
+                .find(|&line| row_text(vt, line).trim_end() == text)
+                .unwrap();
+            &term.grid()[Line(line)][Column(0)]
+        }
+
+        let mut vt = VtTerminal::new(200, 50);
+        let text: String = (0..12_000).map(|i| format!("line {i}\r\n")).collect();
+        vt.write(text.as_bytes());
+        let address = row_address(&vt, "line 6000");
+
+        // Changing the height leaves a full history where it is.
+        let start = Instant::now();
+        vt.resize(200, 40);
+        vt.resize(200, 60);
+        let height_only = start.elapsed();
+        assert_eq!(row_address(&vt, "line 6000"), address);
+
+        // Changing the width moves the lines that still fit on one row
+        // instead of copying them.
+        let start = Instant::now();
+        vt.resize(150, 60);
+        let width = start.elapsed();
+        assert_eq!(row_address(&vt, "line 6000"), address);
+
+        // Both stay well below the time a full copy used to take; the
+        // bounds only catch a change of scale.
+        assert!(height_only < Duration::from_millis(200), "{height_only:?}");
+        assert!(width < Duration::from_secs(3), "{width:?}");
+    }
+
+    #[test]
+    fn test_wide_chars_at_narrow_sizes() {
+        let mut vt = VtTerminal::new(10, 4);
+        vt.write("a日本".as_bytes());
+        vt.resize(1, 4);
+        vt.resize(10, 4);
+        assert_eq!(row_text(&vt, 0).trim_end(), "a");
+
+        // The alternate screen is cropped, halving the wide character at
+        // the new edge.
+        vt.write("\x1b[?1049h\x1b[Hab日".as_bytes());
+        vt.resize(3, 4);
+        assert_eq!(row_text(&vt, 0), "ab ");
+        assert!(!vt.screen().row_cells(0).iter().any(|cell| cell.width == 2));
+    }
+}
@@ -1,25 +1,38 @@
+use std::cell::RefCell;
+
 use alacritty_terminal::grid::Dimensions;
 use alacritty_terminal::index::{Column, Line};
 use alacritty_terminal::term::cell::Flags as AlacFlags;
 use alacritty_terminal::term::Term;
-use alacritty_terminal::vte::ansi::{Color, CursorShape as AlacCursorShape, NamedColor, Rgb as AlacRgb};
+use alacritty_terminal::vte::ansi::{Color, CursorShape as AlacCursorShape};
 
-use crate::cell::{CellFlags, Rgb, VtCell};
+use crate::cell::{CellFlags, Rgb, UnderlineStyle, VtCell};
+use crate::hyperlink::HyperlinkTable;
 use crate::terminal::EventProxy;
+use crate::theme::Theme;
 
 /// Current state of the cursor.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CursorState {
     pub row: u16,
     pub col: u16,
+    /// The shape set by the program, or `HollowBlock` while the terminal is
+    /// not focused.
     pub shape: CursorShape,
     pub visible: bool,
+    /// The program asked for a blinking cursor (DECSCUSR 0, 1, 3 or 5, or
+    /// mode 12). Always `false` while the terminal is not focused.
+    pub blinking: bool,
+    /// Whether the terminal has keyboard focus (see `VtTerminal::set_focused`).
+    pub focused: bool,
 }
 
 /// Shape of the terminal cursor.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CursorShape {
     Block,
+    /// Outline of a block, drawn where the terminal is not focused.
+    HollowBlock,
     Underline,
     Bar,
     Hidden,
@@ -28,11 +41,21 @@ pub enum CursorShape {
 /// A read-only view into the terminal screen.
 pub struct ScreenView<'a> {
     term: &'a Term<EventProxy>,
+    hyperlinks: &'a RefCell<HyperlinkTable>,
+    theme: &'a Theme,
 }
 
 impl<'a> ScreenView<'a> {
-    pub(crate) fn new(term: &'a Term<EventProxy>) -> Self {
-        Self { term }
+    pub(crate) fn new(
+        term: &'a Term<EventProxy>,
+        hyperlinks: &'a RefCell<HyperlinkTable>,
+        theme: &'a Theme,
+    ) -> Self {
+        Self {
+            term,
+            hyperlinks,
+            theme,
+        }
     }
 
     /// Number of visible rows.
@@ -45,20 +68,35 @@ impl<'a> ScreenView<'a> {
         self.term.columns() as u16
     }
 
+    /// Number of lines in the scrollback history (excluding the visible screen).
+    pub fn history_size(&self) -> usize {
+        self.term.history_size()
+    }
+
+    /// Total number of lines held by the terminal, scrollback plus screen.
+    pub fn total_lines(&self) -> usize {
+        self.term.total_lines()
+    }
+
+    /// How many lines the viewport is scrolled up into the history.
+    ///
+    /// 0 means the viewport shows the live screen.
+    pub fn display_offset(&self) -> usize {
+        self.term.grid().display_offset()
+    }
+
     /// Get a single cell at the given row and column.
     ///
-    /// Row 0 is the top of the visible screen.
+    /// Row 0 is the top of the viewport. When the viewport is scrolled into
+    /// the history, this reads the scrolled-back content rather than the live
+    /// screen.
     pub fn cell(&self, row: u16, col: u16) -> VtCell {
-        let line = Line(row as i32);
-        let column = Column(col as usize);
-        let grid = self.term.grid();
-
-        if (row as usize) >= self.term.screen_lines() || (col as usize) >= self.term.columns() {
+        if (row as usize) >= self.term.screen_lines() {
             return VtCell::default();
         }
 
-        let cell = &grid[line][column];
-        convert_cell(cell, self.term.colors())
+        let line = row as i32 - self.display_offset() as i32;
+        self.line_cell(line, col)
     }
 
     /// Get all cells in a row.
@@ -67,6 +105,48 @@ impl<'a> ScreenView<'a> {
         (0..cols).map(|col| self.cell(row, col)).collect()
     }
 
+    /// Get a single cell on a grid line, independent of the viewport.
+    ///
+    /// Line 0 is the top of the live screen, negative lines index into the
+    /// scrollback (-1 is the most recent history line). Out-of-range lines and
+    /// columns return a default cell.
+    pub fn line_cell(&self, line: i32, col: u16) -> VtCell {
+        if !self.contains_line(line) || (col as usize) >= self.term.columns() {
+            return VtCell::default();
+        }
+
+        let cell = &self.term.grid()[Line(line)][Column(col as usize)];
+        let mut vt_cell = convert_cell(cell, self.term.colors(), self.theme);
+        if let Some(link) = cell.hyperlink() {
+            vt_cell.hyperlink_id = self.hyperlinks.borrow_mut().intern(&link);
+        }
+        vt_cell
+    }
+
+    /// Get all cells on a grid line, using the same indexing as `line_cell`.
+    ///
+    /// Returns `None` if the line is outside the scrollback and screen.
+    pub fn line_cells(&self, line: i32) -> Option<Vec<VtCell>> {
+        if !self.contains_line(line) {
+            return None;
+        }
+        let cols = self.cols();
+        Some((0..cols).map(|col| self.line_cell(line, col)).collect())
+    }
+
+    /// Get all cells on a line by absolute index.
+    ///
+    /// Index 0 is the oldest line in the scrollback and `total_lines() - 1`
+    /// is the bottom row of the live screen.
+    pub fn history_line_cells(&self, index: usize) -> Option<Vec<VtCell>> {
+        let line = index as i64 - self.history_size() as i64;
+        i32::try_from(line).ok().and_then(|line| self.line_cells(line))
+    }
+
+    /// Whether the given grid line exists in the scrollback or screen.
+    pub fn contains_line(&self, line: i32) -> bool {
+        line >= self.term.topmost_line().0 && line <= self.term.bottommost_line().0
+    }
 }
 
 /// Information about which parts of the screen have changed.
@@ -86,91 +166,29 @@ pub struct DamagedRow {
     pub right: u16,
 }
 
-/// Standard xterm-256color ANSI palette.
-const ANSI_COLORS: [AlacRgb; 16] = [
-    AlacRgb { r: 0, g: 0, b: 0 },       // Black
-    AlacRgb { r: 205, g: 0, b: 0 },      // Red
-    AlacRgb { r: 0, g: 205, b: 0 },      // Green
-    AlacRgb { r: 205, g: 205, b: 0 },    // Yellow
-    AlacRgb { r: 0, g: 0, b: 238 },      // Blue
-    AlacRgb { r: 205, g: 0, b: 205 },    // Magenta
-    AlacRgb { r: 0, g: 205, b: 205 },    // Cyan
-    AlacRgb { r: 229, g: 229, b: 229 },  // White
-    AlacRgb { r: 127, g: 127, b: 127 },  // Bright Black
-    AlacRgb { r: 255, g: 0, b: 0 },      // Bright Red
-    AlacRgb { r: 0, g: 255, b: 0 },      // Bright Green
-    AlacRgb { r: 255, g: 255, b: 0 },    // Bright Yellow
-    AlacRgb { r: 92, g: 92, b: 255 },    // Bright Blue
-    AlacRgb { r: 255, g: 0, b: 255 },    // Bright Magenta
-    AlacRgb { r: 0, g: 255, b: 255 },    // Bright Cyan
-    AlacRgb { r: 255, g: 255, b: 255 },  // Bright White
-];
-
-/// Resolve a `vte::ansi::Color` to an `Rgb` using the terminal's color palette.
+/// Resolve a `vte::ansi::Color` to an `Rgb`: colors set by the program
+/// (OSC 4/10/11/12) take precedence over the theme.
 pub(crate) fn resolve_color(
     color: &Color,
     colors: &alacritty_terminal::term::color::Colors,
-    is_fg: bool,
+    theme: &Theme,
 ) -> Rgb {
     match color {
         Color::Spec(rgb) => Rgb::new(rgb.r, rgb.g, rgb.b),
-        Color::Named(named) => {
-            if let Some(rgb) = colors[*named] {
-                Rgb::new(rgb.r, rgb.g, rgb.b)
-            } else {
-                let idx = *named as usize;
-                // Use default ANSI palette or default fg/bg.
-                match named {
-                    NamedColor::Foreground | NamedColor::BrightForeground => {
-                        Rgb::new(255, 255, 255)
-                    }
-                    NamedColor::Background => Rgb::new(0, 0, 0),
-                    NamedColor::Cursor => Rgb::new(255, 255, 255),
-                    _ if idx < 16 => {
-                        let c = ANSI_COLORS[idx];
-                        Rgb::new(c.r, c.g, c.b)
-                    }
-                    // Dim colors: map to corresponding normal color with dimming.
-                    NamedColor::DimBlack => Rgb::new(0, 0, 0),
-                    NamedColor::DimRed => Rgb::new(154, 0, 0),
-                    NamedColor::DimGreen => Rgb::new(0, 154, 0),
-                    NamedColor::DimYellow => Rgb::new(154, 154, 0),
-                    NamedColor::DimBlue => Rgb::new(0, 0, 178),
-                    NamedColor::DimMagenta => Rgb::new(154, 0, 154),
-                    NamedColor::DimCyan => Rgb::new(0, 154, 154),
-                    NamedColor::DimWhite => Rgb::new(178, 178, 178),
-                    NamedColor::DimForeground => Rgb::new(178, 178, 178),
-                    _ => {
-                        if is_fg {
-                            Rgb::new(255, 255, 255)
-                        } else {
-                            Rgb::new(0, 0, 0)
-                        }
-                    }
-                }
-            }
-        }
-        Color::Indexed(idx) => {
-            let idx = *idx as usize;
-            if let Some(rgb) = colors[idx] {
-                Rgb::new(rgb.r, rgb.g, rgb.b)
-            } else if idx < 16 {
-                let c = ANSI_COLORS[idx];
-                Rgb::new(c.r, c.g, c.b)
-            } else if idx < 232 {
-                // 216-color cube (indices 16..232).
-                let n = idx - 16;
-                let r = (n / 36) % 6;
-                let g = (n / 6) % 6;
-                let b = n % 6;
-                let to_byte = |v: usize| if v == 0 { 0u8 } else { (55 + 40 * v) as u8 };
-                Rgb::new(to_byte(r), to_byte(g), to_byte(b))
-            } else {
-                // Grayscale ramp (indices 232..256).
-                let v = (8 + 10 * (idx - 232)) as u8;
-                Rgb::new(v, v, v)
-            }
-        }
+        Color::Named(named) => palette_color(*named as usize, colors, theme),
+        Color::Indexed(idx) => palette_color(*idx as usize, colors, theme),
+    }
+}
+
+/// Resolve an entry of alacritty's color table.
+pub(crate) fn palette_color(
+    index: usize,
+    colors: &alacritty_terminal::term::color::Colors,
+    theme: &Theme,
+) -> Rgb {
+    match colors[index] {
+        Some(rgb) => Rgb::new(rgb.r, rgb.g, rgb.b),
+        None => theme.palette_color(index),
     }
 }
 
@@ -178,9 +196,10 @@ pub(crate) fn resolve_color(
 pub(crate) fn convert_cell(
     cell: &alacritty_terminal::term::cell::Cell,
     colors: &alacritty_terminal::term::color::Colors,
+    theme: &Theme,
 ) -> VtCell {
-    let fg = resolve_color(&cell.fg, colors, true);
-    let bg = resolve_color(&cell.bg, colors, false);
+    let fg = resolve_color(&cell.fg, colors, theme);
+    let bg = resolve_color(&cell.bg, colors, theme);
 
     let mut flags = CellFlags::empty();
     if cell.flags.contains(AlacFlags::BOLD) {
@@ -189,7 +208,7 @@ pub(crate) fn convert_cell(
     if cell.flags.contains(AlacFlags::ITALIC) {
         flags |= CellFlags::ITALIC;
     }
-    if cell.flags.contains(AlacFlags::UNDERLINE) {
+    if cell.flags.intersects(AlacFlags::ALL_UNDERLINES) {
         flags |= CellFlags::UNDERLINE;
     }
     if cell.flags.contains(AlacFlags::STRIKEOUT) {
@@ -215,17 +234,41 @@ pub(crate) fn convert_cell(
 
     VtCell {
         codepoint: cell.c,
+        combining: cell.zerowidth().map(<[char]>::to_vec).unwrap_or_default(),
         fg,
         bg,
         flags,
+        underline: convert_underline(cell.flags),
+        underline_color: cell
+            .underline_color()
+            .map(|color| resolve_color(&color, colors, theme)),
         width,
+        hyperlink_id: 0,
+    }
+}
+
+/// Read the underline style from alacritty's cell flags.
+fn convert_underline(flags: AlacFlags) -> UnderlineStyle {
+    if flags.contains(AlacFlags::UNDERLINE) {
+        UnderlineStyle::Single
+    } else if flags.contains(AlacFlags::DOUBLE_UNDERLINE) {
+        UnderlineStyle::Double
+    } else if flags.contains(AlacFlags::UNDERCURL) {
+        UnderlineStyle::Curly
+    } else if flags.contains(AlacFlags::DOTTED_UNDERLINE) {
+        UnderlineStyle::Dotted
+    } else if flags.contains(AlacFlags::DASHED_UNDERLINE) {
+        UnderlineStyle::Dashed
+    } else {
+        UnderlineStyle::None
     }
 }
 
 /// Convert alacritty's CursorShape to our CursorShape.
 pub(crate) fn convert_cursor_shape(shape: AlacCursorShape) -> CursorShape {
     match shape {
-        AlacCursorShape::Block | AlacCursorShape::HollowBlock => CursorShape::Block,
+        AlacCursorShape::Block => CursorShape::Block,
+        AlacCursorShape::HollowBlock => CursorShape::HollowBlock,
         AlacCursorShape::Underline => CursorShape::Underline,
         AlacCursorShape::Beam => CursorShape::Bar,
         AlacCursorShape::Hidden => CursorShape::Hidden,
@@ -0,0 +1,284 @@
+//! Regex and literal search across the screen and scrollback.
+//!
+//! Searching is delegated to alacritty's lazy-DFA regex engine, which walks
+//! the grid directly and treats soft-wrapped lines as one logical line. Matches
+//! are reported in grid coordinates (see [`GridPoint`]), so they can be
+//! highlighted regardless of the viewport scroll position.
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Direction, Line, Point};
+use alacritty_terminal::term::search::{Match, RegexIter, RegexSearch};
+use alacritty_terminal::term::Term;
+
+use crate::selection::GridPoint;
+use crate::terminal::EventProxy;
+
+/// Characters with special meaning in regex syntax.
+const REGEX_META: &str = "\\.+*?()|[]{}^$#&-~";
+
+/// Errors from building a search query.
+#[derive(Debug)]
+pub enum SearchError {
+    /// The query is empty.
+    EmptyQuery,
+    /// The regex failed to compile.
+    InvalidRegex(String),
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::EmptyQuery => write!(f, "search query is empty"),
+            SearchError::InvalidRegex(msg) => write!(f, "invalid search regex: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// How a search query should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchKind {
+    /// The query is a regular expression.
+    Regex,
+    /// The query is matched literally.
+    Literal,
+}
+
+/// A single search match. Both ends are inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: GridPoint,
+    pub end: GridPoint,
+}
+
+impl SearchMatch {
+    fn from_alac(m: &Match) -> Self {
+        Self {
+            start: GridPoint::from_alac(*m.start()),
+            end: GridPoint::from_alac(*m.end()),
+        }
+    }
+}
+
+/// An active search: the compiled query and the currently focused match.
+pub(crate) struct SearchState {
+    regex: RegexSearch,
+    pub(crate) focused: Option<SearchMatch>,
+}
+
+impl SearchState {
+    /// Compile a query.
+    ///
+    /// Matching is smart-case: case-insensitive unless the query contains an
+    /// uppercase character.
+    pub(crate) fn new(query: &str, kind: SearchKind) -> Result<Self, SearchError> {
+        if query.is_empty() {
+            return Err(SearchError::EmptyQuery);
+        }
+
+        let pattern = match kind {
+            SearchKind::Regex => query.to_string(),
+            SearchKind::Literal => escape_literal(query),
+        };
+
+        let regex =
+            RegexSearch::new(&pattern).map_err(|e| SearchError::InvalidRegex(e.to_string()))?;
+
+        Ok(Self {
+            regex,
+            focused: None,
+        })
+    }
+
+    /// Find all matches between two grid lines (inclusive), in order.
+    ///
+    /// At most `limit` matches are returned.
+    pub(crate) fn matches_in_lines(
+        &mut self,
+        term: &Term<EventProxy>,
+        top: i32,
+        bottom: i32,
+        limit: usize,
+    ) -> Vec<SearchMatch> {
+        let top = top.max(term.topmost_line().0);
+        let bottom = bottom.min(term.bottommost_line().0);
+        if top > bottom {
+            return Vec::new();
+        }
+
+        // Start from the beginning of the logical line so matches that begin
+        // on a soft-wrapped line above `top` are still found.
+        let start = term.line_search_left(Point::new(Line(top), Column(0)));
+        let end = term.line_search_right(Point::new(Line(bottom), term.last_column()));
+
+        RegexIter::new(start, end, Direction::Right, term, &mut self.regex)
+            .filter(|m| m.end().line.0 >= top)
+            .take(limit)
+            .map(|m| SearchMatch::from_alac(&m))
+            .collect()
+    }
+
+    /// Find the nearest match in `direction` from `origin`.
+    ///
+    /// Searching right returns the first match starting at or after `origin`;
+    /// searching left returns the last match ending at or before it. Wraps
+    /// around the ends of the buffer.
+    pub(crate) fn next_match(
+        &mut self,
+        term: &Term<EventProxy>,
+        origin: Point,
+        direction: Direction,
+    ) -> Option<SearchMatch> {
+        let side = direction.opposite();
+        term.search_next(&mut self.regex, origin, direction, side, None)
+            .map(|m| SearchMatch::from_alac(&m))
+    }
+}
+
+/// Escape regex metacharacters so `query` matches literally.
+fn escape_literal(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if REGEX_META.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    fn lines(term: &mut VtTerminal, count: usize) {
+        for i in 0..count {
+            term.write(format!("line {i}\r\n").as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_escape_literal() {
+        assert_eq!(escape_literal("a.b*c"), "a\\.b\\*c");
+        assert_eq!(escape_literal("plain"), "plain");
+    }
+
+    #[test]
+    fn test_search_empty_query() {
+        let mut term = VtTerminal::new(20, 5);
+        assert!(matches!(
+            term.search("", SearchKind::Regex),
+            Err(SearchError::EmptyQuery)
+        ));
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let mut term = VtTerminal::new(20, 5);
+        assert!(matches!(
+            term.search("(unclosed", SearchKind::Regex),
+            Err(SearchError::InvalidRegex(_))
+        ));
+        // The same text is fine as a literal.
+        assert!(term.search("(unclosed", SearchKind::Literal).is_ok());
+    }
+
+    #[test]
+    fn test_search_all_includes_scrollback() {
+        let mut term = VtTerminal::new(20, 5);
+        lines(&mut term, 20);
+        term.write(b"error: boom");
+
+        term.search("line 1[0-9]?", SearchKind::Regex).unwrap();
+        let matches = term.search_all_matches();
+        // "line 1" plus "line 10" through "line 19".
+        assert_eq!(matches.len(), 11);
+        assert!(matches[0].start.line < 0, "first match is in the scrollback");
+        assert_eq!(matches[0].start.col, 0);
+        assert_eq!(matches[0].end.col, 5);
+    }
+
+    #[test]
+    fn test_search_literal_metacharacters() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"a.b axb a.b");
+
+        term.search("a.b", SearchKind::Literal).unwrap();
+        assert_eq!(term.search_all_matches().len(), 2);
+
+        term.search("a.b", SearchKind::Regex).unwrap();
+        assert_eq!(term.search_all_matches().len(), 3);
+    }
+
+    #[test]
+    fn test_search_joins_soft_wrapped_lines() {
+        let mut term = VtTerminal::new(10, 5);
+        // "needle" is split across the wrap at column 10.
+        term.write(b"xxxxxxxneedle");
+
+        term.search("needle", SearchKind::Literal).unwrap();
+        let matches = term.search_all_matches();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start, GridPoint::new(0, 7));
+        assert_eq!(matches[0].end, GridPoint::new(1, 2));
+    }
+
+    #[test]
+    fn test_search_next_and_previous_scroll_viewport() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"target one\r\n");
+        lines(&mut term, 20);
+        term.write(b"target two");
+
+        term.search("target", SearchKind::Literal).unwrap();
+
+        // Searching backwards from the live screen finds the latest match first.
+        let m = term.search_previous().unwrap();
+        assert_eq!(m.start.line, 4);
+        assert_eq!(term.display_offset(), 0);
+
+        // The next match backwards is in the scrollback; the viewport follows it.
+        let m = term.search_previous().unwrap();
+        assert!(m.start.line < 0);
+        assert!(term.display_offset() > 0);
+        let viewport_row = m.start.line + term.display_offset() as i32;
+        assert!((0..5).contains(&viewport_row));
+        assert_eq!(term.search_focused(), Some(m));
+
+        // Forward again wraps back to the match on the live screen.
+        let m = term.search_next().unwrap();
+        assert_eq!(m.start.line, 4);
+        assert_eq!(term.display_offset(), 0);
+    }
+
+    #[test]
+    fn test_visible_search_matches() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"hit\r\n");
+        lines(&mut term, 10);
+        term.write(b"hit");
+
+        term.search("hit", SearchKind::Literal).unwrap();
+        assert_eq!(term.visible_search_matches().len(), 1);
+
+        term.scroll_to_top();
+        let visible = term.visible_search_matches();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].start.line, -(term.history_size() as i32));
+    }
+
+    #[test]
+    fn test_clear_search() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"hit");
+        term.search("hit", SearchKind::Literal).unwrap();
+        assert!(term.search_next().is_some());
+
+        term.clear_search();
+        assert!(term.search_next().is_none());
+        assert!(term.visible_search_matches().is_empty());
+        assert!(term.search_focused().is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! Text selection over the screen and scrollback.
+//!
+//! Selections are tracked in grid coordinates, so they stay attached to their
+//! content while new output scrolls the screen or the viewport is scrolled
+//! into the history. The heavy lifting (word expansion, wide chars, soft-wrap
+//! joining) is delegated to alacritty's selection engine.
+
+use alacritty_terminal::index::{Column, Direction, Line, Point};
+use alacritty_terminal::selection::{Selection, SelectionType};
+
+/// A position in the terminal grid.
+///
+/// Line 0 is the top of the live screen, negative lines index into the
+/// scrollback. This matches the indexing of `ScreenView::line_cell`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GridPoint {
+    pub line: i32,
+    pub col: u16,
+}
+
+impl GridPoint {
+    pub const fn new(line: i32, col: u16) -> Self {
+        Self { line, col }
+    }
+
+    /// Convert a viewport position into a grid position.
+    ///
+    /// `display_offset` is the current scroll position of the viewport.
+    pub fn from_viewport(row: u16, col: u16, display_offset: usize) -> Self {
+        Self {
+            line: row as i32 - display_offset as i32,
+            col,
+        }
+    }
+
+    pub(crate) fn to_alac(self) -> Point {
+        Point::new(Line(self.line), Column(self.col as usize))
+    }
+
+    pub(crate) fn from_alac(point: Point) -> Self {
+        Self {
+            line: point.line.0,
+            col: point.column.0 as u16,
+        }
+    }
+}
+
+/// Which half of a cell a selection point falls on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn to_alac(self) -> Direction {
+        match self {
+            Side::Left => Direction::Left,
+            Side::Right => Direction::Right,
+        }
+    }
+}
+
+/// How a selection expands from its anchor points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Cell-precise selection.
+    Simple,
+    /// Semantic selection that expands to word boundaries, as delimited by the
+    /// terminal's word separators.
+    Word,
+    /// Whole lines, following soft wraps.
+    Line,
+    /// Rectangular block of cells.
+    Block,
+}
+
+impl SelectionMode {
+    fn to_alac(self) -> SelectionType {
+        match self {
+            SelectionMode::Simple => SelectionType::Simple,
+            SelectionMode::Word => SelectionType::Semantic,
+            SelectionMode::Line => SelectionType::Lines,
+            SelectionMode::Block => SelectionType::Block,
+        }
+    }
+}
+
+/// The resolved extent of the current selection, for highlighting.
+///
+/// Both ends are inclusive. For block selections, only the columns between
+/// `start.col` and `end.col` of each line are selected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelectionRange {
+    pub start: GridPoint,
+    pub end: GridPoint,
+    pub is_block: bool,
+}
+
+impl SelectionRange {
+    /// Whether the given grid cell lies within the selection.
+    pub fn contains(&self, point: GridPoint) -> bool {
+        if point.line < self.start.line || point.line > self.end.line {
+            return false;
+        }
+        if self.is_block {
+            return point.col >= self.start.col && point.col <= self.end.col;
+        }
+        (point.line != self.start.line || point.col >= self.start.col)
+            && (point.line != self.end.line || point.col <= self.end.col)
+    }
+}
+
+/// Create a new alacritty selection anchored at `point`.
+pub(crate) fn new_selection(mode: SelectionMode, point: GridPoint, side: Side) -> Selection {
+    Selection::new(mode.to_alac(), point.to_alac(), side.to_alac())
+}
+
+/// Extend an alacritty selection to `point`.
+pub(crate) fn update_selection(selection: &mut Selection, point: GridPoint, side: Side) {
+    selection.update(point.to_alac(), side.to_alac());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    #[test]
+    fn test_simple_selection_single_line() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"hello world");
+
+        term.start_selection(SelectionMode::Simple, GridPoint::new(0, 0), Side::Left);
+        term.update_selection(GridPoint::new(0, 4), Side::Right);
+
+        assert_eq!(term.selection_text().as_deref(), Some("hello"));
+        let range = term.selection_range().unwrap();
+        assert_eq!(range.start, GridPoint::new(0, 0));
+        assert_eq!(range.end, GridPoint::new(0, 4));
+        assert!(range.contains(GridPoint::new(0, 2)));
+        assert!(!range.contains(GridPoint::new(0, 5)));
+    }
+
+    #[test]
+    fn test_simple_selection_joins_soft_wrapped_lines() {
+        let mut term = VtTerminal::new(10, 5);
+        // 15 chars on a 10-column terminal: soft-wraps onto the second line.
+        term.write(b"abcdefghijklmno\r\nnext");
+
+        term.start_selection(SelectionMode::Simple, GridPoint::new(0, 0), Side::Left);
+        term.update_selection(GridPoint::new(2, 3), Side::Right);
+
+        assert_eq!(term.selection_text().as_deref(), Some("abcdefghijklmno\nnext"));
+    }
+
+    #[test]
+    fn test_selection_wide_chars() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write("a世界b".as_bytes());
+
+        // Select starting on the spacer half of the first wide char.
+        term.start_selection(SelectionMode::Simple, GridPoint::new(0, 2), Side::Left);
+        term.update_selection(GridPoint::new(0, 5), Side::Right);
+
+        assert_eq!(term.selection_text().as_deref(), Some("世界b"));
+    }
+
+    #[test]
+    fn test_word_selection_default_separators() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"cargo test --package phantom-vt");
+
+        term.start_selection(SelectionMode::Word, GridPoint::new(0, 23), Side::Left);
+        assert_eq!(term.selection_text().as_deref(), Some("phantom-vt"));
+    }
+
+    #[test]
+    fn test_word_selection_custom_separators() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(b"cargo test --package phantom-vt");
+        term.set_word_separators(" -");
+
+        term.start_selection(SelectionMode::Word, GridPoint::new(0, 23), Side::Left);
+        assert_eq!(term.selection_text().as_deref(), Some("phantom"));
+    }
+
+    #[test]
+    fn test_line_selection() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"first\r\nsecond\r\nthird");
+
+        term.start_selection(SelectionMode::Line, GridPoint::new(1, 3), Side::Left);
+        assert_eq!(term.selection_text().as_deref(), Some("second\n"));
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"abcdef\r\nghijkl\r\nmnopqr");
+
+        term.start_selection(SelectionMode::Block, GridPoint::new(0, 1), Side::Left);
+        term.update_selection(GridPoint::new(2, 3), Side::Right);
+
+        assert_eq!(term.selection_text().as_deref(), Some("bcd\nhij\nnop"));
+        let range = term.selection_range().unwrap();
+        assert!(range.is_block);
+        assert!(range.contains(GridPoint::new(1, 2)));
+        assert!(!range.contains(GridPoint::new(1, 5)));
+    }
+
+    #[test]
+    fn test_selection_into_scrollback() {
+        let mut term = VtTerminal::new(20, 3);
+        term.write(b"old1\r\nold2\r\nnew1\r\nnew2");
+        assert_eq!(term.history_size(), 1);
+
+        term.start_selection(SelectionMode::Simple, GridPoint::new(-1, 0), Side::Left);
+        term.update_selection(GridPoint::new(0, 3), Side::Right);
+
+        assert_eq!(term.selection_text().as_deref(), Some("old1\nold2"));
+    }
+
+    #[test]
+    fn test_selection_follows_scrolled_content() {
+        let mut term = VtTerminal::new(20, 3);
+        term.write(b"keep\r\n");
+        term.start_selection(SelectionMode::Line, GridPoint::new(0, 0), Side::Left);
+
+        term.write(b"a\r\nb\r\nc\r\n");
+        let range = term.selection_range().unwrap();
+        assert!(range.start.line < 0, "selection should move into the scrollback");
+        assert_eq!(term.selection_text().as_deref(), Some("keep\n"));
+    }
+
+    #[test]
+    fn test_clear_selection() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"hello");
+        term.start_selection(SelectionMode::Simple, GridPoint::new(0, 0), Side::Left);
+        term.update_selection(GridPoint::new(0, 4), Side::Right);
+        assert!(term.selection_text().is_some());
+
+        term.clear_selection();
+        assert!(term.selection_text().is_none());
+        assert!(term.selection_range().is_none());
+    }
+
+    #[test]
+    fn test_grid_point_from_viewport() {
+        assert_eq!(GridPoint::from_viewport(0, 3, 0), GridPoint::new(0, 3));
+        assert_eq!(GridPoint::from_viewport(2, 3, 5), GridPoint::new(-3, 3));
+    }
+}
@@ -0,0 +1,430 @@
+//! Shell integration: command blocks from OSC 133 semantic prompt marks.
+//!
+//! Shells with FinalTerm-style integration emit four marks around every
+//! command:
+//!
+//! - `OSC 133 ; A` where the prompt starts
+//! - `OSC 133 ; B` where the prompt ends and the typed command starts
+//! - `OSC 133 ; C` where the command's output starts (after Enter)
+//! - `OSC 133 ; D [; exit]` when the command finished, with its exit status
+//!
+//! Blocks are stored against absolute line numbers (see
+//! `VtTerminal::write`), so they stay attached to their text as it scrolls
+//! into the history. Blocks whose prompt line has dropped out of the history
+//! are pruned.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::selection::GridPoint;
+
+/// Maximum number of command blocks kept per terminal.
+const MAX_COMMAND_BLOCKS: usize = 1_000;
+
+/// An OSC 133 mark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ShellMark {
+    PromptStart,
+    CommandStart,
+    OutputStart,
+    CommandFinished(Option<i32>),
+}
+
+/// A command run at a shell prompt, as delimited by OSC 133 marks.
+///
+/// Positions are grid points (line 0 is the top of the live screen, negative
+/// lines are in the scrollback), resolved when the block is read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandBlock {
+    /// Unique, increasing ID of this block within its terminal.
+    pub id: u64,
+    /// Where the prompt starts.
+    pub prompt: GridPoint,
+    /// Where the typed command starts (end of the prompt).
+    pub command_start: Option<GridPoint>,
+    /// Where the command's output starts.
+    pub output_start: Option<GridPoint>,
+    /// Where the command's output ends (exclusive), once it finished.
+    pub output_end: Option<GridPoint>,
+    /// The command line as typed at the prompt.
+    pub command: Option<String>,
+    /// Exit status reported by the shell, if any.
+    pub exit_code: Option<i32>,
+    /// Time from the start of output to the command finishing.
+    pub duration: Option<Duration>,
+}
+
+impl CommandBlock {
+    /// Whether the shell reported the command as finished.
+    pub fn is_finished(&self) -> bool {
+        self.output_end.is_some()
+    }
+
+    /// Whether the command finished with a non-zero exit status.
+    pub fn failed(&self) -> bool {
+        self.exit_code.is_some_and(|code| code != 0)
+    }
+}
+
+/// A point in absolute line coordinates, stable while text scrolls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AbsPoint {
+    pub(crate) line: i64,
+    pub(crate) col: u16,
+}
+
+impl AbsPoint {
+    fn to_grid(self, scrolled_lines: i64) -> GridPoint {
+        GridPoint::new((self.line - scrolled_lines) as i32, self.col)
+    }
+}
+
+/// A block as tracked internally, in absolute coordinates.
+#[derive(Clone, Debug)]
+pub(crate) struct Block {
+    pub(crate) id: u64,
+    pub(crate) prompt: AbsPoint,
+    pub(crate) command_start: Option<AbsPoint>,
+    pub(crate) output_start: Option<AbsPoint>,
+    pub(crate) output_end: Option<AbsPoint>,
+    command: Option<String>,
+    exit_code: Option<i32>,
+    started_at: Option<Instant>,
+    duration: Option<Duration>,
+}
+
+impl Block {
+    pub(crate) fn to_public(&self, scrolled_lines: i64) -> CommandBlock {
+        CommandBlock {
+            id: self.id,
+            prompt: self.prompt.to_grid(scrolled_lines),
+            command_start: self.command_start.map(|p| p.to_grid(scrolled_lines)),
+            output_start: self.output_start.map(|p| p.to_grid(scrolled_lines)),
+            output_end: self.output_end.map(|p| p.to_grid(scrolled_lines)),
+            command: self.command.clone(),
+            exit_code: self.exit_code,
+            duration: self.duration,
+        }
+    }
+}
+
+/// The command blocks of one terminal, oldest first.
+#[derive(Default)]
+pub(crate) struct CommandBlocks {
+    blocks: VecDeque<Block>,
+    next_id: u64,
+    /// IDs of blocks finished since the last `take_finished`.
+    finished: Vec<u64>,
+}
+
+impl CommandBlocks {
+    /// Record a new prompt at `at`.
+    ///
+    /// A previous block that never started running a command (e.g. the prompt
+    /// was redrawn or cancelled) is replaced. One that is still running is
+    /// closed at the new prompt.
+    pub(crate) fn prompt_start(&mut self, at: AbsPoint) {
+        if let Some(last) = self.blocks.back_mut() {
+            if last.output_start.is_none() {
+                self.blocks.pop_back();
+            } else if last.output_end.is_none() {
+                last.output_end = Some(at);
+            }
+        }
+
+        if self.blocks.len() >= MAX_COMMAND_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.next_id += 1;
+        self.blocks.push_back(Block {
+            id: self.next_id,
+            prompt: at,
+            command_start: None,
+            output_start: None,
+            output_end: None,
+            command: None,
+            exit_code: None,
+            started_at: None,
+            duration: None,
+        });
+    }
+
+    /// Record the end of the prompt and start of the command line.
+    pub(crate) fn command_start(&mut self, at: AbsPoint) {
+        if let Some(last) = self.open_block() {
+            last.command_start = Some(at);
+        }
+    }
+
+    /// Record the start of output. `command` is the text typed since the
+    /// command start mark.
+    pub(crate) fn output_start(&mut self, at: AbsPoint, command: Option<String>, now: Instant) {
+        if let Some(last) = self.open_block() {
+            last.output_start = Some(at);
+            last.command = command;
+            last.started_at = Some(now);
+        }
+    }
+
+    /// Record that the running command finished.
+    pub(crate) fn command_finished(&mut self, at: AbsPoint, exit_code: Option<i32>, now: Instant) {
+        let Some(last) = self.open_block() else {
+            return;
+        };
+        if last.output_start.is_none() {
+            // Finished without running anything (e.g. an empty command line).
+            return;
+        }
+        last.output_end = Some(at);
+        last.exit_code = exit_code;
+        last.duration = last.started_at.map(|start| now.duration_since(start));
+        let id = last.id;
+        self.finished.push(id);
+    }
+
+    /// The most recent block, if it has not finished yet.
+    pub(crate) fn open_block(&mut self) -> Option<&mut Block> {
+        self.blocks.back_mut().filter(|b| b.output_end.is_none())
+    }
+
+    /// Drop blocks whose prompt line is above `topmost` (no longer stored).
+    pub(crate) fn prune(&mut self, topmost: i64) {
+        while self.blocks.front().is_some_and(|b| b.prompt.line < topmost) {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Drop all blocks, e.g. after a terminal reset.
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+        self.finished.clear();
+    }
+
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = &Block> {
+        self.blocks.iter()
+    }
+
+    pub(crate) fn get(&self, id: u64) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.id == id)
+    }
+
+    /// Drain the IDs of blocks finished since the last call.
+    pub(crate) fn take_finished(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.finished)
+    }
+}
+
+/// Parse the parameters of an OSC 133 sequence (after the `133`).
+pub(crate) fn parse_osc133(params: &[&[u8]]) -> Option<ShellMark> {
+    let (kind, rest) = params.split_first()?;
+    match *kind {
+        b"A" => Some(ShellMark::PromptStart),
+        b"B" => Some(ShellMark::CommandStart),
+        b"C" => Some(ShellMark::OutputStart),
+        b"D" => {
+            // The exit status is the first parameter; later ones are
+            // `key=value` options.
+            let exit_code = rest
+                .first()
+                .and_then(|p| std::str::from_utf8(p).ok())
+                .and_then(|p| p.parse().ok());
+            Some(ShellMark::CommandFinished(exit_code))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+
+    /// Emulate one prompt/command/output cycle of an integrated shell.
+    fn run_command(term: &mut VtTerminal, command: &str, output: &str, exit: i32) {
+        term.write(b"\x1b]133;A\x07$ \x1b]133;B\x07");
+        term.write(command.as_bytes());
+        term.write(b"\r\n\x1b]133;C\x07");
+        term.write(output.as_bytes());
+        term.write(format!("\x1b]133;D;{exit}\x07").as_bytes());
+    }
+
+    #[test]
+    fn test_parse_osc133() {
+        assert_eq!(parse_osc133(&[b"A"]), Some(ShellMark::PromptStart));
+        assert_eq!(
+            parse_osc133(&[b"A", b"aid=1"]),
+            Some(ShellMark::PromptStart)
+        );
+        assert_eq!(
+            parse_osc133(&[b"D", b"2"]),
+            Some(ShellMark::CommandFinished(Some(2)))
+        );
+        assert_eq!(
+            parse_osc133(&[b"D"]),
+            Some(ShellMark::CommandFinished(None))
+        );
+        assert_eq!(parse_osc133(&[b"Z"]), None);
+        assert_eq!(parse_osc133(&[]), None);
+    }
+
+    #[test]
+    fn test_command_block_recorded() {
+        let mut term = VtTerminal::new(40, 10);
+        run_command(&mut term, "ls", "a\r\nb\r\n", 0);
+
+        let blocks = term.command_blocks();
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        assert_eq!(block.prompt, GridPoint::new(0, 0));
+        assert_eq!(block.command_start, Some(GridPoint::new(0, 2)));
+        assert_eq!(block.output_start, Some(GridPoint::new(1, 0)));
+        assert_eq!(block.output_end, Some(GridPoint::new(3, 0)));
+        assert_eq!(block.command.as_deref(), Some("ls"));
+        assert_eq!(block.exit_code, Some(0));
+        assert!(block.duration.is_some());
+        assert!(block.is_finished());
+        assert!(!block.failed());
+
+        assert_eq!(term.command_output(block.id).as_deref(), Some("a\nb"));
+    }
+
+    #[test]
+    fn test_failed_command_and_finished_queue() {
+        let mut term = VtTerminal::new(40, 10);
+        run_command(&mut term, "true", "", 0);
+        run_command(&mut term, "false", "", 1);
+
+        let finished = term.take_finished_commands();
+        assert_eq!(finished.len(), 2);
+        assert!(!finished[0].failed());
+        assert!(finished[1].failed());
+        assert!(term.take_finished_commands().is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_prompt_replaced() {
+        let mut term = VtTerminal::new(40, 10);
+        // Prompt shown, then Ctrl-C redraws it without running anything.
+        term.write(b"\x1b]133;A\x07$ \x1b]133;B\x07^C\r\n");
+        run_command(&mut term, "pwd", "/\r\n", 0);
+
+        let blocks = term.command_blocks();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].command.as_deref(), Some("pwd"));
+        assert_eq!(blocks[0].prompt.line, 1);
+    }
+
+    #[test]
+    fn test_blocks_follow_scrolling() {
+        let mut term = VtTerminal::new(40, 5);
+        run_command(&mut term, "first", "out\r\n", 0);
+        for i in 0..20 {
+            term.write(format!("filler {i}\r\n").as_bytes());
+        }
+
+        let blocks = term.command_blocks();
+        let block = &blocks[0];
+        assert!(block.prompt.line < 0, "block should be in the scrollback");
+        assert_eq!(term.command_output(block.id).as_deref(), Some("out"));
+        let screen = term.screen();
+        let prompt: String = screen
+            .line_cells(block.prompt.line)
+            .unwrap()
+            .iter()
+            .map(|c| c.codepoint)
+            .collect();
+        assert_eq!(prompt.trim_end(), "$ first");
+    }
+
+    #[test]
+    fn test_blocks_follow_scrolling_with_full_history() {
+        let mut term = VtTerminal::new(20, 5);
+        for i in 0..12_000 {
+            term.write(format!("old {i}\r\n").as_bytes());
+        }
+        run_command(&mut term, "make", "done\r\n", 2);
+        for i in 0..50 {
+            term.write(format!("new {i}\r\n").as_bytes());
+        }
+
+        let blocks = term.command_blocks();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(term.command_output(blocks[0].id).as_deref(), Some("done"));
+        assert_eq!(blocks[0].exit_code, Some(2));
+    }
+
+    #[test]
+    fn test_blocks_follow_large_writes_with_full_history() {
+        let mut term = VtTerminal::new(20, 5);
+        let old: String = (0..12_000).map(|i| format!("old {i}\r\n")).collect();
+        term.write(old.as_bytes());
+        run_command(&mut term, "make", "done\r\n", 2);
+        // Scrolled all at once when the synchronized update ends.
+        let new: String = (0..50).map(|i| format!("new {i}\r\n")).collect();
+        term.write(format!("\x1b[?2026h{new}\x1b[?2026l").as_bytes());
+
+        let blocks = term.command_blocks();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(term.command_output(blocks[0].id).as_deref(), Some("done"));
+        assert_eq!(term.history_size(), 10_000);
+    }
+
+    #[test]
+    fn test_blocks_pruned_with_history() {
+        let mut term = VtTerminal::new(40, 3);
+        run_command(&mut term, "ls", "x\r\n", 0);
+        for _ in 0..5 {
+            term.write(b"line\r\n");
+        }
+        assert_eq!(term.command_blocks().len(), 1);
+
+        // CSI 3 J clears the scrollback, taking the block with it.
+        term.write(b"\x1b[3J");
+        assert!(term.command_blocks().is_empty());
+    }
+
+    #[test]
+    fn test_blocks_survive_resize() {
+        let mut term = VtTerminal::new(40, 5);
+        run_command(&mut term, "echo hi", "hi\r\n", 0);
+        for _ in 0..10 {
+            term.write(b"line\r\n");
+        }
+
+        term.resize(30, 8);
+        let block = &term.command_blocks()[0];
+        assert_eq!(term.command_output(block.id).as_deref(), Some("hi"));
+    }
+
+    #[test]
+    fn test_command_after_narrowing() {
+        let mut term = VtTerminal::new(40, 5);
+        term.write(format!("\x1b]133;A\x07{:30}$ \x1b]133;B\x07", "~/project").as_bytes());
+        term.set_reflow(false);
+        term.resize(10, 5);
+        // The command starts past the new last column.
+        term.write(b"\r\nls\x1b]133;C\x07\r\n\x1b]133;D;0\x07");
+
+        let block = &term.command_blocks()[0];
+        assert_eq!(block.command.as_deref(), Some("ls"));
+    }
+
+    #[test]
+    fn test_jump_between_prompts() {
+        let mut term = VtTerminal::new(40, 5);
+        for cmd in ["one", "two", "three"] {
+            run_command(&mut term, cmd, "1\r\n2\r\n3\r\n4\r\n", 0);
+        }
+        term.write(b"\x1b]133;A\x07$ ");
+
+        let block = term.scroll_to_previous_prompt().unwrap();
+        assert_eq!(block.command.as_deref(), Some("three"));
+        assert_eq!(term.display_offset() as i32, -block.prompt.line);
+
+        let block = term.scroll_to_previous_prompt().unwrap();
+        assert_eq!(block.command.as_deref(), Some("two"));
+
+        let block = term.scroll_to_next_prompt().unwrap();
+        assert_eq!(block.command.as_deref(), Some("three"));
+    }
+}
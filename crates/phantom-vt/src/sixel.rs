@@ -0,0 +1,317 @@
+//! Sixel image decoding.
+//!
+//! A sixel image is a DCS `P1 ; P2 ; P3 q` followed by a stream of six-pixel
+//! high columns. Only the parts that affect the pixels are interpreted:
+//! raster attributes, color definitions and selection, repeats, and the
+//! carriage return and line feed controls. Aspect ratio and grid size
+//! parameters are ignored, as most modern terminals do, and so is the size
+//! in the raster attributes: the image covers what was drawn, so a tiny
+//! sequence cannot claim a huge image.
+
+use crate::image::{DecodedImage, ImageError, MAX_IMAGE_DIMENSION};
+
+/// Number of color registers.
+const REGISTERS: usize = 256;
+
+/// The VT340 default palette, in percent.
+const VT340_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (20, 20, 80),
+    (80, 13, 13),
+    (20, 80, 20),
+    (80, 20, 80),
+    (20, 80, 80),
+    (80, 80, 20),
+    (53, 53, 53),
+    (26, 26, 26),
+    (33, 33, 60),
+    (60, 26, 26),
+    (33, 60, 33),
+    (60, 33, 60),
+    (33, 60, 60),
+    (60, 60, 33),
+    (80, 80, 80),
+];
+
+/// Decode a sixel image of at most `budget` bytes of RGBA pixels. `params`
+/// are the DCS parameters; `data` is everything after the `q`.
+pub(crate) fn decode(
+    params: &[u16],
+    data: &[u8],
+    budget: usize,
+) -> Result<DecodedImage, ImageError> {
+    // P2 = 1: pixels that are never drawn stay transparent.
+    let transparent = params.get(1) == Some(&1);
+    let mut decoder = Decoder::new(budget);
+    decoder.run(data)?;
+    decoder.finish(transparent)
+}
+
+struct Decoder {
+    palette: [[u8; 4]; REGISTERS],
+    color: usize,
+    x: u32,
+    y: u32,
+    /// Width of the widest row drawn.
+    width: u32,
+    /// Drawn pixels, one row per line, grown as needed; `None` is unset.
+    rows: Vec<Vec<Option<[u8; 4]>>>,
+    budget: usize,
+}
+
+impl Decoder {
+    fn new(budget: usize) -> Self {
+        let mut palette = [[0, 0, 0, 255]; REGISTERS];
+        for (entry, &(r, g, b)) in palette.iter_mut().zip(&VT340_PALETTE) {
+            *entry = [percent(r as u32), percent(g as u32), percent(b as u32), 255];
+        }
+        Self {
+            palette,
+            color: 0,
+            x: 0,
+            y: 0,
+            width: 0,
+            rows: Vec::new(),
+            budget,
+        }
+    }
+
+    fn run(&mut self, data: &[u8]) -> Result<(), ImageError> {
+        let mut i = 0;
+        while i < data.len() {
+            let byte = data[i];
+            i += 1;
+            match byte {
+                b'"' => i += numbers(&data[i..]).1,
+                b'#' => {
+                    let (values, n) = numbers(&data[i..]);
+                    i += n;
+                    self.select_color(&values);
+                }
+                b'!' => {
+                    let (values, n) = numbers(&data[i..]);
+                    i += n;
+                    let count = values.first().copied().unwrap_or(1).max(1);
+                    if let Some(&sixel @ 0x3f..=0x7e) = data.get(i) {
+                        i += 1;
+                        self.draw(sixel - 0x3f, count)?;
+                    }
+                }
+                b'$' => self.x = 0,
+                b'-' => {
+                    self.x = 0;
+                    self.y += 6;
+                    if self.y >= MAX_IMAGE_DIMENSION {
+                        return Err(ImageError::TooLarge);
+                    }
+                }
+                0x3f..=0x7e => self.draw(byte - 0x3f, 1)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// `#Pc` selects a register; `#Pc;Pu;Px;Py;Pz` also defines it.
+    fn select_color(&mut self, values: &[u32]) {
+        let Some(&register) = values.first() else {
+            return;
+        };
+        self.color = register as usize % REGISTERS;
+        let &[_, space, x, y, z] = values else {
+            return;
+        };
+        self.palette[self.color] = match space {
+            1 => hls_to_rgb(x, y, z),
+            2 => [percent(x), percent(y), percent(z), 255],
+            _ => return,
+        };
+    }
+
+    /// Draw a sixel `count` times at the current position.
+    fn draw(&mut self, bits: u8, count: u32) -> Result<(), ImageError> {
+        let end = self.x.saturating_add(count);
+        if end > MAX_IMAGE_DIMENSION {
+            return Err(ImageError::TooLarge);
+        }
+        // Bounds the rows allocated so far too, which hold no more pixels
+        // than the image will.
+        let width = self.width.max(end);
+        if pixel_bytes(width, self.y + 6) > self.budget {
+            return Err(ImageError::TooLarge);
+        }
+        let color = self.palette[self.color];
+        for bit in 0..6 {
+            if bits & (1 << bit) == 0 {
+                continue;
+            }
+            let y = (self.y + bit) as usize;
+            if self.rows.len() <= y {
+                self.rows.resize_with(y + 1, Vec::new);
+            }
+            let row = &mut self.rows[y];
+            if row.len() < end as usize {
+                row.resize(end as usize, None);
+            }
+            row[self.x as usize..end as usize].fill(Some(color));
+        }
+        self.x = end;
+        self.width = width;
+        Ok(())
+    }
+
+    fn finish(self, transparent: bool) -> Result<DecodedImage, ImageError> {
+        let (width, height) = (self.width, self.rows.len() as u32);
+        if width == 0 || height == 0 {
+            return Err(ImageError::Invalid("empty sixel image"));
+        }
+        if pixel_bytes(width, height) > self.budget {
+            return Err(ImageError::TooLarge);
+        }
+        let background = if transparent {
+            [0, 0, 0, 0]
+        } else {
+            self.palette[0]
+        };
+
+        let mut rgba = Vec::with_capacity(pixel_bytes(width, height));
+        for y in 0..height as usize {
+            let row = self.rows.get(y).map(Vec::as_slice).unwrap_or_default();
+            for x in 0..width as usize {
+                let pixel = row.get(x).copied().flatten().unwrap_or(background);
+                rgba.extend_from_slice(&pixel);
+            }
+        }
+        Ok(DecodedImage {
+            width,
+            height,
+            rgba,
+        })
+    }
+}
+
+/// Bytes of RGBA pixels in an image of the given size.
+fn pixel_bytes(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 4
+}
+
+/// Parse `;`-separated decimal numbers, returning them and the bytes used.
+fn numbers(data: &[u8]) -> (Vec<u32>, usize) {
+    let mut values = Vec::new();
+    let mut current: Option<u32> = None;
+    let mut i = 0;
+    while let Some(&byte) = data.get(i) {
+        match byte {
+            b'0'..=b'9' => {
+                let digit = (byte - b'0') as u32;
+                current = Some(
+                    current
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+            }
+            b';' => values.push(current.take().unwrap_or(0)),
+            _ => break,
+        }
+        i += 1;
+    }
+    if let Some(value) = current {
+        values.push(value);
+    }
+    (values, i)
+}
+
+/// Scale a percentage to a color component.
+fn percent(value: u32) -> u8 {
+    (value.min(100) * 255 / 100) as u8
+}
+
+/// Convert a sixel HLS color to RGB. Sixel hues start at blue: 0 is blue,
+/// 120 red and 240 green.
+fn hls_to_rgb(hue: u32, lightness: u32, saturation: u32) -> [u8; 4] {
+    let h = ((hue + 240) % 360) as f32 / 360.0;
+    let l = lightness.min(100) as f32 / 100.0;
+    let s = saturation.min(100) as f32 / 100.0;
+    if s == 0.0 {
+        let v = (l * 255.0).round() as u8;
+        return [v, v, v, 255];
+    }
+
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        let v = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (v * 255.0).round() as u8
+    };
+    [
+        channel(h + 1.0 / 3.0),
+        channel(h),
+        channel(h - 1.0 / 3.0),
+        255,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::DEFAULT_IMAGE_BUDGET;
+
+    fn pixel(image: &DecodedImage, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * image.width + x) * 4) as usize;
+        image.rgba[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_decode_sixel() {
+        // Register 1 red (RGB), register 2 green (HLS: hue 240 is green).
+        // Two red columns with all six pixels set, one green column with
+        // only the top pixel, then a second band that is one column wide.
+        let image = decode(&[0, 1], b"#1;2;100;0;0!2~#2;1;240;50;100@-#1A", DEFAULT_IMAGE_BUDGET).unwrap();
+        assert_eq!((image.width, image.height), (3, 8));
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 1, 5), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 2, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(&image, 2, 1), [0, 0, 0, 0]);
+        // 'A' is 0b000010: the second pixel of the band.
+        assert_eq!(pixel(&image, 0, 6), [0, 0, 0, 0]);
+        assert_eq!(pixel(&image, 0, 7), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_sixel_raster_and_background() {
+        // The image covers what was drawn, not the raster attributes;
+        // without P2 = 1 the pixels left unset are filled with register 0.
+        let data = b"\"1;1;8000;8000#0;2;0;0;100#3~$!2@";
+        let image = decode(&[], data, DEFAULT_IMAGE_BUDGET).unwrap();
+        assert_eq!((image.width, image.height), (2, 6));
+        assert_eq!(pixel(&image, 0, 0), [51, 204, 51, 255]);
+        assert_eq!(pixel(&image, 1, 5), [0, 0, 255, 255]);
+
+        let empty = decode(&[], b"\"1;1;8000;8000", DEFAULT_IMAGE_BUDGET);
+        assert!(matches!(empty, Err(ImageError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_decode_sixel_too_large() {
+        let too_wide = decode(&[], b"!99999~", DEFAULT_IMAGE_BUDGET);
+        assert_eq!(too_wide.unwrap_err(), ImageError::TooLarge);
+        // 100x12 pixels take 4800 bytes.
+        let data = b"!100~-!100~";
+        assert!(decode(&[], data, 4800).is_ok());
+        assert_eq!(decode(&[], data, 4799).unwrap_err(), ImageError::TooLarge);
+    }
+}
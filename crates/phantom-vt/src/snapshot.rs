@@ -0,0 +1,856 @@
+//! Binary snapshots of a terminal's state.
+//!
+//! A snapshot holds the screen and scrollback cell by cell, the cursor, the
+//! modes programs set, the title, icon name and working directory, and the
+//! palette overrides: enough to bring a tab's contents back after a restart
+//! or a crash, or to reproduce a rendering bug from a file. Restoring fills a
+//! fresh terminal; the programs that drew the contents are not part of it.
+//!
+//! Colors are kept as the program set them (named, palette index or RGB), so
+//! a restored terminal follows the theme in effect. alacritty does not expose
+//! the scroll region, saved cursor, tab stops or charsets, so those start out
+//! at their defaults. While a program has the alternate screen open, only
+//! that screen can be read; the primary screen is lost.
+//!
+//! # Format
+//!
+//! Integers are unsigned LEB128 varints unless noted; a string is a length
+//! and UTF-8 bytes, and an optional value is a presence byte and the value.
+//!
+//! ```text
+//! "PHSNAP" version:u8
+//! cols rows alt_screen:u8
+//! title:opt<string> icon_name:opt<string> cwd:opt<string>
+//! modes (bits in `MODES` order) kitty_keyboard x10_mouse:u8 modify_other_keys
+//! palette: count, (index r:u8 g:u8 b:u8)*
+//! styles: count, style*
+//! cursor: row col needs_wrap:u8 shape blinking:u8 pen_style
+//! lines: count, line* (oldest first)
+//! ```
+//!
+//! A style is the foreground and background colors, the cell flags (bits in
+//! `FLAGS` order), an optional underline color and an optional hyperlink
+//! (ID and URI). A color is a tag byte (0 named, 1 palette index, 2 RGB) and
+//! the color number or three bytes.
+//!
+//! A line is a wrapped byte and runs of cells sharing a style: the run count,
+//! then (style, cell count, cells) for each. A cell is its character shifted
+//! left by one, with the low bit set when zero-width characters follow as a
+//! count and characters. Default cells at the end of a line are left out.
+
+use std::collections::HashMap;
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line, Point};
+use alacritty_terminal::term::cell::{Cell, Flags, Hyperlink};
+use alacritty_terminal::term::color::COUNT as PALETTE_SIZE;
+use alacritty_terminal::term::{Term, TermMode};
+use alacritty_terminal::vte::ansi::{Color, CursorShape, NamedColor, Rgb};
+
+use crate::terminal::{EventProxy, MAX_COLUMNS, MAX_ROWS, SCROLLBACK_LINES};
+
+const MAGIC: &[u8] = b"PHSNAP";
+const VERSION: u8 = 1;
+
+/// Cell flags, in the order of their bits in a snapshot. Soft wraps are
+/// stored per line instead.
+const FLAGS: [Flags; 14] = [
+    Flags::INVERSE,
+    Flags::BOLD,
+    Flags::ITALIC,
+    Flags::UNDERLINE,
+    Flags::WIDE_CHAR,
+    Flags::WIDE_CHAR_SPACER,
+    Flags::DIM,
+    Flags::HIDDEN,
+    Flags::STRIKEOUT,
+    Flags::LEADING_WIDE_CHAR_SPACER,
+    Flags::DOUBLE_UNDERLINE,
+    Flags::UNDERCURL,
+    Flags::DOTTED_UNDERLINE,
+    Flags::DASHED_UNDERLINE,
+];
+
+/// Modes, in the order of their bits in a snapshot, with the sequences that
+/// set and reset them.
+const MODES: [(TermMode, &str, &str); 16] = [
+    (TermMode::SHOW_CURSOR, "\x1b[?25h", "\x1b[?25l"),
+    (TermMode::APP_CURSOR, "\x1b[?1h", "\x1b[?1l"),
+    (TermMode::APP_KEYPAD, "\x1b=", "\x1b>"),
+    (TermMode::MOUSE_REPORT_CLICK, "\x1b[?1000h", "\x1b[?1000l"),
+    (TermMode::MOUSE_DRAG, "\x1b[?1002h", "\x1b[?1002l"),
+    (TermMode::MOUSE_MOTION, "\x1b[?1003h", "\x1b[?1003l"),
+    (TermMode::FOCUS_IN_OUT, "\x1b[?1004h", "\x1b[?1004l"),
+    (TermMode::UTF8_MOUSE, "\x1b[?1005h", "\x1b[?1005l"),
+    (TermMode::SGR_MOUSE, "\x1b[?1006h", "\x1b[?1006l"),
+    (TermMode::ALTERNATE_SCROLL, "\x1b[?1007h", "\x1b[?1007l"),
+    (TermMode::URGENCY_HINTS, "\x1b[?1042h", "\x1b[?1042l"),
+    (TermMode::BRACKETED_PASTE, "\x1b[?2004h", "\x1b[?2004l"),
+    (TermMode::LINE_WRAP, "\x1b[?7h", "\x1b[?7l"),
+    (TermMode::ORIGIN, "\x1b[?6h", "\x1b[?6l"),
+    (TermMode::INSERT, "\x1b[4h", "\x1b[4l"),
+    (TermMode::LINE_FEED_NEW_LINE, "\x1b[20h", "\x1b[20l"),
+];
+
+/// Kitty keyboard modes with their bit in the protocol's flags.
+const KITTY_MODES: [(TermMode, u8); 5] = [
+    (TermMode::DISAMBIGUATE_ESC_CODES, 1),
+    (TermMode::REPORT_EVENT_TYPES, 2),
+    (TermMode::REPORT_ALTERNATE_KEYS, 4),
+    (TermMode::REPORT_ALL_KEYS_AS_ESC, 8),
+    (TermMode::REPORT_ASSOCIATED_TEXT, 16),
+];
+
+/// Named colors a cell can refer to.
+const NAMED_COLORS: [NamedColor; 29] = [
+    NamedColor::Black,
+    NamedColor::Red,
+    NamedColor::Green,
+    NamedColor::Yellow,
+    NamedColor::Blue,
+    NamedColor::Magenta,
+    NamedColor::Cyan,
+    NamedColor::White,
+    NamedColor::BrightBlack,
+    NamedColor::BrightRed,
+    NamedColor::BrightGreen,
+    NamedColor::BrightYellow,
+    NamedColor::BrightBlue,
+    NamedColor::BrightMagenta,
+    NamedColor::BrightCyan,
+    NamedColor::BrightWhite,
+    NamedColor::Foreground,
+    NamedColor::Background,
+    NamedColor::Cursor,
+    NamedColor::DimBlack,
+    NamedColor::DimRed,
+    NamedColor::DimGreen,
+    NamedColor::DimYellow,
+    NamedColor::DimBlue,
+    NamedColor::DimMagenta,
+    NamedColor::DimCyan,
+    NamedColor::DimWhite,
+    NamedColor::BrightForeground,
+    NamedColor::DimForeground,
+];
+
+/// Cursor shapes, in the order of their numbers in a snapshot.
+const CURSOR_SHAPES: [CursorShape; 5] = [
+    CursorShape::Block,
+    CursorShape::Underline,
+    CursorShape::Beam,
+    CursorShape::HollowBlock,
+    CursorShape::Hidden,
+];
+
+/// Most zero-width characters kept with one cell.
+const MAX_ZEROWIDTH: usize = 64;
+
+/// Errors from reading a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The data does not start like a snapshot.
+    NotASnapshot,
+    /// The snapshot was written by a newer version of the format.
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of the snapshot.
+    Truncated,
+    /// A value is out of range.
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a terminal snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {v}")
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Invalid(what) => write!(f, "invalid {what} in snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// A row of cells; `cells` may be shorter than the terminal is wide.
+pub(crate) struct SnapshotLine {
+    cells: Vec<Cell>,
+    wrapped: bool,
+}
+
+pub(crate) struct SnapshotCursor {
+    row: u16,
+    col: u16,
+    needs_wrap: bool,
+    shape: CursorShape,
+    blinking: bool,
+    /// Colors and attributes new text is written with.
+    pen: Cell,
+}
+
+/// The decoded contents of a snapshot.
+pub(crate) struct Snapshot {
+    pub(crate) cols: u16,
+    pub(crate) rows: u16,
+    pub(crate) alt_screen: bool,
+    pub(crate) title: Option<String>,
+    pub(crate) icon_name: Option<String>,
+    pub(crate) cwd: Option<String>,
+    pub(crate) modes: TermMode,
+    pub(crate) x10_mouse: bool,
+    pub(crate) modify_other_keys: u8,
+    pub(crate) palette: Vec<(usize, Rgb)>,
+    pub(crate) cursor: SnapshotCursor,
+    /// Scrollback and screen, oldest first.
+    pub(crate) lines: Vec<SnapshotLine>,
+}
+
+impl Snapshot {
+    /// Capture the grid, cursor, modes and palette of `term`. The state kept
+    /// outside of `Term` is left empty for the caller to fill in.
+    pub(crate) fn capture(term: &Term<EventProxy>) -> Self {
+        let grid = term.grid();
+        let cols = term.columns();
+        let lines = (term.topmost_line().0..=term.bottommost_line().0)
+            .map(|line| {
+                let row = &grid[Line(line)];
+                let mut cells: Vec<Cell> = (0..cols).map(|col| row[Column(col)].clone()).collect();
+                let wrapped = cells[cols - 1].flags.contains(Flags::WRAPLINE);
+                cells[cols - 1].flags.remove(Flags::WRAPLINE);
+                let default = Cell::default();
+                let end = cells
+                    .iter()
+                    .rposition(|c| *c != default)
+                    .map_or(0, |i| i + 1);
+                cells.truncate(end);
+                SnapshotLine { cells, wrapped }
+            })
+            .collect();
+
+        let style = term.cursor_style();
+        let cursor = SnapshotCursor {
+            row: grid.cursor.point.line.0 as u16,
+            col: grid.cursor.point.column.0 as u16,
+            needs_wrap: grid.cursor.input_needs_wrap,
+            shape: style.shape,
+            blinking: style.blinking,
+            pen: grid.cursor.template.clone(),
+        };
+
+        let palette = (0..PALETTE_SIZE)
+            .filter_map(|i| term.colors()[i].map(|rgb| (i, rgb)))
+            .collect();
+
+        Self {
+            cols: cols as u16,
+            rows: term.screen_lines() as u16,
+            alt_screen: term.mode().contains(TermMode::ALT_SCREEN),
+            title: None,
+            icon_name: None,
+            cwd: None,
+            modes: *term.mode(),
+            x10_mouse: false,
+            modify_other_keys: 0,
+            palette,
+            cursor,
+            lines,
+        }
+    }
+
+    /// Sequences that put a fresh terminal in the snapshot's modes.
+    pub(crate) fn mode_sequences(&self) -> Vec<u8> {
+        let mut out = String::new();
+        for (mode, set, reset) in MODES {
+            let sequence = if self.modes.contains(mode) {
+                set
+            } else {
+                reset
+            };
+            out.push_str(sequence);
+        }
+        let kitty = kitty_flags(self.modes);
+        if kitty != 0 {
+            out.push_str(&format!("\x1b[={kitty};1u"));
+        }
+        if self.x10_mouse {
+            out.push_str("\x1b[?9h");
+        }
+        if self.modify_other_keys != 0 {
+            out.push_str(&format!("\x1b[>4;{}m", self.modify_other_keys));
+        }
+        out.into_bytes()
+    }
+
+    /// Write the lines into the screen of a fresh terminal of the same size,
+    /// scrolling the earlier ones into the history.
+    pub(crate) fn restore_lines(&self, term: &mut Term<EventProxy>) {
+        let rows = self.rows as usize;
+        let last_column = Column(self.cols as usize - 1);
+        let region = Line(0)..Line(rows as i32);
+        let grid = term.grid_mut();
+        for (i, line) in self.lines.iter().enumerate() {
+            let row = if i < rows {
+                i
+            } else {
+                grid.scroll_up(&region, 1);
+                rows - 1
+            };
+            let row = &mut grid[Line(row as i32)];
+            for (col, cell) in line.cells.iter().enumerate() {
+                row[Column(col)] = cell.clone();
+            }
+            if line.wrapped {
+                row[last_column].flags.insert(Flags::WRAPLINE);
+            }
+        }
+    }
+
+    /// Move the cursor into place and restore its style and the palette.
+    pub(crate) fn restore_cursor_and_palette(&self, term: &mut Term<EventProxy>) {
+        use alacritty_terminal::vte::ansi::{CursorStyle, Handler};
+
+        let cursor = &self.cursor;
+        let grid = term.grid_mut();
+        grid.cursor.point = Point::new(Line(cursor.row as i32), Column(cursor.col as usize));
+        grid.cursor.input_needs_wrap = cursor.needs_wrap;
+        grid.cursor.template = cursor.pen.clone();
+        term.set_cursor_style(Some(CursorStyle {
+            shape: cursor.shape,
+            blinking: cursor.blinking,
+        }));
+        for &(index, rgb) in &self.palette {
+            term.set_color(index, rgb);
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        out.bytes.extend_from_slice(MAGIC);
+        out.byte(VERSION);
+        out.varint(self.cols as u64);
+        out.varint(self.rows as u64);
+        out.byte(self.alt_screen as u8);
+        out.opt_str(self.title.as_deref());
+        out.opt_str(self.icon_name.as_deref());
+        out.opt_str(self.cwd.as_deref());
+
+        let modes = MODES
+            .iter()
+            .enumerate()
+            .filter(|(_, (mode, ..))| self.modes.contains(*mode))
+            .fold(0u64, |bits, (i, _)| bits | 1 << i);
+        out.varint(modes);
+        out.varint(kitty_flags(self.modes) as u64);
+        out.byte(self.x10_mouse as u8);
+        out.varint(self.modify_other_keys as u64);
+
+        out.varint(self.palette.len() as u64);
+        for &(index, rgb) in &self.palette {
+            out.varint(index as u64);
+            out.bytes.extend_from_slice(&[rgb.r, rgb.g, rgb.b]);
+        }
+
+        // Give every distinct style an ID, then write the table ahead of the
+        // cells that refer to it.
+        let mut styles = StyleTable::default();
+        let pen = styles.id(&self.cursor.pen);
+        let lines: Vec<Vec<(usize, &Cell)>> = self
+            .lines
+            .iter()
+            .map(|line| line.cells.iter().map(|c| (styles.id(c), c)).collect())
+            .collect();
+        out.varint(styles.encoded.len() as u64);
+        for style in &styles.encoded {
+            out.bytes.extend_from_slice(style);
+        }
+
+        let cursor = &self.cursor;
+        out.varint(cursor.row as u64);
+        out.varint(cursor.col as u64);
+        out.byte(cursor.needs_wrap as u8);
+        let shape = CURSOR_SHAPES.iter().position(|&s| s == cursor.shape);
+        out.varint(shape.unwrap_or(0) as u64);
+        out.byte(cursor.blinking as u8);
+        out.varint(pen as u64);
+
+        out.varint(lines.len() as u64);
+        for (line, cells) in self.lines.iter().zip(&lines) {
+            out.byte(line.wrapped as u8);
+            let runs: Vec<&[(usize, &Cell)]> = cells.chunk_by(|a, b| a.0 == b.0).collect();
+            out.varint(runs.len() as u64);
+            for run in runs {
+                out.varint(run[0].0 as u64);
+                out.varint(run.len() as u64);
+                for (_, cell) in run {
+                    out.cell(cell);
+                }
+            }
+        }
+        out.bytes
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(SnapshotError::NotASnapshot)?;
+        let mut input = Decoder { bytes: rest };
+        let version = input.byte()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let cols = input.bounded(1, MAX_COLUMNS as u64, "size")? as u16;
+        let rows = input.bounded(1, MAX_ROWS as u64, "size")? as u16;
+        let alt_screen = input.flag()?;
+        let title = input.opt_str()?;
+        let icon_name = input.opt_str()?;
+        let cwd = input.opt_str()?;
+
+        let mode_bits = input.varint()?;
+        let mut modes = TermMode::empty();
+        for (i, (mode, ..)) in MODES.iter().enumerate() {
+            if mode_bits & 1 << i != 0 {
+                modes.insert(*mode);
+            }
+        }
+        let kitty = input.bounded(0, 31, "keyboard flags")? as u8;
+        for (mode, bit) in KITTY_MODES {
+            if kitty & bit != 0 {
+                modes.insert(mode);
+            }
+        }
+        modes.set(TermMode::ALT_SCREEN, alt_screen);
+        let x10_mouse = input.flag()?;
+        let modify_other_keys = input.bounded(0, 2, "modifyOtherKeys level")? as u8;
+
+        let palette_len = input.bounded(0, PALETTE_SIZE as u64, "palette")?;
+        let mut palette = Vec::with_capacity(palette_len as usize);
+        for _ in 0..palette_len {
+            let index = input.bounded(0, PALETTE_SIZE as u64 - 1, "palette index")? as usize;
+            let [r, g, b] = [input.byte()?, input.byte()?, input.byte()?];
+            palette.push((index, Rgb { r, g, b }));
+        }
+
+        // Each style takes at least four bytes, which bounds the count.
+        let style_count = input.bounded(0, input.bytes.len() as u64 / 4, "style count")?;
+        let styles = (0..style_count)
+            .map(|_| input.style())
+            .collect::<Result<Vec<Cell>, _>>()?;
+        let style = |id: u64| -> Result<&Cell, SnapshotError> {
+            styles
+                .get(id as usize)
+                .ok_or(SnapshotError::Invalid("style"))
+        };
+
+        let cursor = SnapshotCursor {
+            row: input.bounded(0, rows as u64 - 1, "cursor")? as u16,
+            col: input.bounded(0, cols as u64 - 1, "cursor")? as u16,
+            needs_wrap: input.flag()?,
+            shape: CURSOR_SHAPES[input.bounded(0, 4, "cursor shape")? as usize],
+            blinking: input.flag()?,
+            pen: style(input.varint()?)?.clone(),
+        };
+
+        // The alternate screen has no history.
+        let max_lines = if alt_screen { 0 } else { SCROLLBACK_LINES } + rows as usize;
+        let line_count = input.bounded(rows as u64, max_lines as u64, "line count")?;
+        let mut lines = Vec::with_capacity(line_count as usize);
+        for _ in 0..line_count {
+            let wrapped = input.flag()?;
+            let mut cells = Vec::new();
+            for _ in 0..input.bounded(0, cols as u64, "line")? {
+                let template = style(input.varint()?)?;
+                let len = input.bounded(1, (cols as usize - cells.len()) as u64, "line")?;
+                for _ in 0..len {
+                    cells.push(input.cell(template)?);
+                }
+            }
+            lines.push(SnapshotLine { cells, wrapped });
+        }
+
+        if !input.bytes.is_empty() {
+            return Err(SnapshotError::Invalid("trailing data"));
+        }
+        Ok(Self {
+            cols,
+            rows,
+            alt_screen,
+            title,
+            icon_name,
+            cwd,
+            modes,
+            x10_mouse,
+            modify_other_keys,
+            palette,
+            cursor,
+            lines,
+        })
+    }
+}
+
+/// The kitty keyboard protocol flags of a mode set.
+fn kitty_flags(modes: TermMode) -> u8 {
+    KITTY_MODES
+        .iter()
+        .filter(|(mode, _)| modes.contains(*mode))
+        .fold(0, |flags, (_, bit)| flags | bit)
+}
+
+/// Styles seen so far, keyed by their encoding.
+#[derive(Default)]
+struct StyleTable {
+    ids: HashMap<Vec<u8>, usize>,
+    encoded: Vec<Vec<u8>>,
+}
+
+impl StyleTable {
+    fn id(&mut self, cell: &Cell) -> usize {
+        let mut style = Encoder::default();
+        style.style(cell);
+        if let Some(&id) = self.ids.get(&style.bytes) {
+            return id;
+        }
+        let id = self.encoded.len();
+        self.ids.insert(style.bytes.clone(), id);
+        self.encoded.push(style.bytes);
+        id
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn str(&mut self, text: &str) {
+        self.varint(text.len() as u64);
+        self.bytes.extend_from_slice(text.as_bytes());
+    }
+
+    fn opt_str(&mut self, text: Option<&str>) {
+        self.byte(text.is_some() as u8);
+        if let Some(text) = text {
+            self.str(text);
+        }
+    }
+
+    fn color(&mut self, color: Color) {
+        match color {
+            Color::Named(named) => {
+                self.byte(0);
+                self.varint(named as u64);
+            }
+            Color::Indexed(index) => {
+                self.byte(1);
+                self.byte(index);
+            }
+            Color::Spec(rgb) => {
+                self.byte(2);
+                self.bytes.extend_from_slice(&[rgb.r, rgb.g, rgb.b]);
+            }
+        }
+    }
+
+    fn style(&mut self, cell: &Cell) {
+        self.color(cell.fg);
+        self.color(cell.bg);
+        let flags = FLAGS
+            .iter()
+            .enumerate()
+            .filter(|(_, flag)| cell.flags.contains(**flag))
+            .fold(0u64, |bits, (i, _)| bits | 1 << i);
+        self.varint(flags);
+        let underline = cell.underline_color();
+        self.byte(underline.is_some() as u8);
+        if let Some(color) = underline {
+            self.color(color);
+        }
+        let link = cell.hyperlink();
+        self.byte(link.is_some() as u8);
+        if let Some(link) = link {
+            self.str(link.id());
+            self.str(link.uri());
+        }
+    }
+
+    fn cell(&mut self, cell: &Cell) {
+        let zerowidth = cell.zerowidth().unwrap_or_default();
+        self.varint((cell.c as u64) << 1 | !zerowidth.is_empty() as u64);
+        if !zerowidth.is_empty() {
+            self.varint(zerowidth.len() as u64);
+            for &c in zerowidth {
+                self.varint(c as u64);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(SnapshotError::Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn flag(&mut self) -> Result<bool, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid("flag")),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::Invalid("number"))
+    }
+
+    /// A varint that must lie in `min..=max`.
+    fn bounded(&mut self, min: u64, max: u64, what: &'static str) -> Result<u64, SnapshotError> {
+        let value = self.varint()?;
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(SnapshotError::Invalid(what))
+        }
+    }
+
+    fn str(&mut self) -> Result<String, SnapshotError> {
+        let len = self.varint()?;
+        if len > self.bytes.len() as u64 {
+            return Err(SnapshotError::Truncated);
+        }
+        let (text, rest) = self.bytes.split_at(len as usize);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| SnapshotError::Invalid("text"))
+    }
+
+    fn opt_str(&mut self) -> Result<Option<String>, SnapshotError> {
+        Ok(if self.flag()? {
+            Some(self.str()?)
+        } else {
+            None
+        })
+    }
+
+    fn char(&mut self, value: u64) -> Result<char, SnapshotError> {
+        u32::try_from(value)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(SnapshotError::Invalid("character"))
+    }
+
+    fn color(&mut self) -> Result<Color, SnapshotError> {
+        match self.byte()? {
+            0 => {
+                let index = self.varint()?;
+                NAMED_COLORS
+                    .iter()
+                    .find(|&&named| named as u64 == index)
+                    .map(|&named| Color::Named(named))
+                    .ok_or(SnapshotError::Invalid("color"))
+            }
+            1 => Ok(Color::Indexed(self.byte()?)),
+            2 => Ok(Color::Spec(Rgb {
+                r: self.byte()?,
+                g: self.byte()?,
+                b: self.byte()?,
+            })),
+            _ => Err(SnapshotError::Invalid("color")),
+        }
+    }
+
+    /// A style, as a blank cell carrying it.
+    fn style(&mut self) -> Result<Cell, SnapshotError> {
+        let mut cell = Cell {
+            fg: self.color()?,
+            bg: self.color()?,
+            ..Cell::default()
+        };
+        let bits = self.varint()?;
+        if bits >> FLAGS.len() != 0 {
+            return Err(SnapshotError::Invalid("cell flags"));
+        }
+        for (i, flag) in FLAGS.iter().enumerate() {
+            cell.flags.set(*flag, bits & 1 << i != 0);
+        }
+        if self.flag()? {
+            let color = self.color()?;
+            cell.set_underline_color(Some(color));
+        }
+        if self.flag()? {
+            let id = self.str()?;
+            let uri = self.str()?;
+            cell.set_hyperlink(Some(Hyperlink::new(Some(id), uri)));
+        }
+        Ok(cell)
+    }
+
+    fn cell(&mut self, template: &Cell) -> Result<Cell, SnapshotError> {
+        let value = self.varint()?;
+        let mut cell = template.clone();
+        cell.c = self.char(value >> 1)?;
+        if value & 1 != 0 {
+            let count = self.bounded(1, MAX_ZEROWIDTH as u64, "zero-width characters")?;
+            for _ in 0..count {
+                let value = self.varint()?;
+                cell.push_zerowidth(self.char(value)?);
+            }
+        }
+        Ok(cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::VtTerminal;
+    use crate::ExportFormat;
+
+    /// Every cell of the scrollback and screen.
+    fn grid_cells(vt: &VtTerminal) -> Vec<Cell> {
+        let term = vt.inner();
+        (term.topmost_line().0..=term.bottommost_line().0)
+            .flat_map(|line| (0..term.columns()).map(move |col| (line, col)))
+            .map(|(line, col)| term.grid()[Line(line)][Column(col)].clone())
+            .collect()
+    }
+
+    /// A terminal with a bit of everything a snapshot keeps.
+    fn sample() -> VtTerminal {
+        let mut term = VtTerminal::new(20, 4);
+        term.write(b"\x1b]7;file://host/home/me\x07\x1b]2;build\x07\x1b]1;b\x07");
+        for i in 0..6 {
+            term.write(format!("\x1b[3{}mline {i}\x1b[m\r\n", i % 8).as_bytes());
+        }
+        term.write("\x1b[1;4:3;58;5;9mwide 日本 e\u{301}\x1b[m ".as_bytes());
+        term.write(b"\x1b]8;id=x;https://example.com\x1b\\link\x1b]8;;\x1b\\ wraps here");
+        term.write(b"\x1b]4;1;rgb:12/34/56\x07\x1b[?2004h\x1b[?1002h\x1b[?1006h\x1b[?25l");
+        term.write(b"\x1b[>1u\x1b[6 q\x1b[2;3H\x1b[7m");
+        term
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut term = sample();
+        let bytes = term.snapshot();
+        let mut restored = VtTerminal::from_snapshot(&bytes).unwrap();
+
+        assert_eq!(restored.history_size(), term.history_size());
+        for format in [ExportFormat::Text, ExportFormat::Ansi] {
+            assert_eq!(restored.export(format, None), term.export(format, None));
+        }
+        assert_eq!(restored.cursor(), term.cursor());
+        assert_eq!(restored.modes(), term.modes());
+        assert_eq!(restored.title(), Some("build"));
+        assert_eq!(restored.icon_name().as_deref(), Some("b"));
+        assert_eq!(restored.cwd(), Some("/home/me"));
+        assert_eq!(restored.colors(), term.colors());
+        assert!(grid_cells(&restored) == grid_cells(&term));
+
+        // Text written after restoring keeps the pen (reverse video here).
+        restored.write(b"x");
+        term.write(b"x");
+        assert!(grid_cells(&restored) == grid_cells(&term));
+
+        // Taking a snapshot again gives the same bytes.
+        assert_eq!(restored.snapshot(), term.snapshot());
+    }
+
+    #[test]
+    fn test_alt_screen_round_trip() {
+        let mut term = VtTerminal::new(10, 3);
+        term.write(b"shell\r\n\x1b[?1049h\x1b[Hvim");
+        let restored = VtTerminal::from_snapshot(&term.snapshot()).unwrap();
+
+        assert!(restored.modes().alt_screen);
+        assert!(grid_cells(&restored) == grid_cells(&term));
+    }
+
+    #[test]
+    fn test_rejects_bad_data() {
+        let bytes = sample().snapshot();
+        assert!(matches!(
+            VtTerminal::from_snapshot(b"hello"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        assert!(matches!(
+            VtTerminal::from_snapshot(&bytes[..bytes.len() - 3]),
+            Err(SnapshotError::Truncated)
+        ));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(
+            VtTerminal::from_snapshot(&newer),
+            Err(SnapshotError::UnsupportedVersion(_))
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            VtTerminal::from_snapshot(&trailing),
+            Err(SnapshotError::Invalid(_))
+        ));
+
+        // A varint that never ends.
+        let mut endless = MAGIC.to_vec();
+        endless.push(VERSION);
+        endless.extend_from_slice(&[0xff; 16]);
+        assert!(matches!(
+            VtTerminal::from_snapshot(&endless),
+            Err(SnapshotError::Invalid(_))
+        ));
+
+        // The sample is 20x4, so its size takes one byte each after the
+        // version; swap in sizes no terminal could be resized to.
+        let body = &bytes[MAGIC.len() + 3..];
+        for (cols, rows) in [
+            (0, 4),
+            (20, 0),
+            (MAX_COLUMNS as u64 + 1, 4),
+            (20, MAX_ROWS as u64 + 1),
+            (u16::MAX as u64, u16::MAX as u64),
+        ] {
+            let mut out = Encoder::default();
+            out.bytes.extend_from_slice(MAGIC);
+            out.byte(VERSION);
+            out.varint(cols);
+            out.varint(rows);
+            out.bytes.extend_from_slice(body);
+            assert!(
+                matches!(
+                    VtTerminal::from_snapshot(&out.bytes),
+                    Err(SnapshotError::Invalid("size"))
+                ),
+                "{cols}x{rows}"
+            );
+        }
+    }
+}
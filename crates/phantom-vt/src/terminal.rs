@@ -1,20 +1,65 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use alacritty_terminal::event::{Event, EventListener};
-use alacritty_terminal::grid::Dimensions;
-use alacritty_terminal::term::{Config, Term, TermDamage};
-use alacritty_terminal::vte::ansi;
+use alacritty_terminal::event::{Event, EventListener, WindowSize};
+use alacritty_terminal::grid::{Dimensions, Scroll};
+use alacritty_terminal::index::{Boundary, Column, Direction, Line, Point};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::{Config, Osc52, Term, TermDamage, TermMode};
+use alacritty_terminal::vte::ansi::{self, Handler, NamedColor, Timeout};
 
+use crate::clipboard::{ClipboardRequest, PendingLoads, RawClipboardRequest};
+use crate::export::{self, ExportContext, ExportFormat, ExportLine};
+use crate::handler::{GraphemeState, Interceptor};
+use crate::hyperlink::{Hyperlink, HyperlinkTable};
+use crate::image::{Anchor, Image, ImagePlacement, ImageStore};
+use crate::input::{self, MouseEvent};
+use crate::keyboard::{self, KeyEvent};
+use crate::kitty::KittyGraphics;
+use crate::links::{DetectedLink, LinkDetector};
+use crate::modes::{KittyKeyboardFlags, MouseEncoding, MouseTracking, TerminalModes};
+use crate::notify::{Notification, Notifications, Progress};
+use crate::osc::{ScanEvent, SideScanner};
+use crate::paste::{self, PasteRisk};
+use crate::reflow::{self, GridContents};
 use crate::screen::{
-    convert_cursor_shape, CursorState, DamageInfo, DamagedRow, ScreenView,
+    convert_cursor_shape, palette_color, CursorShape, CursorState, DamageInfo, DamagedRow,
+    ScreenView,
 };
+use crate::search::{SearchError, SearchKind, SearchMatch, SearchState};
+use crate::selection::{self, GridPoint, SelectionMode, SelectionRange, Side};
+use crate::shell::{AbsPoint, CommandBlock, CommandBlocks, ShellMark};
+use crate::sixel;
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::theme::Theme;
+use crate::titles::Titles;
 
 /// Shared event state captured from the terminal.
 #[derive(Default)]
 struct EventState {
-    title: Option<String>,
+    titles: Titles,
     bell: bool,
-    pty_writes: Vec<String>,
+    pty_writes: Vec<PendingWrite>,
+    clipboard: Vec<RawClipboardRequest>,
+}
+
+/// Formats a color as the reply to an OSC 4/10/11/12 query.
+type ColorFormatter = Arc<dyn Fn(ansi::Rgb) -> String + Sync + Send>;
+
+/// Formats the text area size as the reply to `CSI 14 t`.
+type SizeFormatter = Arc<dyn Fn(WindowSize) -> String + Sync + Send>;
+
+/// A reply to the program, kept in order with the others.
+enum PendingWrite {
+    Text(String),
+    /// A color query, answered when the writes are taken since the color
+    /// depends on the theme, which the event proxy does not know.
+    Color(usize, ColorFormatter),
+    /// A text area size query, answered when the writes are taken since the
+    /// cell size in pixels is only known to the terminal.
+    TextAreaSize(SizeFormatter),
 }
 
 /// Event proxy that captures terminal events.
@@ -39,16 +84,28 @@ impl EventListener for EventProxy {
         let mut state = self.state.lock().unwrap();
         match event {
             Event::Title(title) => {
-                state.title = Some(title);
+                state.titles.set_title(Some(title));
             }
             Event::ResetTitle => {
-                state.title = None;
+                state.titles.set_title(None);
             }
             Event::Bell => {
                 state.bell = true;
             }
             Event::PtyWrite(data) => {
-                state.pty_writes.push(data);
+                state.pty_writes.push(PendingWrite::Text(data));
+            }
+            Event::ClipboardStore(ty, text) => {
+                state.clipboard.push(RawClipboardRequest::Store(ty, text));
+            }
+            Event::ClipboardLoad(ty, formatter) => {
+                state.clipboard.push(RawClipboardRequest::Load(ty, formatter));
+            }
+            Event::ColorRequest(index, formatter) => {
+                state.pty_writes.push(PendingWrite::Color(index, formatter));
+            }
+            Event::TextAreaSizeRequest(formatter) => {
+                state.pty_writes.push(PendingWrite::TextAreaSize(formatter));
             }
             // We don't act on other events for now.
             _ => {}
@@ -56,6 +113,21 @@ impl EventListener for EventProxy {
     }
 }
 
+/// Number of scrollback lines kept by each terminal.
+pub(crate) const SCROLLBACK_LINES: usize = 10_000;
+
+/// Largest number of columns; wider sizes are clamped to it.
+pub const MAX_COLUMNS: u16 = 1000;
+
+/// Largest number of rows; taller sizes are clamped to it.
+pub const MAX_ROWS: u16 = 500;
+
+/// Pixel size of a cell until the embedder sets the real one.
+const DEFAULT_CELL_SIZE: (u16, u16) = (8, 16);
+
+/// Upper bound on matches returned by `VtTerminal::search_all_matches`.
+const MAX_SEARCH_MATCHES: usize = 10_000;
+
 /// Dimensions helper for creating / resizing the terminal.
 struct TermSize {
     columns: usize,
@@ -64,7 +136,7 @@ struct TermSize {
 
 impl Dimensions for TermSize {
     fn total_lines(&self) -> usize {
-        self.screen_lines
+        self.screen_lines + SCROLLBACK_LINES
     }
 
     fn screen_lines(&self) -> usize {
@@ -76,40 +148,161 @@ impl Dimensions for TermSize {
     }
 }
 
+/// Deadline of the synchronized update in progress.
+///
+/// Unlike alacritty's `StdSyncHandler`, an update can be resumed with the
+/// deadline it had, so one cut short to apply a positional sequence does not
+/// get extended.
+#[derive(Default)]
+struct SyncTimeout {
+    deadline: Option<Instant>,
+    /// Deadline for the next `CSI ? 2026 h` instead of a fresh timeout.
+    resume_at: Cell<Option<Instant>>,
+}
+
+impl SyncTimeout {
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl Timeout for SyncTimeout {
+    fn set_timeout(&mut self, duration: Duration) {
+        let deadline = self.resume_at.take().unwrap_or_else(|| Instant::now() + duration);
+        self.deadline = Some(deadline);
+    }
+
+    fn clear_timeout(&mut self) {
+        self.deadline = None;
+    }
+
+    fn pending_timeout(&self) -> bool {
+        self.deadline.is_some()
+    }
+}
+
+/// The kitty keyboard enhancements active in a mode set.
+fn kitty_keyboard_flags(mode: TermMode) -> KittyKeyboardFlags {
+    let mut flags = KittyKeyboardFlags::empty();
+    for (term_mode, flag) in [
+        (TermMode::DISAMBIGUATE_ESC_CODES, KittyKeyboardFlags::DISAMBIGUATE_ESC_CODES),
+        (TermMode::REPORT_EVENT_TYPES, KittyKeyboardFlags::REPORT_EVENT_TYPES),
+        (TermMode::REPORT_ALTERNATE_KEYS, KittyKeyboardFlags::REPORT_ALTERNATE_KEYS),
+        (TermMode::REPORT_ALL_KEYS_AS_ESC, KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESC),
+        (TermMode::REPORT_ASSOCIATED_TEXT, KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT),
+    ] {
+        flags.set(flag, mode.contains(term_mode));
+    }
+    flags
+}
+
 /// The core terminal emulator.
 ///
 /// Wraps `alacritty_terminal::Term` and a VTE parser, providing a clean API
 /// for the rest of the Phantom app.
 pub struct VtTerminal {
     term: Term<EventProxy>,
-    parser: ansi::Processor,
+    parser: ansi::Processor<SyncTimeout>,
+    /// The grapheme cluster being written, joined across parser calls.
+    graphemes: GraphemeState,
+    /// Scans for sequences `parser` ignores; runs in lockstep with it.
+    scanner: SideScanner,
     event_proxy: EventProxy,
+    /// Options the terminal was configured with, kept so individual settings
+    /// can be changed at runtime via `Term::set_options`.
+    config: Config,
+    /// The active search query, if any.
+    search: Option<SearchState>,
+    /// OSC 8 hyperlinks seen in rendered cells, interned to compact IDs.
+    hyperlinks: RefCell<HyperlinkTable>,
+    /// Working directory last reported by the shell via OSC 7.
+    cwd: Option<String>,
+    /// URL and path detector, compiled on first use.
+    link_detector: Option<LinkDetector>,
+    /// Lines scrolled into the history of the primary screen since creation.
+    ///
+    /// `scrolled_lines + line` is an absolute line number that stays attached
+    /// to its text as it scrolls; used for command blocks.
+    scrolled_lines: i64,
+    /// Command blocks from OSC 133 shell integration marks.
+    command_blocks: CommandBlocks,
+    /// OSC 9 / OSC 777 notifications and OSC 9;4 progress.
+    notifications: Notifications,
+    /// Inline images and where they are shown.
+    images: ImageStore,
+    /// Scanner events seen during a synchronized update, applied when it
+    /// ends.
+    deferred_events: Vec<ScanEvent>,
+    /// State of kitty graphics commands split into chunks.
+    kitty_graphics: KittyGraphics,
+    /// Pixel size of a cell, used to size images in cells.
+    cell_size: (u16, u16),
+    /// OSC 52 clipboard reads waiting for the embedder to answer.
+    clipboard_loads: PendingLoads,
+    /// X10 mouse reporting (mode 9), tracked here since alacritty ignores it.
+    x10_mouse: bool,
+    /// xterm modifyOtherKeys level, tracked here since alacritty ignores it.
+    modify_other_keys: u8,
+    /// Colors cells are resolved against, unless the program overrode them.
+    theme: Theme,
+    /// The theme changed since the last damage reset; everything must be
+    /// redrawn.
+    theme_damaged: bool,
+    /// Whether resizing rewraps soft-wrapped lines.
+    reflow: bool,
     /// Cached title, synced from EventProxy before each access.
     cached_title: Option<String>,
 }
 
 impl VtTerminal {
-    /// Create a new terminal with the given dimensions.
+    /// Create a new terminal with the given dimensions, clamped to between
+    /// 1x1 and `MAX_COLUMNS` x `MAX_ROWS`.
     ///
     /// Uses 10,000 lines of scrollback history by default.
     pub fn new(cols: u16, rows: u16) -> Self {
         let config = Config {
-            scrolling_history: 10_000,
+            scrolling_history: SCROLLBACK_LINES,
+            // Clipboard requests are only queued here; the embedder decides
+            // which of them to honor.
+            osc52: Osc52::CopyPaste,
+            kitty_keyboard: true,
             ..Config::default()
         };
 
         let size = TermSize {
-            columns: cols as usize,
-            screen_lines: rows as usize,
+            columns: cols.clamp(1, MAX_COLUMNS) as usize,
+            screen_lines: rows.clamp(1, MAX_ROWS) as usize,
         };
 
         let event_proxy = EventProxy::new();
-        let term = Term::new(config, &size, event_proxy.clone());
+        let mut term = Term::new(config.clone(), &size, event_proxy.clone());
+        // A new terminal is assumed to be in front until told otherwise.
+        term.is_focused = true;
 
         Self {
             term,
             parser: ansi::Processor::new(),
+            graphemes: GraphemeState::default(),
+            scanner: SideScanner::default(),
             event_proxy,
+            config,
+            search: None,
+            hyperlinks: RefCell::default(),
+            cwd: None,
+            link_detector: None,
+            scrolled_lines: 0,
+            command_blocks: CommandBlocks::default(),
+            notifications: Notifications::default(),
+            images: ImageStore::default(),
+            deferred_events: Vec::new(),
+            kitty_graphics: KittyGraphics::default(),
+            cell_size: DEFAULT_CELL_SIZE,
+            clipboard_loads: PendingLoads::default(),
+            x10_mouse: false,
+            modify_other_keys: 0,
+            theme: Theme::default(),
+            theme_damaged: false,
+            reflow: true,
             cached_title: None,
         }
     }
@@ -119,46 +312,778 @@ impl VtTerminal {
     /// This parses the bytes through the VTE state machine and updates the
     /// terminal grid accordingly.
     pub fn write(&mut self, bytes: &[u8]) {
-        self.parser.advance(&mut self.term, bytes);
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (n, event) = self.scanner.advance(rest);
+            match event {
+                Some(event) if event.is_positional() => {
+                    // Output held back by a synchronized update is applied
+                    // first: the event acts on the screen as it stands here.
+                    // The update then resumes, so the rest of the frame is
+                    // still held back until the program ends it.
+                    self.advance_parser(&rest[..n - 1]);
+                    let deadline = self.synchronized_update_deadline();
+                    self.end_synchronized_update();
+                    self.advance_parser(&rest[n - 1..n]);
+                    self.handle_scan_event(event);
+                    if let Some(deadline) = deadline {
+                        self.parser.sync_timeout().resume_at.set(Some(deadline));
+                        self.advance_parser(b"\x1b[?2026h");
+                    }
+                }
+                Some(event) => {
+                    // Anything else waits for the frame to be shown, so a
+                    // title or notification does not end it early.
+                    self.advance_parser(&rest[..n]);
+                    self.deferred_events.push(event);
+                }
+                None => self.advance_parser(&rest[..n]),
+            }
+            rest = &rest[n..];
+            if !self.is_synchronized_update() {
+                self.handle_deferred_events();
+            }
+        }
+        if self.hyperlinks.get_mut().needs_collection() {
+            self.collect_hyperlinks();
+        }
+    }
+
+    /// Free the IDs of hyperlinks no cell on the grid refers to any more.
+    ///
+    /// On the alternate screen, links only on the primary screen are freed
+    /// too; they get IDs again when the primary screen is read.
+    fn collect_hyperlinks(&mut self) {
+        let grid = self.term.grid();
+        let mut live = HashSet::new();
+        for line in grid.topmost_line().0..=grid.bottommost_line().0 {
+            for cell in &grid[Line(line)] {
+                if let Some(link) = cell.hyperlink() {
+                    live.insert(link);
+                }
+            }
+        }
+        self.hyperlinks.get_mut().retain(&live);
+    }
+
+    /// Apply the scanner events held back by a synchronized update.
+    fn handle_deferred_events(&mut self) {
+        for event in std::mem::take(&mut self.deferred_events) {
+            self.handle_scan_event(event);
+        }
+    }
+
+    /// Run bytes through the main parser, joining grapheme clusters and
+    /// counting lines scrolled into the history on the way.
+    fn advance_parser(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let mut handler = Interceptor {
+            term: &mut self.term,
+            graphemes: &mut self.graphemes,
+            history_limit: self.config.scrolling_history,
+            scrolled: 0,
+        };
+        self.parser.advance(&mut handler, bytes);
+        let scrolled = handler.scrolled;
+        self.track_scrolling(scrolled);
+    }
+
+    /// Whether the program is in the middle of a synchronized update (DEC
+    /// mode 2026).
+    ///
+    /// Output between `CSI ? 2026 h` and `CSI ? 2026 l` is held back and
+    /// applied all at once when the update ends, so until then the screen
+    /// shows the previous frame.
+    pub fn is_synchronized_update(&self) -> bool {
+        self.parser.sync_timeout().pending_timeout()
+    }
+
+    /// When the synchronized update in progress should be given up on.
+    ///
+    /// Each `CSI ? 2026 h` extends the deadline. The parser only checks it
+    /// when more output arrives, so a program that stops writing mid-update
+    /// must be cut off with `end_synchronized_update`.
+    pub fn synchronized_update_deadline(&self) -> Option<Instant> {
+        self.parser.sync_timeout().deadline()
+    }
+
+    /// End the synchronized update in progress, if any, applying the output
+    /// held back so far.
+    pub fn end_synchronized_update(&mut self) {
+        if !self.is_synchronized_update() {
+            return;
+        }
+        let mut handler = Interceptor {
+            term: &mut self.term,
+            graphemes: &mut self.graphemes,
+            history_limit: self.config.scrolling_history,
+            scrolled: 0,
+        };
+        self.parser.stop_sync(&mut handler);
+        let scrolled = handler.scrolled;
+        self.track_scrolling(scrolled);
+        self.handle_deferred_events();
+    }
+
+    /// Account for `lines` scrolled into the history of the primary screen
+    /// by the parser.
+    fn track_scrolling(&mut self, lines: usize) {
+        self.scrolled_lines += lines as i64;
+        if !self.is_alt_screen() {
+            self.forget_trimmed_lines();
+        }
+    }
+
+    /// Drop state that points at history lines which no longer exist.
+    fn forget_trimmed_lines(&mut self) {
+        let topmost = self.term.topmost_line().0;
+        let selection_trimmed = self
+            .term
+            .selection
+            .as_ref()
+            .is_some_and(|s| s.intersects_range(..Line(topmost)));
+        if selection_trimmed {
+            self.term.selection = None;
+        }
+        if let Some(search) = self.search.as_mut() {
+            if search.focused.is_some_and(|m| m.start.line < topmost) {
+                search.focused = None;
+            }
+        }
+        self.command_blocks.prune(self.scrolled_lines + topmost as i64);
+        self.images.prune(self.scrolled_lines + topmost as i64);
+    }
+
+    /// Apply a sequence the main parser does not handle.
+    fn handle_scan_event(&mut self, event: ScanEvent) {
+        match event {
+            ScanEvent::WorkingDirectory(path) => self.cwd = Some(path),
+            ScanEvent::ShellMark(mark) => self.handle_shell_mark(mark),
+            ScanEvent::Notify(event) => self.notifications.handle(event),
+            ScanEvent::IconName(name) => {
+                let mut state = self.event_proxy.state.lock().unwrap();
+                state.titles.set_icon_name(Some(name));
+            }
+            ScanEvent::TitleStack { push, target } => {
+                let mut state = self.event_proxy.state.lock().unwrap();
+                if push {
+                    state.titles.push(target);
+                } else {
+                    state.titles.pop(target);
+                }
+            }
+            ScanEvent::ReportCellSize => {
+                let (width, height) = self.cell_size;
+                let mut state = self.event_proxy.state.lock().unwrap();
+                let reply = format!("\x1b[6;{height};{width}t");
+                state.pty_writes.push(PendingWrite::Text(reply));
+            }
+            // The alternate screen is cleared on the way in and out.
+            ScanEvent::AltScreen => self.images.clear_alt_screen(),
+            ScanEvent::KittyGraphics(data) => self.handle_kitty_graphics(&data),
+            ScanEvent::Sixel { params, data } => self.handle_sixel(&params, &data),
+            ScanEvent::X10Mouse(enabled) => self.x10_mouse = enabled,
+            ScanEvent::ModifyOtherKeys(level) => self.modify_other_keys = level,
+            ScanEvent::Reset => {
+                self.command_blocks.clear();
+                self.notifications.clear();
+                self.clipboard_loads.clear();
+                self.x10_mouse = false;
+                self.modify_other_keys = 0;
+                self.images.clear();
+                let mut state = self.event_proxy.state.lock().unwrap();
+                state.titles.clear_stacks();
+            }
+        }
+    }
+
+    /// Where an image placed at the cursor is anchored.
+    fn cursor_anchor(&self) -> Anchor {
+        let cursor = self.term.grid().cursor.point;
+        let alt_screen = self.is_alt_screen();
+        let line = cursor.line.0 as i64;
+        Anchor {
+            line: if alt_screen { line } else { self.scrolled_lines + line },
+            col: cursor.column.0 as u16,
+            alt_screen,
+        }
+    }
+
+    /// Run a kitty graphics command.
+    fn handle_kitty_graphics(&mut self, data: &[u8]) {
+        let at = self.cursor_anchor();
+        let outcome = self
+            .kitty_graphics
+            .handle(data, &mut self.images, at, self.cell_size);
+        if let Some(reply) = outcome.reply {
+            let mut state = self.event_proxy.state.lock().unwrap();
+            state.pty_writes.push(PendingWrite::Text(reply));
+        }
+        // The cursor ends up on the image's last row, after its right edge.
+        if let Some((cols, rows)) = outcome.move_cursor {
+            self.move_cursor_past_image(rows - 1, at.col.saturating_add(cols));
+        }
+    }
+
+    /// Decode and place a sixel image at the cursor.
+    fn handle_sixel(&mut self, params: &[u16], data: &[u8]) {
+        let Ok(image) = sixel::decode(params, data, self.images.budget()) else {
+            return;
+        };
+        let (width, height) = (image.width, image.height);
+        let Ok(id) = self.images.insert(image, None) else {
+            return;
+        };
+        let at = self.cursor_anchor();
+        let (cell_width, cell_height) = self.cell_size;
+        let cols = width.div_ceil(cell_width.max(1) as u32).min(u16::MAX as u32) as u16;
+        let rows = height.div_ceil(cell_height.max(1) as u32).min(u16::MAX as u32) as u16;
+        self.images.place(id, 0, at, (cols, rows), 0);
+        // Like xterm, the cursor moves to the line below the image.
+        self.move_cursor_past_image(rows, at.col);
+    }
+
+    /// Move the cursor down `lines` lines, scrolling at the bottom, and to
+    /// column `col`.
+    ///
+    /// Done on `Term` directly: the parser is still inside the string
+    /// terminator of the image sequence.
+    fn move_cursor_past_image(&mut self, lines: u16, col: u16) {
+        let mut handler = Interceptor {
+            term: &mut self.term,
+            graphemes: &mut self.graphemes,
+            history_limit: self.config.scrolling_history,
+            scrolled: 0,
+        };
+        for _ in 0..lines {
+            handler.linefeed();
+        }
+        let scrolled = handler.scrolled;
+        let last_column = self.term.columns() - 1;
+        self.term.goto_col((col as usize).min(last_column));
+        self.track_scrolling(scrolled);
+    }
+
+    /// Record an OSC 133 mark at the cursor position.
+    fn handle_shell_mark(&mut self, mark: ShellMark) {
+        if self.is_alt_screen() {
+            return;
+        }
+
+        let cursor = self.term.grid().cursor.point;
+        let at = AbsPoint {
+            line: self.scrolled_lines + cursor.line.0 as i64,
+            col: cursor.column.0 as u16,
+        };
+        let now = Instant::now();
+        match mark {
+            ShellMark::PromptStart => self.command_blocks.prompt_start(at),
+            ShellMark::CommandStart => self.command_blocks.command_start(at),
+            ShellMark::OutputStart => {
+                let command = self
+                    .command_blocks
+                    .open_block()
+                    .and_then(|block| block.command_start)
+                    .map(|start| self.text_between(start, at).trim().to_string());
+                self.command_blocks.output_start(at, command, now);
+            }
+            ShellMark::CommandFinished(exit_code) => {
+                self.command_blocks.command_finished(at, exit_code, now)
+            }
+        }
+    }
+
+    /// Text between two absolute points, end exclusive. Soft-wrapped lines
+    /// are joined; trailing blank cells are dropped.
+    fn text_between(&self, start: AbsPoint, end: AbsPoint) -> String {
+        let topmost = self.term.topmost_line().0 as i64;
+        let start_line = (start.line - self.scrolled_lines).max(topmost);
+        let end_line = end.line - self.scrolled_lines;
+        if end_line < start_line || (end_line == start_line && end.col <= start.col) {
+            return String::new();
+        }
+
+        // Points recorded before the terminal got narrower may lie past the
+        // last column.
+        let last_column = self.term.last_column();
+        let start = Point::new(
+            Line(start_line as i32),
+            Column(start.col as usize).min(last_column),
+        );
+        let end = if end.col == 0 {
+            Point::new(Line(end_line as i32 - 1), last_column)
+        } else {
+            Point::new(
+                Line(end_line as i32),
+                Column(end.col as usize - 1).min(last_column),
+            )
+        };
+        let end = end.min(Point::new(self.term.bottommost_line(), self.term.last_column()));
+        if end < start {
+            return String::new();
+        }
+        self.term.bounds_to_string(start, end)
     }
 
-    /// Resize the terminal to new dimensions.
+    /// The modes set by the program that affect how input is encoded.
+    pub fn modes(&self) -> TerminalModes {
+        let mode = *self.term.mode();
+        // alacritty's mouse modes replace each other; X10 mode is only used
+        // when none of them is set.
+        let mouse_tracking = if mode.contains(TermMode::MOUSE_MOTION) {
+            MouseTracking::AnyEvent
+        } else if mode.contains(TermMode::MOUSE_DRAG) {
+            MouseTracking::ButtonEvent
+        } else if mode.contains(TermMode::MOUSE_REPORT_CLICK) {
+            MouseTracking::Normal
+        } else if self.x10_mouse {
+            MouseTracking::X10
+        } else {
+            MouseTracking::None
+        };
+        let mouse_encoding = if mode.contains(TermMode::SGR_MOUSE) {
+            MouseEncoding::Sgr
+        } else if mode.contains(TermMode::UTF8_MOUSE) {
+            MouseEncoding::Utf8
+        } else {
+            MouseEncoding::Default
+        };
+
+        TerminalModes {
+            mouse_tracking,
+            mouse_encoding,
+            alternate_scroll: mode.contains(TermMode::ALTERNATE_SCROLL),
+            app_cursor: mode.contains(TermMode::APP_CURSOR),
+            app_keypad: mode.contains(TermMode::APP_KEYPAD),
+            modify_other_keys: self.modify_other_keys,
+            kitty_keyboard: kitty_keyboard_flags(mode),
+            bracketed_paste: mode.contains(TermMode::BRACKETED_PASTE),
+            focus_reporting: mode.contains(TermMode::FOCUS_IN_OUT),
+            alt_screen: mode.contains(TermMode::ALT_SCREEN),
+        }
+    }
+
+    /// Encode a mouse event for the program, per its current modes.
+    ///
+    /// Returns `None` when the event should not be sent, in which case the
+    /// embedder is free to use it (e.g. for selection or scrolling).
+    pub fn encode_mouse(&self, event: &MouseEvent) -> Option<Vec<u8>> {
+        input::encode_mouse(event, &self.modes())
+    }
+
+    /// Encode a key event for the program, per its current modes.
+    ///
+    /// Returns `None` for events the program should not receive, such as
+    /// key releases when it did not ask for them.
+    pub fn encode_key(&self, event: &KeyEvent) -> Option<Vec<u8>> {
+        keyboard::encode_key(event, &self.modes())
+    }
+
+    /// Encode pasted text for the program: escape sequences are stripped and
+    /// the text is bracketed if the program enabled bracketed paste.
+    pub fn encode_paste(&self, text: &str) -> Vec<u8> {
+        paste::encode_paste(text, &self.modes())
+    }
+
+    /// Reasons pasting `text` into the terminal now should be confirmed by
+    /// the user. Empty if it is safe to send as-is.
+    pub fn paste_risks(&self, text: &str) -> Vec<PasteRisk> {
+        paste::paste_risks(text, &self.modes())
+    }
+
+    fn is_alt_screen(&self) -> bool {
+        self.term.mode().contains(TermMode::ALT_SCREEN)
+    }
+
+    /// Resize the terminal to new dimensions, clamped like those given to
+    /// `new`.
+    ///
+    /// Soft-wrapped lines of the primary screen and its history are
+    /// rewrapped to the new width and the cursor keeps its place in the text;
+    /// see `set_reflow`. The screen scrolls as little as it can while keeping
+    /// the cursor's line in view. The alternate screen is cropped or padded,
+    /// since the program on it redraws for the new size.
+    ///
+    /// Reflow changes how many rows the history takes, so absolute line
+    /// positions are re-anchored on the cursor: text near the cursor keeps
+    /// its position exactly, older text may drift by the number of lines that
+    /// rewrapped.
     pub fn resize(&mut self, cols: u16, rows: u16) {
+        let columns = cols.clamp(1, MAX_COLUMNS) as usize;
+        let screen_lines = rows.clamp(1, MAX_ROWS) as usize;
         let size = TermSize {
-            columns: cols as usize,
-            screen_lines: rows as usize,
+            columns,
+            screen_lines,
         };
-        self.term.resize(size);
+        if columns == self.term.columns() && screen_lines == self.term.screen_lines() {
+            return;
+        }
+
+        // The primary screen is out of reach behind the alternate one, so
+        // step back to it for the resize. Leaving the alternate screen keeps
+        // it intact, but entering it again clears it, so it is put back
+        // from a copy.
+        let alt_grid = self.is_alt_screen().then(|| {
+            let grid = self.term.grid().clone();
+            self.term.swap_alt();
+            grid
+        });
+
+        let cursor_before = self.term.grid().cursor.point.line.0 as i64;
+        if columns == self.term.columns() {
+            // Nothing to rewrap; alacritty keeps the text when only the
+            // height changes, short of the cursor's line.
+            if self.reflow && reflow::fit_cursor_line(self.term.grid_mut(), screen_lines) {
+                self.term.selection = None;
+            }
+            self.term.resize(size);
+        } else {
+            // The contents are laid out again below, so alacritty only has
+            // to resize an empty grid. Its own reflow never finishes
+            // wrapping a wide character onto a single column.
+            let contents = GridContents::take(self.term.grid_mut());
+            self.term.resize(size);
+            let history = self.config.scrolling_history;
+            contents.restore(self.term.grid_mut(), self.reflow, history);
+        }
+        let cursor_after = self.term.grid().cursor.point.line.0 as i64;
+        self.scrolled_lines += cursor_before - cursor_after;
+        self.forget_trimmed_lines();
+
+        if let Some(mut grid) = alt_grid {
+            grid.resize(false, screen_lines, columns);
+            reflow::blank_cut_wide_chars(&mut grid);
+            self.term.swap_alt();
+            *self.term.grid_mut() = grid;
+        }
+    }
+
+    /// Whether resizing rewraps soft-wrapped lines; on by default.
+    ///
+    /// Turn it off for panes that run full-screen programs on the alternate
+    /// screen, where text should keep its columns: lines are then cropped
+    /// or padded, and text cut off by a narrower size is lost.
+    pub fn set_reflow(&mut self, reflow: bool) {
+        self.reflow = reflow;
     }
 
     /// Get a read-only view of the terminal screen.
     pub fn screen(&self) -> ScreenView<'_> {
-        ScreenView::new(&self.term)
+        ScreenView::new(&self.term, &self.hyperlinks, &self.theme)
+    }
+
+    /// The color scheme set with `set_theme`.
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Change the color scheme. The whole screen is damaged.
+    pub fn set_theme(&mut self, theme: Theme) {
+        if theme != self.theme {
+            self.theme = theme;
+            self.theme_damaged = true;
+        }
+    }
+
+    /// The colors in effect: the theme, with any colors the program set via
+    /// OSC 4/10/11/12 applied.
+    pub fn colors(&self) -> Theme {
+        let colors = self.term.colors();
+        let mut effective = self.theme.clone();
+        for (i, color) in effective.ansi.iter_mut().enumerate() {
+            *color = palette_color(i, colors, &self.theme);
+        }
+        effective.foreground = palette_color(NamedColor::Foreground as usize, colors, &self.theme);
+        effective.background = palette_color(NamedColor::Background as usize, colors, &self.theme);
+        effective.cursor = palette_color(NamedColor::Cursor as usize, colors, &self.theme);
+        effective
     }
 
     /// Get the current cursor state (position, shape, visibility).
+    ///
+    /// The row is relative to the viewport. While the viewport is scrolled far
+    /// enough into the history that the cursor line is off screen, the cursor
+    /// is reported as not visible.
     pub fn cursor(&self) -> CursorState {
         let content = self.term.renderable_content();
         let cursor = &content.cursor;
 
-        let visible = cursor.shape != alacritty_terminal::vte::ansi::CursorShape::Hidden;
-        let shape = convert_cursor_shape(cursor.shape);
+        let viewport_row = cursor.point.line.0 + content.display_offset as i32;
+        let on_screen = viewport_row < self.term.screen_lines() as i32;
+        let visible =
+            cursor.shape != alacritty_terminal::vte::ansi::CursorShape::Hidden && on_screen;
+        let focused = self.term.is_focused;
+        let shape = match convert_cursor_shape(cursor.shape) {
+            CursorShape::Hidden => CursorShape::Hidden,
+            _ if !focused => CursorShape::HollowBlock,
+            shape => shape,
+        };
 
         CursorState {
-            row: cursor.point.line.0 as u16,
+            row: viewport_row.min(u16::MAX as i32) as u16,
             col: cursor.point.column.0 as u16,
             shape,
             visible,
+            blinking: visible && focused && self.term.cursor_style().blinking,
+            focused,
         }
     }
 
+    /// Whether the terminal has keyboard focus.
+    pub fn is_focused(&self) -> bool {
+        self.term.is_focused
+    }
+
+    /// Tell the terminal whether it has keyboard focus.
+    ///
+    /// Returns the report to send to the program (`CSI I` on focus in,
+    /// `CSI O` on focus out) if focus changed and the program enabled focus
+    /// reporting (mode 1004).
+    pub fn set_focused(&mut self, focused: bool) -> Option<Vec<u8>> {
+        if self.term.is_focused == focused {
+            return None;
+        }
+        self.term.is_focused = focused;
+        if !self.term.mode().contains(TermMode::FOCUS_IN_OUT) {
+            return None;
+        }
+        Some(if focused { b"\x1b[I".to_vec() } else { b"\x1b[O".to_vec() })
+    }
+
+    /// Number of lines in the scrollback history.
+    pub fn history_size(&self) -> usize {
+        self.term.history_size()
+    }
+
+    /// How many lines the viewport is scrolled up into the history.
+    pub fn display_offset(&self) -> usize {
+        self.term.grid().display_offset()
+    }
+
+    /// Scroll the viewport by `delta` lines.
+    ///
+    /// Positive values scroll up into the history, negative values scroll back
+    /// towards the live screen. The offset is clamped to the available history.
+    /// Moving the viewport marks the whole screen as damaged.
+    pub fn scroll_display(&mut self, delta: i32) {
+        self.term.scroll_display(Scroll::Delta(delta));
+    }
+
+    /// Scroll the viewport up by one screen.
+    pub fn scroll_page_up(&mut self) {
+        self.term.scroll_display(Scroll::PageUp);
+    }
+
+    /// Scroll the viewport down by one screen.
+    pub fn scroll_page_down(&mut self) {
+        self.term.scroll_display(Scroll::PageDown);
+    }
+
+    /// Scroll the viewport to the oldest line in the history.
+    pub fn scroll_to_top(&mut self) {
+        self.term.scroll_display(Scroll::Top);
+    }
+
+    /// Scroll the viewport back to the live screen.
+    pub fn scroll_to_bottom(&mut self) {
+        self.term.scroll_display(Scroll::Bottom);
+    }
+
+    /// Start a new selection at `point`, replacing any existing selection.
+    pub fn start_selection(&mut self, mode: SelectionMode, point: GridPoint, side: Side) {
+        self.term.selection = Some(selection::new_selection(mode, point, side));
+    }
+
+    /// Extend the current selection to `point`.
+    ///
+    /// Does nothing if no selection has been started.
+    pub fn update_selection(&mut self, point: GridPoint, side: Side) {
+        if let Some(sel) = self.term.selection.as_mut() {
+            selection::update_selection(sel, point, side);
+        }
+    }
+
+    /// Clear the current selection.
+    pub fn clear_selection(&mut self) {
+        self.term.selection = None;
+    }
+
+    /// Get the resolved extent of the current selection.
+    ///
+    /// Word and line selections are expanded to their boundaries. Returns
+    /// `None` if nothing is selected.
+    pub fn selection_range(&self) -> Option<SelectionRange> {
+        let range = self.term.selection.as_ref()?.to_range(&self.term)?;
+        Some(SelectionRange {
+            start: GridPoint::from_alac(range.start),
+            end: GridPoint::from_alac(range.end),
+            is_block: range.is_block,
+        })
+    }
+
+    /// Get the text of the current selection.
+    ///
+    /// Soft-wrapped lines are joined, wide-char spacers are skipped, and
+    /// combining characters are kept with their base character.
+    pub fn selection_text(&self) -> Option<String> {
+        self.term.selection_to_string()
+    }
+
+    /// Export the scrollback and screen, or only the lines of `range`, as
+    /// plain text, ANSI escaped text or an HTML document.
+    ///
+    /// Soft-wrapped lines are joined and trailing blanks are trimmed. A block
+    /// range exports its columns of each line as separate lines.
+    pub fn export(&self, format: ExportFormat, range: Option<SelectionRange>) -> String {
+        let screen = self.screen();
+        let last_column = self.term.last_column();
+        let (first, last) = match range {
+            Some(range) => (range.start.line, range.end.line),
+            None => (self.term.topmost_line().0, self.term.bottommost_line().0),
+        };
+
+        let mut lines = Vec::new();
+        for line in first.max(self.term.topmost_line().0)..=last {
+            let Some(mut cells) = screen.line_cells(line) else {
+                continue;
+            };
+            let last_flags = self.term.grid()[Line(line)][last_column].flags;
+            let mut wrapped = last_flags.contains(Flags::WRAPLINE);
+            // A wide character that did not fit leaves a blank behind as it
+            // wraps; it is not part of the text.
+            if wrapped && last_flags.contains(Flags::LEADING_WIDE_CHAR_SPACER) {
+                cells.truncate(last_column.0);
+            }
+            if let Some(range) = range {
+                let start = if range.is_block || line == range.start.line {
+                    range.start.col as usize
+                } else {
+                    0
+                };
+                let end = if range.is_block || line == range.end.line {
+                    range.end.col as usize + 1
+                } else {
+                    cells.len()
+                };
+                cells.truncate(end.min(cells.len()));
+                cells.drain(..start.min(cells.len()));
+                wrapped &= !range.is_block && line != range.end.line;
+            }
+            lines.push(ExportLine { cells, wrapped });
+        }
+
+        let colors = self.colors();
+        let cx = ExportContext {
+            foreground: colors.foreground,
+            background: colors.background,
+            link: |id| self.hyperlink(id).map(|link| link.uri),
+        };
+        export::export(lines, format, &cx)
+    }
+
+    /// Set the characters that delimit words for `SelectionMode::Word`.
+    pub fn set_word_separators(&mut self, separators: &str) {
+        self.config.semantic_escape_chars = separators.to_string();
+        self.term.set_options(self.config.clone());
+    }
+
+    /// Start a new search over the screen and scrollback.
+    ///
+    /// Replaces any previous search. Matching is smart-case: case-insensitive
+    /// unless the query contains an uppercase character. Use `search_next` and
+    /// `search_previous` to move between matches.
+    pub fn search(&mut self, query: &str, kind: SearchKind) -> Result<(), SearchError> {
+        self.search = Some(SearchState::new(query, kind)?);
+        Ok(())
+    }
+
+    /// End the active search.
+    pub fn clear_search(&mut self) {
+        self.search = None;
+    }
+
+    /// The match most recently navigated to with `search_next`/`search_previous`.
+    pub fn search_focused(&self) -> Option<SearchMatch> {
+        self.search.as_ref().and_then(|s| s.focused)
+    }
+
+    /// All matches in the screen and scrollback, oldest first.
+    ///
+    /// Capped at 10,000 matches. Returns an empty list if no search is active.
+    pub fn search_all_matches(&mut self) -> Vec<SearchMatch> {
+        let top = self.term.topmost_line().0;
+        let bottom = self.term.bottommost_line().0;
+        match self.search.as_mut() {
+            Some(search) => search.matches_in_lines(&self.term, top, bottom, MAX_SEARCH_MATCHES),
+            None => Vec::new(),
+        }
+    }
+
+    /// Matches that intersect the current viewport, for highlighting.
+    pub fn visible_search_matches(&mut self) -> Vec<SearchMatch> {
+        let top = -(self.display_offset() as i32);
+        let bottom = top + self.term.screen_lines() as i32 - 1;
+        match self.search.as_mut() {
+            Some(search) => search.matches_in_lines(&self.term, top, bottom, MAX_SEARCH_MATCHES),
+            None => Vec::new(),
+        }
+    }
+
+    /// Focus the next match below the current one and scroll it into view.
+    ///
+    /// Without a focused match, starts from the top of the viewport. Wraps
+    /// around at the bottom of the buffer.
+    pub fn search_next(&mut self) -> Option<SearchMatch> {
+        self.search_step(Direction::Right)
+    }
+
+    /// Focus the previous match above the current one and scroll it into view.
+    ///
+    /// Without a focused match, starts from the bottom of the viewport. Wraps
+    /// around at the top of the buffer.
+    pub fn search_previous(&mut self) -> Option<SearchMatch> {
+        self.search_step(Direction::Left)
+    }
+
+    fn search_step(&mut self, direction: Direction) -> Option<SearchMatch> {
+        let search = self.search.as_mut()?;
+
+        let origin = match (search.focused, direction) {
+            (Some(focused), Direction::Right) => {
+                focused.start.to_alac().add(&self.term, Boundary::None, 1)
+            }
+            (Some(focused), Direction::Left) => {
+                focused.end.to_alac().sub(&self.term, Boundary::None, 1)
+            }
+            (None, Direction::Right) => {
+                Point::new(Line(-(self.term.grid().display_offset() as i32)), Column(0))
+            }
+            (None, Direction::Left) => {
+                let top = -(self.term.grid().display_offset() as i32);
+                let bottom = top + self.term.screen_lines() as i32 - 1;
+                Point::new(Line(bottom), self.term.last_column())
+            }
+        };
+
+        let found = search.next_match(&self.term, origin, direction);
+        search.focused = found;
+
+        if let Some(m) = found {
+            self.term.scroll_to_point(m.start.to_alac());
+            self.term.scroll_to_point(m.end.to_alac());
+        }
+        found
+    }
+
     /// Sync the cached title from the event proxy.
     ///
     /// Call this before `title()` if you need the latest title without
     /// going through `title_owned()`.
     fn sync_title(&mut self) {
         let state = self.event_proxy.state.lock().unwrap();
-        self.cached_title = state.title.clone();
+        self.cached_title = state.titles.title().map(str::to_string);
     }
 
     /// Get the current window title, if set by OSC escape sequences.
@@ -173,7 +1098,24 @@ impl VtTerminal {
     /// Get the current window title as an owned String.
     pub fn title_owned(&self) -> Option<String> {
         let state = self.event_proxy.state.lock().unwrap();
-        state.title.clone()
+        state.titles.title().map(str::to_string)
+    }
+
+    /// Get the icon name, if set by OSC 0 or OSC 1.
+    ///
+    /// xterm shows it for a minimized window; tab bars may prefer it over the
+    /// title since programs tend to keep it short.
+    pub fn icon_name(&self) -> Option<String> {
+        let state = self.event_proxy.state.lock().unwrap();
+        state.titles.icon_name().map(str::to_string)
+    }
+
+    /// Titles the window has had, oldest first, without repeats in a row.
+    ///
+    /// Capped at the last 100.
+    pub fn title_history(&self) -> Vec<String> {
+        let state = self.event_proxy.state.lock().unwrap();
+        state.titles.history()
     }
 
     /// Get damage information since the last reset.
@@ -181,6 +1123,12 @@ impl VtTerminal {
     /// After using this information for rendering, call `reset_damage()`.
     /// Note: each call consumes the current damage state from the underlying terminal.
     pub fn damage(&mut self) -> DamageInfo {
+        if self.theme_damaged {
+            return DamageInfo::Full;
+        }
+        // alacritty damages one column past the end when erasing characters
+        // up to the right edge.
+        let last_column = self.term.last_column().0;
         match self.term.damage() {
             TermDamage::Full => DamageInfo::Full,
             TermDamage::Partial(iter) => {
@@ -188,7 +1136,7 @@ impl VtTerminal {
                     .map(|d| DamagedRow {
                         row: d.line as u16,
                         left: d.left as u16,
-                        right: d.right as u16,
+                        right: d.right.min(last_column) as u16,
                     })
                     .collect();
                 DamageInfo::Partial(rows)
@@ -198,6 +1146,7 @@ impl VtTerminal {
 
     /// Reset damage tracking after rendering.
     pub fn reset_damage(&mut self) {
+        self.theme_damaged = false;
         self.term.reset_damage();
     }
 
@@ -206,8 +1155,27 @@ impl VtTerminal {
     /// The terminal sometimes needs to respond to queries by writing data back
     /// to the PTY. This method returns and clears that buffer.
     pub fn take_pty_writes(&mut self) -> Vec<String> {
-        let mut state = self.event_proxy.state.lock().unwrap();
-        std::mem::take(&mut state.pty_writes)
+        let writes = std::mem::take(&mut self.event_proxy.state.lock().unwrap().pty_writes);
+        writes
+            .into_iter()
+            .map(|write| match write {
+                PendingWrite::Text(text) => text,
+                PendingWrite::Color(index, formatter) => {
+                    let color = palette_color(index, self.term.colors(), &self.theme);
+                    formatter(ansi::Rgb {
+                        r: color.r,
+                        g: color.g,
+                        b: color.b,
+                    })
+                }
+                PendingWrite::TextAreaSize(formatter) => formatter(WindowSize {
+                    num_lines: self.term.screen_lines() as u16,
+                    num_cols: self.term.columns() as u16,
+                    cell_width: self.cell_size.0,
+                    cell_height: self.cell_size.1,
+                }),
+            })
+            .collect()
     }
 
     /// Check and clear the bell flag.
@@ -220,6 +1188,228 @@ impl VtTerminal {
         bell
     }
 
+    /// Drain OSC 52 clipboard requests made since the last call.
+    ///
+    /// Nothing is copied or sent back automatically: stores must be applied by
+    /// the caller, and loads answered with `respond_clipboard_load`.
+    pub fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        let raw = std::mem::take(&mut self.event_proxy.state.lock().unwrap().clipboard);
+        raw.into_iter()
+            .map(|request| match request {
+                RawClipboardRequest::Store(ty, text) => ClipboardRequest::Store {
+                    kind: ty.into(),
+                    text,
+                },
+                RawClipboardRequest::Load(ty, formatter) => ClipboardRequest::Load {
+                    id: self.clipboard_loads.insert(formatter),
+                    kind: ty.into(),
+                },
+            })
+            .collect()
+    }
+
+    /// Answer a clipboard load with `text`, queueing the reply as a PTY write.
+    ///
+    /// Returns `false` if the load is unknown, already answered, or was
+    /// dropped because too many loads were pending.
+    pub fn respond_clipboard_load(&mut self, id: u64, text: &str) -> bool {
+        let Some(formatter) = self.clipboard_loads.take(id) else {
+            return false;
+        };
+        let reply = formatter(text);
+        let mut state = self.event_proxy.state.lock().unwrap();
+        state.pty_writes.push(PendingWrite::Text(reply));
+        true
+    }
+
+    /// Drop a clipboard load without answering it.
+    pub fn discard_clipboard_load(&mut self, id: u64) {
+        self.clipboard_loads.take(id);
+    }
+
+    /// The shell's current working directory, as last reported via OSC 7.
+    ///
+    /// `None` until the shell sends a report; most shells need integration
+    /// scripts to do so.
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_deref()
+    }
+
+    /// URLs and file paths in the viewport, in order.
+    ///
+    /// Relative paths are resolved against the working directory from OSC 7
+    /// when the shell reported one.
+    pub fn detect_links(&mut self) -> Vec<DetectedLink> {
+        let top = -(self.display_offset() as i32);
+        let bottom = top + self.term.screen_lines() as i32 - 1;
+        self.link_detector.get_or_insert_with(LinkDetector::new).detect(
+            &self.term,
+            top,
+            bottom,
+            self.cwd.as_deref(),
+        )
+    }
+
+    /// Command blocks recorded from OSC 133 shell integration, oldest first.
+    pub fn command_blocks(&self) -> Vec<CommandBlock> {
+        self.command_blocks
+            .iter()
+            .map(|b| b.to_public(self.scrolled_lines))
+            .collect()
+    }
+
+    /// Take the command blocks that finished since the last call.
+    pub fn take_finished_commands(&mut self) -> Vec<CommandBlock> {
+        let ids = self.command_blocks.take_finished();
+        ids.into_iter()
+            .filter_map(|id| self.command_blocks.get(id))
+            .map(|b| b.to_public(self.scrolled_lines))
+            .collect()
+    }
+
+    /// Take the notifications (OSC 9 / OSC 777) sent since the last call.
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        self.notifications.take()
+    }
+
+    /// Progress last reported with OSC 9;4, or `None` if the program never
+    /// reported any or cleared it.
+    pub fn progress(&self) -> Option<Progress> {
+        self.notifications.progress()
+    }
+
+    /// The output text of a finished (or still running) command.
+    ///
+    /// Returns `None` if the block is unknown or has not produced output yet.
+    /// Output that has scrolled out of the history is omitted.
+    pub fn command_output(&self, id: u64) -> Option<String> {
+        let block = self.command_blocks.get(id)?;
+        let start = block.output_start?;
+        let end = block.output_end.unwrap_or_else(|| {
+            let cursor = self.term.grid().cursor.point;
+            AbsPoint {
+                line: self.scrolled_lines + cursor.line.0 as i64,
+                col: cursor.column.0 as u16,
+            }
+        });
+        let text = self.text_between(start, end);
+        Some(text.trim_end_matches('\n').to_string())
+    }
+
+    /// Scroll the viewport so the closest prompt above its top is at the top.
+    ///
+    /// Returns the block of that prompt, or `None` if there is none.
+    pub fn scroll_to_previous_prompt(&mut self) -> Option<CommandBlock> {
+        let top = self.scrolled_lines - self.display_offset() as i64;
+        let block = self.command_blocks.iter().rev().find(|b| b.prompt.line < top)?;
+        let block = block.to_public(self.scrolled_lines);
+        self.scroll_prompt_to_top(&block);
+        Some(block)
+    }
+
+    /// Scroll the viewport so the closest prompt below its top is at the top.
+    ///
+    /// The viewport does not scroll past the live screen. Returns the block of
+    /// that prompt, or `None` if there is none.
+    pub fn scroll_to_next_prompt(&mut self) -> Option<CommandBlock> {
+        let top = self.scrolled_lines - self.display_offset() as i64;
+        let block = self.command_blocks.iter().find(|b| b.prompt.line > top)?;
+        let block = block.to_public(self.scrolled_lines);
+        self.scroll_prompt_to_top(&block);
+        Some(block)
+    }
+
+    fn scroll_prompt_to_top(&mut self, block: &CommandBlock) {
+        let top = -(self.display_offset() as i32);
+        self.term
+            .scroll_display(Scroll::Delta(top - block.prompt.line));
+    }
+
+    /// Look up an OSC 8 hyperlink by the ID carried in `VtCell::hyperlink_id`.
+    pub fn hyperlink(&self, id: u16) -> Option<Hyperlink> {
+        self.hyperlinks.borrow().get(id).cloned()
+    }
+
+    /// Take hyperlinks first seen since the last call.
+    ///
+    /// Links are assigned IDs lazily as cells are read through `screen()`, so
+    /// call this after reading cells to pick up the definitions they reference,
+    /// and send those along with the cells. An ID whose link left the grid may
+    /// be given to a new link; it is then returned here again.
+    pub fn take_new_hyperlinks(&mut self) -> Vec<Hyperlink> {
+        self.hyperlinks.get_mut().take_new()
+    }
+
+    /// The pixel size of a cell, as last set with `set_cell_size`.
+    pub fn cell_size(&self) -> (u16, u16) {
+        self.cell_size
+    }
+
+    /// Set the pixel size of a cell, which decides how many cells an image
+    /// covers and is reported to programs (`CSI 14 t` / `CSI 16 t`). Images
+    /// already placed keep their size.
+    pub fn set_cell_size(&mut self, width: u16, height: u16) {
+        self.cell_size = (width.max(1), height.max(1));
+    }
+
+    /// Change the memory budget for image pixels, evicting the oldest images
+    /// to fit. Defaults to `image::DEFAULT_IMAGE_BUDGET`.
+    pub fn set_image_budget(&mut self, bytes: usize) {
+        self.images.set_budget(bytes);
+    }
+
+    /// Images shown on the current screen, including those scrolled into
+    /// the history.
+    pub fn images(&self) -> Vec<ImagePlacement> {
+        self.images.placements(self.is_alt_screen(), self.scrolled_lines)
+    }
+
+    /// Look up an image by the ID in `ImagePlacement::image_id`.
+    pub fn image(&self, id: u64) -> Option<Arc<Image>> {
+        self.images.get(id)
+    }
+
+    /// Serialize the screen, scrollback, cursor, modes, title and palette
+    /// into a compact binary snapshot; see the `snapshot` module for what is
+    /// kept.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let state = self.event_proxy.state.lock().unwrap();
+        Snapshot {
+            title: state.titles.title().map(str::to_string),
+            icon_name: state.titles.icon_name().map(str::to_string),
+            cwd: self.cwd.clone(),
+            x10_mouse: self.x10_mouse,
+            modify_other_keys: self.modify_other_keys,
+            ..Snapshot::capture(&self.term)
+        }
+        .encode()
+    }
+
+    /// Create a terminal from a snapshot taken with `snapshot`, at the size
+    /// it was taken at.
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let snapshot = Snapshot::decode(bytes)?;
+        let mut vt = Self::new(snapshot.cols, snapshot.rows);
+        if snapshot.alt_screen {
+            vt.write(b"\x1b[?1049h");
+        }
+        vt.write(&snapshot.mode_sequences());
+        // The history starts out empty and the snapshot holds no more than
+        // fits, so every line restored into it is still there.
+        snapshot.restore_lines(&mut vt.term);
+        vt.track_scrolling(vt.term.history_size());
+        snapshot.restore_cursor_and_palette(&mut vt.term);
+
+        if let Some(title) = snapshot.title {
+            vt.term.set_title(Some(title));
+        }
+        let mut state = vt.event_proxy.state.lock().unwrap();
+        state.titles.set_icon_name(snapshot.icon_name);
+        drop(state);
+        vt.cwd = snapshot.cwd;
+        Ok(vt)
+    }
+
     /// Get a reference to the underlying alacritty Term.
     ///
     /// Escape hatch for advanced use cases.
@@ -238,7 +1428,7 @@ impl VtTerminal {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cell::CellFlags;
+    use crate::cell::{CellFlags, Rgb, UnderlineStyle};
 
     #[test]
     fn test_create_terminal_dimensions() {
@@ -263,6 +1453,31 @@ mod tests {
         assert_eq!(screen.cell(0, 5).codepoint, ' ');
     }
 
+    #[test]
+    fn test_grapheme_clusters() {
+        let mut term = VtTerminal::new(80, 24);
+        // e + combining acute, a ZWJ family split across two writes, a flag
+        // and a thumbs up with a skin tone.
+        term.write("e\u{301}👨\u{200d}👩".as_bytes());
+        term.write("\u{200d}👧🇩🇪👍🏽x".as_bytes());
+
+        let screen = term.screen();
+        assert_eq!(screen.cell(0, 0).grapheme(), "e\u{301}");
+        assert_eq!(screen.cell(0, 1).grapheme(), "👨\u{200d}👩\u{200d}👧");
+        assert_eq!(screen.cell(0, 1).width, 2);
+        assert_eq!(screen.cell(0, 3).grapheme(), "🇩🇪");
+        assert_eq!(screen.cell(0, 4).grapheme(), "👍🏽");
+        assert_eq!(screen.cell(0, 6).codepoint, 'x');
+        assert!(screen.cell(0, 6).combining.is_empty());
+        assert_eq!(term.cursor().col, 7);
+
+        // A cursor movement in between keeps the characters apart.
+        term.write("\r\n👍\x1b[C🏽".as_bytes());
+        let screen = term.screen();
+        assert_eq!(screen.cell(1, 0).grapheme(), "👍");
+        assert_eq!(screen.cell(1, 3).grapheme(), "🏽");
+    }
+
     #[test]
     fn test_ansi_color_escape() {
         let mut term = VtTerminal::new(80, 24);
@@ -311,6 +1526,37 @@ mod tests {
         assert_eq!(cursor.col, 2);
     }
 
+    #[test]
+    fn test_cursor_style_and_focus() {
+        let mut term = VtTerminal::new(80, 24);
+        let cursor = term.cursor();
+        assert_eq!((cursor.shape, cursor.blinking), (CursorShape::Block, false));
+
+        // DECSCUSR: odd values blink, even ones are steady.
+        term.write(b"\x1b[5 q");
+        let cursor = term.cursor();
+        assert_eq!((cursor.shape, cursor.blinking), (CursorShape::Bar, true));
+        term.write(b"\x1b[4 q");
+        let cursor = term.cursor();
+        assert_eq!((cursor.shape, cursor.blinking), (CursorShape::Underline, false));
+
+        // Without focus reporting, focus changes only affect the cursor.
+        term.write(b"\x1b[3 q");
+        assert_eq!(term.set_focused(false), None);
+        let cursor = term.cursor();
+        assert_eq!(
+            (cursor.shape, cursor.blinking, cursor.focused),
+            (CursorShape::HollowBlock, false, false)
+        );
+        assert_eq!(term.set_focused(true), None);
+        assert!(term.cursor().blinking);
+
+        term.write(b"\x1b[?1004h");
+        assert_eq!(term.set_focused(false), Some(b"\x1b[O".to_vec()));
+        assert_eq!(term.set_focused(false), None);
+        assert_eq!(term.set_focused(true), Some(b"\x1b[I".to_vec()));
+    }
+
     #[test]
     fn test_bold_flag() {
         let mut term = VtTerminal::new(80, 24);
@@ -322,6 +1568,39 @@ mod tests {
         assert!(cell.flags.contains(CellFlags::BOLD));
     }
 
+    #[test]
+    fn test_underline_styles() {
+        let mut term = VtTerminal::new(80, 24);
+        // Plain, double (4:2), curly with an RGB color (4:3, 58:2), dotted
+        // with a palette color (58;5), dashed, then color and style reset.
+        term.write(b"\x1b[4ma\x1b[4:2mb\x1b[4:3;58:2::255:0:0mc\x1b[4:4;58;5;4md");
+        term.write(b"\x1b[4:5;59me\x1b[24mf");
+
+        let screen = term.screen();
+        let styles: Vec<_> = (0..6).map(|col| screen.cell(0, col).underline).collect();
+        assert_eq!(
+            styles,
+            [
+                UnderlineStyle::Single,
+                UnderlineStyle::Double,
+                UnderlineStyle::Curly,
+                UnderlineStyle::Dotted,
+                UnderlineStyle::Dashed,
+                UnderlineStyle::None,
+            ]
+        );
+        assert!(screen.cell(0, 2).flags.contains(CellFlags::UNDERLINE));
+        assert!(!screen.cell(0, 5).flags.contains(CellFlags::UNDERLINE));
+
+        assert_eq!(screen.cell(0, 0).underline_color, None);
+        assert_eq!(screen.cell(0, 2).underline_color, Some(Rgb::new(255, 0, 0)));
+        assert_eq!(
+            screen.cell(0, 3).underline_color,
+            Some(term.theme().ansi[4])
+        );
+        assert_eq!(screen.cell(0, 4).underline_color, None);
+    }
+
     #[test]
     fn test_title_change() {
         let mut term = VtTerminal::new(80, 24);
@@ -332,6 +1611,37 @@ mod tests {
         assert_eq!(term.title(), Some("My Terminal"));
     }
 
+    #[test]
+    fn test_title_stack_and_icon_name() {
+        let mut term = VtTerminal::new(80, 24);
+        term.write(b"\x1b]2;~/src\x07\x1b]1;zsh\x07");
+        assert_eq!(term.icon_name().as_deref(), Some("zsh"));
+
+        // vim saves both separately, sets both, and restores them on exit.
+        term.write(b"\x1b[22;2t\x1b[22;1t\x1b]0;main.rs - VIM\x07");
+        assert_eq!(term.title_owned().as_deref(), Some("main.rs - VIM"));
+        assert_eq!(term.icon_name().as_deref(), Some("main.rs - VIM"));
+        term.write(b"\x1b[23;2t\x1b[23;1t");
+        assert_eq!(term.title_owned().as_deref(), Some("~/src"));
+        assert_eq!(term.icon_name().as_deref(), Some("zsh"));
+
+        // A restore with nothing saved leaves the title alone.
+        term.write(b"\x1b[23;0t");
+        assert_eq!(term.title_owned().as_deref(), Some("~/src"));
+        assert_eq!(term.title_history(), ["~/src", "main.rs - VIM", "~/src"]);
+    }
+
+    #[test]
+    fn test_size_reports() {
+        let mut term = VtTerminal::new(80, 24);
+        term.set_cell_size(9, 18);
+        term.write(b"\x1b[14t\x1b[16t\x1b[18t");
+        assert_eq!(
+            term.take_pty_writes(),
+            ["\x1b[4;432;720t", "\x1b[6;18;9t", "\x1b[8;24;80t"]
+        );
+    }
+
     #[test]
     fn test_bell() {
         let mut term = VtTerminal::new(80, 24);
@@ -356,6 +1666,64 @@ mod tests {
         assert!(!matches!(damage, crate::screen::DamageInfo::Partial(ref rows) if rows.is_empty()));
     }
 
+    /// Write `count` numbered lines so the earliest ones scroll into history.
+    fn write_numbered_lines(term: &mut VtTerminal, count: usize) {
+        for i in 0..count {
+            term.write(format!("line{i}\r\n").as_bytes());
+        }
+    }
+
+    fn line_text(cells: &[crate::cell::VtCell]) -> String {
+        cells.iter().map(|c| c.codepoint).collect::<String>().trim_end().to_string()
+    }
+
+    #[test]
+    fn test_scrollback_history_lines() {
+        let mut term = VtTerminal::new(20, 5);
+        write_numbered_lines(&mut term, 10);
+
+        let screen = term.screen();
+        // 10 lines plus the empty cursor line on a 5-row screen: 6 scrolled off.
+        assert_eq!(screen.history_size(), 6);
+        assert_eq!(screen.total_lines(), 11);
+
+        // Negative indices count back from the top of the screen.
+        assert_eq!(line_text(&screen.line_cells(-1).unwrap()), "line5");
+        assert_eq!(line_text(&screen.line_cells(-6).unwrap()), "line0");
+        assert!(screen.line_cells(-7).is_none());
+
+        // Absolute indices start at the oldest history line.
+        assert_eq!(line_text(&screen.history_line_cells(0).unwrap()), "line0");
+        assert_eq!(line_text(&screen.history_line_cells(6).unwrap()), "line6");
+        assert!(screen.history_line_cells(11).is_none());
+    }
+
+    #[test]
+    fn test_scroll_display_moves_viewport() {
+        let mut term = VtTerminal::new(20, 5);
+        write_numbered_lines(&mut term, 10);
+        let _ = term.damage();
+        term.reset_damage();
+
+        term.scroll_display(3);
+        assert_eq!(term.display_offset(), 3);
+        assert_eq!(line_text(&term.screen().row_cells(0)), "line3");
+        assert!(matches!(term.damage(), crate::screen::DamageInfo::Full));
+        term.reset_damage();
+
+        // The cursor line is now below the viewport.
+        assert!(!term.cursor().visible);
+
+        term.scroll_display(100);
+        assert_eq!(term.display_offset(), 6);
+        assert_eq!(line_text(&term.screen().row_cells(0)), "line0");
+
+        term.scroll_to_bottom();
+        assert_eq!(term.display_offset(), 0);
+        assert_eq!(line_text(&term.screen().row_cells(0)), "line6");
+        assert!(term.cursor().visible);
+    }
+
     #[test]
     fn test_device_status_response() {
         let mut term = VtTerminal::new(80, 24);
@@ -368,4 +1736,159 @@ mod tests {
         // Response should be in the form ESC[1;1R (for position 1,1).
         assert!(writes[0].starts_with("\x1b["));
     }
+
+    #[test]
+    fn test_osc7_sets_cwd() {
+        let mut term = VtTerminal::new(40, 5);
+        assert_eq!(term.cwd(), None);
+
+        term.write(b"$ \x1b]7;file://host/home/user\x07cd src\r\n");
+        assert_eq!(term.cwd(), Some("/home/user"));
+        // Text around the sequence is still rendered by the main parser.
+        assert_eq!(line_text(&term.screen().row_cells(0)), "$ cd src");
+
+        term.write(b"\x1b]7;file://host/home/user/src\x1b\\");
+        assert_eq!(term.cwd(), Some("/home/user/src"));
+    }
+
+    #[test]
+    fn test_images_placed_at_cursor_and_scrolled() {
+        let mut term = VtTerminal::new(20, 5);
+
+        // A 16x32 sixel covers 2x2 cells of 8x16 pixels; the cursor moves
+        // to the line below it.
+        term.write(b"\x1bP0;1q#1!16~-!16~-!16~-!16~-!16~-!16B\x1b\\");
+        let placements = term.images();
+        assert_eq!(placements.len(), 1);
+        let sixel = placements[0];
+        assert_eq!((sixel.line, sixel.col, sixel.cols, sixel.rows), (0, 0, 2, 2));
+        let image = term.image(sixel.image_id).unwrap();
+        assert_eq!((image.width, image.height), (16, 32));
+        let cursor = term.cursor();
+        assert_eq!((cursor.row, cursor.col), (2, 0));
+
+        // A 1x1 kitty image is transmitted and shown in one go; the reply
+        // goes back to the application and the cursor moves past it.
+        term.write(b"\x1b_Ga=T,f=24,s=1,v=1,i=5;AAAA\x1b\\");
+        assert_eq!(term.take_pty_writes(), vec!["\x1b_Gi=5;OK\x1b\\".to_string()]);
+        assert_eq!(term.images().len(), 2);
+        let cursor = term.cursor();
+        assert_eq!((cursor.row, cursor.col), (2, 1));
+
+        // Images scroll up with the text.
+        term.write(b"\r\n\r\n\r\n");
+        let lines: Vec<i32> = term.images().iter().map(|p| p.line).collect();
+        assert_eq!(lines, [-1, 1]);
+    }
+
+    #[test]
+    fn test_notifications_and_progress() {
+        let mut term = VtTerminal::new(80, 24);
+        term.write(b"\x1b]9;Build finished\x07\x1b]777;notify;Agent;Waiting\x1b\\");
+        term.write(b"\x1b]9;4;1;60\x07");
+        let bodies: Vec<String> = term.take_notifications().into_iter().map(|n| n.body).collect();
+        assert_eq!(bodies, ["Build finished", "Waiting"]);
+        assert!(term.take_notifications().is_empty());
+        assert_eq!(term.progress().map(|p| p.percent), Some(60));
+
+        term.write(b"\x1bc");
+        assert_eq!(term.progress(), None);
+    }
+
+    #[test]
+    fn test_synchronized_update() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"old");
+
+        // Output inside the update is held back until it ends.
+        term.write(b"\x1b[?2026h\x1b[Hnew");
+        assert!(term.is_synchronized_update());
+        assert!(term.synchronized_update_deadline().is_some());
+        assert_eq!(term.screen().cell(0, 0).codepoint, 'o');
+        term.write(b"\x1b[?2026l");
+        assert!(!term.is_synchronized_update());
+        assert_eq!(term.screen().cell(0, 0).codepoint, 'n');
+
+        // An update can be cut off by the embedder.
+        term.write(b"\x1b[?2026h\x1b[Hmid");
+        term.end_synchronized_update();
+        assert!(!term.is_synchronized_update());
+        assert_eq!(term.screen().cell(0, 0).codepoint, 'm');
+
+        // Sequences handled outside the parser see the output before them.
+        term.write(b"\x1b[?2026h\x1b[3;4H\x1bP0;1q\"1;1;8;16#1~\x1b\\");
+        let placement = term.images()[0];
+        assert_eq!((placement.line, placement.col), (2, 3));
+    }
+
+    #[test]
+    fn test_synchronized_update_defers_side_sequences() {
+        let mut term = VtTerminal::new(20, 5);
+
+        // A title or working directory inside the frame does not end it.
+        term.write(b"\x1b[?2026hframe-start\x1b]0;spinner\x07more");
+        term.write(b"\x1b]7;file://host/tmp\x07\x1b]9;done\x07\x1b[22t");
+        assert!(term.is_synchronized_update());
+        assert_eq!(term.screen().cell(0, 0).codepoint, ' ');
+        assert_eq!(term.cwd(), None);
+        assert_eq!(term.icon_name(), None);
+
+        term.write(b"\x1b[?2026l");
+        assert_eq!(term.screen().cell(0, 0).codepoint, 'f');
+        assert_eq!(term.title(), Some("spinner"));
+        assert_eq!(term.icon_name().as_deref(), Some("spinner"));
+        assert_eq!(term.cwd(), Some("/tmp"));
+        assert_eq!(term.take_notifications().len(), 1);
+
+        // A shell mark needs the screen, and applies what came before it.
+        term.write(b"\x1b[?2026h\x1b]7;file://host/home\x07\x1b]133;A\x07");
+        assert_eq!(term.cwd(), Some("/home"));
+        term.write(b"\x1b[?2026l");
+    }
+
+    #[test]
+    fn test_synchronized_update_resumes_after_positional_sequence() {
+        let mut term = VtTerminal::new(20, 5);
+        term.write(b"\x1b[?2026h\x1b[Hprompt");
+        let deadline = term.synchronized_update_deadline();
+
+        // The mark applies the output before it, and the rest of the frame
+        // is held back with the same deadline.
+        term.write(b"\x1b]133;A\x07\x1b[2;1Hrest");
+        assert!(term.is_synchronized_update());
+        assert_eq!(term.synchronized_update_deadline(), deadline);
+        assert_eq!(term.screen().cell(0, 0).codepoint, 'p');
+        assert_eq!(term.screen().cell(1, 0).codepoint, ' ');
+
+        term.write(b"\x1b[?2026l");
+        assert!(!term.is_synchronized_update());
+        assert_eq!(term.screen().cell(1, 0).codepoint, 'r');
+    }
+
+    #[test]
+    fn test_export_scrollback_and_range() {
+        let mut term = VtTerminal::new(10, 3);
+        term.write(b"first\r\n\x1b[31mwrapped line\x1b[0m\r\nlast\r\nend");
+
+        // "first" is in the scrollback; the soft wrap is joined.
+        let text = term.export(ExportFormat::Text, None);
+        assert_eq!(text, "first\nwrapped line\nlast\nend");
+
+        let ansi = term.export(ExportFormat::Ansi, None);
+        assert!(ansi.contains("\x1b[0;38;2;205;0;0mwrapped line\x1b[0m\nlast"));
+
+        // "wrapped li" is history line -1, "ne" line 0.
+        let range = SelectionRange {
+            start: GridPoint::new(-1, 8),
+            end: GridPoint::new(1, 1),
+            is_block: false,
+        };
+        assert_eq!(term.export(ExportFormat::Text, Some(range)), "line\nla");
+        let block = SelectionRange {
+            start: GridPoint::new(-1, 1),
+            end: GridPoint::new(1, 2),
+            is_block: true,
+        };
+        assert_eq!(term.export(ExportFormat::Text, Some(block)), "ra\ne\nas");
+    }
 }
@@ -0,0 +1,508 @@
+//! Color schemes.
+//!
+//! A `Theme` supplies the colors cells are resolved against: the 16 ANSI
+//! colors, the default foreground and background, and the cursor and
+//! selection colors. Programs can still override individual colors at runtime
+//! via OSC 4/10/11/12; those overrides live in the terminal and are dropped on
+//! reset, the theme is not changed.
+//!
+//! Themes can be loaded from iTerm2 `.itermcolors` files, Alacritty TOML
+//! configs and base16 YAML schemes.
+
+use std::path::Path;
+
+use alacritty_terminal::vte::ansi::NamedColor;
+
+use crate::cell::Rgb;
+
+/// How much darker dim colors are than their normal counterparts, when the
+/// theme does not define them.
+const DIM_FACTOR: f32 = 0.66;
+
+/// Error returned when a theme cannot be loaded.
+#[derive(Debug)]
+pub enum ThemeError {
+    /// The theme file could not be read.
+    Io(std::io::Error),
+    /// The file extension does not name a supported format.
+    UnknownFormat,
+    /// The file is not valid for its format.
+    Invalid(String),
+    /// A color the theme must define is missing.
+    MissingColor(String),
+}
+
+impl std::fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThemeError::Io(e) => write!(f, "failed to read theme: {e}"),
+            ThemeError::UnknownFormat => write!(f, "unknown theme format"),
+            ThemeError::Invalid(msg) => write!(f, "invalid theme: {msg}"),
+            ThemeError::MissingColor(name) => write!(f, "theme is missing color {name}"),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ThemeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ThemeError {
+    fn from(e: std::io::Error) -> Self {
+        ThemeError::Io(e)
+    }
+}
+
+/// A terminal color scheme.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    /// ANSI colors 0-15: the eight normal colors, then their bright variants.
+    pub ansi: [Rgb; 16],
+    pub foreground: Rgb,
+    pub background: Rgb,
+    pub cursor: Rgb,
+    /// Background of selected text.
+    pub selection: Rgb,
+    /// Dim variants of the eight normal colors. Derived from them if `None`.
+    pub dim: Option<[Rgb; 8]>,
+}
+
+impl Default for Theme {
+    /// The xterm palette, white on black.
+    fn default() -> Self {
+        Self {
+            ansi: [
+                Rgb::new(0, 0, 0),
+                Rgb::new(205, 0, 0),
+                Rgb::new(0, 205, 0),
+                Rgb::new(205, 205, 0),
+                Rgb::new(0, 0, 238),
+                Rgb::new(205, 0, 205),
+                Rgb::new(0, 205, 205),
+                Rgb::new(229, 229, 229),
+                Rgb::new(127, 127, 127),
+                Rgb::new(255, 0, 0),
+                Rgb::new(0, 255, 0),
+                Rgb::new(255, 255, 0),
+                Rgb::new(92, 92, 255),
+                Rgb::new(255, 0, 255),
+                Rgb::new(0, 255, 255),
+                Rgb::new(255, 255, 255),
+            ],
+            foreground: Rgb::new(255, 255, 255),
+            background: Rgb::new(0, 0, 0),
+            cursor: Rgb::new(255, 255, 255),
+            selection: Rgb::new(68, 68, 68),
+            dim: None,
+        }
+    }
+}
+
+impl Theme {
+    /// Load a theme file, picking the format from its extension:
+    /// `.itermcolors`, `.toml` (Alacritty) or `.yaml`/`.yml` (base16).
+    pub fn load(path: &Path) -> Result<Self, ThemeError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let parse = match extension.as_deref() {
+            Some("itermcolors") => Self::from_itermcolors,
+            Some("toml") => Self::from_alacritty_toml,
+            Some("yaml" | "yml") => Self::from_base16_yaml,
+            _ => return Err(ThemeError::UnknownFormat),
+        };
+        parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse an iTerm2 `.itermcolors` property list.
+    ///
+    /// Colors are taken as sRGB regardless of their declared color space.
+    pub fn from_itermcolors(text: &str) -> Result<Self, ThemeError> {
+        let mut colors = Vec::new();
+        let mut rest = text;
+        while let Some((key, after)) = next_element(rest, "key") {
+            rest = after;
+            if !key.ends_with(" Color") {
+                continue;
+            }
+            let end = after
+                .find("</dict>")
+                .ok_or_else(|| ThemeError::Invalid(format!("unterminated entry {key}")))?;
+            colors.push((key, itermcolors_color(key, &after[..end])?));
+            rest = &after[end..];
+        }
+        let get = |name: &str| colors.iter().find(|(key, _)| *key == name).map(|(_, c)| *c);
+        let require = |name: &str| get(name).ok_or_else(|| ThemeError::MissingColor(name.into()));
+
+        let mut ansi = [Rgb::new(0, 0, 0); 16];
+        for (i, color) in ansi.iter_mut().enumerate() {
+            *color = require(&format!("Ansi {i} Color"))?;
+        }
+        let foreground = require("Foreground Color")?;
+        Ok(Self {
+            ansi,
+            foreground,
+            background: require("Background Color")?,
+            cursor: get("Cursor Color").unwrap_or(foreground),
+            selection: get("Selection Color").unwrap_or(Theme::default().selection),
+            dim: None,
+        })
+    }
+
+    /// Parse the `[colors]` tables of an Alacritty TOML config.
+    ///
+    /// Colors Alacritty derives from the cell (`CellForeground`,
+    /// `CellBackground`) fall back to the theme's own defaults.
+    pub fn from_alacritty_toml(text: &str) -> Result<Self, ThemeError> {
+        let root: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| ThemeError::Invalid(e.to_string()))?;
+        let colors = root
+            .get("colors")
+            .and_then(|v| v.as_table())
+            .ok_or_else(|| ThemeError::MissingColor("colors".into()))?;
+        let get = |section: &str, name: &str| {
+            colors
+                .get(section)
+                .and_then(|s| s.get(name))
+                .and_then(|v| v.as_str())
+                .and_then(parse_hex)
+        };
+        let require = |section: &str, name: &str| {
+            get(section, name).ok_or_else(|| ThemeError::MissingColor(format!("{section}.{name}")))
+        };
+
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        let mut ansi = [Rgb::new(0, 0, 0); 16];
+        for (i, name) in NAMES.iter().enumerate() {
+            ansi[i] = require("normal", name)?;
+            ansi[i + 8] = require("bright", name)?;
+        }
+        let dim = NAMES
+            .iter()
+            .map(|name| get("dim", name))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|dim| dim.try_into().ok());
+        let foreground = require("primary", "foreground")?;
+        Ok(Self {
+            ansi,
+            foreground,
+            background: require("primary", "background")?,
+            cursor: get("cursor", "cursor").unwrap_or(foreground),
+            selection: get("selection", "background").unwrap_or(Theme::default().selection),
+            dim,
+        })
+    }
+
+    /// Parse a base16 YAML scheme, in either the classic flat layout or the
+    /// newer one with the colors under `palette:`.
+    ///
+    /// The ANSI colors are mapped as base16-shell does.
+    pub fn from_base16_yaml(text: &str) -> Result<Self, ThemeError> {
+        let mut base = [None; 16];
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(index) = key
+                .trim()
+                .strip_prefix("base0")
+                .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+            else {
+                continue;
+            };
+            let value = value
+                .split('#')
+                .find(|s| !s.trim().trim_matches('"').is_empty());
+            let value = value.map(|v| v.trim().trim_matches(|c| c == '"' || c == '\''));
+            let color = value
+                .and_then(parse_hex)
+                .ok_or_else(|| ThemeError::Invalid(format!("bad color for base0{index:X}")))?;
+            base[index as usize] = Some(color);
+        }
+        let mut colors = [Rgb::new(0, 0, 0); 16];
+        for (i, color) in base.into_iter().enumerate() {
+            colors[i] = color.ok_or_else(|| ThemeError::MissingColor(format!("base0{i:X}")))?;
+        }
+
+        const ANSI_BASE: [usize; 16] = [
+            0x0, 0x8, 0xB, 0xA, 0xD, 0xE, 0xC, 0x5, 0x3, 0x8, 0xB, 0xA, 0xD, 0xE, 0xC, 0x7,
+        ];
+        Ok(Self {
+            ansi: ANSI_BASE.map(|i| colors[i]),
+            foreground: colors[0x5],
+            background: colors[0x0],
+            cursor: colors[0x5],
+            selection: colors[0x2],
+            dim: None,
+        })
+    }
+
+    /// The dim variant of normal color `index` (0-7).
+    pub fn dim_color(&self, index: usize) -> Rgb {
+        match &self.dim {
+            Some(dim) => dim[index],
+            None => dimmed(self.ansi[index]),
+        }
+    }
+
+    /// The foreground used for dim text.
+    pub fn dim_foreground(&self) -> Rgb {
+        dimmed(self.foreground)
+    }
+
+    /// The theme's color for an entry of alacritty's color table: 0-255 are
+    /// the 256 indexed colors, followed by the named colors.
+    pub(crate) fn palette_color(&self, index: usize) -> Rgb {
+        const DIM_BLACK: usize = NamedColor::DimBlack as usize;
+        const DIM_WHITE: usize = NamedColor::DimWhite as usize;
+        match index {
+            0..=15 => self.ansi[index],
+            16..=231 => {
+                // 6x6x6 color cube.
+                let n = index - 16;
+                let level = |v: usize| if v == 0 { 0 } else { (55 + 40 * v) as u8 };
+                Rgb::new(level(n / 36), level((n / 6) % 6), level(n % 6))
+            }
+            232..=255 => {
+                // Grayscale ramp.
+                let v = (8 + 10 * (index - 232)) as u8;
+                Rgb::new(v, v, v)
+            }
+            _ if index == NamedColor::Background as usize => self.background,
+            _ if index == NamedColor::Cursor as usize => self.cursor,
+            DIM_BLACK..=DIM_WHITE => self.dim_color(index - DIM_BLACK),
+            _ if index == NamedColor::DimForeground as usize => self.dim_foreground(),
+            _ => self.foreground,
+        }
+    }
+}
+
+fn dimmed(color: Rgb) -> Rgb {
+    let scale = |v: u8| (v as f32 * DIM_FACTOR).round() as u8;
+    Rgb::new(scale(color.r), scale(color.g), scale(color.b))
+}
+
+/// Parse `#rrggbb`, `0xrrggbb` or `rrggbb`.
+fn parse_hex(value: &str) -> Option<Rgb> {
+    let hex = value
+        .strip_prefix('#')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgb::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Find the next `<tag>content</tag>` element, returning its content and the
+/// text after it.
+fn next_element<'a>(text: &'a str, tag: &str) -> Option<(&'a str, &'a str)> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = text.find(&open)? + open.len();
+    let len = text[start..].find(&close)?;
+    Some((
+        text[start..start + len].trim(),
+        &text[start + len + close.len()..],
+    ))
+}
+
+/// Read a color from the `<dict>` of an `.itermcolors` entry.
+fn itermcolors_color(name: &str, dict: &str) -> Result<Rgb, ThemeError> {
+    let component = |component: &str| {
+        let key = format!("<key>{component} Component</key>");
+        let after = dict[dict.find(&key)? + key.len()..].trim_start();
+        let tag = if after.starts_with("<integer>") {
+            "integer"
+        } else {
+            "real"
+        };
+        let value: f64 = next_element(after, tag)?.0.parse().ok()?;
+        Some((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    let invalid = || ThemeError::Invalid(format!("bad color components for {name}"));
+    Ok(Rgb::new(
+        component("Red").ok_or_else(invalid)?,
+        component("Green").ok_or_else(invalid)?,
+        component("Blue").ok_or_else(invalid)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::DamageInfo;
+    use crate::terminal::VtTerminal;
+
+    #[test]
+    fn test_itermcolors() {
+        let mut plist = String::from("<?xml version=\"1.0\"?>\n<plist version=\"1.0\">\n<dict>\n");
+        for i in 0..16 {
+            plist.push_str(&format!(
+                "\t<key>Ansi {i} Color</key>\n\t<dict>\n\
+                 \t\t<key>Alpha Component</key>\n\t\t<real>1</real>\n\
+                 \t\t<key>Blue Component</key>\n\t\t<real>{}</real>\n\
+                 \t\t<key>Color Space</key>\n\t\t<string>sRGB</string>\n\
+                 \t\t<key>Green Component</key>\n\t\t<real>0</real>\n\
+                 \t\t<key>Red Component</key>\n\t\t<real>1</real>\n\t</dict>\n",
+                i as f64 / 15.0
+            ));
+        }
+        plist.push_str(
+            "\t<key>Background Color</key>\n\t<dict>\n\
+             \t\t<key>Blue Component</key>\n\t\t<real>0.2</real>\n\
+             \t\t<key>Green Component</key>\n\t\t<real>0.2</real>\n\
+             \t\t<key>Red Component</key>\n\t\t<real>0.2</real>\n\t</dict>\n\
+             \t<key>Foreground Color</key>\n\t<dict>\n\
+             \t\t<key>Blue Component</key>\n\t\t<integer>1</integer>\n\
+             \t\t<key>Green Component</key>\n\t\t<real>0.5</real>\n\
+             \t\t<key>Red Component</key>\n\t\t<real>0.0</real>\n\t</dict>\n\
+             </dict>\n</plist>\n",
+        );
+
+        let theme = Theme::from_itermcolors(&plist).unwrap();
+        assert_eq!(theme.ansi[0], Rgb::new(255, 0, 0));
+        assert_eq!(theme.ansi[15], Rgb::new(255, 0, 255));
+        assert_eq!(theme.background, Rgb::new(51, 51, 51));
+        assert_eq!(theme.foreground, Rgb::new(0, 128, 255));
+        assert_eq!(theme.cursor, theme.foreground);
+
+        let truncated = plist.replace("<key>Ansi 7 Color</key>", "<key>Ansi 7</key>");
+        assert!(matches!(
+            Theme::from_itermcolors(&truncated),
+            Err(ThemeError::MissingColor(name)) if name == "Ansi 7 Color"
+        ));
+    }
+
+    #[test]
+    fn test_alacritty_toml() {
+        let toml = r##"
+            [colors.primary]
+            background = "#1d1f21"
+            foreground = "0xc5c8c6"
+
+            [colors.cursor]
+            text = "CellBackground"
+            cursor = "CellForeground"
+
+            [colors.normal]
+            black = "#000000"
+            red = "#cc6666"
+            green = "#b5bd68"
+            yellow = "#f0c674"
+            blue = "#81a2be"
+            magenta = "#b294bb"
+            cyan = "#8abeb7"
+            white = "#ffffff"
+
+            [colors.bright]
+            black = "#666666"
+            red = "#d54e53"
+            green = "#b9ca4a"
+            yellow = "#e7c547"
+            blue = "#7aa6da"
+            magenta = "#c397d8"
+            cyan = "#70c0b1"
+            white = "#eaeaea"
+        "##;
+        let theme = Theme::from_alacritty_toml(toml).unwrap();
+        assert_eq!(theme.background, Rgb::new(0x1d, 0x1f, 0x21));
+        assert_eq!(theme.foreground, Rgb::new(0xc5, 0xc8, 0xc6));
+        assert_eq!(theme.cursor, theme.foreground);
+        assert_eq!(theme.ansi[1], Rgb::new(0xcc, 0x66, 0x66));
+        assert_eq!(theme.ansi[15], Rgb::new(0xea, 0xea, 0xea));
+        assert_eq!(theme.dim, None);
+        assert_eq!(theme.dim_color(7), Rgb::new(168, 168, 168));
+
+        assert!(Theme::from_alacritty_toml("[colors.primary]\nbackground = \"#000000\"").is_err());
+    }
+
+    #[test]
+    fn test_base16_yaml() {
+        let classic = "scheme: \"Default Dark\"\nauthor: \"Chris Kempson\"\n\
+            base00: \"181818\"\nbase01: \"282828\"\nbase02: \"383838\"\nbase03: \"585858\"\n\
+            base04: \"b8b8b8\"\nbase05: \"d8d8d8\"\nbase06: \"e8e8e8\"\nbase07: \"f8f8f8\"\n\
+            base08: \"ab4642\"\nbase09: \"dc9656\"\nbase0A: \"f7ca88\"\nbase0B: \"a1b56c\"\n\
+            base0C: \"86c1b9\"\nbase0D: \"7cafc2\"\nbase0E: \"ba8baf\"\nbase0F: \"a16946\"\n";
+        let theme = Theme::from_base16_yaml(classic).unwrap();
+        assert_eq!(theme.background, Rgb::new(0x18, 0x18, 0x18));
+        assert_eq!(theme.foreground, Rgb::new(0xd8, 0xd8, 0xd8));
+        assert_eq!(theme.selection, Rgb::new(0x38, 0x38, 0x38));
+        assert_eq!(theme.ansi[1], Rgb::new(0xab, 0x46, 0x42));
+        assert_eq!(theme.ansi[8], Rgb::new(0x58, 0x58, 0x58));
+
+        // The newer layout nests the palette and prefixes colors with '#'.
+        let palette = classic
+            .replace("base0", "  base0")
+            .replace(": \"", ": \"#")
+            .replace("scheme:", "palette:\nname:");
+        assert_eq!(Theme::from_base16_yaml(&palette).unwrap(), theme);
+
+        let missing = classic.replace("base0F", "baseXX");
+        assert!(matches!(
+            Theme::from_base16_yaml(&missing),
+            Err(ThemeError::MissingColor(name)) if name == "base0F"
+        ));
+    }
+
+    #[test]
+    fn test_color_queries() {
+        let mut term = VtTerminal::new(80, 24);
+        // Queries are answered in order with other replies, so a program can
+        // use a device attributes query to detect a missing answer.
+        term.write(b"\x1b]11;?\x07\x1b]4;1;?\x1b\\\x1b[c");
+        let writes = term.take_pty_writes();
+        assert_eq!(writes[0], "\x1b]11;rgb:0000/0000/0000\x07");
+        assert_eq!(writes[1], "\x1b]4;1;rgb:cdcd/0000/0000\x1b\\");
+        assert!(writes[2].starts_with("\x1b[?"));
+
+        term.set_theme(Theme {
+            background: Rgb::new(0x1d, 0x1f, 0x21),
+            ..Theme::default()
+        });
+        assert!(matches!(term.damage(), DamageInfo::Full));
+        assert_eq!(term.screen().cell(0, 0).bg, Rgb::new(0x1d, 0x1f, 0x21));
+        term.write(b"\x1b]11;?\x07");
+        assert_eq!(
+            term.take_pty_writes(),
+            vec!["\x1b]11;rgb:1d1d/1f1f/2121\x07"]
+        );
+
+        // Colors set by the program override the theme until reset.
+        term.write(b"\x1b]10;#123456\x07\x1b]10;?\x07");
+        assert_eq!(
+            term.take_pty_writes(),
+            vec!["\x1b]10;rgb:1212/3434/5656\x07"]
+        );
+        assert_eq!(term.colors().foreground, Rgb::new(0x12, 0x34, 0x56));
+        assert_eq!(term.screen().cell(0, 0).fg, Rgb::new(0x12, 0x34, 0x56));
+        term.write(b"\x1b]110\x07");
+        assert_eq!(term.colors().foreground, term.theme().foreground);
+    }
+
+    #[test]
+    fn test_palette_color() {
+        let theme = Theme::default();
+        assert_eq!(theme.palette_color(1), Rgb::new(205, 0, 0));
+        assert_eq!(theme.palette_color(196), Rgb::new(255, 0, 0));
+        assert_eq!(theme.palette_color(244), Rgb::new(128, 128, 128));
+        assert_eq!(
+            theme.palette_color(NamedColor::Background as usize),
+            theme.background
+        );
+        assert_eq!(
+            theme.palette_color(NamedColor::DimRed as usize),
+            Rgb::new(135, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Window title and icon name, and the stacks programs save them on.
+//!
+//! Programs set the title with OSC 2, the icon name (shown by xterm for a
+//! minimized window, and by many tab bars) with OSC 1, and both at once with
+//! OSC 0. Full-screen programs save them with XTWINOPS `CSI 22 ; Ps t` and
+//! restore them with `CSI 23 ; Ps t` on exit, where `Ps` picks both (0), the
+//! icon name (1) or the title (2).
+//!
+//! alacritty keeps one stack for the title and ignores `Ps`, so a program
+//! that saves the title and the icon name separately restores the wrong
+//! entries. Both stacks are kept here instead, fed by the side scanner.
+
+use std::collections::VecDeque;
+
+/// Deepest a stack may grow; the oldest entries are dropped beyond that.
+/// The same limit alacritty uses.
+const MAX_STACK_DEPTH: usize = 4096;
+
+/// Number of titles remembered in the history.
+const MAX_TITLE_HISTORY: usize = 100;
+
+/// Which of the title and icon name a push or pop applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TitleTarget {
+    Both,
+    IconName,
+    Title,
+}
+
+impl TitleTarget {
+    /// The target named by the `Ps` parameter of `CSI 22/23 ; Ps t`.
+    pub(crate) fn from_param(param: u16) -> Option<Self> {
+        match param {
+            0 => Some(TitleTarget::Both),
+            1 => Some(TitleTarget::IconName),
+            2 => Some(TitleTarget::Title),
+            _ => None,
+        }
+    }
+
+    fn title(self) -> bool {
+        matches!(self, TitleTarget::Both | TitleTarget::Title)
+    }
+
+    fn icon_name(self) -> bool {
+        matches!(self, TitleTarget::Both | TitleTarget::IconName)
+    }
+}
+
+/// The current title and icon name, their saved values, and the titles
+/// shown so far.
+#[derive(Default)]
+pub(crate) struct Titles {
+    title: Option<String>,
+    icon_name: Option<String>,
+    title_stack: VecDeque<Option<String>>,
+    icon_stack: VecDeque<Option<String>>,
+    history: VecDeque<String>,
+}
+
+impl Titles {
+    pub(crate) fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub(crate) fn icon_name(&self) -> Option<&str> {
+        self.icon_name.as_deref()
+    }
+
+    /// Titles shown so far, oldest first, without repeats in a row.
+    pub(crate) fn history(&self) -> Vec<String> {
+        self.history.iter().cloned().collect()
+    }
+
+    pub(crate) fn set_title(&mut self, title: Option<String>) {
+        if let Some(title) = title.as_ref().filter(|t| !t.is_empty()) {
+            if self.history.back() != Some(title) {
+                if self.history.len() == MAX_TITLE_HISTORY {
+                    self.history.pop_front();
+                }
+                self.history.push_back(title.clone());
+            }
+        }
+        self.title = title;
+    }
+
+    pub(crate) fn set_icon_name(&mut self, icon_name: Option<String>) {
+        self.icon_name = icon_name;
+    }
+
+    /// Save the current values (`CSI 22 ; Ps t`).
+    pub(crate) fn push(&mut self, target: TitleTarget) {
+        if target.title() {
+            push_capped(&mut self.title_stack, self.title.clone());
+        }
+        if target.icon_name() {
+            push_capped(&mut self.icon_stack, self.icon_name.clone());
+        }
+    }
+
+    /// Restore the last saved values (`CSI 23 ; Ps t`). A value with nothing
+    /// saved is left as it is.
+    pub(crate) fn pop(&mut self, target: TitleTarget) {
+        if target.title() {
+            if let Some(title) = self.title_stack.pop_back() {
+                self.set_title(title);
+            }
+        }
+        if target.icon_name() {
+            if let Some(icon_name) = self.icon_stack.pop_back() {
+                self.icon_name = icon_name;
+            }
+        }
+    }
+
+    /// Forget the saved values, as a full reset does.
+    pub(crate) fn clear_stacks(&mut self) {
+        self.title_stack.clear();
+        self.icon_stack.clear();
+    }
+}
+
+fn push_capped(stack: &mut VecDeque<Option<String>>, value: Option<String>) {
+    if stack.len() == MAX_STACK_DEPTH {
+        stack.pop_front();
+    }
+    stack.push_back(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(titles: &mut Titles, title: &str) {
+        titles.set_title(Some(title.to_string()));
+    }
+
+    #[test]
+    fn test_separate_stacks() {
+        let mut titles = Titles::default();
+        set(&mut titles, "shell");
+        titles.set_icon_name(Some("sh".to_string()));
+
+        // vim saves the title only, then sets both.
+        titles.push(TitleTarget::Title);
+        set(&mut titles, "vim");
+        titles.set_icon_name(Some("vim".to_string()));
+
+        // Restoring both brings the title back; the icon name has nothing
+        // saved and stays.
+        titles.pop(TitleTarget::Both);
+        assert_eq!(titles.title(), Some("shell"));
+        assert_eq!(titles.icon_name(), Some("vim"));
+
+        titles.push(TitleTarget::IconName);
+        titles.set_icon_name(None);
+        titles.pop(TitleTarget::IconName);
+        assert_eq!(titles.icon_name(), Some("vim"));
+        assert_eq!(titles.title(), Some("shell"));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut titles = Titles::default();
+        for title in ["a", "a", "b", "", "a"] {
+            set(&mut titles, title);
+        }
+        assert_eq!(titles.history(), ["a", "b", "a"]);
+
+        for i in 0..MAX_TITLE_HISTORY {
+            set(&mut titles, &format!("t{i}"));
+        }
+        let history = titles.history();
+        assert_eq!(history.len(), MAX_TITLE_HISTORY);
+        assert_eq!(history[0], "t0");
+    }
+}
@@ -0,0 +1,548 @@
+//! Golden-file conformance tests for `VtTerminal`.
+//!
+//! Every `tests/conformance/<name>.vt` file is a case: a byte stream fed to a
+//! fresh terminal, whose resulting state is compared against
+//! `<name>.snap` next to it. See `tests/conformance/README.md` for the file
+//! formats.
+//!
+//! Run with `PHANTOM_BLESS=1` to write the snapshots from the current
+//! behavior instead of comparing; review the diff before committing it.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use phantom_vt::{
+    CellFlags, MouseEncoding, MouseTracking, Rgb, UnderlineStyle, VtCell, VtTerminal,
+};
+
+/// Terminal size used when a case does not set one.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// Most differing cells listed per case; the rest are only counted.
+const MAX_CELL_DIFFS: usize = 20;
+
+/// Letters assigned to the distinct cell styles of a snapshot, in order of
+/// first appearance.
+const STYLE_LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+#[test]
+fn conformance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let bless = std::env::var_os("PHANTOM_BLESS").is_some();
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("read tests/conformance")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vt"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no cases in {}", dir.display());
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy();
+        let actual = match run_case(case) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                failures.push(format!("{name}: {e}"));
+                continue;
+            }
+        };
+        let snap_path = case.with_extension("snap");
+        if bless {
+            fs::write(&snap_path, actual.render()).expect("write snapshot");
+            continue;
+        }
+        match fs::read_to_string(&snap_path) {
+            Ok(expected) => {
+                if let Some(diff) = diff_snapshots(&expected, &actual) {
+                    failures.push(format!("{name}:\n{diff}"));
+                }
+            }
+            Err(_) => failures.push(format!("{name}: no snapshot at {}", snap_path.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} conformance cases failed (PHANTOM_BLESS=1 rewrites the snapshots):\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// Feed a case to a fresh terminal and snapshot the result.
+fn run_case(path: &Path) -> Result<Snapshot, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let (header, payload) = source
+        .split_once("\n---\n")
+        .ok_or("missing `---` line between the header and the input")?;
+
+    let mut size = DEFAULT_SIZE;
+    let mut input = None;
+    for line in header.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(' ') {
+            Some(("size", value)) => {
+                let (cols, rows) = value.split_once('x').ok_or("size must be COLSxROWS")?;
+                size = (
+                    cols.parse().map_err(|_| "bad column count")?,
+                    rows.parse().map_err(|_| "bad row count")?,
+                );
+            }
+            Some(("input", file)) => {
+                let file = path.with_file_name(file);
+                input = Some(fs::read(&file).map_err(|e| format!("{}: {e}", file.display()))?);
+            }
+            _ => return Err(format!("unknown header line: {line}")),
+        }
+    }
+    let bytes = match input {
+        Some(bytes) => bytes,
+        None => unescape(payload)?,
+    };
+
+    let mut term = VtTerminal::new(size.0, size.1);
+    term.write(&bytes);
+    Ok(Snapshot::capture(&term))
+}
+
+/// Decode the escaped input of a case. Line breaks in the file are ignored;
+/// bytes are written with `\e`, `\a`, `\b`, `\t`, `\n`, `\r`, `\\` and `\xHH`.
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | '\r' => {}
+            '\\' => {
+                let byte = match chars.next() {
+                    Some('e') => 0x1b,
+                    Some('a') => 0x07,
+                    Some('b') => 0x08,
+                    Some('t') => b'\t',
+                    Some('n') => b'\n',
+                    Some('r') => b'\r',
+                    Some('\\') => b'\\',
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        u8::from_str_radix(&hex, 16).map_err(|_| format!("bad escape \\x{hex}"))?
+                    }
+                    other => return Err(format!("bad escape \\{}", other.unwrap_or(' '))),
+                };
+                out.push(byte);
+            }
+            c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Ok(out)
+}
+
+/// A cell as it appears in a snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CellSnap {
+    /// The grapheme shown, or empty for the second half of a wide character.
+    text: String,
+    /// Attributes that differ from the default, e.g. `bold fg=#cd0000`.
+    style: String,
+}
+
+impl CellSnap {
+    fn is_spacer(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// The state of a terminal as compared by the tests.
+#[derive(Debug, PartialEq, Eq)]
+struct Snapshot {
+    /// `size`, `cursor`, `title` and `modes` lines.
+    header: Vec<String>,
+    rows: Vec<Vec<CellSnap>>,
+}
+
+impl Snapshot {
+    fn capture(term: &VtTerminal) -> Self {
+        let theme = term.theme().clone();
+        let screen = term.screen();
+        let rows: Vec<Vec<CellSnap>> = (0..screen.rows())
+            .map(|row| {
+                screen
+                    .row_cells(row)
+                    .iter()
+                    .map(|cell| match cell.width {
+                        // Drawn as part of the wide character before it.
+                        0 => CellSnap {
+                            text: String::new(),
+                            style: String::new(),
+                        },
+                        _ => CellSnap {
+                            text: cell.grapheme(),
+                            style: cell_style(cell, theme.foreground, theme.background),
+                        },
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let cursor = term.cursor();
+        let shape = format!("{:?}", cursor.shape).to_lowercase();
+        let visibility = if cursor.visible { "visible" } else { "hidden" };
+        let blinking = if cursor.blinking { " blinking" } else { "" };
+        let title = term.title_owned().unwrap_or_default();
+        let header = vec![
+            format!("size {}x{}", screen.cols(), screen.rows()),
+            format!("cursor {},{} {shape} {visibility}{blinking}", cursor.row, cursor.col),
+            format!("title {title:?}"),
+            format!("modes {}", modes(term)),
+        ];
+        Self { header, rows }
+    }
+
+    /// Write the snapshot file format.
+    fn render(&self) -> String {
+        let mut legend: Vec<&str> = Vec::new();
+        let mut letters = HashMap::new();
+        let mut screen = String::new();
+        let mut styles = String::new();
+        let mut graphemes = String::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            screen.push('|');
+            styles.push('|');
+            for (x, cell) in row.iter().enumerate() {
+                let mut chars = cell.text.chars();
+                if let Some(base) = chars.next() {
+                    screen.push(base);
+                    if chars.next().is_some() {
+                        let _ = writeln!(graphemes, "{y},{x} {:?}", cell.text);
+                    }
+                }
+                let letter = if cell.is_spacer() {
+                    '~'
+                } else if cell.style.is_empty() {
+                    '.'
+                } else {
+                    *letters.entry(cell.style.as_str()).or_insert_with(|| {
+                        legend.push(cell.style.as_str());
+                        STYLE_LETTERS
+                            .chars()
+                            .nth(legend.len() - 1)
+                            .expect("too many distinct styles for one snapshot")
+                    })
+                };
+                styles.push(letter);
+            }
+            screen.push_str("|\n");
+            styles.push_str("|\n");
+        }
+
+        let mut out = String::new();
+        for line in &self.header {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push_str("screen\n");
+        out.push_str(&screen);
+        out.push_str("style\n");
+        out.push_str(&styles);
+        out.push_str("legend\n");
+        for (letter, style) in STYLE_LETTERS.chars().zip(&legend) {
+            let _ = writeln!(out, "{letter} {style}");
+        }
+        if !graphemes.is_empty() {
+            out.push_str("graphemes\n");
+            out.push_str(&graphemes);
+        }
+        out
+    }
+
+    /// Read the snapshot file format.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        let mut header = Vec::new();
+        for line in lines.by_ref() {
+            if line == "screen" {
+                break;
+            }
+            header.push(line.to_string());
+        }
+
+        let mut section = |name: &str| -> Result<Vec<&str>, String> {
+            let mut body = Vec::new();
+            for line in lines.by_ref() {
+                if line == name {
+                    return Ok(body);
+                }
+                body.push(line);
+            }
+            if name.is_empty() {
+                Ok(body)
+            } else {
+                Err(format!("missing `{name}` section"))
+            }
+        };
+        let screen = section("style")?;
+        let styles = section("legend")?;
+        let rest = section("")?;
+        let split = rest
+            .iter()
+            .position(|&l| l == "graphemes")
+            .unwrap_or(rest.len());
+        let (legend, graphemes) = (&rest[..split], rest.get(split + 1..).unwrap_or_default());
+
+        let legend: HashMap<char, &str> = legend
+            .iter()
+            .filter_map(|line| {
+                let mut chars = line.chars();
+                Some((chars.next()?, chars.as_str().trim_start()))
+            })
+            .collect();
+        if screen.len() != styles.len() {
+            return Err("`screen` and `style` have different row counts".to_string());
+        }
+
+        let strip = |line: &str| -> Result<String, String> {
+            line.strip_prefix('|')
+                .and_then(|l| l.strip_suffix('|'))
+                .map(str::to_string)
+                .ok_or_else(|| format!("row not enclosed in `|`: {line}"))
+        };
+        let mut rows = Vec::new();
+        for (text, style) in screen.iter().zip(&styles) {
+            let text = strip(text)?;
+            let mut chars = text.chars();
+            let mut row = Vec::new();
+            for letter in strip(style)?.chars() {
+                let (text, style) = match letter {
+                    '~' => (String::new(), String::new()),
+                    '.' => (chars.next().unwrap_or(' ').to_string(), String::new()),
+                    letter => {
+                        let style = legend
+                            .get(&letter)
+                            .ok_or_else(|| format!("style `{letter}` not in the legend"))?;
+                        (chars.next().unwrap_or(' ').to_string(), style.to_string())
+                    }
+                };
+                row.push(CellSnap { text, style });
+            }
+            rows.push(row);
+        }
+        for line in graphemes {
+            let (at, text) = line.split_once(' ').ok_or("bad grapheme line")?;
+            let (y, x) = at.split_once(',').ok_or("bad grapheme position")?;
+            let y: usize = y.parse().map_err(|_| "bad grapheme row")?;
+            let x: usize = x.parse().map_err(|_| "bad grapheme column")?;
+            let cell = rows
+                .get_mut(y)
+                .and_then(|row| row.get_mut(x))
+                .ok_or("grapheme outside the screen")?;
+            cell.text = unquote(text)?;
+        }
+        Ok(Self { header, rows })
+    }
+}
+
+/// Undo the `{:?}` quoting of a grapheme.
+fn unquote(text: &str) -> Result<String, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or("grapheme not quoted")?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                let code = u32::from_str_radix(&hex, 16).map_err(|_| "bad \\u escape")?;
+                out.push(char::from_u32(code).ok_or("bad \\u escape")?);
+            }
+            Some(c @ ('"' | '\\' | '\'')) => out.push(c),
+            _ => return Err("unsupported escape in grapheme".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// Compare a snapshot file with the actual state; `None` if they match.
+fn diff_snapshots(expected: &str, actual: &Snapshot) -> Option<String> {
+    let expected = match Snapshot::parse(expected) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Some(format!("  unreadable snapshot: {e}")),
+    };
+    if &expected == actual {
+        return None;
+    }
+
+    let mut out = String::new();
+    for i in 0..expected.header.len().max(actual.header.len()) {
+        let want = expected.header.get(i).map_or("nothing", String::as_str);
+        let got = actual.header.get(i).map_or("nothing", String::as_str);
+        if want != got {
+            let _ = writeln!(out, "  expected `{want}`\n       got `{got}`");
+        }
+    }
+    if expected.rows.len() != actual.rows.len() {
+        let _ = writeln!(
+            out,
+            "  expected {} rows, got {}",
+            expected.rows.len(),
+            actual.rows.len()
+        );
+    }
+
+    let mut differing = 0;
+    for (y, (want_row, got_row)) in expected.rows.iter().zip(&actual.rows).enumerate() {
+        if want_row != got_row {
+            let _ = writeln!(
+                out,
+                "  row {y}:\n    expected |{}|\n         got |{}|",
+                row_text(want_row),
+                row_text(got_row)
+            );
+        }
+        let cols = want_row.len().max(got_row.len());
+        for x in 0..cols {
+            let (want, got) = (want_row.get(x), got_row.get(x));
+            if want == got {
+                continue;
+            }
+            differing += 1;
+            if differing <= MAX_CELL_DIFFS {
+                let _ = writeln!(
+                    out,
+                    "    col {x}: expected {}, got {}",
+                    describe(want),
+                    describe(got)
+                );
+            }
+        }
+    }
+    if differing > MAX_CELL_DIFFS {
+        let _ = writeln!(
+            out,
+            "  ... {} more differing cells",
+            differing - MAX_CELL_DIFFS
+        );
+    }
+    Some(out.trim_end().to_string())
+}
+
+fn row_text(row: &[CellSnap]) -> String {
+    row.iter().map(|cell| cell.text.as_str()).collect()
+}
+
+fn describe(cell: Option<&CellSnap>) -> String {
+    match cell {
+        None => "no cell".to_string(),
+        Some(cell) if cell.is_spacer() => "wide-char spacer".to_string(),
+        Some(cell) if cell.style.is_empty() => format!("{:?}", cell.text),
+        Some(cell) => format!("{:?} [{}]", cell.text, cell.style),
+    }
+}
+
+/// The attributes of a cell that differ from a default cell.
+fn cell_style(cell: &VtCell, foreground: Rgb, background: Rgb) -> String {
+    let hex = |c: Rgb| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+    let mut parts = Vec::new();
+    if cell.fg != foreground {
+        parts.push(format!("fg={}", hex(cell.fg)));
+    }
+    if cell.bg != background {
+        parts.push(format!("bg={}", hex(cell.bg)));
+    }
+    let flags = [
+        (CellFlags::BOLD, "bold"),
+        (CellFlags::DIM, "dim"),
+        (CellFlags::ITALIC, "italic"),
+        (CellFlags::STRIKETHROUGH, "strike"),
+        (CellFlags::INVERSE, "inverse"),
+        (CellFlags::HIDDEN, "hidden"),
+        (CellFlags::BLINK, "blink"),
+    ];
+    for (flag, name) in flags {
+        if cell.flags.contains(flag) {
+            parts.push(name.to_string());
+        }
+    }
+    match cell.underline {
+        UnderlineStyle::None => {}
+        UnderlineStyle::Single => parts.push("underline".to_string()),
+        style => parts.push(format!("underline={}", format!("{style:?}").to_lowercase())),
+    }
+    if let Some(color) = cell.underline_color {
+        parts.push(format!("ul={}", hex(color)));
+    }
+    if cell.hyperlink_id != 0 {
+        parts.push("link".to_string());
+    }
+    parts.join(" ")
+}
+
+/// The modes that differ from a fresh terminal, or `-`.
+fn modes(term: &VtTerminal) -> String {
+    let modes = term.modes();
+    let mut set = Vec::new();
+    match modes.mouse_tracking {
+        MouseTracking::None => {}
+        tracking => set.push(format!("mouse={tracking:?}").to_lowercase()),
+    }
+    match modes.mouse_encoding {
+        MouseEncoding::Default => {}
+        encoding => set.push(format!("mouse_encoding={encoding:?}").to_lowercase()),
+    }
+    let flags = [
+        (modes.alternate_scroll, "alternate_scroll"),
+        (modes.app_cursor, "app_cursor"),
+        (modes.app_keypad, "app_keypad"),
+        (modes.bracketed_paste, "bracketed_paste"),
+        (modes.focus_reporting, "focus_reporting"),
+        (modes.alt_screen, "alt_screen"),
+    ];
+    set.extend(
+        flags
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, name)| name.to_string()),
+    );
+    if modes.modify_other_keys != 0 {
+        set.push(format!("modify_other_keys={}", modes.modify_other_keys));
+    }
+    if !modes.kitty_keyboard.is_empty() {
+        set.push(format!("kitty_keyboard={}", modes.kitty_keyboard.bits()));
+    }
+    if set.is_empty() {
+        "-".to_string()
+    } else {
+        set.join(" ")
+    }
+}
+
+#[test]
+fn snapshot_format_round_trips() {
+    let mut term = VtTerminal::new(6, 2);
+    term.write("\x1b[1;31mab\x1b[0m世e\u{301}\r\n\x1b[4:3mx".as_bytes());
+    let snapshot = Snapshot::capture(&term);
+    let text = snapshot.render();
+    assert_eq!(Snapshot::parse(&text), Ok(snapshot));
+    // Wide characters take two cells, the second marked `~`; graphemes are
+    // listed apart so every row keeps one character per cell.
+    assert!(
+        text.contains("|ab世e |\n|x     |\nstyle\n|aa.~..|\n|b.....|\n"),
+        "{text}"
+    );
+    assert!(
+        text.contains("b underline=curly\ngraphemes\n0,4 \"e\\u{301}\"\n"),
+        "{text}"
+    );
+}
@@ -0,0 +1,196 @@
+//! Property tests that feed `VtTerminal` hostile output and resizes.
+//!
+//! Phantom shows output from agents and remote hosts it does not control, and
+//! a panic on the I/O thread takes the tab down with it. Each case writes a
+//! random mix of text, control sequences and garbage in random pieces,
+//! resizing and scrolling in between, and checks the screen after every step
+//! with `phantom_vt::invariants`. Failures are shrunk to a minimal sequence
+//! of steps.
+//!
+//! Set `PROPTEST_CASES` to run more cases than the default, e.g. in release
+//! mode:
+//!
+//! ```sh
+//! PROPTEST_CASES=100000 cargo test --release -p phantom-vt --test fuzz
+//! ```
+//!
+//! For coverage-guided fuzzing of the same checks, see `fuzz/`.
+
+use phantom_vt::invariants::check_terminal;
+use phantom_vt::VtTerminal;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+/// Final bytes of the CSI sequences the terminal implements, plus a few it
+/// does not.
+const CSI_FINALS: &[u8] = b"@ABCDEFGHIJKLMPSTXZ`abcdefghijlmnpqrstuxyz{|}~";
+
+/// Escape sequences without parameters.
+const ESCAPES: &[&str] = &[
+    "\x1b7", "\x1b8", "\x1bc", "\x1bD", "\x1bE", "\x1bH", "\x1bM", "\x1b=", "\x1b>", "\x1b(0",
+    "\x1b(B", "\x1b)0", "\x1b#8", "\x1bN", "\x1bO", "\x1b\\",
+];
+
+/// Modes worth toggling: alternate screens, origin mode, autowrap, margins,
+/// mouse and keyboard reporting, bracketed paste and synchronized updates.
+const MODES: &[&str] = &[
+    "?1049", "?1047", "?47", "?6", "?7", "?25", "?69", "?1000", "?1006", "?2004", "?2026", "4",
+    "20", "?1",
+];
+
+/// Text that is hard to lay out: wide characters, combining marks, emoji
+/// sequences and zero-width characters.
+const TEXT: &[&str] = &[
+    "日本語", "😀", "👨\u{200d}👩\u{200d}👧", "e\u{301}", "\u{301}", "❤\u{fe0f}", "\u{200b}",
+    "🇯🇵", "a\u{20dd}", "\u{fe0f}", "ﾃｽﾄ", "\u{0}", "\u{7f}", "\u{9b}", "\u{ad}",
+];
+
+/// Something done to the terminal between checks.
+#[derive(Clone, Debug)]
+enum Step {
+    /// Output, written in pieces of the given lengths and then the rest, so
+    /// sequences are split across reads.
+    Write(Vec<u8>, Vec<usize>),
+    Resize(u16, u16),
+    Scroll(i32),
+    SetReflow(bool),
+}
+
+proptest! {
+    #[test]
+    fn random_output_keeps_screen_consistent(
+        (cols, rows) in (1u16..100, 1u16..40),
+        steps in vec(step(), 1..40),
+    ) {
+        let mut vt = VtTerminal::new(cols, rows);
+        for (i, step) in steps.into_iter().enumerate() {
+            match step {
+                Step::Write(bytes, pieces) => {
+                    let mut rest = &bytes[..];
+                    for len in pieces {
+                        let (piece, tail) = rest.split_at(len.min(rest.len()));
+                        vt.write(piece);
+                        rest = tail;
+                    }
+                    vt.write(rest);
+                }
+                Step::Resize(cols, rows) => vt.resize(cols, rows),
+                Step::Scroll(delta) => vt.scroll_display(delta),
+                Step::SetReflow(reflow) => vt.set_reflow(reflow),
+            }
+            check_terminal(&mut vt).map_err(|e| TestCaseError::fail(format!("step {i}: {e}")))?;
+        }
+    }
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        7 => (output(), vec(1usize..64, 0..8)).prop_map(|(bytes, pieces)| Step::Write(bytes, pieces)),
+        // Shrinking to nothing is clamped to one cell.
+        1 => prop_oneof![9 => (1u16..100, 1u16..40), 1 => Just((0, 0))]
+            .prop_map(|(cols, rows)| Step::Resize(cols, rows)),
+        1 => (-50i32..50).prop_map(Step::Scroll),
+        1 => any::<bool>().prop_map(Step::SetReflow),
+    ]
+}
+
+/// A burst of output, most of it well-formed with random parameters.
+fn output() -> impl Strategy<Value = Vec<u8>> {
+    let piece = prop_oneof![
+        1 => vec(b' '..=b'~', 1..80),
+        1 => select(TEXT).prop_map(|text| text.as_bytes().to_vec()),
+        1 => vec(0u8..0x20, 1..4),
+        1 => select(ESCAPES).prop_map(|escape| escape.as_bytes().to_vec()),
+        2 => csi(),
+        1 => (select(MODES), any::<bool>()).prop_map(|(mode, set)| {
+            format!("\x1b[{mode}{}", if set { 'h' } else { 'l' }).into_bytes()
+        }),
+        1 => osc(),
+        1 => dcs_or_apc(),
+        1 => vec(any::<u8>(), 1..40),
+        // Scroll regions and cursor moves to the edges.
+        1 => (0u16..50, 0u16..50, 0u16..120, 0u16..120).prop_map(|(top, bottom, row, col)| {
+            format!("\x1b[{top};{bottom}r\x1b[{row};{col}H").into_bytes()
+        }),
+        1 => Just(b"\r\n".to_vec()),
+    ];
+    vec(piece, 1..20).prop_map(|pieces| pieces.concat())
+}
+
+/// A CSI sequence with random private marker, parameters and final byte.
+fn csi() -> impl Strategy<Value = Vec<u8>> {
+    let param = prop_oneof![1 => any::<u32>(), 1 => 0u32..70_000, 2 => 0u32..300];
+    (
+        option::weighted(0.25, select(b"?>=<".to_vec())),
+        vec((param, prop::bool::weighted(0.2)), 0..6),
+        option::weighted(0.2, select(b" !\"$'*".to_vec())),
+        prop_oneof![9 => select(CSI_FINALS.to_vec()), 1 => 0x40u8..0x7f],
+    )
+        .prop_map(|(marker, params, intermediate, final_byte)| {
+            let mut out = b"\x1b[".to_vec();
+            out.extend(marker);
+            for (i, (param, colon)) in params.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(if colon { b':' } else { b';' });
+                }
+                out.extend_from_slice(param.to_string().as_bytes());
+            }
+            out.extend(intermediate);
+            out.push(final_byte);
+            out
+        })
+}
+
+/// An OSC sequence the terminal handles, with random contents.
+fn osc() -> impl Strategy<Value = Vec<u8>> {
+    let text = || vec(any::<char>(), 0..30).prop_map(String::from_iter);
+    let n = || 0u16..300;
+    let body = prop_oneof![
+        text().prop_map(|text| format!("0;{text}")),
+        (n(), any::<[u8; 3]>()).prop_map(|(n, [r, g, b])| format!("4;{n};rgb:{r:x}/{g:x}/{b:x}")),
+        n().prop_map(|n| format!("8;id={n};https://example.com/{n}")),
+        Just("8;;".to_owned()),
+        (10u8..13, 0u32..0x100_0000).prop_map(|(n, color)| format!("{n};#{color:06x}")),
+        select(["?", "aGVsbG8="].to_vec()).prop_map(|data| format!("52;c;{data}")),
+        select(["A", "B", "C", "D;1", "D"].to_vec()).prop_map(|mark| format!("133;{mark}")),
+        text().prop_map(|text| format!("7;file://host/{text}")),
+        (0u8..5, n()).prop_map(|(state, n)| format!("9;4;{state};{n}")),
+        (n(), text()).prop_map(|(n, text)| format!("{n};{text}")),
+    ];
+    (body, any::<bool>()).prop_map(|(body, bel)| {
+        let end = if bel { "\x07" } else { "\x1b\\" };
+        format!("\x1b]{body}{end}").into_bytes()
+    })
+}
+
+/// A sixel image, kitty graphics command or status string request.
+fn dcs_or_apc() -> impl Strategy<Value = Vec<u8>> {
+    let body = prop_oneof![
+        (1u16..200, 1u16..200, vec(select(b"?@~#0123;!$-\"".to_vec()), 0..60)).prop_map(
+            |(w, h, data)| {
+                let data = String::from_utf8(data).unwrap();
+                format!("\x1bPq\"1;1;{w};{h}#1;2;100;0;0#1{data}")
+            }
+        ),
+        select(["m", "r", "\"q", " q"].to_vec()).prop_map(|request| format!("\x1bP$q{request}")),
+        (
+            0u16..20,
+            0u16..20,
+            select(["T", "t", "p", "d", "q"].to_vec()),
+            any::<u8>(),
+            0usize..10,
+        )
+            .prop_map(|(w, h, action, id, chunks)| {
+                let data = "AAAA".repeat(chunks);
+                format!("\x1b_Ga={action},f=32,s={w},v={h},i={id};{data}")
+            }),
+        any::<u32>().prop_map(|n| format!("\x1bP{n}")),
+    ];
+    // Sometimes left unterminated, to be cut off by the next sequence.
+    (body, prop::bool::weighted(0.8)).prop_map(|(body, terminated)| {
+        let end = if terminated { "\x1b\\" } else { "" };
+        format!("{body}{end}").into_bytes()
+    })
+}
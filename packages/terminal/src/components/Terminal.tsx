@@ -6,10 +6,31 @@
 import { createSignal, onCleanup, onMount, Show, type Component } from "solid-js";
 import TerminalCanvas from "./TerminalCanvas";
 import StatusBar from "./StatusBar";
-import { createTerminal, writeInput, resizeTerminal, closeTerminal, type SessionId } from "../lib/ipc";
-import { encodeKeyEvent } from "../lib/keybindings";
+import {
+  createTerminal,
+  detectLinks,
+  writeInput,
+  sendKey,
+  pasteText,
+  type PasteRisk,
+  KITTY_REPORT_EVENT_TYPES,
+  resizeTerminal,
+  closeTerminal,
+  answerClipboardRead,
+  scrollTerminal,
+  sendMouseEvent,
+  setTerminalFocus,
+  type LinkSpan,
+  type MouseButtonName,
+  type MouseEventKind,
+  type ProgressInfo,
+  type SessionId,
+  type TerminalEvent,
+} from "../lib/ipc";
+import { keyInputFromEvent } from "../lib/keybindings";
+import { cellAt, modifierBits, mouseButtonName, wheelButtonName } from "../lib/mouse";
 import { createSessionStore } from "../stores/sessions";
-import { measureFontMetrics } from "../renderer/font-metrics";
+import { measureFontMetrics, type FontMetrics } from "../renderer/font-metrics";
 import { calculateDimensions } from "../renderer/font-metrics";
 
 const DEFAULT_FONT_FAMILY = "Menlo, Monaco, Courier New, monospace";
@@ -18,29 +39,104 @@ const DEFAULT_FONT_SIZE = 14;
 interface TerminalProps {
   command?: string;
   workingDir?: string;
+  /** Start in this session's current directory when `workingDir` is unset. */
+  inheritCwdFrom?: SessionId;
+  /** Record the session from the start. */
+  record?: boolean;
+  /** Called with the backend session ID once the session is created. */
+  onSessionCreated?: (id: SessionId) => void;
+  /** Called when the program asks for a desktop notification (OSC 9 / OSC 777). */
+  onNotification?: (title: string | null, body: string) => void;
+  /** Called when the program reports or clears task progress (OSC 9;4). */
+  onProgress?: (progress: ProgressInfo | null) => void;
+  /** Called when a recording stops because writing to its file failed. */
+  onRecordingStopped?: (error: string) => void;
+  /** Called when a detected URL or file path is Ctrl/Cmd-clicked. */
+  onOpenLink?: (link: LinkSpan) => void;
 }
 
 const Terminal: Component<TerminalProps> = (props) => {
   let containerRef!: HTMLDivElement;
   const [sessionId, setSessionId] = createSignal<SessionId | null>(null);
   const [initialized, setInitialized] = createSignal(false);
+  let metrics: FontMetrics | null = null;
+  /** Button held down, for drag reporting. */
+  let heldButton: MouseButtonName = "none";
+  /** Last cell reported for motion, so each cell is reported once. */
+  let lastMotionCell = "";
 
   const { session, handleEvent } = createSessionStore(0, 80, 24);
 
+  function onEvent(event: TerminalEvent) {
+    if (event.type === "ClipboardWrite" || event.type === "ClipboardRead") {
+      handleClipboardEvent(event).catch((err) => console.error("OSC 52 clipboard:", err));
+      return;
+    }
+    if (event.type === "Notification") {
+      props.onNotification?.(event.title, event.body);
+      return;
+    }
+    if (event.type === "RecordingStopped") {
+      props.onRecordingStopped?.(event.error);
+      return;
+    }
+    if (event.type === "Progress") {
+      props.onProgress?.(event.progress);
+    }
+    handleEvent(event);
+  }
+
+  // The webview only has the system clipboard; primary-selection requests are
+  // ignored (writes) or declined (reads).
+  async function handleClipboardEvent(
+    event: Extract<TerminalEvent, { type: "ClipboardWrite" | "ClipboardRead" }>,
+  ) {
+    if (event.type === "ClipboardWrite") {
+      if (event.target !== "clipboard") return;
+      if (event.confirm && !window.confirm("Allow the terminal program to set the clipboard?")) {
+        return;
+      }
+      await navigator.clipboard.writeText(event.text);
+      return;
+    }
+
+    const id = sessionId();
+    if (id === null) return;
+    let text: string | null = null;
+    if (
+      event.target === "clipboard" &&
+      (!event.confirm || window.confirm("Allow the terminal program to read the clipboard?"))
+    ) {
+      text = await navigator.clipboard.readText();
+    }
+    await answerClipboardRead(id, event.request_id, text);
+  }
+
   onMount(async () => {
     const canvas = document.createElement("canvas");
     const ctx = canvas.getContext("2d");
     if (!ctx) return;
 
-    const metrics = measureFontMetrics(DEFAULT_FONT_FAMILY, DEFAULT_FONT_SIZE, ctx);
+    metrics = measureFontMetrics(DEFAULT_FONT_FAMILY, DEFAULT_FONT_SIZE, ctx);
     const rect = containerRef.getBoundingClientRect();
     const dims = calculateDimensions(rect.width, rect.height, metrics);
     const cols = Math.max(dims.cols, 2);
     const rows = Math.max(dims.rows, 2);
 
     try {
-      const id = await createTerminal(null, cols, rows, handleEvent, props.workingDir);
+      const id = await createTerminal(
+        null,
+        cols,
+        rows,
+        onEvent,
+        props.workingDir,
+        props.inheritCwdFrom,
+        props.record,
+      );
       setSessionId(id);
+      // The backend sizes images by the cell size in pixels.
+      resizeTerminal(id, cols, rows, cellPixels() ?? undefined).catch(() => {});
+      props.onSessionCreated?.(id);
       setInitialized(true);
       containerRef.focus();
 
@@ -62,22 +158,156 @@ const Terminal: Component<TerminalProps> = (props) => {
     }
   });
 
+  function sendKeyEvent(event: KeyboardEvent, release: boolean): boolean {
+    const id = sessionId();
+    if (id === null) return false;
+
+    const input = keyInputFromEvent(event, release);
+    if (input === null) return false;
+    if (typeof input === "string") {
+      writeInput(id, new TextEncoder().encode(input)).catch(() => {});
+    } else {
+      sendKey(id, input).catch(() => {});
+    }
+    return true;
+  }
+
   function handleKeyDown(event: KeyboardEvent) {
+    if (sendKeyEvent(event, false)) {
+      event.preventDefault();
+      event.stopPropagation();
+    }
+  }
+
+  const PASTE_RISK_TEXT: Record<PasteRisk, string> = {
+    multi_line: "contains multiple lines, which the shell will run immediately",
+    sudo: "runs a command with sudo",
+    pipe_to_shell: "pipes a download into a shell",
+  };
+
+  async function paste(text: string) {
     const id = sessionId();
-    if (id === null) return;
+    if (id === null || text === "") return;
+
+    const result = await pasteText(id, text);
+    if (result.written) return;
+    const reasons = result.risks.map((risk) => `- ${PASTE_RISK_TEXT[risk]}`).join("\n");
+    if (window.confirm(`This paste:\n${reasons}\n\nPaste anyway?`)) {
+      await pasteText(id, text, true);
+    }
+  }
+
+  function handlePaste(event: ClipboardEvent) {
+    const text = event.clipboardData?.getData("text/plain");
+    if (text === undefined) return;
+    event.preventDefault();
+    event.stopPropagation();
+    paste(text).catch((err) => console.error("Paste failed:", err));
+  }
 
-    const bytes = encodeKeyEvent(event);
-    if (bytes !== null) {
+  /** Key releases are only sent when the program asked for them. */
+  function handleKeyUp(event: KeyboardEvent) {
+    const flags = session().modes?.kitty_keyboard ?? 0;
+    if ((flags & KITTY_REPORT_EVENT_TYPES) === 0) return;
+    if (sendKeyEvent(event, true)) {
       event.preventDefault();
       event.stopPropagation();
-      writeInput(id, bytes).catch(() => {});
     }
   }
 
+  /**
+   * Report a mouse event to the program if it enabled mouse tracking.
+   * Resolves to whether it was sent.
+   */
+  async function reportMouse(
+    event: MouseEvent,
+    kind: MouseEventKind,
+    button: MouseButtonName,
+  ): Promise<boolean> {
+    const id = sessionId();
+    const modes = session().modes;
+    if (id === null || metrics === null) return false;
+    const tracking = modes?.mouse_tracking ?? "none";
+    const altScroll = modes?.alt_screen && modes.alternate_scroll;
+    if (tracking === "none" && !(altScroll && button.startsWith("wheel"))) return false;
+
+    const { row, col } = cellAt(event, containerRef, metrics, session().cols, session().rows);
+    if (kind === "motion") {
+      const key = `${row}:${col}`;
+      if (key === lastMotionCell) return true;
+      lastMotionCell = key;
+    }
+    return await sendMouseEvent(id, kind, button, row, col, modifierBits(event));
+  }
+
+  /** Open the detected link under the pointer, if there is one. */
+  async function openLinkAt(event: MouseEvent) {
+    const id = sessionId();
+    if (id === null || metrics === null) return;
+    const { row, col } = cellAt(event, containerRef, metrics, session().cols, session().rows);
+    const line = row - session().displayOffset;
+    const link = (await detectLinks(id)).find(
+      (l) =>
+        (line > l.start_line || (line === l.start_line && col >= l.start_col)) &&
+        (line < l.end_line || (line === l.end_line && col <= l.end_col)),
+    );
+    if (link) props.onOpenLink?.(link);
+  }
+
+  function handleMouseDown(event: MouseEvent) {
+    const button = mouseButtonName(event.button);
+    if (button === null) return;
+    if (button === "left" && (event.ctrlKey || event.metaKey) && props.onOpenLink) {
+      event.preventDefault();
+      openLinkAt(event).catch(() => {});
+      return;
+    }
+    heldButton = button;
+    reportMouse(event, "press", button).catch(() => {});
+  }
+
+  function handleMouseUp(event: MouseEvent) {
+    const button = mouseButtonName(event.button);
+    heldButton = "none";
+    if (button === null) return;
+    reportMouse(event, "release", button).catch(() => {});
+  }
+
+  function handleMouseMove(event: MouseEvent) {
+    const tracking = session().modes?.mouse_tracking;
+    if (tracking !== "button" && tracking !== "any") return;
+    reportMouse(event, "motion", heldButton).catch(() => {});
+  }
+
+  function handleWheel(event: WheelEvent) {
+    const id = sessionId();
+    const button = wheelButtonName(event);
+    if (id === null || button === null) return;
+    event.preventDefault();
+    reportMouse(event, "press", button)
+      .then((sent) => {
+        if (sent || metrics === null) return;
+        // Not wanted by the program: scroll the scrollback instead.
+        const lines = Math.round(event.deltaY / metrics.cellHeight) || Math.sign(event.deltaY);
+        return scrollTerminal(id, -lines);
+      })
+      .catch(() => {});
+  }
+
+  function handleFocusChange(focused: boolean) {
+    const id = sessionId();
+    if (id === null) return;
+    setTerminalFocus(id, focused).catch(() => {});
+  }
+
+  function cellPixels(): { width: number; height: number } | null {
+    return metrics && { width: metrics.cellWidth, height: metrics.cellHeight };
+  }
+
   function handleResize(cols: number, rows: number) {
     const id = sessionId();
     if (id === null) return;
-    resizeTerminal(id, cols, rows).catch(() => {});
+    resizeTerminal(id, cols, rows, cellPixels() ?? undefined).catch(() => {});
   }
 
   return (
@@ -93,6 +323,14 @@ const Terminal: Component<TerminalProps> = (props) => {
         ref={containerRef}
         tabIndex={0}
         onKeyDown={handleKeyDown}
+        onKeyUp={handleKeyUp}
+        onPaste={handlePaste}
+        onMouseDown={handleMouseDown}
+        onMouseUp={handleMouseUp}
+        onMouseMove={handleMouseMove}
+        onWheel={handleWheel}
+        onFocus={() => handleFocusChange(true)}
+        onBlur={() => handleFocusChange(false)}
         style={{
           flex: "1",
           "min-height": "0",
@@ -106,12 +344,18 @@ const Terminal: Component<TerminalProps> = (props) => {
             cols={session().cols}
             rows={session().rows}
             cells={session().cells ?? undefined}
+            graphemes={session().graphemes}
+            images={session().images}
+            imagePlacements={session().imagePlacements}
+            displayOffset={session().displayOffset}
             frameVersion={session().frameVersion}
             dirtyRowIndices={session().dirtyRowIndices}
             cursorRow={session().cursorRow}
             cursorCol={session().cursorCol}
             cursorShape={session().cursorShape}
             cursorVisible={session().cursorVisible}
+            cursorBlinking={session().cursorBlinking}
+            theme={session().theme}
             fontFamily={DEFAULT_FONT_FAMILY}
             fontSize={DEFAULT_FONT_SIZE}
             onResize={handleResize}
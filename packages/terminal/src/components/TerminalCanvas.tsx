@@ -9,13 +9,23 @@
 import { createEffect, createSignal, onCleanup, onMount, type Component } from "solid-js";
 import { CanvasRenderer } from "../renderer/canvas-renderer";
 import { calculateDimensions } from "../renderer/font-metrics";
+import type { ImageSpan, ThemeInfo } from "../lib/ipc";
 
-const CELL_SIZE = 16;
+const CELL_SIZE = 20;
+/** Time the blinking cursor spends shown, then hidden. */
+const CURSOR_BLINK_MS = 530;
 
 export interface TerminalCanvasProps {
   cols: number;
   rows: number;
   cells?: Uint8Array;
+  /** Text of multi-codepoint cells, by cell index in `cells`. */
+  graphemes?: Map<number, string>;
+  /** Pixels of the images in `imagePlacements`, by image ID. */
+  images?: Map<number, ImageData>;
+  imagePlacements?: ImageSpan[];
+  /** Lines the viewport is scrolled into the history. */
+  displayOffset?: number;
   frameVersion: number;
   /** Row indices that changed, or null/undefined for full frame. */
   dirtyRowIndices?: number[] | null;
@@ -23,6 +33,10 @@ export interface TerminalCanvasProps {
   cursorCol: number;
   cursorShape: string;
   cursorVisible: boolean;
+  /** Blink the cursor; it is always shown right after new output. */
+  cursorBlinking?: boolean;
+  /** Colors in effect; null until the backend reports them. */
+  theme?: ThemeInfo | null;
   fontFamily?: string;
   fontSize?: number;
   onResize?: (cols: number, rows: number) => void;
@@ -72,7 +86,14 @@ const TerminalCanvas: Component<TerminalCanvasProps> = (props) => {
 
     if (!r || !cells || cells.byteLength === 0) return;
 
+    const theme = props.theme;
+    if (theme) {
+      r.setTheme(theme.background, theme.cursor);
+    }
+
     const dirty = props.dirtyRowIndices;
+    const graphemes = props.graphemes;
+    r.setImages(props.images ?? new Map(), props.imagePlacements ?? [], props.displayOffset ?? 0);
 
     if (dirty != null && dirty.length > 0) {
       // Incremental: only repaint changed rows.
@@ -82,21 +103,49 @@ const TerminalCanvas: Component<TerminalCanvasProps> = (props) => {
           y,
           cells: cells.subarray(y * rowBytes, (y + 1) * rowBytes),
         })),
+        graphemes,
       );
     } else {
       // Full frame: resize or initial render.
-      r.renderFullFrame(cells, props.cols, props.rows);
+      r.renderFullFrame(cells, props.cols, props.rows, graphemes);
     }
 
     r.renderCursor(props.cursorRow, props.cursorCol, props.cursorShape, props.cursorVisible);
+
+    if (props.cursorBlinking && props.cursorVisible) {
+      startBlinking(r, cells);
+    }
   });
 
+  /**
+   * Toggle the cursor until the next frame. Each phase repaints the cursor row
+   * from the cell buffer, then draws the cursor on top when it is shown.
+   */
+  function startBlinking(r: CanvasRenderer, cells: Uint8Array) {
+    const row = props.cursorRow;
+    const col = props.cursorCol;
+    const shape = props.cursorShape;
+    const graphemes = props.graphemes;
+    const rowBytes = props.cols * CELL_SIZE;
+    let shown = true;
+
+    const timer = setInterval(() => {
+      shown = !shown;
+      r.renderDirtyRows(
+        [{ y: row, cells: cells.subarray(row * rowBytes, (row + 1) * rowBytes) }],
+        graphemes,
+      );
+      r.renderCursor(row, col, shape, shown);
+    }, CURSOR_BLINK_MS);
+    onCleanup(() => clearInterval(timer));
+  }
+
   return (
     <canvas
       ref={canvasRef}
       style={{
         display: "block",
-        background: "#000",
+        background: props.theme?.background ?? "#000",
       }}
     />
   );
@@ -1 +1,2 @@
 export { mountTerminal, type MountOptions } from "./mount";
+export type { LinkSpan, ProgressInfo } from "./lib/ipc";
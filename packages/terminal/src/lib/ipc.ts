@@ -10,10 +10,206 @@ import { invoke, Channel } from "@tauri-apps/api/core";
 
 export type SessionId = number;
 
+/**
+ * Full text of a cell holding more than one codepoint (combining marks,
+ * emoji sequences). The binary cell data only has the first codepoint.
+ */
+export interface Grapheme {
+  /** Index of the cell in the accompanying cell data. */
+  index: number;
+  text: string;
+}
+
 /** A single dirty row from an incremental update. */
 export interface DirtyRowData {
   y: number;
   cells: number[];
+  graphemes: Grapheme[];
+}
+
+/** A line of cell data read from the scrollback. */
+export interface ScrollbackLine {
+  /** Grid line: 0 is the top of the live screen, negative is history. */
+  line: number;
+  cells: number[];
+  graphemes: Grapheme[];
+}
+
+/** Lines read from the scrollback, with the hyperlinks they introduce. */
+export interface ScrollbackLines {
+  lines: ScrollbackLine[];
+  /** Links the lines use that were not defined before, or were redefined. */
+  links: HyperlinkDef[];
+}
+
+/** Selection extent in grid lines (inclusive on both ends). */
+export interface SelectionSpan {
+  start_line: number;
+  start_col: number;
+  end_line: number;
+  end_col: number;
+  block: boolean;
+}
+
+/** Search match extent in grid lines (inclusive on both ends). */
+export interface MatchSpan {
+  start_line: number;
+  start_col: number;
+  end_line: number;
+  end_col: number;
+}
+
+/**
+ * A URL or file path detected in the viewport text. The extent is in grid
+ * coordinates like `MatchSpan`.
+ */
+export interface LinkSpan {
+  start_line: number;
+  start_col: number;
+  end_line: number;
+  end_col: number;
+  text: string;
+  kind: "url" | "file";
+  /** The URL, or the file path; absolute when the working directory is known. */
+  target: string;
+  line: number | null;
+  column: number | null;
+}
+
+export type SelectionMode = "simple" | "word" | "line" | "block";
+
+/** A command run under shell integration (OSC 133), in grid lines. */
+export interface CommandBlockSpan {
+  id: number;
+  prompt_line: number;
+  command_line: number | null;
+  output_start_line: number | null;
+  /** Line where the output ends (exclusive), once the command finished. */
+  output_end_line: number | null;
+  command: string | null;
+  exit_code: number | null;
+  duration_ms: number | null;
+}
+
+/** Progress of a task reported by the program with OSC 9;4. */
+export interface ProgressInfo {
+  state: "normal" | "error" | "indeterminate" | "paused";
+  /** Percentage done, 0 to 100. */
+  percent: number;
+}
+
+/** An OSC 8 hyperlink, keyed by the ID at byte offset 12-13 of each cell. */
+export interface HyperlinkDef {
+  id: number;
+  uri: string;
+  /** The `id=` parameter of the OSC 8 sequence, if any. */
+  link_id: string | null;
+}
+
+/** The pixels of an image shown by the kitty graphics protocol or sixel. */
+export interface ImageDef {
+  id: number;
+  width: number;
+  height: number;
+  /** RGBA pixels, row-major, base64-encoded. */
+  rgba: string;
+}
+
+/** Decode the base64 pixels of an `ImageDef`. */
+export function decodeImagePixels(def: ImageDef): ImageData {
+  const binary = atob(def.rgba);
+  const rgba = new Uint8ClampedArray(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    rgba[i] = binary.charCodeAt(i);
+  }
+  return new ImageData(rgba, def.width, def.height);
+}
+
+/**
+ * Where an image is shown. Lines are indexed like `ScrollbackLine.line`;
+ * the image is scaled to cover `cols` x `rows` cells.
+ */
+export interface ImageSpan {
+  image_id: number;
+  line: number;
+  col: number;
+  cols: number;
+  rows: number;
+  /** Negative values are drawn below the text. */
+  z: number;
+}
+
+/** Terminal modes that affect input handling. */
+export interface TerminalModes {
+  mouse_tracking: "none" | "x10" | "normal" | "button" | "any";
+  mouse_encoding: "default" | "utf8" | "sgr";
+  alternate_scroll: boolean;
+  app_cursor: boolean;
+  app_keypad: boolean;
+  /** xterm modifyOtherKeys level (0-2). */
+  modify_other_keys: number;
+  /** Kitty keyboard protocol flags; 0 for legacy input. */
+  kitty_keyboard: number;
+  bracketed_paste: boolean;
+  focus_reporting: boolean;
+  alt_screen: boolean;
+}
+
+/** Colors in effect for a terminal, as `#rrggbb` strings. */
+export interface ThemeInfo {
+  /** The 16 ANSI colors. */
+  ansi: string[];
+  foreground: string;
+  background: string;
+  cursor: string;
+  selection: string;
+}
+
+/** Kitty keyboard flag: the program wants key repeat and release events. */
+export const KITTY_REPORT_EVENT_TYPES = 0b10;
+
+/**
+ * A key event, encoded by the backend according to the terminal's modes.
+ *
+ * `key` is a `KeyboardEvent.key` name ("Enter", "ArrowUp", "F5"), a keypad
+ * `KeyboardEvent.code` ("Numpad5"), or a single character.
+ */
+export interface KeyInput {
+  key: string;
+  /** Bitmask of MOD_* values. */
+  modifiers: number;
+  /** Text the key types, if any. */
+  text: string | null;
+  kind: "press" | "repeat" | "release";
+}
+
+export type MouseEventKind = "press" | "release" | "motion";
+
+export type MouseButtonName =
+  | "left"
+  | "middle"
+  | "right"
+  | "wheel_up"
+  | "wheel_down"
+  | "wheel_left"
+  | "wheel_right"
+  | "none";
+
+/** Modifier bitmask bits for `sendMouseEvent`. */
+export const MOD_SHIFT = 1;
+export const MOD_ALT = 2;
+export const MOD_CTRL = 4;
+export const MOD_SUPER = 8;
+
+/** Clipboard targeted by an OSC 52 request. */
+export type ClipboardTarget = "clipboard" | "primary";
+
+/** How OSC 52 clipboard requests from programs are handled. */
+export type ClipboardPermission = "allow" | "deny" | "prompt";
+
+export interface ClipboardPolicy {
+  write: ClipboardPermission;
+  read: ClipboardPermission;
 }
 
 /**
@@ -28,10 +224,13 @@ export type TerminalEvent =
       cols: number;
       rows: number;
       cells: number[];
+      graphemes: Grapheme[];
       cursor_row: number;
       cursor_col: number;
       cursor_shape: string;
       cursor_visible: boolean;
+      /** Whether the cursor should blink; false while the window is unfocused. */
+      cursor_blinking: boolean;
     }
   | {
       type: "DirtyRows";
@@ -40,10 +239,85 @@ export type TerminalEvent =
       cursor_col: number;
       cursor_shape: string;
       cursor_visible: boolean;
+      /** Whether the cursor should blink; false while the window is unfocused. */
+      cursor_blinking: boolean;
     }
   | {
       type: "TitleChanged";
       title: string;
+      /** Short name for tabs (OSC 0 / OSC 1), if the program set one. */
+      icon_name: string | null;
+    }
+  | {
+      type: "CwdChanged";
+      cwd: string;
+    }
+  | {
+      type: "ScrollChanged";
+      display_offset: number;
+      history_size: number;
+    }
+  | {
+      type: "SelectionChanged";
+      selection: SelectionSpan | null;
+    }
+  | {
+      type: "SearchMatches";
+      matches: MatchSpan[];
+      focused: MatchSpan | null;
+    }
+  | {
+      type: "HyperlinksDefined";
+      links: HyperlinkDef[];
+    }
+  | {
+      type: "ImagesChanged";
+      /** Pixels of images not sent since they were last placed. */
+      images: ImageDef[];
+      /** Replaces the previous placements. */
+      placements: ImageSpan[];
+    }
+  | {
+      type: "CommandFinished";
+      block: CommandBlockSpan;
+    }
+  | {
+      type: "ModesChanged";
+      modes: TerminalModes;
+    }
+  | {
+      type: "ThemeChanged";
+      theme: ThemeInfo;
+    }
+  | {
+      type: "ClipboardWrite";
+      target: ClipboardTarget;
+      text: string;
+      /** Ask the user before setting the clipboard. */
+      confirm: boolean;
+    }
+  | {
+      type: "ClipboardRead";
+      request_id: number;
+      target: ClipboardTarget;
+      /** Ask the user before sending the clipboard. */
+      confirm: boolean;
+    }
+  | {
+      /** The program asked for a desktop notification (OSC 9 / OSC 777). */
+      type: "Notification";
+      title: string | null;
+      body: string;
+    }
+  | {
+      type: "Progress";
+      /** Null once the program cleared its progress. */
+      progress: ProgressInfo | null;
+    }
+  | {
+      /** Writing to the recording failed, so it stopped. */
+      type: "RecordingStopped";
+      error: string;
     }
   | {
       type: "Bell";
@@ -59,6 +333,12 @@ export type TerminalEvent =
  * Spawns a PTY with the given shell (or system default if null), starts the
  * I/O thread and render pump on the backend, and returns the session ID.
  * Events are delivered to `onEvent` via a Tauri Channel.
+ *
+ * Without an explicit `workingDir`, the shell starts in the last directory
+ * reported by session `inheritCwdFrom` (if any).
+ *
+ * With `record`, the session is recorded from the start, with its input if
+ * `recordInput` is also set (see `startRecording`).
  */
 export async function createTerminal(
   shell: string | null,
@@ -66,6 +346,9 @@ export async function createTerminal(
   rows: number,
   onEvent: (event: TerminalEvent) => void,
   workingDir?: string,
+  inheritCwdFrom?: SessionId,
+  record?: boolean,
+  recordInput?: boolean,
 ): Promise<SessionId> {
   const channel = new Channel<TerminalEvent>();
   channel.onmessage = onEvent;
@@ -75,6 +358,9 @@ export async function createTerminal(
     rows,
     channel,
     workingDir: workingDir ?? null,
+    inheritCwdFrom: inheritCwdFrom ?? null,
+    record: record ?? null,
+    recordInput: recordInput ?? null,
   });
 }
 
@@ -90,15 +376,314 @@ export async function writeInput(sessionId: SessionId, data: Uint8Array): Promis
   });
 }
 
+/**
+ * Send a key event to a terminal session. The backend encodes it for the
+ * program's keyboard modes, or drops it if the program did not ask for it
+ * (e.g. key releases).
+ */
+export async function sendKey(sessionId: SessionId, key: KeyInput): Promise<void> {
+  await invoke("write_input", { sessionId, key });
+}
+
+/** Why a paste needs confirmation before it is sent. */
+export type PasteRisk = "multi_line" | "sudo" | "pipe_to_shell";
+
+export interface PasteResult {
+  /** Whether the text was sent to the program. */
+  written: boolean;
+  /** If not written, why the user should confirm the paste. */
+  risks: PasteRisk[];
+}
+
+/**
+ * Paste text into a terminal session. Escape sequences are stripped and the
+ * text is bracketed if the program asked for it. Risky pastes are not sent
+ * unless `confirmed` is true; ask the user and paste again.
+ */
+export async function pasteText(
+  sessionId: SessionId,
+  text: string,
+  confirmed = false,
+): Promise<PasteResult> {
+  return await invoke<PasteResult>("paste_text", { sessionId, text, confirmed });
+}
+
+/**
+ * Tell a terminal session whether its view has keyboard focus. The cursor is
+ * drawn hollow while unfocused, and programs that enabled focus reporting are
+ * told about the change.
+ */
+export async function setTerminalFocus(sessionId: SessionId, focused: boolean): Promise<void> {
+  await invoke("set_terminal_focus", { sessionId, focused });
+}
+
+/**
+ * Report a mouse event at a viewport cell to the program in the terminal.
+ *
+ * Returns `true` if the program asked for it and it was sent; otherwise the
+ * caller should handle the event itself (selection, scrolling).
+ */
+export async function sendMouseEvent(
+  sessionId: SessionId,
+  kind: MouseEventKind,
+  button: MouseButtonName,
+  row: number,
+  col: number,
+  modifiers: number,
+): Promise<boolean> {
+  return await invoke<boolean>("send_mouse_event", {
+    sessionId,
+    kind,
+    button,
+    row,
+    col,
+    modifiers,
+  });
+}
+
+/** Get the terminal modes that affect input handling. */
+export async function getTerminalModes(sessionId: SessionId): Promise<TerminalModes> {
+  return await invoke<TerminalModes>("get_terminal_modes", { sessionId });
+}
+
 /**
  * Resize a terminal session's PTY and virtual terminal.
+ *
+ * The cell size in pixels, if given, decides how many cells newly shown
+ * images cover.
  */
 export async function resizeTerminal(
   sessionId: SessionId,
   cols: number,
   rows: number,
+  cellSize?: { width: number; height: number },
 ): Promise<void> {
-  await invoke("resize_terminal", { sessionId, cols, rows });
+  await invoke("resize_terminal", {
+    sessionId,
+    cols,
+    rows,
+    cellWidth: cellSize ? Math.round(cellSize.width) : null,
+    cellHeight: cellSize ? Math.round(cellSize.height) : null,
+  });
+}
+
+/**
+ * Choose whether resizing a session rewraps soft-wrapped lines (the default)
+ * or crops and pads them so text keeps its columns. Turn reflow off for panes
+ * that run full-screen programs on the alternate screen.
+ */
+export async function setTerminalReflow(sessionId: SessionId, reflow: boolean): Promise<void> {
+  await invoke("set_terminal_reflow", { sessionId, reflow });
+}
+
+/**
+ * Scroll a terminal session's viewport by `delta` lines.
+ *
+ * Positive values scroll up into the scrollback; negative values scroll back
+ * towards the live screen. A FullFrame for the new viewport follows.
+ */
+export async function scrollTerminal(sessionId: SessionId, delta: number): Promise<void> {
+  await invoke("scroll_terminal", { sessionId, delta });
+}
+
+/**
+ * Read `count` grid lines starting at `start` (negative lines are scrollback),
+ * along with definitions of the hyperlinks they use.
+ */
+export async function readScrollback(
+  sessionId: SessionId,
+  start: number,
+  count: number,
+): Promise<ScrollbackLines> {
+  return await invoke<ScrollbackLines>("read_scrollback", { sessionId, start, count });
+}
+
+/**
+ * Start a selection at a viewport cell. `side` is the half of the cell that was clicked.
+ */
+export async function startSelection(
+  sessionId: SessionId,
+  row: number,
+  col: number,
+  mode: SelectionMode,
+  side: "left" | "right" = "left",
+): Promise<SelectionSpan | null> {
+  return await invoke<SelectionSpan | null>("start_selection", { sessionId, row, col, mode, side });
+}
+
+/**
+ * Extend the current selection to a viewport cell.
+ */
+export async function updateSelection(
+  sessionId: SessionId,
+  row: number,
+  col: number,
+  side: "left" | "right" = "left",
+): Promise<SelectionSpan | null> {
+  return await invoke<SelectionSpan | null>("update_selection", { sessionId, row, col, side });
+}
+
+/**
+ * Clear the current selection.
+ */
+export async function clearSelection(sessionId: SessionId): Promise<void> {
+  await invoke("clear_selection", { sessionId });
+}
+
+/**
+ * Get the selected text, for copying to the clipboard.
+ */
+export async function getSelectionText(sessionId: SessionId): Promise<string | null> {
+  return await invoke<string | null>("get_selection_text", { sessionId });
+}
+
+/**
+ * Start recording a session's output and resizes to a new asciicast v2 file
+ * in ~/.phantom/recordings. Returns the file's path.
+ *
+ * Typed input, passwords included, is only recorded with `recordInput`.
+ */
+export async function startRecording(
+  sessionId: SessionId,
+  recordInput?: boolean,
+): Promise<string> {
+  return await invoke<string>("start_recording", {
+    sessionId,
+    recordInput: recordInput ?? null,
+  });
+}
+
+/** Stop recording a session. */
+export async function stopRecording(sessionId: SessionId): Promise<void> {
+  await invoke("stop_recording", { sessionId });
+}
+
+/**
+ * Save a snapshot of a session's screen, scrollback, cursor, modes, title
+ * and palette to a new file in ~/.phantom/snapshots. Returns the file's path.
+ */
+export async function saveTerminalSnapshot(sessionId: SessionId): Promise<string> {
+  return await invoke<string>("save_terminal_snapshot", { sessionId });
+}
+
+/**
+ * Replace a session's screen and scrollback with a snapshot file saved by
+ * `saveTerminalSnapshot`. The shell keeps running.
+ */
+export async function restoreTerminalSnapshot(
+  sessionId: SessionId,
+  path: string,
+): Promise<void> {
+  await invoke("restore_terminal_snapshot", { sessionId, path });
+}
+
+/** Output format of `exportTerminal`. */
+export type ExportFormat = "text" | "ansi" | "html";
+
+/**
+ * Export the scrollback and screen, or only the selection, as plain text,
+ * ANSI escaped text or a self-contained HTML document. Soft-wrapped lines
+ * are joined and trailing whitespace is trimmed.
+ *
+ * Returns null if `selectionOnly` is set and nothing is selected.
+ */
+export async function exportTerminal(
+  sessionId: SessionId,
+  format: ExportFormat,
+  selectionOnly = false,
+): Promise<string | null> {
+  return await invoke<string | null>("export_terminal", { sessionId, format, selectionOnly });
+}
+
+/**
+ * Search the screen and scrollback. Returns the total number of matches.
+ *
+ * Matching is smart-case: case-insensitive unless the query has an uppercase letter.
+ */
+export async function searchTerminal(
+  sessionId: SessionId,
+  query: string,
+  regex = false,
+): Promise<number> {
+  return await invoke<number>("search_terminal", { sessionId, query, regex });
+}
+
+/**
+ * Focus the next match and scroll it into view.
+ */
+export async function searchNext(sessionId: SessionId): Promise<MatchSpan | null> {
+  return await invoke<MatchSpan | null>("search_next", { sessionId });
+}
+
+/**
+ * Focus the previous match and scroll it into view.
+ */
+export async function searchPrevious(sessionId: SessionId): Promise<MatchSpan | null> {
+  return await invoke<MatchSpan | null>("search_previous", { sessionId });
+}
+
+/**
+ * End the active search and remove its highlights.
+ */
+export async function clearSearch(sessionId: SessionId): Promise<void> {
+  await invoke("clear_search", { sessionId });
+}
+
+/** Get the working directory last reported by the shell (OSC 7), if any. */
+export async function getTerminalCwd(sessionId: SessionId): Promise<string | null> {
+  return await invoke<string | null>("get_terminal_cwd", { sessionId });
+}
+
+/** List the titles the session's window has had, oldest first. */
+export async function getTitleHistory(sessionId: SessionId): Promise<string[]> {
+  return await invoke<string[]>("get_title_history", { sessionId });
+}
+
+/** List the command blocks recorded by shell integration, oldest first. */
+export async function listCommandBlocks(sessionId: SessionId): Promise<CommandBlockSpan[]> {
+  return await invoke<CommandBlockSpan[]>("list_command_blocks", { sessionId });
+}
+
+/** Find the URLs and file paths (e.g. `src/main.rs:12:5`) shown in the viewport. */
+export async function detectLinks(sessionId: SessionId): Promise<LinkSpan[]> {
+  return await invoke<LinkSpan[]>("detect_links", { sessionId });
+}
+
+/** Scroll the viewport to the previous or next shell prompt. */
+export async function jumpToPrompt(
+  sessionId: SessionId,
+  direction: "previous" | "next",
+): Promise<CommandBlockSpan | null> {
+  return await invoke<CommandBlockSpan | null>("jump_to_prompt", { sessionId, direction });
+}
+
+/** Get the output text of a command block. */
+export async function getCommandOutput(
+  sessionId: SessionId,
+  blockId: number,
+): Promise<string | null> {
+  return await invoke<string | null>("get_command_output", { sessionId, blockId });
+}
+
+/**
+ * Answer an OSC 52 clipboard read. Pass `null` to decline it.
+ */
+export async function answerClipboardRead(
+  sessionId: SessionId,
+  requestId: number,
+  text: string | null,
+): Promise<void> {
+  await invoke("answer_clipboard_read", { sessionId, requestId, text });
+}
+
+/** Get the OSC 52 clipboard policy. */
+export async function getClipboardPolicy(): Promise<ClipboardPolicy> {
+  return await invoke<ClipboardPolicy>("get_clipboard_policy");
+}
+
+/** Set the OSC 52 clipboard policy for all sessions. */
+export async function setClipboardPolicy(policy: ClipboardPolicy): Promise<ClipboardPolicy> {
+  return await invoke<ClipboardPolicy>("set_clipboard_policy", { ...policy });
 }
 
 /**
@@ -107,3 +692,16 @@ export async function resizeTerminal(
 export async function closeTerminal(sessionId: SessionId): Promise<void> {
   await invoke("close_terminal", { sessionId });
 }
+
+/**
+ * Set the terminal color scheme from a theme file (`.itermcolors`, Alacritty
+ * TOML or base16 YAML), or the default scheme for `null`. With a session ID
+ * only that session changes; otherwise the theme is saved and applies to all
+ * sessions.
+ */
+export async function setTerminalTheme(
+  path: string | null,
+  sessionId?: SessionId,
+): Promise<ThemeInfo> {
+  return await invoke<ThemeInfo>("set_terminal_theme", { path, sessionId: sessionId ?? null });
+}
@@ -0,0 +1,60 @@
+/**
+ * DOM mouse event helpers for mouse reporting.
+ *
+ * The backend encodes mouse events for the program according to the
+ * terminal's modes; these helpers only translate DOM events into the
+ * abstract form it expects (button name, modifier bits, viewport cell).
+ */
+
+import type { FontMetrics } from "../renderer/font-metrics";
+import { MOD_ALT, MOD_CTRL, MOD_SHIFT, MOD_SUPER, type MouseButtonName } from "./ipc";
+
+/** Map a DOM `MouseEvent.button` to a button name, or null if unsupported. */
+export function mouseButtonName(button: number): MouseButtonName | null {
+  switch (button) {
+    case 0:
+      return "left";
+    case 1:
+      return "middle";
+    case 2:
+      return "right";
+    default:
+      return null;
+  }
+}
+
+/** Map a wheel event to a wheel button, or null if it did not scroll. */
+export function wheelButtonName(event: WheelEvent): MouseButtonName | null {
+  if (event.deltaY < 0) return "wheel_up";
+  if (event.deltaY > 0) return "wheel_down";
+  if (event.deltaX < 0) return "wheel_left";
+  if (event.deltaX > 0) return "wheel_right";
+  return null;
+}
+
+/** Modifier bitmask for a DOM mouse or keyboard event. */
+export function modifierBits(event: MouseEvent | KeyboardEvent): number {
+  return (
+    (event.shiftKey ? MOD_SHIFT : 0) |
+    (event.altKey ? MOD_ALT : 0) |
+    (event.ctrlKey ? MOD_CTRL : 0) |
+    (event.metaKey ? MOD_SUPER : 0)
+  );
+}
+
+/** The viewport cell under the pointer, clamped to the grid. */
+export function cellAt(
+  event: MouseEvent,
+  element: HTMLElement,
+  metrics: FontMetrics,
+  cols: number,
+  rows: number,
+): { row: number; col: number } {
+  const rect = element.getBoundingClientRect();
+  const col = Math.floor((event.clientX - rect.left) / metrics.cellWidth);
+  const row = Math.floor((event.clientY - rect.top) / metrics.cellHeight);
+  return {
+    row: Math.min(Math.max(row, 0), rows - 1),
+    col: Math.min(Math.max(col, 0), cols - 1),
+  };
+}
@@ -1,11 +1,26 @@
 import { render } from "solid-js/web";
 import Terminal from "./components/Terminal";
+import type { LinkSpan, ProgressInfo } from "./lib/ipc";
 
 export interface MountOptions {
   /** Shell command to execute after session creation (e.g., "claude --model opus"). */
   command?: string;
   /** Working directory for the PTY shell process. */
   workingDir?: string;
+  /** Session whose current directory (OSC 7) to start in when `workingDir` is unset. */
+  inheritCwdFrom?: number;
+  /** Record the session to an asciicast file in ~/.phantom/recordings. */
+  record?: boolean;
+  /** Called with the backend session ID once the session is created. */
+  onSessionCreated?: (id: number) => void;
+  /** Called when the program asks for a desktop notification (OSC 9 / OSC 777). */
+  onNotification?: (title: string | null, body: string) => void;
+  /** Called when the program reports or clears task progress (OSC 9;4). */
+  onProgress?: (progress: ProgressInfo | null) => void;
+  /** Called when a recording stops because writing to its file failed. */
+  onRecordingStopped?: (error: string) => void;
+  /** Called when a detected URL or `path:line:col` is Ctrl/Cmd-clicked. */
+  onOpenLink?: (link: LinkSpan) => void;
 }
 
 /**
@@ -13,6 +28,20 @@ export interface MountOptions {
  * Returns a dispose function to unmount.
  */
 export function mountTerminal(container: HTMLElement, options?: MountOptions): () => void {
-  const dispose = render(() => Terminal({ command: options?.command, workingDir: options?.workingDir }), container);
+  const dispose = render(
+    () =>
+      Terminal({
+        command: options?.command,
+        workingDir: options?.workingDir,
+        inheritCwdFrom: options?.inheritCwdFrom,
+        record: options?.record,
+        onSessionCreated: options?.onSessionCreated,
+        onNotification: options?.onNotification,
+        onProgress: options?.onProgress,
+        onRecordingStopped: options?.onRecordingStopped,
+        onOpenLink: options?.onOpenLink,
+      }),
+    container,
+  );
   return dispose;
 }
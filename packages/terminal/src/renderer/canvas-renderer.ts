@@ -1,23 +1,31 @@
 /**
  * Canvas2D terminal renderer.
  *
- * Decodes binary cell data (16 bytes per cell) and draws a terminal grid
+ * Decodes binary cell data (20 bytes per cell) and draws a terminal grid
  * onto an HTMLCanvasElement using the Canvas 2D API. Supports ligature-
- * friendly text runs, HiDPI scaling, and incremental (dirty-row) redraws.
+ * friendly text runs, HiDPI scaling, incremental (dirty-row) redraws, and
+ * inline images drawn over the text.
  */
 
+import type { ImageSpan } from "../lib/ipc";
 import { type FontMetrics, measureFontMetrics } from "./font-metrics";
 
-// ── Binary cell layout (16 bytes) ──────────────────────────────────────────
+// ── Binary cell layout (20 bytes) ──────────────────────────────────────────
 // Offset  0-3 : codepoint (u32 LE)
 // Offset  4-6 : fg RGB
 // Offset  7-9 : bg RGB
 // Offset   10 : flags (bold=1, italic=2, underline=4, strikethrough=8,
 //                       inverse=16, dim=32, hidden=64, blink=128)
 // Offset   11 : width (0=spacer, 1=normal, 2=wide)
-// Offset 12-15: reserved
+// Offset 12-13: hyperlink id (u16 LE, 0=none)
+// Offset   14 : grapheme length (further codepoints; 0=single codepoint,
+//               otherwise the full text comes in the grapheme side table)
+// Offset   15 : underline style (0=none, 1=single, 2=double, 3=curly,
+//               4=dotted, 5=dashed)
+// Offset 16-18: underline color RGB (SGR 58)
+// Offset   19 : underline color set (0=use the foreground)
 
-const CELL_SIZE = 16;
+const CELL_SIZE = 20;
 
 const FLAG_BOLD = 1;
 const FLAG_ITALIC = 2;
@@ -28,14 +36,24 @@ const FLAG_DIM = 32;
 const FLAG_HIDDEN = 64;
 // const FLAG_BLINK = 128;  // Not used in rendering currently
 
+const UNDERLINE_SINGLE = 1;
+const UNDERLINE_DOUBLE = 2;
+const UNDERLINE_CURLY = 3;
+const UNDERLINE_DOTTED = 4;
+const UNDERLINE_DASHED = 5;
+
 const DEFAULT_FONT_FAMILY = "Menlo, Monaco, Courier New, monospace";
 const DEFAULT_FONT_SIZE = 14;
-const DEFAULT_BG = "rgb(0,0,0)";
-const DEFAULT_FG = "rgb(255,255,255)";
 
-// Packed RGB for default colors, used for fast comparison.
+// Packed RGB for default colors, used until a theme is set.
 const DEFAULT_BG_PACKED = 0;
-const DEFAULT_FG_PACKED = (255 << 16) | (255 << 8) | 255;
+const DEFAULT_CURSOR_PACKED = (255 << 16) | (255 << 8) | 255;
+
+/** Parse a `#rrggbb` color into packed RGB, or null if malformed. */
+function parseHexColor(color: string): number | null {
+  const match = /^#([0-9a-f]{6})$/i.exec(color);
+  return match ? parseInt(match[1], 16) : null;
+}
 
 export class CanvasRenderer {
   private canvas: HTMLCanvasElement;
@@ -53,6 +71,15 @@ export class CanvasRenderer {
   private lastFont: string = "";
   private lastFillPacked: number = -1;
 
+  // Theme colors (packed RGB).
+  private backgroundPacked: number = DEFAULT_BG_PACKED;
+  private cursorPacked: number = DEFAULT_CURSOR_PACKED;
+
+  // Inline images: placements in grid lines, and a canvas per image.
+  private imagePlacements: readonly ImageSpan[] = [];
+  private imageSources = new Map<number, HTMLCanvasElement>();
+  private displayOffset: number = 0;
+
   // Pre-computed font strings for the 4 possible style combos.
   private fontNormal: string = "";
   private fontBold: string = "";
@@ -95,33 +122,84 @@ export class CanvasRenderer {
     this.applyFont(false, false);
   }
 
-  renderFullFrame(cells: ArrayBuffer | Uint8Array, cols: number, rows: number): void {
+  /**
+   * Set the background and cursor colors (`#rrggbb`). Takes effect on the
+   * next full frame; the backend sends one when the theme changes.
+   */
+  setTheme(background: string, cursor: string): void {
+    this.backgroundPacked = parseHexColor(background) ?? DEFAULT_BG_PACKED;
+    this.cursorPacked = parseHexColor(cursor) ?? DEFAULT_CURSOR_PACKED;
+  }
+
+  /**
+   * Set the images drawn over the text. Placement lines are grid lines;
+   * `displayOffset` maps them to viewport rows. Takes effect on the next
+   * frame.
+   */
+  setImages(
+    images: ReadonlyMap<number, ImageData>,
+    placements: readonly ImageSpan[],
+    displayOffset: number,
+  ): void {
+    for (const id of this.imageSources.keys()) {
+      if (!images.has(id)) this.imageSources.delete(id);
+    }
+    for (const [id, data] of images) {
+      if (this.imageSources.has(id)) continue;
+      const source = document.createElement("canvas");
+      source.width = data.width;
+      source.height = data.height;
+      source.getContext("2d")?.putImageData(data, 0, 0);
+      this.imageSources.set(id, source);
+    }
+    this.imagePlacements = [...placements].sort((a, b) => a.z - b.z);
+    this.displayOffset = displayOffset;
+  }
+
+  /**
+   * Draw a whole frame. `graphemes` holds the text of multi-codepoint cells,
+   * by cell index in `cells`.
+   */
+  renderFullFrame(
+    cells: ArrayBuffer | Uint8Array,
+    cols: number,
+    rows: number,
+    graphemes?: ReadonlyMap<number, string>,
+  ): void {
     if (this.cols !== cols || this.rows !== rows) {
       this.setDimensions(cols, rows);
     }
 
     const data = cells instanceof Uint8Array ? cells : new Uint8Array(cells);
 
-    this.ctx.fillStyle = DEFAULT_BG;
-    this.lastFillPacked = DEFAULT_BG_PACKED;
+    this.setFillPacked(this.backgroundPacked);
     this.ctx.fillRect(0, 0, this.cols * this.cellWidth, this.rows * this.cellHeight);
 
     for (let y = 0; y < rows; y++) {
       const rowOffset = y * cols * CELL_SIZE;
-      this.drawRow(data, rowOffset, y, cols);
+      this.drawRow(data, rowOffset, y, cols, graphemes, y * cols);
     }
+    this.drawImages(0, rows);
   }
 
-  renderDirtyRows(rows: Array<{ y: number; cells: ArrayBuffer | Uint8Array }>): void {
+  /**
+   * Redraw changed rows. `graphemes` is keyed by cell index in the full
+   * frame, as for `renderFullFrame`.
+   */
+  renderDirtyRows(
+    rows: Array<{ y: number; cells: ArrayBuffer | Uint8Array }>,
+    graphemes?: ReadonlyMap<number, string>,
+  ): void {
     for (const row of rows) {
       const data = row.cells instanceof Uint8Array ? row.cells : new Uint8Array(row.cells);
       const rowCols = data.byteLength / CELL_SIZE;
 
       const yPx = row.y * this.cellHeight;
-      this.setFill(0, 0, 0);
+      this.setFillPacked(this.backgroundPacked);
       this.ctx.fillRect(0, yPx, this.cols * this.cellWidth, this.cellHeight);
 
-      this.drawRow(data, 0, row.y, rowCols);
+      this.drawRow(data, 0, row.y, rowCols, graphemes, row.y * this.cols);
+      this.drawImages(row.y, row.y + 1);
     }
   }
 
@@ -133,7 +211,7 @@ export class CanvasRenderer {
     const x = col * this.cellWidth;
     const y = row * this.cellHeight;
 
-    this.setFill(255, 255, 255);
+    this.setFillPacked(this.cursorPacked);
 
     switch (shape) {
       case "block":
@@ -147,6 +225,13 @@ export class CanvasRenderer {
       case "bar":
         this.ctx.fillRect(x, y, 2, this.cellHeight);
         break;
+      case "hollow_block":
+        // Unfocused: an outline that leaves the character readable.
+        this.ctx.fillRect(x, y, this.cellWidth, 1);
+        this.ctx.fillRect(x, y + this.cellHeight - 1, this.cellWidth, 1);
+        this.ctx.fillRect(x, y, 1, this.cellHeight);
+        this.ctx.fillRect(x + this.cellWidth - 1, y, 1, this.cellHeight);
+        break;
       default:
         this.ctx.globalAlpha = 0.5;
         this.ctx.fillRect(x, y, this.cellWidth, this.cellHeight);
@@ -176,12 +261,43 @@ export class CanvasRenderer {
     this.fontBoldItalic = `italic bold ${base}`;
   }
 
+  /**
+   * Draw the parts of images covering viewport rows `firstRow` up to (not
+   * including) `endRow`, scaled to their cells.
+   */
+  private drawImages(firstRow: number, endRow: number): void {
+    if (this.imagePlacements.length === 0) return;
+    const cw = this.cellWidth;
+    const ch = this.cellHeight;
+
+    this.ctx.save();
+    this.ctx.beginPath();
+    this.ctx.rect(0, firstRow * ch, this.cols * cw, (endRow - firstRow) * ch);
+    this.ctx.clip();
+    for (const p of this.imagePlacements) {
+      const top = p.line + this.displayOffset;
+      if (top >= endRow || top + p.rows <= firstRow) continue;
+      const source = this.imageSources.get(p.image_id);
+      if (!source) continue;
+      this.ctx.drawImage(source, p.col * cw, top * ch, p.cols * cw, p.rows * ch);
+    }
+    this.ctx.restore();
+  }
+
   /**
    * Draw a row directly from binary cell data without allocating DecodedCell objects.
    *
-   * Three passes: backgrounds, text runs, decorations.
+   * Three passes: backgrounds, text runs, decorations. `firstIndex` is the
+   * frame cell index of the row's first cell, for looking up `graphemes`.
    */
-  private drawRow(data: Uint8Array, dataOffset: number, y: number, cols: number): void {
+  private drawRow(
+    data: Uint8Array,
+    dataOffset: number,
+    y: number,
+    cols: number,
+    graphemes: ReadonlyMap<number, string> | undefined,
+    firstIndex: number,
+  ): void {
     const yPx = y * this.cellHeight;
     const cw = this.cellWidth;
     const ch = this.cellHeight;
@@ -207,7 +323,7 @@ export class CanvasRenderer {
       }
 
       const packed = (bgR << 16) | (bgG << 8) | bgB;
-      if (packed !== DEFAULT_BG_PACKED) {
+      if (packed !== this.backgroundPacked) {
         const xPx = c * cw;
         const w = width === 2 ? cw * 2 : cw;
         this.setFill(bgR, bgG, bgB);
@@ -270,7 +386,10 @@ export class CanvasRenderer {
       // Decode codepoint.
       const cp =
         data[off] | (data[off + 1] << 8) | (data[off + 2] << 16) | ((data[off + 3] << 24) >>> 0);
-      const ch0 = cp === 0 ? " " : String.fromCodePoint(cp >>> 0);
+      let ch0 = cp === 0 ? " " : String.fromCodePoint(cp >>> 0);
+      if (data[off + 14] !== 0) {
+        ch0 = graphemes?.get(firstIndex + c) ?? ch0;
+      }
 
       if (sameStyle && runStart >= 0) {
         runText += ch0;
@@ -307,18 +426,59 @@ export class CanvasRenderer {
       const xPx = c * cw;
       const w = width === 2 ? cw * 2 : cw;
 
-      this.setFill(fgR, fgG, fgB);
-
       if (hasUnderline) {
-        this.ctx.fillRect(xPx, yPx + this.ascent + 2, w, 1);
+        if (data[off + 19] !== 0) {
+          this.setFill(data[off + 16], data[off + 17], data[off + 18]);
+        } else {
+          this.setFill(fgR, fgG, fgB);
+        }
+        const style = data[off + 15] || UNDERLINE_SINGLE;
+        this.drawUnderline(style, xPx, yPx + this.ascent + 2, w);
       }
 
       if (hasStrikethrough) {
+        this.setFill(fgR, fgG, fgB);
         this.ctx.fillRect(xPx, yPx + Math.round(this.ascent * 0.55), w, 1);
       }
     }
   }
 
+  /**
+   * Draw an underline of the given style with the current fill. Patterns are
+   * phased on the absolute x position so they continue across cells.
+   */
+  private drawUnderline(style: number, x: number, y: number, w: number): void {
+    switch (style) {
+      case UNDERLINE_DOUBLE:
+        this.ctx.fillRect(x, y - 1, w, 1);
+        this.ctx.fillRect(x, y + 1, w, 1);
+        break;
+      case UNDERLINE_CURLY:
+        for (let i = 0; i < w; i++) {
+          const dy = Math.round(Math.sin(((x + i) / 8) * Math.PI * 2) * 1.5);
+          this.ctx.fillRect(x + i, y + dy, 1, 1);
+        }
+        break;
+      case UNDERLINE_DOTTED:
+        for (let i = 0; i < w; i++) {
+          if (Math.floor(x + i) % 2 === 0) {
+            this.ctx.fillRect(x + i, y, 1, 1);
+          }
+        }
+        break;
+      case UNDERLINE_DASHED:
+        for (let i = 0; i < w; i++) {
+          if (Math.floor(x + i) % 6 < 4) {
+            this.ctx.fillRect(x + i, y, 1, 1);
+          }
+        }
+        break;
+      default:
+        this.ctx.fillRect(x, y, w, 1);
+        break;
+    }
+  }
+
   private drawTextRun(
     text: string,
     col: number,
@@ -371,4 +531,8 @@ export class CanvasRenderer {
       this.lastFillPacked = packed;
     }
   }
+
+  private setFillPacked(packed: number): void {
+    this.setFill((packed >> 16) & 0xff, (packed >> 8) & 0xff, packed & 0xff);
+  }
 }
@@ -7,17 +7,34 @@
  */
 
 import { createSignal } from "solid-js";
-import type { TerminalEvent, SessionId } from "../lib/ipc";
+import { decodeImagePixels } from "../lib/ipc";
+import type {
+  CommandBlockSpan,
+  ImageSpan,
+  ProgressInfo,
+  TerminalEvent,
+  TerminalModes,
+  SessionId,
+  ThemeInfo,
+} from "../lib/ipc";
 
-const CELL_SIZE = 16;
+const CELL_SIZE = 20;
 
 /** Represents the full state of a terminal session. */
 export interface SessionState {
   id: SessionId;
   cols: number;
   rows: number;
-  /** Persistent cell buffer (binary, 16 bytes per cell, row-major). */
+  /** Persistent cell buffer (binary, 20 bytes per cell, row-major). */
   cells: Uint8Array | null;
+  /** Text of multi-codepoint cells, by cell index in `cells`. */
+  graphemes: Map<number, string>;
+  /** Pixels of the images in `imagePlacements`, by image ID. */
+  images: Map<number, ImageData>;
+  /** Images shown, with lines indexed like `ScrollbackLine.line`. */
+  imagePlacements: ImageSpan[];
+  /** Lines the viewport is scrolled into the history. */
+  displayOffset: number;
   /** Monotonic counter incremented on every visual update. */
   frameVersion: number;
   /** Row indices that changed in the last update, or null for full frame. */
@@ -26,7 +43,20 @@ export interface SessionState {
   cursorCol: number;
   cursorShape: string;
   cursorVisible: boolean;
+  cursorBlinking: boolean;
   title: string;
+  /** Icon name set by the program (OSC 0 / OSC 1); shorter than the title. */
+  iconName: string | null;
+  /** Working directory last reported by the shell (OSC 7). */
+  cwd: string | null;
+  /** The most recently finished shell-integrated command. */
+  lastCommand: CommandBlockSpan | null;
+  /** Progress of the program's current task (OSC 9;4), if it reports one. */
+  progress: ProgressInfo | null;
+  /** Input modes set by the program; null until first reported. */
+  modes: TerminalModes | null;
+  /** Colors in effect (theme plus program overrides); null until reported. */
+  theme: ThemeInfo | null;
   alive: boolean;
 }
 
@@ -39,13 +69,24 @@ export function createSessionStore(id: SessionId, cols: number, rows: number) {
     cols,
     rows,
     cells: null,
+    graphemes: new Map(),
+    images: new Map(),
+    imagePlacements: [],
+    displayOffset: 0,
     frameVersion: 0,
     dirtyRowIndices: null,
     cursorRow: 0,
     cursorCol: 0,
     cursorShape: "block",
     cursorVisible: true,
+    cursorBlinking: false,
     title: "Phantom Terminal",
+    iconName: null,
+    cwd: null,
+    lastCommand: null,
+    progress: null,
+    modes: null,
+    theme: null,
     alive: true,
   });
 
@@ -53,17 +94,20 @@ export function createSessionStore(id: SessionId, cols: number, rows: number) {
     switch (event.type) {
       case "FullFrame": {
         const cells = new Uint8Array(event.cells);
+        const graphemes = new Map(event.graphemes.map((g) => [g.index, g.text]));
         setSession((prev) => ({
           ...prev,
           cols: event.cols,
           rows: event.rows,
           cells,
+          graphemes,
           frameVersion: prev.frameVersion + 1,
           dirtyRowIndices: null, // null = full frame
           cursorRow: event.cursor_row,
           cursorCol: event.cursor_col,
           cursorShape: event.cursor_shape,
           cursorVisible: event.cursor_visible,
+          cursorBlinking: event.cursor_blinking,
         }));
         break;
       }
@@ -74,6 +118,7 @@ export function createSessionStore(id: SessionId, cols: number, rows: number) {
 
           // Clone the cell buffer and patch dirty rows in-place.
           const cells = new Uint8Array(prev.cells);
+          const graphemes = new Map(prev.graphemes);
           const rowBytes = prev.cols * CELL_SIZE;
           const indices: number[] = [];
 
@@ -83,18 +128,58 @@ export function createSessionStore(id: SessionId, cols: number, rows: number) {
             if (offset + rowData.byteLength <= cells.byteLength) {
               cells.set(rowData, offset);
               indices.push(row.y);
+
+              const first = row.y * prev.cols;
+              for (let i = first; i < first + prev.cols; i++) {
+                graphemes.delete(i);
+              }
+              for (const g of row.graphemes) {
+                graphemes.set(first + g.index, g.text);
+              }
             }
           }
 
           return {
             ...prev,
             cells,
+            graphemes,
             frameVersion: prev.frameVersion + 1,
             dirtyRowIndices: indices,
             cursorRow: event.cursor_row,
             cursorCol: event.cursor_col,
             cursorShape: event.cursor_shape,
             cursorVisible: event.cursor_visible,
+            cursorBlinking: event.cursor_blinking,
+          };
+        });
+        break;
+      }
+
+      case "ScrollChanged":
+        setSession((prev) => ({
+          ...prev,
+          displayOffset: event.display_offset,
+        }));
+        break;
+
+      case "ImagesChanged": {
+        setSession((prev) => {
+          // Keep only images still placed; the backend resends the pixels
+          // of an image placed again later.
+          const images = new Map<number, ImageData>();
+          for (const p of event.placements) {
+            const image = prev.images.get(p.image_id);
+            if (image) images.set(p.image_id, image);
+          }
+          for (const def of event.images) {
+            images.set(def.id, decodeImagePixels(def));
+          }
+          return {
+            ...prev,
+            images,
+            imagePlacements: event.placements,
+            frameVersion: prev.frameVersion + 1,
+            dirtyRowIndices: null,
           };
         });
         break;
@@ -104,9 +189,47 @@ export function createSessionStore(id: SessionId, cols: number, rows: number) {
         setSession((prev) => ({
           ...prev,
           title: event.title,
+          iconName: event.icon_name,
+        }));
+        break;
+
+      case "CwdChanged":
+        setSession((prev) => ({
+          ...prev,
+          cwd: event.cwd,
+        }));
+        break;
+
+      case "CommandFinished":
+        setSession((prev) => ({
+          ...prev,
+          lastCommand: event.block,
+        }));
+        break;
+
+      case "Progress":
+        setSession((prev) => ({
+          ...prev,
+          progress: event.progress,
+        }));
+        break;
+
+      case "ModesChanged":
+        setSession((prev) => ({
+          ...prev,
+          modes: event.modes,
+        }));
+        break;
+
+      case "ThemeChanged":
+        setSession((prev) => ({
+          ...prev,
+          theme: event.theme,
         }));
         break;
 
+      case "Notification":
+      case "RecordingStopped":
       case "Bell":
         break;
 
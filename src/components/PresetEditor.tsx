@@ -1,7 +1,13 @@
 import React, { useState } from "react";
 
 interface PresetEditorProps {
-  onSave: (name: string, cliBinary: string, flags: string, workingDir?: string) => void;
+  onSave: (
+    name: string,
+    cliBinary: string,
+    flags: string,
+    workingDir?: string,
+    record?: boolean,
+  ) => void;
   onCancel: () => void;
 }
 
@@ -13,12 +19,13 @@ export function PresetEditor({ onSave, onCancel }: PresetEditorProps) {
   const [customBinary, setCustomBinary] = useState("");
   const [flags, setFlags] = useState("");
   const [workingDir, setWorkingDir] = useState("");
+  const [record, setRecord] = useState(false);
 
   const handleSubmit = (e: React.FormEvent) => {
     e.preventDefault();
     const binary = cliBinary === "custom" ? customBinary : cliBinary;
     if (!name || !binary) return;
-    onSave(name, binary, flags, workingDir || undefined);
+    onSave(name, binary, flags, workingDir || undefined, record);
   };
 
   return (
@@ -62,6 +69,11 @@ export function PresetEditor({ onSave, onCancel }: PresetEditorProps) {
         <input className="input" value={workingDir} onChange={(e) => setWorkingDir(e.target.value)} placeholder="/path/to/project" />
       </div>
 
+      <label style={{ display: "flex", alignItems: "center", gap: "var(--space-2)" }}>
+        <input type="checkbox" checked={record} onChange={(e) => setRecord(e.target.checked)} />
+        Record sessions (asciicast, saved to ~/.phantom/recordings)
+      </label>
+
       <div style={{ display: "flex", gap: "var(--space-2)", justifyContent: "flex-end" }}>
         <button type="button" className="btn-ghost" onClick={onCancel}>Cancel</button>
         <button type="submit" className="btn-primary">Save</button>
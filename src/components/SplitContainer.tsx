@@ -1,13 +1,46 @@
 import React, { useCallback, useRef } from "react";
 import { type LayoutNode, type Split, type Pane, isSplit, useTerminalLayout } from "../stores/terminal-layout";
 import { TerminalIsland } from "./TerminalIsland";
+import type { ProgressInfo } from "@phantom/terminal";
+import { api } from "../lib/api";
+import { toast } from "./ui/Toast";
 
 const MIN_PANE_PX = 80;
 const SNAP_THRESHOLD_PX = 20;
 
+const PROGRESS_COLORS: Record<ProgressInfo["state"], string> = {
+  normal: "var(--accent)",
+  indeterminate: "var(--accent)",
+  paused: "var(--status-warning)",
+  error: "var(--status-error)",
+};
+
+/** A thin bar along the top of a pane showing the program's task progress. */
+function ProgressBar({ progress }: { progress: ProgressInfo }) {
+  const indeterminate = progress.state === "indeterminate";
+  return (
+    <div
+      title={indeterminate ? "Working" : `${progress.percent}%`}
+      style={{ height: "2px", flexShrink: 0, background: "var(--border-default)" }}
+    >
+      <div
+        style={{
+          height: "100%",
+          width: indeterminate ? "100%" : `${progress.percent}%`,
+          background: PROGRESS_COLORS[progress.state],
+          transition: "width 120ms ease-out",
+          animation: indeterminate ? "pulse-opacity 1.5s ease-in-out infinite" : undefined,
+        }}
+      />
+    </div>
+  );
+}
+
 function PaneView({ pane }: { pane: Pane }) {
   const activePane = useTerminalLayout((s) => s.activePane);
   const setActivePane = useTerminalLayout((s) => s.setActivePane);
+  const updatePaneSession = useTerminalLayout((s) => s.updatePaneSession);
+  const updatePaneProgress = useTerminalLayout((s) => s.updatePaneProgress);
   const isActive = pane.id === activePane;
 
   return (
@@ -22,7 +55,24 @@ function PaneView({ pane }: { pane: Pane }) {
         outlineOffset: "-1px",
       }}
     >
-      <TerminalIsland command={pane.command} workingDir={pane.workingDir} />
+      {pane.progress && <ProgressBar progress={pane.progress} />}
+      <TerminalIsland
+        command={pane.command}
+        workingDir={pane.workingDir}
+        inheritCwdFrom={pane.inheritCwdFrom}
+        record={pane.record}
+        onSessionCreated={(id) => updatePaneSession(pane.id, id)}
+        onNotification={(title, body) => {
+          api.showNotification(title ?? pane.title, body).catch((err) => {
+            console.error("Notification failed:", err);
+          });
+        }}
+        onProgress={(progress) => updatePaneProgress(pane.id, progress)}
+        onRecordingStopped={(error) => toast.error(`Recording stopped: ${error}`)}
+        onOpenLink={(link) => {
+          api.openLink(link).catch((err) => toast.error(String(err)));
+        }}
+      />
     </div>
   );
 }
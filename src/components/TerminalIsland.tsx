@@ -1,17 +1,44 @@
 import React, { useEffect, useRef } from "react";
-import { mountTerminal } from "@phantom/terminal";
+import { mountTerminal, type LinkSpan, type ProgressInfo } from "@phantom/terminal";
 
 interface TerminalIslandProps {
   command?: string;
   workingDir?: string;
+  inheritCwdFrom?: number;
+  record?: boolean;
+  onSessionCreated?: (id: number) => void;
+  onNotification?: (title: string | null, body: string) => void;
+  onProgress?: (progress: ProgressInfo | null) => void;
+  onRecordingStopped?: (error: string) => void;
+  onOpenLink?: (link: LinkSpan) => void;
 }
 
-export function TerminalIsland({ command, workingDir }: TerminalIslandProps) {
+export function TerminalIsland({
+  command,
+  workingDir,
+  inheritCwdFrom,
+  record,
+  onSessionCreated,
+  onNotification,
+  onProgress,
+  onRecordingStopped,
+  onOpenLink,
+}: TerminalIslandProps) {
   const containerRef = useRef<HTMLDivElement>(null);
 
   useEffect(() => {
     if (!containerRef.current) return;
-    const dispose = mountTerminal(containerRef.current, { command, workingDir });
+    const dispose = mountTerminal(containerRef.current, {
+      command,
+      workingDir,
+      inheritCwdFrom,
+      record,
+      onSessionCreated,
+      onNotification,
+      onProgress,
+      onRecordingStopped,
+      onOpenLink,
+    });
     return () => dispose();
   }, []);
 
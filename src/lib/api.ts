@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import type { LinkSpan } from "@phantom/terminal";
 import type { GraphDiff } from "./graph-types";
 
 export interface BranchInfo {
@@ -14,6 +15,8 @@ export interface CliPreset {
   flags: string;
   working_dir: string | null;
   env_vars: string | null;
+  /** Record sessions launched from this preset. */
+  record: boolean;
 }
 
 export interface AnalysisPreset {
@@ -77,8 +80,13 @@ export const api = {
 
   // CLI Presets
   listCliPresets: () => invoke<CliPreset[]>("list_cli_presets"),
-  createCliPreset: (name: string, cliBinary: string, flags: string, workingDir?: string) =>
-    invoke<number>("create_cli_preset", { name, cliBinary, flags, workingDir }),
+  createCliPreset: (
+    name: string,
+    cliBinary: string,
+    flags: string,
+    workingDir?: string,
+    record?: boolean,
+  ) => invoke<number>("create_cli_preset", { name, cliBinary, flags, workingDir, record }),
 
   // Analysis Presets
   listAnalysisPresets: () => invoke<AnalysisPreset[]>("list_analysis_presets"),
@@ -112,4 +120,17 @@ export const api = {
 
   // Menu
   rebuildMenu: () => invoke<void>("rebuild_menu"),
+
+  // Links
+  openLink: (link: LinkSpan) =>
+    invoke<void>("open_link", {
+      kind: link.kind,
+      target: link.target,
+      line: link.line,
+      column: link.column,
+    }),
+
+  // Notifications
+  showNotification: (title: string, body: string) =>
+    invoke<void>("show_notification", { title, body }),
 };
@@ -1,4 +1,5 @@
 import { create } from "zustand";
+import type { ProgressInfo } from "@phantom/terminal";
 
 export interface Pane {
   id: string;
@@ -9,6 +10,12 @@ export interface Pane {
   command?: string;
   /** Working directory for the PTY shell process. */
   workingDir?: string;
+  /** Session whose current directory to start in when `workingDir` is unset. */
+  inheritCwdFrom?: number;
+  /** Record the session to an asciicast file from the start. */
+  record?: boolean;
+  /** Task progress the program last reported (OSC 9;4), if any. */
+  progress?: ProgressInfo | null;
 }
 
 export interface Split {
@@ -29,13 +36,23 @@ function genId(): string {
   return `pane-${nextId++}`;
 }
 
-function createPane(options?: { title?: string; command?: string; workingDir?: string }): Pane {
+interface PaneOptions {
+  title?: string;
+  command?: string;
+  workingDir?: string;
+  inheritCwdFrom?: number;
+  record?: boolean;
+}
+
+function createPane(options?: PaneOptions): Pane {
   return {
     id: genId(),
     type: "terminal",
     title: options?.title ?? "Terminal",
     command: options?.command,
     workingDir: options?.workingDir,
+    inheritCwdFrom: options?.inheritCwdFrom,
+    record: options?.record,
   };
 }
 
@@ -46,9 +63,18 @@ interface TerminalLayoutState {
   splitPane: (paneId: string, direction: "horizontal" | "vertical") => void;
   closePane: (paneId: string) => void;
   updatePaneSession: (paneId: string, sessionId: number) => void;
+  updatePaneProgress: (paneId: string, progress: ProgressInfo | null) => void;
   updateSplitSizes: (splitId: string, sizes: number[]) => void;
-  /** Add a new pane by splitting the active pane. Returns the new pane ID. */
-  addPane: (options?: { title?: string; command?: string; workingDir?: string }) => string;
+  /**
+   * Add a new pane by splitting the active pane. Returns the new pane ID.
+   * Without a `workingDir`, the pane starts in the active pane's directory.
+   */
+  addPane: (options?: {
+    title?: string;
+    command?: string;
+    workingDir?: string;
+    record?: boolean;
+  }) => string;
 }
 
 function findAndReplace(
@@ -91,6 +117,17 @@ function removeNode(node: LayoutNode, targetId: string): LayoutNode | null {
   return node;
 }
 
+function findPane(node: LayoutNode, targetId: string): Pane | undefined {
+  if (isSplit(node)) {
+    for (const child of node.children) {
+      const found = findPane(child, targetId);
+      if (found) return found;
+    }
+    return undefined;
+  }
+  return node.id === targetId ? node : undefined;
+}
+
 function collectPaneIds(node: LayoutNode): string[] {
   if (isSplit(node)) {
     return node.children.flatMap(collectPaneIds);
@@ -107,7 +144,10 @@ export const useTerminalLayout = create<TerminalLayoutState>((set) => ({
   splitPane: (paneId, direction) =>
     set((state) => ({
       root: findAndReplace(state.root, paneId, (target) => {
-        const newPane = createPane();
+        // New splits start in the split pane's current directory.
+        const newPane = createPane({
+          inheritCwdFrom: isSplit(target) ? undefined : target.sessionId,
+        });
         return {
           id: genId(),
           direction,
@@ -139,6 +179,14 @@ export const useTerminalLayout = create<TerminalLayoutState>((set) => ({
       })),
     })),
 
+  updatePaneProgress: (paneId, progress) =>
+    set((state) => ({
+      root: findAndReplace(state.root, paneId, (node) => ({
+        ...node,
+        progress,
+      })),
+    })),
+
   updateSplitSizes: (splitId, sizes) =>
     set((state) => ({
       root: findAndReplace(state.root, splitId, (node) => ({
@@ -148,7 +196,11 @@ export const useTerminalLayout = create<TerminalLayoutState>((set) => ({
     })),
 
   addPane: (options) => {
-    const newPane = createPane(options);
+    const { root, activePane } = useTerminalLayout.getState();
+    const newPane = createPane({
+      ...options,
+      inheritCwdFrom: findPane(root, activePane)?.sessionId,
+    });
     set((state) => ({
       root: findAndReplace(state.root, state.activePane, (target) => ({
         id: genId(),
@@ -28,13 +28,19 @@ export function LauncherView() {
       ? `${preset.cli_binary} ${preset.flags}`
       : preset.cli_binary;
 
-    addPane({ title: preset.name, command });
+    addPane({ title: preset.name, command, record: preset.record });
     navigate("/terminal");
   };
 
-  const handleSave = async (name: string, cliBinary: string, flags: string, workingDir?: string) => {
+  const handleSave = async (
+    name: string,
+    cliBinary: string,
+    flags: string,
+    workingDir?: string,
+    record?: boolean,
+  ) => {
     try {
-      await api.createCliPreset(name, cliBinary, flags, workingDir);
+      await api.createCliPreset(name, cliBinary, flags, workingDir, record);
       const updated = await api.listCliPresets();
       setPresets(updated);
       setShowEditor(false);